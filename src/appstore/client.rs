@@ -24,6 +24,7 @@ pub struct AppStoreConfig {
 pub struct AppStoreServerClient {
     config: AppStoreConfig,
     http_client: Client,
+    base_url: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -45,14 +46,25 @@ impl AppStoreServerClient {
         Ok(AppStoreServerClient {
             config,
             http_client,
+            base_url: None,
         })
     }
 
+    /// Sends requests to `base_url` instead of Apple's production or sandbox
+    /// host, for example a local stand-in.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.trim_end_matches('/').to_string());
+        self
+    }
+
     pub fn config(&self) -> &AppStoreConfig {
         &self.config
     }
 
-    fn base_url(&self) -> &str {
+    pub fn base_url(&self) -> &str {
+        if let Some(base_url) = &self.base_url {
+            return base_url;
+        }
         match self.config.environment {
             AppStoreEnvironment::Production => PRODUCTION_BASE_URL,
             _ => SANDBOX_BASE_URL,
@@ -0,0 +1,716 @@
+mod scenario;
+
+pub use scenario::{HistoryNotification, Scenario};
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::appstore::client::{AppStoreConfig, AppStoreServerClient};
+use crate::appstore::error::AppStoreErrorCode;
+use crate::appstore::models::*;
+use crate::appstore::signed_data::SignedDataVerifier;
+use crate::appstore::testing::TestSigner;
+use crate::appstore::types::*;
+use crate::error::AppleError;
+use crate::signing::AppleKeyPair;
+use scenario::ApiError;
+
+/// Entries per history page, as Apple returns.
+const DEFAULT_PAGE_SIZE: usize = 20;
+const AUDIENCE: &str = "appstoreconnect-v1";
+
+/// A failure served in place of the response to matching requests.
+#[derive(Debug, Clone)]
+pub struct Fault {
+    path: Option<String>,
+    status: u16,
+    error_code: AppStoreErrorCode,
+    remaining: Option<usize>,
+}
+
+impl Fault {
+    /// Responds with `status` and Apple's error body for `error_code`.
+    pub fn error(status: u16, error_code: AppStoreErrorCode) -> Self {
+        Fault {
+            path: None,
+            status,
+            error_code,
+            remaining: None,
+        }
+    }
+
+    /// A 429 with `RateLimitExceeded`, as Apple returns when throttling.
+    pub fn rate_limited() -> Self {
+        Self::error(429, AppStoreErrorCode::RateLimitExceeded)
+    }
+
+    /// Only fails requests whose path starts with `prefix`.
+    pub fn on_path(mut self, prefix: &str) -> Self {
+        self.path = Some(prefix.to_string());
+        self
+    }
+
+    /// Only fails the next `times` matching requests. Faults otherwise last
+    /// until `clear_faults`.
+    pub fn times(mut self, times: usize) -> Self {
+        self.remaining = Some(times);
+        self
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        self.remaining != Some(0)
+            && self
+                .path
+                .as_ref()
+                .is_none_or(|prefix| path.starts_with(prefix.as_str()))
+    }
+}
+
+struct Credentials {
+    issuer_id: String,
+    key_id: String,
+    key: VerifyingKey,
+}
+
+#[derive(Deserialize)]
+struct TokenHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenClaims {
+    iss: String,
+    #[serde(deserialize_with = "audience")]
+    aud: Vec<String>,
+    bid: String,
+    exp: i64,
+}
+
+/// `aud` may be a single string or an array.
+fn audience<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Audience {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match Audience::deserialize(deserializer)? {
+        Audience::One(aud) => vec![aud],
+        Audience::Many(aud) => aud,
+    })
+}
+
+struct Reply {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json<T: Serialize>(value: &T) -> Self {
+        Reply {
+            status: 200,
+            body: serde_json::to_vec(value).unwrap_or_default(),
+        }
+    }
+
+    fn error(err: ApiError) -> Self {
+        Reply {
+            status: err.status,
+            body: err.to_json().to_string().into_bytes(),
+        }
+    }
+}
+
+/// Settings fixed when the emulator starts.
+struct Settings {
+    bundle_id: String,
+    environment: AppStoreEnvironment,
+    app_apple_id: Option<i64>,
+    credentials: Option<Credentials>,
+    signer: Arc<TestSigner>,
+    page_size: usize,
+    rate_limit: Option<(usize, Duration)>,
+}
+
+#[derive(Default)]
+struct EmulatorState {
+    requests: Vec<String>,
+    faults: Vec<Fault>,
+    recent: VecDeque<Instant>,
+}
+
+pub struct AppStoreEmulatorBuilder {
+    bundle_id: String,
+    environment: AppStoreEnvironment,
+    app_apple_id: Option<i64>,
+    credentials: Option<Credentials>,
+    unauthenticated: bool,
+    signer: Option<TestSigner>,
+    scenario: Scenario,
+    page_size: usize,
+    rate_limit: Option<(usize, Duration)>,
+}
+
+impl AppStoreEmulatorBuilder {
+    /// The environment named in responses. Defaults to `Sandbox`.
+    pub fn with_environment(mut self, environment: AppStoreEnvironment) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    pub fn with_app_apple_id(mut self, app_apple_id: i64) -> Self {
+        self.app_apple_id = Some(app_apple_id);
+        self
+    }
+
+    /// Requires every request to carry a bearer JWT signed by `key_pair`,
+    /// issued by `issuer_id` for the emulator's bundle ID, as
+    /// `AppStoreServerClient` sends.
+    pub fn with_key_pair(mut self, issuer_id: &str, key_pair: &AppleKeyPair) -> Self {
+        self.credentials = Some(Credentials {
+            issuer_id: issuer_id.to_string(),
+            key_id: key_pair.key_id().to_string(),
+            key: key_pair.verifying_key(),
+        });
+        self
+    }
+
+    /// Serves requests without checking their bearer JWT. `start` fails
+    /// unless either this or `with_key_pair` is set.
+    pub fn allow_unauthenticated(mut self) -> Self {
+        self.unauthenticated = true;
+        self
+    }
+
+    /// Signs responses with `signer`'s chain instead of a fresh one, so
+    /// payloads it signed elsewhere in a test verify alike.
+    pub fn with_signer(mut self, signer: TestSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    pub fn with_scenario(mut self, scenario: Scenario) -> Self {
+        self.scenario = scenario;
+        self
+    }
+
+    /// Caps the entries per history page. Defaults to 20.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Answers with a 429 and `RateLimitExceeded` once more than
+    /// `max_requests` arrive within `window`.
+    pub fn with_rate_limit(mut self, max_requests: usize, window: Duration) -> Self {
+        self.rate_limit = Some((max_requests, window));
+        self
+    }
+
+    /// Binds to a free port on 127.0.0.1 and starts serving requests on a
+    /// background thread.
+    pub fn start(self) -> Result<AppStoreEmulator, AppleError> {
+        if self.credentials.is_none() && !self.unauthenticated {
+            return Err(AppleError::ValidationError(
+                "App Store emulator needs with_key_pair or allow_unauthenticated".to_string(),
+            ));
+        }
+        let server =
+            Server::http("127.0.0.1:0").map_err(|e| AppleError::HttpError(e.to_string()))?;
+        let port = server
+            .server_addr()
+            .to_ip()
+            .map(|addr| addr.port())
+            .ok_or_else(|| AppleError::HttpError("Emulator is not bound to a port".to_string()))?;
+        let base_url = format!("http://127.0.0.1:{}", port);
+
+        let settings = Arc::new(Settings {
+            bundle_id: self.bundle_id,
+            environment: self.environment,
+            app_apple_id: self.app_apple_id,
+            credentials: self.credentials,
+            signer: Arc::new(self.signer.unwrap_or_default()),
+            page_size: self.page_size,
+            rate_limit: self.rate_limit,
+        });
+        let server = Arc::new(server);
+        let state = Arc::new(Mutex::new(EmulatorState::default()));
+        let scenario = Arc::new(Mutex::new(self.scenario));
+        let worker = {
+            let server = Arc::clone(&server);
+            let settings = Arc::clone(&settings);
+            let state = Arc::clone(&state);
+            let scenario = Arc::clone(&scenario);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(request, &settings, &state, &scenario);
+                }
+            })
+        };
+
+        Ok(AppStoreEmulator {
+            base_url,
+            server,
+            settings,
+            state,
+            scenario,
+            worker: Some(worker),
+        })
+    }
+}
+
+/// A local stand-in for the App Store Server API. Serves transaction
+/// history and info, subscription statuses, order lookup, refund history,
+/// renewal date extensions, consumption data and notification history from
+/// a `Scenario` until dropped, signing every payload with a `TestSigner`.
+pub struct AppStoreEmulator {
+    base_url: String,
+    server: Arc<Server>,
+    settings: Arc<Settings>,
+    state: Arc<Mutex<EmulatorState>>,
+    scenario: Arc<Mutex<Scenario>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl AppStoreEmulator {
+    pub fn builder(bundle_id: &str) -> AppStoreEmulatorBuilder {
+        AppStoreEmulatorBuilder {
+            bundle_id: bundle_id.to_string(),
+            environment: AppStoreEnvironment::Sandbox,
+            app_apple_id: None,
+            credentials: None,
+            unauthenticated: false,
+            signer: None,
+            scenario: Scenario::default(),
+            page_size: DEFAULT_PAGE_SIZE,
+            rate_limit: None,
+        }
+    }
+
+    /// Starts an emulator serving `scenario` to clients signing with
+    /// `key_pair` as `issuer_id`.
+    pub fn start(
+        bundle_id: &str,
+        issuer_id: &str,
+        key_pair: &AppleKeyPair,
+        scenario: Scenario,
+    ) -> Result<Self, AppleError> {
+        Self::builder(bundle_id)
+            .with_key_pair(issuer_id, key_pair)
+            .with_scenario(scenario)
+            .start()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Creates a client whose requests go to this emulator.
+    pub fn client(&self, config: AppStoreConfig) -> Result<AppStoreServerClient, AppleError> {
+        Ok(AppStoreServerClient::new(config)?.with_base_url(&self.base_url))
+    }
+
+    pub fn signer(&self) -> &TestSigner {
+        &self.settings.signer
+    }
+
+    /// A verifier for the emulator's responses.
+    pub fn verifier(&self) -> SignedDataVerifier {
+        self.settings.signer.verifier(
+            &self.settings.bundle_id,
+            self.settings.environment.clone(),
+            self.settings.app_apple_id,
+        )
+    }
+
+    /// The data being served. Changes apply to the next request.
+    pub fn scenario(&self) -> MutexGuard<'_, Scenario> {
+        lock(&self.scenario)
+    }
+
+    /// Serves `fault` to matching requests, ahead of faults injected later.
+    pub fn inject(&self, fault: Fault) {
+        lock(&self.state).faults.push(fault);
+    }
+
+    pub fn clear_faults(&self) {
+        lock(&self.state).faults.clear();
+    }
+
+    /// The method and target of every request received, oldest first.
+    pub fn requests(&self) -> Vec<String> {
+        lock(&self.state).requests.clone()
+    }
+}
+
+impl Drop for AppStoreEmulator {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn handle(
+    mut request: Request,
+    settings: &Settings,
+    state: &Mutex<EmulatorState>,
+    scenario: &Mutex<Scenario>,
+) {
+    let mut body = Vec::new();
+    let reply = match request.as_reader().read_to_end(&mut body) {
+        Ok(_) => admit(&request, settings, state)
+            .and_then(|()| dispatch(&request, &body, settings, &mut lock(scenario)))
+            .unwrap_or_else(Reply::error),
+        Err(e) => Reply::error(ApiError::new(
+            400,
+            AppStoreErrorCode::GeneralBadRequest,
+            e.to_string(),
+        )),
+    };
+
+    let mut response = Response::from_data(reply.body).with_status_code(reply.status);
+    if let Ok(header) = Header::from_bytes("Content-Type", "application/json") {
+        response = response.with_header(header);
+    }
+    let _ = request.respond(response);
+}
+
+/// Records the request, then applies authentication, the rate limit and
+/// injected faults, in that order.
+fn admit(
+    request: &Request,
+    settings: &Settings,
+    state: &Mutex<EmulatorState>,
+) -> Result<(), ApiError> {
+    let mut state = lock(state);
+    state
+        .requests
+        .push(format!("{} {}", request.method(), request.url()));
+
+    authenticate(request, settings)?;
+
+    if let Some((max_requests, window)) = settings.rate_limit {
+        let now = Instant::now();
+        while state
+            .recent
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= window)
+        {
+            state.recent.pop_front();
+        }
+        if state.recent.len() >= max_requests {
+            return Err(ApiError::new(
+                429,
+                AppStoreErrorCode::RateLimitExceeded,
+                "Rate limit exceeded.",
+            ));
+        }
+        state.recent.push_back(now);
+    }
+
+    let path = request.url().split('?').next().unwrap_or_default();
+    if let Some(fault) = state.faults.iter_mut().find(|fault| fault.matches(path)) {
+        if let Some(remaining) = &mut fault.remaining {
+            *remaining -= 1;
+        }
+        return Err(ApiError::new(
+            fault.status,
+            fault.error_code.clone(),
+            "Injected fault.",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the bearer JWT's signature, `kid`, `iss`, `bid`, `aud` and
+/// expiry against the configured key pair.
+fn authenticate(request: &Request, settings: &Settings) -> Result<(), ApiError> {
+    let Some(credentials) = &settings.credentials else {
+        return Ok(());
+    };
+    let unauthorized =
+        |message: &str| ApiError::new(401, AppStoreErrorCode::UnauthorizedAccess, message);
+
+    let token = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .ok_or_else(|| unauthorized("Missing bearer token."))?;
+    let malformed = || unauthorized("Malformed bearer token.");
+    let (signing_input, signature) = token.rsplit_once('.').ok_or_else(malformed)?;
+    let (header, claims) = signing_input.split_once('.').ok_or_else(malformed)?;
+    let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).map_err(|_| malformed());
+    let header: TokenHeader = serde_json::from_slice(&decode(header)?).map_err(|_| malformed())?;
+    let claims: TokenClaims = serde_json::from_slice(&decode(claims)?).map_err(|_| malformed())?;
+    let signature = Signature::from_slice(&decode(signature)?).map_err(|_| malformed())?;
+
+    if header.alg != "ES256" {
+        return Err(unauthorized("Bearer token is not signed with ES256."));
+    }
+    if header.kid.as_deref() != Some(credentials.key_id.as_str()) {
+        return Err(unauthorized("Unknown key ID."));
+    }
+    credentials
+        .key
+        .verify(signing_input.as_bytes(), &signature)
+        .map_err(|_| unauthorized("Invalid bearer token signature."))?;
+    if claims.iss != credentials.issuer_id {
+        return Err(unauthorized("Unexpected issuer ID."));
+    }
+    if claims.bid != settings.bundle_id {
+        return Err(unauthorized("Unexpected bundle ID."));
+    }
+    if !claims.aud.iter().any(|aud| aud == AUDIENCE) {
+        return Err(unauthorized("Unexpected audience."));
+    }
+    if claims.exp <= chrono::Utc::now().timestamp() {
+        return Err(unauthorized("Bearer token has expired."));
+    }
+    Ok(())
+}
+
+fn dispatch(
+    request: &Request,
+    body: &[u8],
+    settings: &Settings,
+    scenario: &mut Scenario,
+) -> Result<Reply, ApiError> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let query: Vec<(String, String)> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let param = |name: &str| {
+        query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let signer = &settings.signer;
+    let now = chrono::Utc::now().timestamp_millis();
+
+    let reply = match (request.method(), segments.as_slice()) {
+        (Method::Get, ["inApps", version @ ("v1" | "v2"), "history", transaction_id]) => {
+            scenario.transaction(transaction_id)?;
+            let filter = (*version == "v2").then(|| history_request(&query));
+            let (transactions, revision, has_more) = paginate(
+                scenario.history(filter.as_ref()),
+                param("revision"),
+                settings.page_size,
+                AppStoreErrorCode::InvalidRequestRevision,
+            )?;
+            Reply::json(&HistoryResponse {
+                signed_transactions: transactions
+                    .into_iter()
+                    .map(|tx| signer.sign_transaction(tx))
+                    .collect(),
+                revision,
+                bundle_id: settings.bundle_id.clone(),
+                app_apple_id: settings.app_apple_id,
+                environment: settings.environment.clone(),
+                has_more,
+            })
+        }
+        (Method::Get, ["inApps", "v1", "transactions", transaction_id]) => {
+            Reply::json(&TransactionInfoResponse {
+                signed_transaction_info: signer
+                    .sign_transaction(scenario.transaction(transaction_id)?),
+            })
+        }
+        (
+            Method::Put,
+            [
+                "inApps",
+                "v1",
+                "transactions",
+                "consumption",
+                transaction_id,
+            ],
+        ) => {
+            scenario.transaction(transaction_id)?;
+            let consumption: ConsumptionRequest = parse(body)?;
+            scenario
+                .consumption
+                .push((transaction_id.to_string(), consumption));
+            Reply {
+                status: 202,
+                body: Vec::new(),
+            }
+        }
+        (Method::Get, ["inApps", "v1", "subscriptions", transaction_id]) => {
+            scenario.transaction(transaction_id)?;
+            Reply::json(&StatusResponse {
+                environment: settings.environment.clone(),
+                bundle_id: settings.bundle_id.clone(),
+                app_apple_id: settings.app_apple_id,
+                data: scenario.subscription_groups(signer, now),
+            })
+        }
+        (Method::Post, ["inApps", "v1", "subscriptions", "extend", "mass"]) => {
+            let extension: MassExtendRenewalDateRequest = parse(body)?;
+            Reply::json(&scenario.mass_extend(&extension, now)?)
+        }
+        (
+            Method::Put,
+            [
+                "inApps",
+                "v1",
+                "subscriptions",
+                "extend",
+                original_transaction_id,
+            ],
+        ) => {
+            let extension: ExtendRenewalDateRequest = parse(body)?;
+            Reply::json(&scenario.extend(original_transaction_id, &extension, now)?)
+        }
+        (
+            Method::Get,
+            [
+                "inApps",
+                "v1",
+                "subscriptions",
+                "extend",
+                "mass",
+                product_id,
+                request_identifier,
+            ],
+        ) => Reply::json(&scenario.mass_extension_status(product_id, request_identifier)?),
+        (Method::Get, ["inApps", "v1", "lookup", order_id]) => {
+            let response = match scenario.order(order_id) {
+                Some(transactions) => OrderLookupResponse {
+                    status: OrderLookupStatus::Valid,
+                    signed_transactions: transactions
+                        .into_iter()
+                        .map(|tx| signer.sign_transaction(tx))
+                        .collect(),
+                },
+                None => OrderLookupResponse {
+                    status: OrderLookupStatus::Invalid,
+                    signed_transactions: Vec::new(),
+                },
+            };
+            Reply::json(&response)
+        }
+        (Method::Get, ["inApps", "v2", "refund", "lookup", transaction_id]) => {
+            scenario.transaction(transaction_id)?;
+            let (transactions, revision, has_more) = paginate(
+                scenario.refunds(),
+                param("revision"),
+                settings.page_size,
+                AppStoreErrorCode::InvalidRequestRevision,
+            )?;
+            Reply::json(&RefundHistoryResponse {
+                signed_transactions: transactions
+                    .into_iter()
+                    .map(|tx| signer.sign_transaction(tx))
+                    .collect(),
+                revision,
+                has_more,
+            })
+        }
+        (Method::Post, ["inApps", "v1", "notifications", "history"]) => {
+            let history: NotificationHistoryRequest = parse(body)?;
+            let (notifications, token, has_more) = paginate(
+                scenario.notification_history(&history)?,
+                param("paginationToken"),
+                settings.page_size,
+                AppStoreErrorCode::InvalidPaginationToken,
+            )?;
+            Reply::json(&NotificationHistoryResponse {
+                notification_history: notifications
+                    .into_iter()
+                    .map(|notification| NotificationHistoryResponseItem {
+                        signed_payload: signer.sign_notification(&notification.payload),
+                        send_attempts: notification.send_attempts.clone(),
+                    })
+                    .collect(),
+                pagination_token: has_more.then_some(token),
+                has_more,
+            })
+        }
+        _ => {
+            return Err(ApiError::new(
+                404,
+                AppStoreErrorCode::GeneralBadRequest,
+                format!("Unknown endpoint: {} {}", request.method(), path),
+            ));
+        }
+    };
+    Ok(reply)
+}
+
+/// Transaction history filters from `/inApps/v2/history` query parameters.
+fn history_request(query: &[(String, String)]) -> TransactionHistoryRequest {
+    let all = |name: &str| {
+        let values: Vec<&str> = query
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .collect();
+        (!values.is_empty()).then_some(values)
+    };
+    let one = |name: &str| all(name).map(|values| values[0]);
+    TransactionHistoryRequest {
+        start_date: one("startDate").and_then(|date| date.parse().ok()),
+        end_date: one("endDate").and_then(|date| date.parse().ok()),
+        product_id: all("productId").map(|ids| ids.into_iter().map(str::to_string).collect()),
+        product_type: all("productType").map(|types| {
+            types
+                .into_iter()
+                .map(TransactionHistoryProductType::from)
+                .collect()
+        }),
+        sort: one("sort").map(Order::from),
+        subscription_group_identifier: all("subscriptionGroupIdentifier")
+            .map(|groups| groups.into_iter().map(str::to_string).collect()),
+        in_app_ownership_type: one("inAppOwnershipType").map(InAppOwnershipType::from),
+        revoked: one("revoked").and_then(|revoked| revoked.parse().ok()),
+    }
+}
+
+/// One page of `items` from the offset in `cursor`, with the cursor for
+/// the next page and whether it has entries.
+fn paginate<T>(
+    items: Vec<T>,
+    cursor: Option<&str>,
+    page_size: usize,
+    invalid: AppStoreErrorCode,
+) -> Result<(Vec<T>, String, bool), ApiError> {
+    let start = match cursor {
+        None => 0,
+        Some(cursor) => cursor
+            .parse::<usize>()
+            .ok()
+            .filter(|start| *start <= items.len())
+            .ok_or_else(|| ApiError::new(400, invalid, "Invalid cursor."))?,
+    };
+    let end = (start + page_size).min(items.len());
+    let has_more = end < items.len();
+    let page = items.into_iter().skip(start).take(end - start).collect();
+    Ok((page, end.to_string(), has_more))
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(body)
+        .map_err(|e| ApiError::new(400, AppStoreErrorCode::GeneralBadRequest, e.to_string()))
+}
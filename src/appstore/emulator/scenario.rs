@@ -0,0 +1,515 @@
+use std::collections::{BTreeMap, HashMap};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde_json::{Value, json};
+
+use crate::appstore::error::AppStoreErrorCode;
+use crate::appstore::models::*;
+use crate::appstore::notifications_v2::ResponseBodyV2DecodedPayload;
+use crate::appstore::signed_data::{JWSRenewalInfoDecodedPayload, JWSTransactionDecodedPayload};
+use crate::appstore::testing::{self, TestSigner};
+use crate::appstore::types::*;
+
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+/// Apple allows two renewal date extensions per subscription per year.
+const MAX_EXTENSIONS: u32 = 2;
+
+pub(super) struct ApiError {
+    pub status: u16,
+    pub code: AppStoreErrorCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: u16, code: AppStoreErrorCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "errorCode": self.code.code(), "errorMessage": self.message })
+    }
+}
+
+/// A notification as notification history reports it.
+#[derive(Debug, Clone)]
+pub struct HistoryNotification {
+    pub payload: ResponseBodyV2DecodedPayload,
+    pub send_attempts: Vec<SendAttemptItem>,
+}
+
+/// The data an `AppStoreEmulator` serves. Every transaction belongs to one
+/// customer, so any of their transaction IDs returns the whole history.
+///
+/// Extensions change the stored transactions and consumption data is
+/// appended to `consumption`, so tests can inspect both afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct Scenario {
+    pub transactions: Vec<JWSTransactionDecodedPayload>,
+    /// Renewal info by original transaction ID. Subscriptions without any
+    /// are served as auto-renewing.
+    pub renewal_infos: HashMap<String, JWSRenewalInfoDecodedPayload>,
+    /// Status by original transaction ID, in place of the one derived from
+    /// the latest transaction's expiry and revocation.
+    pub statuses: HashMap<String, SubscriptionStatus>,
+    /// Transaction IDs by order ID.
+    pub orders: HashMap<String, Vec<String>>,
+    pub notifications: Vec<HistoryNotification>,
+    /// Consumption data received, with the transaction ID it was sent for.
+    pub consumption: Vec<(String, ConsumptionRequest)>,
+    extensions: HashMap<String, ExtendRenewalDateResponse>,
+    extension_counts: HashMap<String, u32>,
+    mass_extensions: HashMap<(String, String), MassExtendRenewalDateStatusResponse>,
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_transaction(mut self, transaction: JWSTransactionDecodedPayload) -> Self {
+        self.transactions.push(transaction);
+        self
+    }
+
+    pub fn with_renewal_info(mut self, renewal_info: JWSRenewalInfoDecodedPayload) -> Self {
+        self.renewal_infos
+            .insert(renewal_info.original_transaction_id.clone(), renewal_info);
+        self
+    }
+
+    pub fn with_status(
+        mut self,
+        original_transaction_id: &str,
+        status: SubscriptionStatus,
+    ) -> Self {
+        self.statuses
+            .insert(original_transaction_id.to_string(), status);
+        self
+    }
+
+    pub fn with_order(mut self, order_id: &str, transaction_ids: &[&str]) -> Self {
+        self.orders.insert(
+            order_id.to_string(),
+            transaction_ids.iter().map(|id| id.to_string()).collect(),
+        );
+        self
+    }
+
+    pub fn with_notification(
+        mut self,
+        payload: ResponseBodyV2DecodedPayload,
+        send_attempts: Vec<SendAttemptItem>,
+    ) -> Self {
+        self.notifications.push(HistoryNotification {
+            payload,
+            send_attempts,
+        });
+        self
+    }
+
+    /// The most recent transaction of a purchase or subscription.
+    pub fn latest_transaction(
+        &self,
+        original_transaction_id: &str,
+    ) -> Option<&JWSTransactionDecodedPayload> {
+        self.transactions
+            .iter()
+            .filter(|tx| tx.original_transaction_id == original_transaction_id)
+            .max_by_key(|tx| (tx.purchase_date, tx.signed_date))
+    }
+
+    fn latest_transaction_mut(
+        &mut self,
+        original_transaction_id: &str,
+    ) -> Option<&mut JWSTransactionDecodedPayload> {
+        self.transactions
+            .iter_mut()
+            .filter(|tx| tx.original_transaction_id == original_transaction_id)
+            .max_by_key(|tx| (tx.purchase_date, tx.signed_date))
+    }
+
+    fn original_transaction_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .transactions
+            .iter()
+            .map(|tx| tx.original_transaction_id.clone())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    pub(super) fn transaction(
+        &self,
+        transaction_id: &str,
+    ) -> Result<&JWSTransactionDecodedPayload, ApiError> {
+        self.transactions
+            .iter()
+            .find(|tx| tx.transaction_id == transaction_id)
+            .ok_or_else(|| {
+                ApiError::new(
+                    404,
+                    AppStoreErrorCode::TransactionIdNotFound,
+                    "Transaction id not found.",
+                )
+            })
+    }
+
+    /// The customer's transactions matching `request`, in purchase order
+    /// unless it asks for `DESCENDING`.
+    pub(super) fn history(
+        &self,
+        request: Option<&TransactionHistoryRequest>,
+    ) -> Vec<&JWSTransactionDecodedPayload> {
+        let mut transactions: Vec<_> = self
+            .transactions
+            .iter()
+            .filter(|tx| request.is_none_or(|request| history_matches(request, tx)))
+            .collect();
+        transactions.sort_by(|a, b| {
+            (a.purchase_date, &a.transaction_id).cmp(&(b.purchase_date, &b.transaction_id))
+        });
+        if request.is_some_and(|request| request.sort == Some(Order::DESCENDING)) {
+            transactions.reverse();
+        }
+        transactions
+    }
+
+    pub(super) fn refunds(&self) -> Vec<&JWSTransactionDecodedPayload> {
+        let mut refunds: Vec<_> = self
+            .transactions
+            .iter()
+            .filter(|tx| tx.revocation_date.is_some())
+            .collect();
+        refunds.sort_by_key(|tx| tx.revocation_date);
+        refunds
+    }
+
+    pub(super) fn order(&self, order_id: &str) -> Option<Vec<&JWSTransactionDecodedPayload>> {
+        let ids = self.orders.get(order_id)?;
+        Some(
+            ids.iter()
+                .filter_map(|id| self.transactions.iter().find(|tx| tx.transaction_id == *id))
+                .collect(),
+        )
+    }
+
+    fn status(&self, transaction: &JWSTransactionDecodedPayload, now: i64) -> SubscriptionStatus {
+        if let Some(status) = self.statuses.get(&transaction.original_transaction_id) {
+            return status.clone();
+        }
+        if transaction.revocation_date.is_some() {
+            SubscriptionStatus::Revoked
+        } else if transaction
+            .expires_date
+            .is_some_and(|expires| expires <= now)
+        {
+            SubscriptionStatus::Expired
+        } else {
+            SubscriptionStatus::Active
+        }
+    }
+
+    /// The latest transaction and renewal info of every auto-renewable
+    /// subscription, signed and grouped by subscription group.
+    pub(super) fn subscription_groups(
+        &self,
+        signer: &TestSigner,
+        now: i64,
+    ) -> Vec<SubscriptionGroupIdentifierItem> {
+        let mut groups: BTreeMap<String, Vec<LastTransactionsItem>> = BTreeMap::new();
+        for original_transaction_id in self.original_transaction_ids() {
+            let Some(transaction) = self.latest_transaction(&original_transaction_id) else {
+                continue;
+            };
+            if transaction.product_type != ProductType::AutoRenewableSubscription {
+                continue;
+            }
+            let renewal_info = self
+                .renewal_infos
+                .get(&original_transaction_id)
+                .cloned()
+                .unwrap_or_else(|| testing::renewal_info(transaction));
+            groups
+                .entry(
+                    transaction
+                        .subscription_group_identifier
+                        .clone()
+                        .unwrap_or_default(),
+                )
+                .or_default()
+                .push(LastTransactionsItem {
+                    status: self.status(transaction, now),
+                    original_transaction_id,
+                    signed_transaction_info: signer.sign_transaction(transaction),
+                    signed_renewal_info: signer.sign_renewal_info(&renewal_info),
+                });
+        }
+        groups
+            .into_iter()
+            .map(|(subscription_group_identifier, last_transactions)| {
+                SubscriptionGroupIdentifierItem {
+                    subscription_group_identifier,
+                    last_transactions,
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the subscription is active and auto-renewable, the only kind
+    /// Apple extends.
+    fn extendable(&self, original_transaction_id: &str, now: i64) -> bool {
+        self.latest_transaction(original_transaction_id)
+            .is_some_and(|tx| {
+                tx.product_type == ProductType::AutoRenewableSubscription
+                    && self.status(tx, now) == SubscriptionStatus::Active
+            })
+    }
+
+    fn apply_extension(&mut self, original_transaction_id: &str, days: i32) -> Option<i64> {
+        let transaction = self.latest_transaction_mut(original_transaction_id)?;
+        let expires_date = transaction.expires_date? + i64::from(days) * DAY_MILLIS;
+        transaction.expires_date = Some(expires_date);
+        if let Some(renewal_info) = self.renewal_infos.get_mut(original_transaction_id) {
+            renewal_info.renewal_date = Some(expires_date);
+        }
+        Some(expires_date)
+    }
+
+    /// Extends one subscription. Repeating a `requestIdentifier` returns the
+    /// first response without extending again.
+    pub(super) fn extend(
+        &mut self,
+        original_transaction_id: &str,
+        request: &ExtendRenewalDateRequest,
+        now: i64,
+    ) -> Result<ExtendRenewalDateResponse, ApiError> {
+        if let Some(response) = self.extensions.get(&request.request_identifier) {
+            return Ok(response.clone());
+        }
+        check_extend_by_days(request.extend_by_days)?;
+        let Some(transaction) = self.latest_transaction(original_transaction_id) else {
+            return Err(ApiError::new(
+                404,
+                AppStoreErrorCode::OriginalTransactionIdNotFound,
+                "Original transaction id not found.",
+            ));
+        };
+        let web_order_line_item_id = transaction.web_order_line_item_id.clone();
+        if !self.extendable(original_transaction_id, now) {
+            return Err(ApiError::new(
+                403,
+                AppStoreErrorCode::SubscriptionExtensionIneligible,
+                "Forbidden - subscription state ineligible for extension.",
+            ));
+        }
+        let count = self
+            .extension_counts
+            .entry(original_transaction_id.to_string())
+            .or_default();
+        if *count >= MAX_EXTENSIONS {
+            return Err(ApiError::new(
+                403,
+                AppStoreErrorCode::SubscriptionMaxExtension,
+                "Forbidden - subscription has reached maximum extension count.",
+            ));
+        }
+        *count += 1;
+
+        let effective_date = self
+            .apply_extension(original_transaction_id, request.extend_by_days)
+            .unwrap_or(now);
+        let response = ExtendRenewalDateResponse {
+            effective_date,
+            original_transaction_id: original_transaction_id.to_string(),
+            web_order_line_item_id: web_order_line_item_id.unwrap_or_default(),
+            success: true,
+        };
+        self.extensions
+            .insert(request.request_identifier.clone(), response.clone());
+        Ok(response)
+    }
+
+    /// Extends every active subscription to `product_id` in the requested
+    /// storefronts, completing immediately.
+    pub(super) fn mass_extend(
+        &mut self,
+        request: &MassExtendRenewalDateRequest,
+        now: i64,
+    ) -> Result<MassExtendRenewalDateResponse, ApiError> {
+        check_extend_by_days(request.extend_by_days)?;
+        let key = (
+            request.product_id.clone(),
+            request.request_identifier.clone(),
+        );
+        if !self.mass_extensions.contains_key(&key) {
+            let mut succeeded = 0;
+            for original_transaction_id in self.original_transaction_ids() {
+                let eligible = self
+                    .latest_transaction(&original_transaction_id)
+                    .is_some_and(|tx| {
+                        tx.product_id == request.product_id
+                            && request
+                                .storefront_country_codes
+                                .as_ref()
+                                .is_none_or(|codes| {
+                                    tx.storefront
+                                        .as_ref()
+                                        .is_some_and(|storefront| codes.contains(storefront))
+                                })
+                    });
+                if eligible
+                    && self.extendable(&original_transaction_id, now)
+                    && self
+                        .apply_extension(&original_transaction_id, request.extend_by_days)
+                        .is_some()
+                {
+                    succeeded += 1;
+                }
+            }
+            self.mass_extensions.insert(
+                key,
+                MassExtendRenewalDateStatusResponse {
+                    request_identifier: request.request_identifier.clone(),
+                    complete: true,
+                    complete_date: Some(now),
+                    succeeded_count: Some(succeeded),
+                    failed_count: Some(0),
+                },
+            );
+        }
+        Ok(MassExtendRenewalDateResponse {
+            request_identifier: request.request_identifier.clone(),
+        })
+    }
+
+    pub(super) fn mass_extension_status(
+        &self,
+        product_id: &str,
+        request_identifier: &str,
+    ) -> Result<MassExtendRenewalDateStatusResponse, ApiError> {
+        self.mass_extensions
+            .get(&(product_id.to_string(), request_identifier.to_string()))
+            .cloned()
+            .ok_or_else(|| {
+                ApiError::new(
+                    404,
+                    AppStoreErrorCode::StatusRequestNotFound,
+                    "The server didn't find a subscription-renewal-date extension request for this requestIdentifier and productId combination.",
+                )
+            })
+    }
+
+    /// Notifications signed in `[startDate, endDate)` matching the request's
+    /// filters, oldest first.
+    pub(super) fn notification_history(
+        &self,
+        request: &NotificationHistoryRequest,
+    ) -> Result<Vec<&HistoryNotification>, ApiError> {
+        if request.start_date > request.end_date {
+            return Err(ApiError::new(
+                400,
+                AppStoreErrorCode::StartDateAfterEndDate,
+                "Invalid request. The end date precedes the start date or the dates are the same.",
+            ));
+        }
+        let original_transaction_id = match &request.transaction_id {
+            Some(transaction_id) => Some(
+                self.transaction(transaction_id)?
+                    .original_transaction_id
+                    .clone(),
+            ),
+            None => None,
+        };
+
+        let mut notifications: Vec<_> = self
+            .notifications
+            .iter()
+            .filter(|notification| {
+                let payload = &notification.payload;
+                (request.start_date..request.end_date).contains(&payload.signed_date)
+                    && request
+                        .notification_type
+                        .as_ref()
+                        .is_none_or(|kind| *kind == payload.notification_type)
+                    && request
+                        .notification_subtype
+                        .as_ref()
+                        .is_none_or(|subtype| payload.subtype.as_ref() == Some(subtype))
+                    && (request.only_failures != Some(true)
+                        || notification.send_attempts.iter().any(|attempt| {
+                            attempt.send_attempt_result != SendAttemptResult::SUCCESS
+                        }))
+                    && original_transaction_id.as_ref().is_none_or(|original| {
+                        notification_transaction(payload)
+                            .is_some_and(|tx| tx.original_transaction_id == *original)
+                    })
+            })
+            .collect();
+        notifications.sort_by_key(|notification| notification.payload.signed_date);
+        Ok(notifications)
+    }
+}
+
+fn check_extend_by_days(days: i32) -> Result<(), ApiError> {
+    if !(1..=90).contains(&days) {
+        return Err(ApiError::new(
+            400,
+            AppStoreErrorCode::InvalidExtendByDays,
+            "Invalid extend by days value.",
+        ));
+    }
+    Ok(())
+}
+
+fn history_matches(request: &TransactionHistoryRequest, tx: &JWSTransactionDecodedPayload) -> bool {
+    let product_type = match &tx.product_type {
+        ProductType::AutoRenewableSubscription => TransactionHistoryProductType::AUTO_RENEWABLE,
+        ProductType::NonRenewingSubscription => TransactionHistoryProductType::NON_RENEWABLE,
+        ProductType::Consumable => TransactionHistoryProductType::CONSUMABLE,
+        ProductType::NonConsumable => TransactionHistoryProductType::NON_CONSUMABLE,
+        ProductType::Unknown(raw) => TransactionHistoryProductType::Unknown(raw.clone()),
+    };
+    request
+        .start_date
+        .is_none_or(|start| tx.purchase_date >= start)
+        && request.end_date.is_none_or(|end| tx.purchase_date < end)
+        && request
+            .product_id
+            .as_ref()
+            .is_none_or(|ids| ids.contains(&tx.product_id))
+        && request
+            .product_type
+            .as_ref()
+            .is_none_or(|types| types.contains(&product_type))
+        && request
+            .subscription_group_identifier
+            .as_ref()
+            .is_none_or(|groups| {
+                tx.subscription_group_identifier
+                    .as_ref()
+                    .is_some_and(|group| groups.contains(group))
+            })
+        && request
+            .in_app_ownership_type
+            .as_ref()
+            .is_none_or(|ownership| *ownership == tx.in_app_ownership_type)
+        && request
+            .revoked
+            .is_none_or(|revoked| revoked == tx.revocation_date.is_some())
+}
+
+/// The transaction a notification is about. Scenario data is trusted, so
+/// it is read without verification.
+fn notification_transaction(
+    payload: &ResponseBodyV2DecodedPayload,
+) -> Option<JWSTransactionDecodedPayload> {
+    let jws = payload.data.as_ref()?.signed_transaction_info.as_ref()?;
+    let claims = URL_SAFE_NO_PAD.decode(jws.split('.').nth(1)?).ok()?;
+    serde_json::from_slice(&claims).ok()
+}
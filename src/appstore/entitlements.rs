@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+
+use crate::error::AppleError;
+
+use super::models::StatusResponse;
+use super::notification_handler::{NotificationEvent, NotificationKind};
+use super::signed_data::{
+    JWSRenewalInfoDecodedPayload, JWSTransactionDecodedPayload, SignedDataVerifier,
+};
+use super::types::{AutoRenewStatus, InAppOwnershipType, SubscriptionStatus};
+
+/// Why an entitlement grants or withholds access.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntitlementReason {
+    /// Paid through `expires_date`, or a purchase that never expires.
+    Active,
+    /// Renewal failed but Apple's billing grace period still grants access.
+    GracePeriod,
+    /// Renewal failed and Apple is still retrying billing.
+    BillingRetry,
+    Expired,
+    /// Refunded, or family sharing was withdrawn.
+    Revoked,
+    /// The customer moved to another product in the same group.
+    Upgraded,
+}
+
+/// Access granted by one subscription (or purchase) at a point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entitlement {
+    pub original_transaction_id: String,
+    pub product_id: String,
+    pub subscription_group_identifier: Option<String>,
+    pub has_access: bool,
+    pub reason: EntitlementReason,
+    /// When access ends if nothing else happens. `None` for purchases that
+    /// do not expire.
+    pub expires_date: Option<i64>,
+    pub family_shared: bool,
+    pub will_auto_renew: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+struct SubscriptionRecord {
+    transaction: JWSTransactionDecodedPayload,
+    renewal_info: Option<JWSRenewalInfoDecodedPayload>,
+    status: Option<SubscriptionStatus>,
+    /// `signedDate` of the newest input applied, to ignore stale updates.
+    signed_date: i64,
+}
+
+impl SubscriptionRecord {
+    fn entitlement(&self, now: i64) -> Entitlement {
+        let transaction = &self.transaction;
+        let grace_expires = self
+            .renewal_info
+            .as_ref()
+            .and_then(|info| info.grace_period_expires_date);
+        let in_grace = grace_expires.is_some_and(|expires| expires > now);
+
+        let (reason, expires_date) = if transaction.revocation_date.is_some()
+            || self.status == Some(SubscriptionStatus::Revoked)
+        {
+            (EntitlementReason::Revoked, transaction.revocation_date)
+        } else if transaction.is_upgraded == Some(true) {
+            (EntitlementReason::Upgraded, transaction.expires_date)
+        } else {
+            match (&self.status, transaction.expires_date) {
+                (Some(SubscriptionStatus::BillingGracePeriod), _) if in_grace => {
+                    (EntitlementReason::GracePeriod, grace_expires)
+                }
+                (Some(SubscriptionStatus::BillingGracePeriod), expires)
+                | (Some(SubscriptionStatus::BillingRetryPeriod), expires) => {
+                    (EntitlementReason::BillingRetry, expires)
+                }
+                (Some(SubscriptionStatus::Expired), expires) => {
+                    (EntitlementReason::Expired, expires)
+                }
+                (_, None) => (EntitlementReason::Active, None),
+                (_, Some(expires)) if expires > now => (EntitlementReason::Active, Some(expires)),
+                (_, Some(_)) if in_grace => (EntitlementReason::GracePeriod, grace_expires),
+                (_, Some(expires))
+                    if self
+                        .renewal_info
+                        .as_ref()
+                        .and_then(|info| info.is_in_billing_retry_period)
+                        == Some(true) =>
+                {
+                    (EntitlementReason::BillingRetry, Some(expires))
+                }
+                (_, Some(expires)) => (EntitlementReason::Expired, Some(expires)),
+            }
+        };
+
+        Entitlement {
+            original_transaction_id: transaction.original_transaction_id.clone(),
+            product_id: transaction.product_id.clone(),
+            subscription_group_identifier: transaction.subscription_group_identifier.clone(),
+            has_access: matches!(
+                reason,
+                EntitlementReason::Active | EntitlementReason::GracePeriod
+            ),
+            reason,
+            expires_date,
+            family_shared: transaction.in_app_ownership_type == InAppOwnershipType::FAMILY_SHARED,
+            will_auto_renew: self
+                .renewal_info
+                .as_ref()
+                .map(|info| info.auto_renew_status == AutoRenewStatus::On),
+        }
+    }
+}
+
+/// Folds subscription statuses, transactions and V2 notifications into
+/// per-product and per-group access.
+///
+/// State is keyed by original transaction ID and evaluated at a time the
+/// caller passes in, so the engine needs no network or clock.
+#[derive(Debug, Clone, Default)]
+pub struct EntitlementEngine {
+    records: HashMap<String, SubscriptionRecord>,
+}
+
+impl EntitlementEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the latest known state of one subscription. Inputs signed
+    /// before the state already held for it are ignored.
+    pub fn apply(
+        &mut self,
+        transaction: JWSTransactionDecodedPayload,
+        renewal_info: Option<JWSRenewalInfoDecodedPayload>,
+        status: Option<SubscriptionStatus>,
+    ) {
+        let signed_date = renewal_info
+            .as_ref()
+            .map_or(transaction.signed_date, |info| {
+                info.signed_date.max(transaction.signed_date)
+            });
+        let key = transaction.original_transaction_id.clone();
+        if let Some(existing) = self.records.get(&key)
+            && existing.signed_date > signed_date
+        {
+            return;
+        }
+        self.records.insert(
+            key,
+            SubscriptionRecord {
+                transaction,
+                renewal_info,
+                status,
+                signed_date,
+            },
+        );
+    }
+
+    /// Applies every subscription in a Get All Subscription Statuses
+    /// response, verifying its signed transaction and renewal info. Uses the
+    /// `_online` checks when the verifier has them enabled.
+    pub async fn apply_status_response(
+        &mut self,
+        response: &StatusResponse,
+        verifier: &SignedDataVerifier,
+    ) -> Result<(), AppleError> {
+        let online = verifier.online_checks_enabled();
+        for item in response
+            .data
+            .iter()
+            .flat_map(|group| &group.last_transactions)
+        {
+            let (transaction, renewal_info) = if online {
+                (
+                    verifier
+                        .verify_and_decode_transaction_online(&item.signed_transaction_info)
+                        .await?,
+                    verifier
+                        .verify_and_decode_renewal_info_online(&item.signed_renewal_info)
+                        .await?,
+                )
+            } else {
+                (
+                    verifier.verify_and_decode_transaction(&item.signed_transaction_info)?,
+                    verifier.verify_and_decode_renewal_info(&item.signed_renewal_info)?,
+                )
+            };
+            self.apply(transaction, Some(renewal_info), Some(item.status.clone()));
+        }
+        Ok(())
+    }
+
+    /// Applies a notification. The status comes from the notification data,
+    /// or is inferred from its kind for notifications that carry none.
+    pub fn apply_notification(&mut self, event: &NotificationEvent) {
+        let Some(transaction) = &event.transaction else {
+            return;
+        };
+        let status = event
+            .payload
+            .data
+            .as_ref()
+            .and_then(|data| data.status.clone())
+            .or_else(|| implied_status(&event.kind));
+        self.apply(transaction.clone(), event.renewal_info.clone(), status);
+    }
+
+    /// Forgets a subscription, e.g. when a user's account is deleted.
+    pub fn remove(&mut self, original_transaction_id: &str) {
+        self.records.remove(original_transaction_id);
+    }
+
+    /// The latest transaction applied for a subscription.
+    pub fn transaction(
+        &self,
+        original_transaction_id: &str,
+    ) -> Option<&JWSTransactionDecodedPayload> {
+        self.records
+            .get(original_transaction_id)
+            .map(|record| &record.transaction)
+    }
+
+    pub fn entitlement(&self, original_transaction_id: &str, now: i64) -> Option<Entitlement> {
+        self.records
+            .get(original_transaction_id)
+            .map(|record| record.entitlement(now))
+    }
+
+    /// Every known subscription evaluated at `now`.
+    pub fn entitlements(&self, now: i64) -> Vec<Entitlement> {
+        self.records
+            .values()
+            .map(|record| record.entitlement(now))
+            .collect()
+    }
+
+    /// The best entitlement for `product_id`: one granting access if any,
+    /// preferring the latest expiry.
+    pub fn for_product(&self, product_id: &str, now: i64) -> Option<Entitlement> {
+        best(
+            self.entitlements(now)
+                .into_iter()
+                .filter(|entitlement| entitlement.product_id == product_id),
+        )
+    }
+
+    /// The best entitlement in a subscription group, as for `for_product`.
+    pub fn for_group(&self, subscription_group_identifier: &str, now: i64) -> Option<Entitlement> {
+        best(self.entitlements(now).into_iter().filter(|entitlement| {
+            entitlement.subscription_group_identifier.as_deref()
+                == Some(subscription_group_identifier)
+        }))
+    }
+
+    pub fn has_access(&self, product_id: &str, now: i64) -> bool {
+        self.for_product(product_id, now)
+            .is_some_and(|entitlement| entitlement.has_access)
+    }
+}
+
+/// The status a notification implies when its data carries none.
+fn implied_status(kind: &NotificationKind) -> Option<SubscriptionStatus> {
+    match kind {
+        NotificationKind::Refunded | NotificationKind::Revoked => Some(SubscriptionStatus::Revoked),
+        NotificationKind::ExpiredVoluntarily
+        | NotificationKind::ExpiredAfterBillingRetry
+        | NotificationKind::ExpiredAfterPriceIncrease
+        | NotificationKind::ExpiredProductNotForSale => Some(SubscriptionStatus::Expired),
+        NotificationKind::RenewalFailed | NotificationKind::GracePeriodExpired => {
+            Some(SubscriptionStatus::BillingRetryPeriod)
+        }
+        NotificationKind::RenewalFailedInGracePeriod => {
+            Some(SubscriptionStatus::BillingGracePeriod)
+        }
+        _ => None,
+    }
+}
+
+fn best(entitlements: impl Iterator<Item = Entitlement>) -> Option<Entitlement> {
+    entitlements.max_by_key(|entitlement| {
+        (
+            entitlement.has_access,
+            entitlement.expires_date.is_none() && entitlement.has_access,
+            entitlement.expires_date,
+        )
+    })
+}
@@ -0,0 +1,222 @@
+use std::collections::VecDeque;
+use std::future::Future;
+
+use futures::stream::{self, BoxStream, StreamExt};
+
+use crate::error::AppleError;
+
+use super::client::AppStoreServerClient;
+use super::models::{
+    HistoryResponse, NotificationHistoryRequest, NotificationHistoryResponse,
+    NotificationHistoryResponseItem, RefundHistoryResponse, TransactionHistoryRequest,
+};
+use super::notifications_v2::ResponseBodyV2DecodedPayload;
+use super::signed_data::{JWSTransactionDecodedPayload, SignedDataVerifier};
+
+/// One entry of a paginated history, with the cursors needed to resume.
+#[derive(Debug, Clone)]
+pub struct HistoryItem<R, D> {
+    /// The entry as Apple returned it.
+    pub raw: R,
+    /// The entry's signed data verified and decoded, when the stream was
+    /// given a verifier.
+    pub decoded: Option<D>,
+    /// Resumes at the page holding this entry, replaying the entries before
+    /// it on that page. `None` is the first page.
+    pub cursor: Option<String>,
+    /// Resumes after the page holding this entry. For transaction and refund
+    /// history the last page's cursor later picks up new entries.
+    pub next_cursor: Option<String>,
+}
+
+pub type TransactionHistoryItem = HistoryItem<String, JWSTransactionDecodedPayload>;
+pub type NotificationHistoryItem =
+    HistoryItem<NotificationHistoryResponseItem, ResponseBodyV2DecodedPayload>;
+
+/// A response page: its entries, the cursor for the next page, and whether
+/// there is one.
+trait Page {
+    type Raw;
+
+    fn into_parts(self) -> (Vec<Self::Raw>, Option<String>, bool);
+}
+
+impl Page for HistoryResponse {
+    type Raw = String;
+
+    fn into_parts(self) -> (Vec<String>, Option<String>, bool) {
+        (self.signed_transactions, Some(self.revision), self.has_more)
+    }
+}
+
+impl Page for RefundHistoryResponse {
+    type Raw = String;
+
+    fn into_parts(self) -> (Vec<String>, Option<String>, bool) {
+        (self.signed_transactions, Some(self.revision), self.has_more)
+    }
+}
+
+impl Page for NotificationHistoryResponse {
+    type Raw = NotificationHistoryResponseItem;
+
+    fn into_parts(self) -> (Vec<NotificationHistoryResponseItem>, Option<String>, bool) {
+        (
+            self.notification_history,
+            self.pagination_token,
+            self.has_more,
+        )
+    }
+}
+
+struct Pager<R, F> {
+    fetch: F,
+    cursor: Option<String>,
+    pending: VecDeque<HistoryItem<R, ()>>,
+    done: bool,
+}
+
+/// Follows `fetch`'s cursors from `cursor` until a page reports no more, or
+/// until the first error, which is yielded and ends the stream.
+fn paginate<'a, P, F, Fut>(
+    cursor: Option<String>,
+    fetch: F,
+) -> BoxStream<'a, Result<HistoryItem<P::Raw, ()>, AppleError>>
+where
+    P: Page + Send + 'a,
+    P::Raw: Clone + Send + 'a,
+    F: FnMut(Option<String>) -> Fut + Send + 'a,
+    Fut: Future<Output = Result<P, AppleError>> + Send + 'a,
+{
+    let pager = Pager {
+        fetch,
+        cursor,
+        pending: VecDeque::new(),
+        done: false,
+    };
+    stream::unfold(pager, |mut pager| async move {
+        loop {
+            if let Some(item) = pager.pending.pop_front() {
+                return Some((Ok(item), pager));
+            }
+            if pager.done {
+                return None;
+            }
+            let cursor = pager.cursor.clone();
+            match (pager.fetch)(cursor.clone()).await {
+                Ok(page) => {
+                    let (entries, next_cursor, has_more) = page.into_parts();
+                    pager.done = !has_more;
+                    pager.cursor = next_cursor.clone();
+                    pager
+                        .pending
+                        .extend(entries.into_iter().map(|raw| HistoryItem {
+                            raw,
+                            decoded: None,
+                            cursor: cursor.clone(),
+                            next_cursor: next_cursor.clone(),
+                        }));
+                }
+                Err(err) => {
+                    pager.done = true;
+                    return Some((Err(err), pager));
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+fn with_decoded<R, D>(item: HistoryItem<R, ()>, decoded: Option<D>) -> HistoryItem<R, D> {
+    HistoryItem {
+        raw: item.raw,
+        decoded,
+        cursor: item.cursor,
+        next_cursor: item.next_cursor,
+    }
+}
+
+fn decode_transactions<'a>(
+    items: BoxStream<'a, Result<HistoryItem<String, ()>, AppleError>>,
+    verifier: Option<&'a SignedDataVerifier>,
+) -> BoxStream<'a, Result<TransactionHistoryItem, AppleError>> {
+    items
+        .then(move |item| async move {
+            let item = item?;
+            let decoded = match verifier {
+                Some(verifier) if verifier.online_checks_enabled() => Some(
+                    verifier
+                        .verify_and_decode_transaction_online(&item.raw)
+                        .await?,
+                ),
+                Some(verifier) => Some(verifier.verify_and_decode_transaction(&item.raw)?),
+                None => None,
+            };
+            Ok(with_decoded(item, decoded))
+        })
+        .boxed()
+}
+
+impl AppStoreServerClient {
+    /// Every transaction in a customer's history, fetching pages as the
+    /// stream is polled. Pass a stored `cursor` (a `revision`) to resume,
+    /// and a `verifier` to verify and decode each transaction.
+    pub fn transaction_history_stream<'a>(
+        &'a self,
+        transaction_id: &'a str,
+        request: Option<&'a TransactionHistoryRequest>,
+        cursor: Option<String>,
+        verifier: Option<&'a SignedDataVerifier>,
+    ) -> BoxStream<'a, Result<TransactionHistoryItem, AppleError>> {
+        let items = paginate(cursor, move |revision: Option<String>| async move {
+            self.get_transaction_history(transaction_id, revision.as_deref(), request)
+                .await
+        });
+        decode_transactions(items, verifier)
+    }
+
+    /// Every refunded transaction for a customer, as for
+    /// `transaction_history_stream`.
+    pub fn refund_history_stream<'a>(
+        &'a self,
+        transaction_id: &'a str,
+        cursor: Option<String>,
+        verifier: Option<&'a SignedDataVerifier>,
+    ) -> BoxStream<'a, Result<TransactionHistoryItem, AppleError>> {
+        let items = paginate(cursor, move |revision: Option<String>| async move {
+            self.get_refund_history(transaction_id, revision.as_deref())
+                .await
+        });
+        decode_transactions(items, verifier)
+    }
+
+    /// Every notification matching `request`. The cursor is a
+    /// `paginationToken`; a `verifier` decodes each `signedPayload`.
+    pub fn notification_history_stream<'a>(
+        &'a self,
+        request: &'a NotificationHistoryRequest,
+        cursor: Option<String>,
+        verifier: Option<&'a SignedDataVerifier>,
+    ) -> BoxStream<'a, Result<NotificationHistoryItem, AppleError>> {
+        paginate(cursor, move |token: Option<String>| async move {
+            self.get_notification_history(request, token.as_deref())
+                .await
+        })
+        .then(move |item| async move {
+            let item = item?;
+            let decoded = match verifier {
+                Some(verifier) if verifier.online_checks_enabled() => Some(
+                    verifier
+                        .verify_and_decode_notification_online(&item.raw.signed_payload)
+                        .await?,
+                ),
+                Some(verifier) => {
+                    Some(verifier.verify_and_decode_notification(&item.raw.signed_payload)?)
+                }
+                None => None,
+            };
+            Ok(with_decoded(item, decoded))
+        })
+        .boxed()
+    }
+}
@@ -1,19 +1,34 @@
 pub mod client;
 pub mod consumption;
+#[cfg(feature = "appstore-emulator")]
+pub mod emulator;
+pub mod entitlements;
 pub(crate) mod error;
+pub mod history;
 pub mod messaging;
 pub mod models;
+pub mod notification_handler;
 pub mod notifications;
 pub mod notifications_v1;
 pub mod notifications_v2;
+pub mod ocsp;
+pub mod reconciliation;
 pub mod signed_data;
 pub mod subscriptions;
+#[cfg(feature = "appstore-testing")]
+pub mod testing;
 pub mod transactions;
 pub mod types;
 
 pub use client::{AppStoreConfig, AppStoreServerClient};
+pub use entitlements::{Entitlement, EntitlementEngine, EntitlementReason};
 pub use error::AppStoreErrorCode;
+pub use history::{HistoryItem, NotificationHistoryItem, TransactionHistoryItem};
 pub use models::*;
+pub use notification_handler::{
+    IdempotencyStore, MemoryIdempotencyStore, NotificationEvent, NotificationHandler,
+    NotificationKind,
+};
 pub use notifications_v1::{
     LatestReceiptInfo, PendingRenewalInfo, ServerNotificationV1, UnifiedReceipt,
 };
@@ -21,7 +36,13 @@ pub use notifications_v2::{
     ExternalPurchaseToken, NotificationData, NotificationSummary, ResponseBodyV2,
     ResponseBodyV2DecodedPayload,
 };
+pub use ocsp::OcspChecker;
+pub use reconciliation::{
+    BackfillFailure, BackfillReport, Divergence, MissedNotification, ReconcileOptions, Reconciler,
+    TransactionReport,
+};
 pub use signed_data::{
     AppTransaction, JWSRenewalInfoDecodedPayload, JWSTransactionDecodedPayload, SignedDataVerifier,
+    VerificationError,
 };
 pub use types::*;
@@ -0,0 +1,311 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::Mutex;
+
+use crate::error::AppleError;
+
+use super::notifications_v2::{ResponseBodyV2, ResponseBodyV2DecodedPayload};
+use super::signed_data::{
+    JWSRenewalInfoDecodedPayload, JWSTransactionDecodedPayload, SignedDataVerifier,
+};
+use super::types::{NotificationTypeV2, Subtype};
+
+/// A notification's type and subtype as one value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotificationKind {
+    InitialBuy,
+    Resubscribed,
+    Renewed,
+    BillingRecovered,
+    Upgraded,
+    Downgraded,
+    /// `DID_CHANGE_RENEWAL_PREF` without a subtype: a pending downgrade was
+    /// canceled.
+    DowngradeCanceled,
+    AutoRenewEnabled,
+    AutoRenewDisabled,
+    RenewalFailed,
+    RenewalFailedInGracePeriod,
+    ExpiredVoluntarily,
+    ExpiredAfterBillingRetry,
+    ExpiredAfterPriceIncrease,
+    ExpiredProductNotForSale,
+    GracePeriodExpired,
+    OfferRedeemed(Option<Subtype>),
+    PriceIncreasePending,
+    PriceIncreaseAccepted,
+    Refunded,
+    RefundDeclined,
+    RefundReversed,
+    RenewalExtended,
+    RenewalExtensionSucceeded,
+    RenewalExtensionFailed,
+    Revoked,
+    ConsumptionRequest,
+    ExternalPurchaseTokenUnreported,
+    OneTimeCharge,
+    Test,
+    /// A combination this library does not name.
+    Other(NotificationTypeV2, Option<Subtype>),
+}
+
+impl NotificationKind {
+    pub fn new(notification_type: &NotificationTypeV2, subtype: Option<&Subtype>) -> Self {
+        use NotificationTypeV2 as T;
+        match (notification_type, subtype) {
+            (T::SUBSCRIBED, Some(Subtype::INITIAL_BUY)) => NotificationKind::InitialBuy,
+            (T::SUBSCRIBED, Some(Subtype::RESUBSCRIBE)) => NotificationKind::Resubscribed,
+            (T::DID_RENEW, None) => NotificationKind::Renewed,
+            (T::DID_RENEW, Some(Subtype::BILLING_RECOVERY)) => NotificationKind::BillingRecovered,
+            (T::DID_CHANGE_RENEWAL_PREF, Some(Subtype::UPGRADE)) => NotificationKind::Upgraded,
+            (T::DID_CHANGE_RENEWAL_PREF, Some(Subtype::DOWNGRADE)) => NotificationKind::Downgraded,
+            (T::DID_CHANGE_RENEWAL_PREF, None) => NotificationKind::DowngradeCanceled,
+            (T::DID_CHANGE_RENEWAL_STATUS, Some(Subtype::AUTO_RENEW_ENABLED)) => {
+                NotificationKind::AutoRenewEnabled
+            }
+            (T::DID_CHANGE_RENEWAL_STATUS, Some(Subtype::AUTO_RENEW_DISABLED)) => {
+                NotificationKind::AutoRenewDisabled
+            }
+            (T::DID_FAIL_TO_RENEW, None) => NotificationKind::RenewalFailed,
+            (T::DID_FAIL_TO_RENEW, Some(Subtype::GRACE_PERIOD)) => {
+                NotificationKind::RenewalFailedInGracePeriod
+            }
+            (T::EXPIRED, Some(Subtype::VOLUNTARY)) => NotificationKind::ExpiredVoluntarily,
+            (T::EXPIRED, Some(Subtype::BILLING_RETRY)) => {
+                NotificationKind::ExpiredAfterBillingRetry
+            }
+            (T::EXPIRED, Some(Subtype::PRICE_INCREASE)) => {
+                NotificationKind::ExpiredAfterPriceIncrease
+            }
+            (T::EXPIRED, Some(Subtype::PRODUCT_NOT_FOR_SALE)) => {
+                NotificationKind::ExpiredProductNotForSale
+            }
+            (T::GRACE_PERIOD_EXPIRED, None) => NotificationKind::GracePeriodExpired,
+            (T::OFFER_REDEEMED, subtype) => NotificationKind::OfferRedeemed(subtype.cloned()),
+            (T::PRICE_INCREASE, Some(Subtype::PENDING)) => NotificationKind::PriceIncreasePending,
+            (T::PRICE_INCREASE, Some(Subtype::ACCEPTED)) => NotificationKind::PriceIncreaseAccepted,
+            (T::REFUND, None) => NotificationKind::Refunded,
+            (T::REFUND_DECLINED, None) => NotificationKind::RefundDeclined,
+            (T::REFUND_REVERSED, None) => NotificationKind::RefundReversed,
+            (T::RENEWAL_EXTENDED, None) => NotificationKind::RenewalExtended,
+            (T::RENEWAL_EXTENSION, Some(Subtype::SUMMARY)) => {
+                NotificationKind::RenewalExtensionSucceeded
+            }
+            (T::RENEWAL_EXTENSION, Some(Subtype::FAILURE)) => {
+                NotificationKind::RenewalExtensionFailed
+            }
+            (T::REVOKE, None) => NotificationKind::Revoked,
+            (T::CONSUMPTION_REQUEST, None) => NotificationKind::ConsumptionRequest,
+            (T::EXTERNAL_PURCHASE_TOKEN, Some(Subtype::UNREPORTED)) => {
+                NotificationKind::ExternalPurchaseTokenUnreported
+            }
+            (T::ONE_TIME_CHARGE, None) => NotificationKind::OneTimeCharge,
+            (T::TEST, None) => NotificationKind::Test,
+            (notification_type, subtype) => {
+                NotificationKind::Other(notification_type.clone(), subtype.cloned())
+            }
+        }
+    }
+}
+
+/// A verified V2 notification with its signed transaction and renewal info
+/// decoded.
+#[derive(Debug, Clone)]
+pub struct NotificationEvent {
+    pub kind: NotificationKind,
+    pub notification_uuid: String,
+    pub signed_date: i64,
+    pub transaction: Option<JWSTransactionDecodedPayload>,
+    pub renewal_info: Option<JWSRenewalInfoDecodedPayload>,
+    pub payload: ResponseBodyV2DecodedPayload,
+}
+
+/// Remembers which notifications have been processed.
+///
+/// `claim` must be atomic: of several concurrent claims for the same UUID,
+/// only one may return `true`.
+pub trait IdempotencyStore: Send + Sync {
+    /// Claims `notification_uuid` for processing. Returns `false` if it was
+    /// already claimed.
+    fn claim(
+        &self,
+        notification_uuid: &str,
+    ) -> impl Future<Output = Result<bool, AppleError>> + Send;
+
+    /// Gives up a claim so that Apple's next delivery is processed again.
+    fn release(
+        &self,
+        notification_uuid: &str,
+    ) -> impl Future<Output = Result<(), AppleError>> + Send;
+}
+
+/// An in-process `IdempotencyStore`. Claims are lost on restart.
+#[derive(Default)]
+pub struct MemoryIdempotencyStore {
+    claimed: Mutex<HashSet<String>>,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, notification_uuid: &str) -> bool {
+        self.claimed.lock().unwrap().contains(notification_uuid)
+    }
+}
+
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn claim(&self, notification_uuid: &str) -> Result<bool, AppleError> {
+        Ok(self
+            .claimed
+            .lock()
+            .unwrap()
+            .insert(notification_uuid.to_string()))
+    }
+
+    async fn release(&self, notification_uuid: &str) -> Result<(), AppleError> {
+        self.claimed.lock().unwrap().remove(notification_uuid);
+        Ok(())
+    }
+}
+
+/// Turns App Store Server Notification V2 webhook bodies into verified
+/// `NotificationEvent`s, processing each `notificationUUID` once.
+///
+/// ```ignore
+/// let handler = NotificationHandler::new(verifier, MemoryIdempotencyStore::new());
+/// handler
+///     .process(&body, |event| async move {
+///         println!("{:?} for {:?}", event.kind, event.transaction);
+///         Ok(())
+///     })
+///     .await?;
+/// ```
+pub struct NotificationHandler<S: IdempotencyStore = MemoryIdempotencyStore> {
+    verifier: SignedDataVerifier,
+    store: S,
+}
+
+impl<S: IdempotencyStore> NotificationHandler<S> {
+    pub fn new(verifier: SignedDataVerifier, store: S) -> Self {
+        NotificationHandler { verifier, store }
+    }
+
+    pub fn verifier(&self) -> &SignedDataVerifier {
+        &self.verifier
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Verifies and decodes a webhook body without consulting the store.
+    /// Uses OCSP when the verifier has online checks enabled.
+    pub async fn decode(&self, body: &[u8]) -> Result<NotificationEvent, AppleError> {
+        self.decode_signed_payload(&signed_payload(body)?).await
+    }
+
+    /// Like `decode`, for a `signedPayload` taken from the body already, such
+    /// as one from notification history.
+    pub async fn decode_signed_payload(
+        &self,
+        signed_payload: &str,
+    ) -> Result<NotificationEvent, AppleError> {
+        let online = self.verifier.online_checks_enabled();
+
+        let payload = if online {
+            self.verifier
+                .verify_and_decode_notification_online(signed_payload)
+                .await?
+        } else {
+            self.verifier
+                .verify_and_decode_notification(signed_payload)?
+        };
+
+        let data = payload.data.as_ref();
+        let transaction = match data.and_then(|d| d.signed_transaction_info.as_deref()) {
+            Some(jws) if online => Some(
+                self.verifier
+                    .verify_and_decode_transaction_online(jws)
+                    .await?,
+            ),
+            Some(jws) => Some(self.verifier.verify_and_decode_transaction(jws)?),
+            None => None,
+        };
+        let renewal_info = match data.and_then(|d| d.signed_renewal_info.as_deref()) {
+            Some(jws) if online => Some(
+                self.verifier
+                    .verify_and_decode_renewal_info_online(jws)
+                    .await?,
+            ),
+            Some(jws) => Some(self.verifier.verify_and_decode_renewal_info(jws)?),
+            None => None,
+        };
+
+        Ok(NotificationEvent {
+            kind: NotificationKind::new(&payload.notification_type, payload.subtype.as_ref()),
+            notification_uuid: payload.notification_uuid.clone(),
+            signed_date: payload.signed_date,
+            transaction,
+            renewal_info,
+            payload,
+        })
+    }
+
+    /// Verifies a webhook body and claims its notification. Returns `None`
+    /// for a notification that was already claimed.
+    pub async fn handle(&self, body: &[u8]) -> Result<Option<NotificationEvent>, AppleError> {
+        self.handle_signed_payload(&signed_payload(body)?).await
+    }
+
+    pub async fn handle_signed_payload(
+        &self,
+        signed_payload: &str,
+    ) -> Result<Option<NotificationEvent>, AppleError> {
+        let event = self.decode_signed_payload(signed_payload).await?;
+        if self.store.claim(&event.notification_uuid).await? {
+            Ok(Some(event))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Like `handle`, then runs `f` on a new event. If `f` fails the claim is
+    /// released, so Apple's retry of the same notification runs `f` again.
+    pub async fn process<F, Fut, T>(&self, body: &[u8], f: F) -> Result<Option<T>, AppleError>
+    where
+        F: FnOnce(NotificationEvent) -> Fut,
+        Fut: Future<Output = Result<T, AppleError>>,
+    {
+        self.process_signed_payload(&signed_payload(body)?, f).await
+    }
+
+    pub async fn process_signed_payload<F, Fut, T>(
+        &self,
+        signed_payload: &str,
+        f: F,
+    ) -> Result<Option<T>, AppleError>
+    where
+        F: FnOnce(NotificationEvent) -> Fut,
+        Fut: Future<Output = Result<T, AppleError>>,
+    {
+        let Some(event) = self.handle_signed_payload(signed_payload).await? else {
+            return Ok(None);
+        };
+        let notification_uuid = event.notification_uuid.clone();
+        match f(event).await {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => {
+                self.store.release(&notification_uuid).await?;
+                Err(err)
+            }
+        }
+    }
+}
+
+fn signed_payload(body: &[u8]) -> Result<String, AppleError> {
+    let body: ResponseBodyV2 =
+        serde_json::from_slice(body).map_err(|e| AppleError::JsonError(e.to_string()))?;
+    Ok(body.signed_payload)
+}
@@ -58,6 +58,10 @@ pub struct NotificationData {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotificationSummary {
     pub environment: AppStoreEnvironment,
+    #[serde(rename = "appAppleId", skip_serializing_if = "Option::is_none")]
+    pub app_apple_id: Option<i64>,
+    #[serde(rename = "bundleId")]
+    pub bundle_id: String,
     #[serde(rename = "requestIdentifier")]
     pub request_identifier: String,
     #[serde(rename = "productId")]
@@ -0,0 +1,307 @@
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use x509_cert::Certificate;
+use x509_cert::der::asn1::{ObjectIdentifier, OctetString};
+use x509_cert::der::oid::AssociatedOid;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::pkix::{AuthorityInfoAccessSyntax, ExtendedKeyUsage, SubjectKeyIdentifier};
+use x509_cert::spki::AlgorithmIdentifierOwned;
+use x509_ocsp::{
+    BasicOcspResponse, CertId, CertStatus, OcspRequest, OcspResponse, OcspResponseStatus, Request,
+    ResponderId, TbsRequest, Version,
+};
+
+use crate::error::AppleError;
+
+use super::signed_data::{check_validity, verify_issued_by, verify_signature};
+
+const SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+const ID_AD_OCSP: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.48.1");
+const ID_PKIX_OCSP_BASIC: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.48.1.1");
+const ID_KP_OCSP_SIGNING: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.9");
+
+/// How far a responder's `thisUpdate` may be ahead of the local clock.
+const CLOCK_SKEW_MS: i64 = 5 * 60 * 1000;
+
+/// Checks certificates against their OCSP responders, caching good answers
+/// until the response's `nextUpdate`.
+///
+/// Requests go to the responder named in each certificate's Authority
+/// Information Access extension unless `with_responder_url` overrides it.
+pub struct OcspChecker {
+    http_client: Client,
+    responder_url: Option<String>,
+    cache: Mutex<HashMap<Vec<u8>, i64>>,
+}
+
+impl OcspChecker {
+    pub fn new() -> Result<Self, AppleError> {
+        let http_client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| AppleError::HttpError(e.to_string()))?;
+        Ok(OcspChecker {
+            http_client,
+            responder_url: None,
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Sends every request to `url` instead of the certificate's responder.
+    pub fn with_responder_url(mut self, url: &str) -> Self {
+        self.responder_url = Some(url.to_string());
+        self
+    }
+
+    /// Drops all cached responses.
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Fails unless `issuer`'s responder reports `cert` as good in a
+    /// response signed by `issuer` or a responder it delegated to.
+    pub async fn check(&self, cert: &Certificate, issuer: &Certificate) -> Result<(), AppleError> {
+        let cert_id = cert_id(cert, issuer)?;
+        let cache_key = cert_id
+            .to_der()
+            .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+        let now = now_millis()?;
+        if self
+            .cache
+            .lock()
+            .unwrap()
+            .get(&cache_key)
+            .is_some_and(|good_until| *good_until > now)
+        {
+            return Ok(());
+        }
+
+        let url = match &self.responder_url {
+            Some(url) => url.clone(),
+            None => responder_url(cert)?,
+        };
+        let request = OcspRequest {
+            tbs_request: TbsRequest {
+                version: Version::V1,
+                requestor_name: None,
+                request_list: vec![Request {
+                    req_cert: cert_id.clone(),
+                    single_request_extensions: None,
+                }],
+                request_extensions: None,
+            },
+            optional_signature: None,
+        }
+        .to_der()
+        .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+
+        let res = self
+            .http_client
+            .post(&url)
+            .header("Content-Type", "application/ocsp-request")
+            .body(request)
+            .send()
+            .await
+            .map_err(|e| AppleError::HttpError(e.to_string()))?;
+        if !res.status().is_success() {
+            return Err(AppleError::HttpError(format!(
+                "OCSP responder returned status: {}",
+                res.status()
+            )));
+        }
+        let body = res
+            .bytes()
+            .await
+            .map_err(|e| AppleError::HttpError(e.to_string()))?;
+
+        let good_until = verify_response(&body, &cert_id, issuer, now)?;
+        if let Some(good_until) = good_until {
+            self.cache.lock().unwrap().insert(cache_key, good_until);
+        }
+        Ok(())
+    }
+}
+
+fn now_millis() -> Result<i64, AppleError> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| AppleError::TimeError(e.to_string()))?
+        .as_millis() as i64)
+}
+
+/// The RFC 6960 CertID for `cert`, hashed with SHA-256.
+fn cert_id(cert: &Certificate, issuer: &Certificate) -> Result<CertId, AppleError> {
+    let issuer_name = issuer
+        .tbs_certificate
+        .subject
+        .to_der()
+        .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+    let issuer_key = issuer
+        .tbs_certificate
+        .subject_public_key_info
+        .subject_public_key
+        .raw_bytes();
+    let hash = |data: &[u8]| {
+        OctetString::new(Sha256::digest(data).to_vec())
+            .map_err(|e| AppleError::CertificateError(e.to_string()))
+    };
+    Ok(CertId {
+        hash_algorithm: AlgorithmIdentifierOwned {
+            oid: SHA256,
+            parameters: None,
+        },
+        issuer_name_hash: hash(&issuer_name)?,
+        issuer_key_hash: hash(issuer_key)?,
+        serial_number: cert.tbs_certificate.serial_number.clone(),
+    })
+}
+
+fn responder_url(cert: &Certificate) -> Result<String, AppleError> {
+    let access = cert
+        .tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .find(|ext| ext.extn_id == AuthorityInfoAccessSyntax::OID)
+        .map(|ext| AuthorityInfoAccessSyntax::from_der(ext.extn_value.as_bytes()))
+        .transpose()
+        .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+    access
+        .into_iter()
+        .flat_map(|access| access.0)
+        .find_map(|description| match description.access_location {
+            GeneralName::UniformResourceIdentifier(uri)
+                if description.access_method == ID_AD_OCSP =>
+            {
+                Some(uri.to_string())
+            }
+            _ => None,
+        })
+        .ok_or_else(|| {
+            AppleError::CertificateError(format!(
+                "Certificate {} names no OCSP responder",
+                cert.tbs_certificate.subject
+            ))
+        })
+}
+
+/// Verifies an OCSP response for `cert_id` and returns when it stops being
+/// fresh, or `None` if the responder gave no `nextUpdate`.
+fn verify_response(
+    body: &[u8],
+    cert_id: &CertId,
+    issuer: &Certificate,
+    now: i64,
+) -> Result<Option<i64>, AppleError> {
+    let response =
+        OcspResponse::from_der(body).map_err(|e| AppleError::CertificateError(e.to_string()))?;
+    if response.response_status != OcspResponseStatus::Successful {
+        return Err(AppleError::CertificateError(format!(
+            "OCSP responder returned {:?}",
+            response.response_status
+        )));
+    }
+    let bytes = response
+        .response_bytes
+        .filter(|bytes| bytes.response_type == ID_PKIX_OCSP_BASIC)
+        .ok_or_else(|| {
+            AppleError::CertificateError("OCSP response is not a basic response".to_string())
+        })?;
+    let basic = BasicOcspResponse::from_der(bytes.response.as_bytes())
+        .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+    verify_responder_signature(&basic, issuer, now)?;
+
+    let single = basic
+        .tbs_response_data
+        .responses
+        .iter()
+        .find(|single| single.cert_id == *cert_id)
+        .ok_or_else(|| {
+            AppleError::CertificateError("OCSP response does not cover the certificate".to_string())
+        })?;
+    let this_update = single.this_update.0.to_unix_duration().as_millis() as i64;
+    let next_update = single
+        .next_update
+        .map(|time| time.0.to_unix_duration().as_millis() as i64);
+    if this_update > now + CLOCK_SKEW_MS || next_update.is_some_and(|next| next < now) {
+        return Err(AppleError::CertificateError(
+            "OCSP response is not current".to_string(),
+        ));
+    }
+    match &single.cert_status {
+        CertStatus::Good(_) => Ok(next_update),
+        CertStatus::Revoked(info) => Err(AppleError::CertificateError(format!(
+            "Certificate was revoked at {}",
+            info.revocation_time.0.to_unix_duration().as_millis()
+        ))),
+        CertStatus::Unknown(_) => Err(AppleError::CertificateError(
+            "OCSP responder does not know the certificate".to_string(),
+        )),
+    }
+}
+
+/// Accepts responses signed by the issuer itself or by a certificate the
+/// issuer signed for OCSP signing that the response names as its responder.
+fn verify_responder_signature(
+    basic: &BasicOcspResponse,
+    issuer: &Certificate,
+    now: i64,
+) -> Result<(), AppleError> {
+    let data = basic
+        .tbs_response_data
+        .to_der()
+        .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+    let signature = basic.signature.as_bytes().ok_or_else(|| {
+        AppleError::CertificateError("OCSP signature is not byte aligned".to_string())
+    })?;
+    let algorithm = basic.signature_algorithm.oid;
+
+    let responder_id = &basic.tbs_response_data.responder_id;
+    let delegated = basic.certs.iter().flatten().filter(|cert| {
+        is_ocsp_signer(cert)
+            && is_responder(cert, responder_id)
+            && check_validity("OCSP responder", cert, now).is_ok()
+            && verify_issued_by(cert, issuer).is_ok()
+    });
+    for signer in std::iter::once(issuer).chain(delegated) {
+        let key_info = &signer.tbs_certificate.subject_public_key_info;
+        // A key the algorithm does not fit is as good as a wrong signature;
+        // a delegated signer may still verify.
+        if verify_signature(&data, algorithm, signature, key_info).unwrap_or(false) {
+            return Ok(());
+        }
+    }
+    Err(AppleError::CertificateError(
+        "OCSP response signature does not verify".to_string(),
+    ))
+}
+
+/// Matches `ByKey` against the certificate's Subject Key Identifier, which
+/// CAs derive from the same SHA-1 key hash.
+fn is_responder(cert: &Certificate, responder_id: &ResponderId) -> bool {
+    match responder_id {
+        ResponderId::ByName(name) => cert.tbs_certificate.subject == *name,
+        ResponderId::ByKey(hash) => cert
+            .tbs_certificate
+            .extensions
+            .iter()
+            .flatten()
+            .filter(|ext| ext.extn_id == SubjectKeyIdentifier::OID)
+            .filter_map(|ext| SubjectKeyIdentifier::from_der(ext.extn_value.as_bytes()).ok())
+            .any(|id| id.0 == *hash),
+    }
+}
+
+fn is_ocsp_signer(cert: &Certificate) -> bool {
+    cert.tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .filter(|ext| ext.extn_id == ExtendedKeyUsage::OID)
+        .filter_map(|ext| ExtendedKeyUsage::from_der(ext.extn_value.as_bytes()).ok())
+        .any(|usage| usage.0.contains(&ID_KP_OCSP_SIGNING))
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::StreamExt;
+
+use crate::error::AppleError;
+
+use super::client::AppStoreServerClient;
+use super::entitlements::EntitlementEngine;
+use super::models::{NotificationHistoryRequest, SendAttemptItem};
+use super::notification_handler::{
+    IdempotencyStore, NotificationEvent, NotificationHandler, NotificationKind,
+};
+use super::signed_data::JWSTransactionDecodedPayload;
+
+/// Scheduling for `Reconciler::run`.
+#[derive(Debug, Clone)]
+pub struct ReconcileOptions {
+    /// Time between backfills. Defaults to one hour.
+    pub interval: Duration,
+    /// How far back the first backfill reaches. Defaults to one day.
+    pub lookback: Duration,
+    /// How far each window reaches back into the previous one, for
+    /// notifications Apple records late. Defaults to ten minutes.
+    pub overlap: Duration,
+    /// Asks Apple only for notifications that never reached the webhook.
+    pub only_failures: bool,
+}
+
+impl Default for ReconcileOptions {
+    fn default() -> Self {
+        ReconcileOptions {
+            interval: Duration::from_secs(60 * 60),
+            lookback: Duration::from_secs(24 * 60 * 60),
+            overlap: Duration::from_secs(10 * 60),
+            only_failures: false,
+        }
+    }
+}
+
+impl ReconcileOptions {
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_lookback(mut self, lookback: Duration) -> Self {
+        self.lookback = lookback;
+        self
+    }
+
+    pub fn with_overlap(mut self, overlap: Duration) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    pub fn with_only_failures(mut self, only_failures: bool) -> Self {
+        self.only_failures = only_failures;
+        self
+    }
+}
+
+/// A notification the backfill processed that live delivery had missed.
+#[derive(Debug, Clone)]
+pub struct MissedNotification {
+    pub notification_uuid: String,
+    pub kind: NotificationKind,
+    pub signed_date: i64,
+    /// Apple's attempts to deliver it to the webhook.
+    pub send_attempts: Vec<SendAttemptItem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BackfillFailure {
+    /// `None` when the notification could not be verified.
+    pub notification_uuid: Option<String>,
+    pub error: AppleError,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackfillReport {
+    pub start_date: i64,
+    pub end_date: i64,
+    /// Notifications Apple listed for the window.
+    pub fetched: usize,
+    /// Notifications that had already been processed.
+    pub duplicates: usize,
+    pub missed: Vec<MissedNotification>,
+    pub failures: Vec<BackfillFailure>,
+    /// Whether fetching history failed part way through the window.
+    pub interrupted: bool,
+    /// When `interrupted`, passing this back to `backfill` resumes the
+    /// window at the page that failed.
+    pub cursor: Option<String>,
+}
+
+impl BackfillReport {
+    pub fn is_complete(&self) -> bool {
+        !self.interrupted && self.failures.is_empty()
+    }
+}
+
+/// A difference between stored subscription state and Apple's history.
+#[derive(Debug, Clone)]
+pub enum Divergence {
+    /// Apple has a purchase or subscription with no stored state.
+    Missing {
+        apple: Box<JWSTransactionDecodedPayload>,
+    },
+    /// The stored latest transaction differs from Apple's in its ID, expiry
+    /// or revocation.
+    Outdated {
+        ours: Box<JWSTransactionDecodedPayload>,
+        apple: Box<JWSTransactionDecodedPayload>,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TransactionReport {
+    /// Original transactions found in Apple's history.
+    pub checked: usize,
+    pub divergences: Vec<Divergence>,
+}
+
+/// Recovers notifications that never reached the webhook and compares
+/// stored state with Apple's transaction history.
+///
+/// Backfilled notifications go through the same `NotificationHandler` as
+/// live webhooks, so ones that were delivered are skipped as duplicates.
+pub struct Reconciler<'a, S: IdempotencyStore> {
+    client: &'a AppStoreServerClient,
+    handler: &'a NotificationHandler<S>,
+}
+
+impl<'a, S: IdempotencyStore> Reconciler<'a, S> {
+    pub fn new(client: &'a AppStoreServerClient, handler: &'a NotificationHandler<S>) -> Self {
+        Reconciler { client, handler }
+    }
+
+    /// Replays every notification matching `request` into `f`, skipping
+    /// ones the handler has processed. Failures are reported rather than
+    /// ending the backfill, and their claims are released for a later run.
+    pub async fn backfill<F, Fut>(
+        &self,
+        request: &NotificationHistoryRequest,
+        cursor: Option<String>,
+        mut f: F,
+    ) -> BackfillReport
+    where
+        F: FnMut(NotificationEvent) -> Fut,
+        Fut: Future<Output = Result<(), AppleError>>,
+    {
+        let mut report = BackfillReport {
+            start_date: request.start_date,
+            end_date: request.end_date,
+            ..Default::default()
+        };
+        let mut history = self
+            .client
+            .notification_history_stream(request, cursor.clone(), None);
+        let mut page_cursor = cursor;
+
+        while let Some(item) = history.next().await {
+            let item = match item {
+                Ok(item) => item,
+                Err(error) => {
+                    report.interrupted = true;
+                    report.cursor = page_cursor;
+                    report.failures.push(BackfillFailure {
+                        notification_uuid: None,
+                        error,
+                    });
+                    break;
+                }
+            };
+            page_cursor = item.next_cursor.clone();
+            report.fetched += 1;
+
+            let mut missed = None;
+            let result = self
+                .handler
+                .process_signed_payload(&item.raw.signed_payload, |event| {
+                    missed = Some(MissedNotification {
+                        notification_uuid: event.notification_uuid.clone(),
+                        kind: event.kind.clone(),
+                        signed_date: event.signed_date,
+                        send_attempts: item.raw.send_attempts.clone(),
+                    });
+                    f(event)
+                })
+                .await;
+            match (result, missed) {
+                (Ok(Some(())), Some(missed)) => report.missed.push(missed),
+                (Ok(_), _) => report.duplicates += 1,
+                (Err(error), missed) => report.failures.push(BackfillFailure {
+                    notification_uuid: missed.map(|missed| missed.notification_uuid),
+                    error,
+                }),
+            }
+        }
+        report
+    }
+
+    /// Backfills in a loop, one window per `options.interval`, passing each
+    /// report to `on_report`. A window that did not complete is retried
+    /// whole on the next pass. Runs until the future is dropped.
+    pub async fn run<F, Fut, R>(&self, options: ReconcileOptions, mut f: F, mut on_report: R)
+    where
+        F: FnMut(NotificationEvent) -> Fut,
+        Fut: Future<Output = Result<(), AppleError>>,
+        R: FnMut(&BackfillReport),
+    {
+        let mut start = now_millis() - options.lookback.as_millis() as i64;
+        loop {
+            let end = now_millis();
+            let request = NotificationHistoryRequest {
+                start_date: start - options.overlap.as_millis() as i64,
+                end_date: end,
+                notification_type: None,
+                notification_subtype: None,
+                only_failures: options.only_failures.then_some(true),
+                transaction_id: None,
+            };
+            let report = self.backfill(&request, None, &mut f).await;
+            on_report(&report);
+            if report.is_complete() {
+                start = end;
+            }
+            tokio::time::sleep(options.interval).await;
+        }
+    }
+
+    /// Compares the latest transaction of each purchase in a customer's
+    /// history, verified by the handler's verifier, with `engine`.
+    pub async fn reconcile_transactions(
+        &self,
+        transaction_id: &str,
+        engine: &EntitlementEngine,
+    ) -> Result<TransactionReport, AppleError> {
+        let mut history = self.client.transaction_history_stream(
+            transaction_id,
+            None,
+            None,
+            Some(self.handler.verifier()),
+        );
+        let mut latest: HashMap<String, JWSTransactionDecodedPayload> = HashMap::new();
+        while let Some(item) = history.next().await {
+            let Some(transaction) = item?.decoded else {
+                continue;
+            };
+            let newer = latest
+                .get(&transaction.original_transaction_id)
+                .is_none_or(|current| {
+                    (transaction.purchase_date, transaction.signed_date)
+                        > (current.purchase_date, current.signed_date)
+                });
+            if newer {
+                latest.insert(transaction.original_transaction_id.clone(), transaction);
+            }
+        }
+
+        let mut report = TransactionReport {
+            checked: latest.len(),
+            divergences: Vec::new(),
+        };
+        let mut latest: Vec<_> = latest.into_values().collect();
+        latest.sort_by(|a, b| a.original_transaction_id.cmp(&b.original_transaction_id));
+        for apple in latest {
+            match engine.transaction(&apple.original_transaction_id) {
+                None => report.divergences.push(Divergence::Missing {
+                    apple: Box::new(apple),
+                }),
+                Some(ours)
+                    if ours.transaction_id != apple.transaction_id
+                        || ours.expires_date != apple.expires_date
+                        || ours.revocation_date != apple.revocation_date =>
+                {
+                    report.divergences.push(Divergence::Outdated {
+                        ours: Box::new(ours.clone()),
+                        apple: Box::new(apple),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
@@ -1,20 +1,89 @@
 use base64::Engine;
 use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
-use p256::ecdsa::{Signature, VerifyingKey, signature::Verifier};
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
 use x509_cert::Certificate;
-use x509_cert::der::Decode;
+use x509_cert::der::asn1::ObjectIdentifier;
+use x509_cert::der::oid::AssociatedOid;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::ext::pkix::BasicConstraints;
+use x509_cert::spki::SubjectPublicKeyInfoOwned;
 
 use crate::error::AppleError;
 
+use super::ocsp::OcspChecker;
+
 use super::types::*;
 
+/// Marker extension on Apple's App Store receipt signing (leaf) certificates.
+pub const LEAF_MARKER_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.2.840.113635.100.6.11.1");
+/// Marker extension on the Apple Worldwide Developer Relations intermediate.
+pub const INTERMEDIATE_MARKER_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.2.840.113635.100.6.2.1");
+
+const ECDSA_WITH_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+const ECDSA_WITH_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+const SECP256R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+const SECP384R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.34");
+
+/// Apple signs with a leaf, an intermediate and the root, in that order.
+const CHAIN_LENGTH: usize = 3;
+
+/// A correctly signed payload that was issued for another app or
+/// environment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationError {
+    BundleIdMismatch {
+        expected: String,
+        found: Option<String>,
+    },
+    EnvironmentMismatch {
+        expected: AppStoreEnvironment,
+        found: AppStoreEnvironment,
+    },
+    AppAppleIdMismatch {
+        expected: Option<i64>,
+        found: Option<i64>,
+    },
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_none = |value: Option<String>| value.unwrap_or_else(|| "none".to_string());
+        match self {
+            VerificationError::BundleIdMismatch { expected, found } => write!(
+                f,
+                "Bundle ID mismatch: expected {}, got {}",
+                expected,
+                or_none(found.clone())
+            ),
+            VerificationError::EnvironmentMismatch { expected, found } => write!(
+                f,
+                "Environment mismatch: expected {}, got {}",
+                expected, found
+            ),
+            VerificationError::AppAppleIdMismatch { expected, found } => write!(
+                f,
+                "App Apple ID mismatch: expected {}, got {}",
+                or_none(expected.map(|id| id.to_string())),
+                or_none(found.map(|id| id.to_string()))
+            ),
+        }
+    }
+}
+
 pub struct SignedDataVerifier {
     root_certificates: Vec<Vec<u8>>,
     bundle_id: String,
     environment: AppStoreEnvironment,
-    #[allow(dead_code)]
     app_apple_id: Option<i64>,
+    validate_at_current_time: bool,
+    ocsp: Option<OcspChecker>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -177,11 +246,22 @@ pub struct AppTransaction {
 struct JWSHeader {
     #[serde(default)]
     x5c: Vec<String>,
-    #[allow(dead_code)]
     alg: String,
 }
 
+/// The `signedDate` every App Store JWS payload carries, read before the
+/// payload is trusted so the chain can be checked at signing time.
+#[derive(Debug, Deserialize)]
+struct SignedDate {
+    #[serde(rename = "signedDate")]
+    signed_date: Option<i64>,
+}
+
 impl SignedDataVerifier {
+    /// `app_apple_id` must be set for `Production`: production notifications
+    /// and app transactions always name the app's Apple ID, so a Production
+    /// verifier built with `None` rejects every one of them with
+    /// `AppAppleIdMismatch`. Sandbox and Xcode verifiers ignore it.
     pub fn new(
         root_certificates: Vec<Vec<u8>>,
         bundle_id: &str,
@@ -193,26 +273,35 @@ impl SignedDataVerifier {
             bundle_id: bundle_id.to_string(),
             environment,
             app_apple_id,
+            validate_at_current_time: false,
+            ocsp: None,
         }
     }
 
+    /// Checks certificate validity periods against the current time instead
+    /// of the payload's `signedDate`. Without this, data signed while the
+    /// chain was valid keeps verifying after the leaf expires.
+    pub fn with_current_time_validation(mut self) -> Self {
+        self.validate_at_current_time = true;
+        self
+    }
+
+    /// Enables the `*_online` methods, which also check the leaf and
+    /// intermediate certificates with OCSP. Like Apple's libraries, this
+    /// evaluates certificate validity at the current time.
+    pub fn with_online_checks(mut self, checker: OcspChecker) -> Self {
+        self.ocsp = Some(checker);
+        self.validate_at_current_time = true;
+        self
+    }
+
     pub fn verify_and_decode_transaction(
         &self,
         signed_jws: &str,
     ) -> Result<JWSTransactionDecodedPayload, AppleError> {
         let payload: JWSTransactionDecodedPayload = self.decode_jws(signed_jws)?;
-        if payload.bundle_id != self.bundle_id {
-            return Err(AppleError::CertificateError(format!(
-                "Bundle ID mismatch: expected {}, got {}",
-                self.bundle_id, payload.bundle_id
-            )));
-        }
-        if payload.environment != self.environment {
-            return Err(AppleError::CertificateError(format!(
-                "Environment mismatch: expected {:?}, got {:?}",
-                self.environment, payload.environment
-            )));
-        }
+        self.check_bundle_id(Some(&payload.bundle_id))?;
+        self.check_environment(&payload.environment)?;
         Ok(payload)
     }
 
@@ -221,20 +310,50 @@ impl SignedDataVerifier {
         signed_jws: &str,
     ) -> Result<JWSRenewalInfoDecodedPayload, AppleError> {
         let payload: JWSRenewalInfoDecodedPayload = self.decode_jws(signed_jws)?;
-        if payload.environment != self.environment {
-            return Err(AppleError::CertificateError(format!(
-                "Environment mismatch: expected {:?}, got {:?}",
-                self.environment, payload.environment
-            )));
-        }
+        self.check_environment(&payload.environment)?;
         Ok(payload)
     }
 
+    /// Verifies a notification and checks that its `data`, `summary` or
+    /// `externalPurchaseToken` belongs to this app and environment.
     pub fn verify_and_decode_notification(
         &self,
         signed_jws: &str,
     ) -> Result<super::notifications_v2::ResponseBodyV2DecodedPayload, AppleError> {
-        self.decode_jws(signed_jws)
+        let payload: super::notifications_v2::ResponseBodyV2DecodedPayload =
+            self.decode_jws(signed_jws)?;
+        let (bundle_id, app_apple_id, environment) = if let Some(data) = &payload.data {
+            (
+                Some(data.bundle_id.as_str()),
+                data.app_apple_id,
+                data.environment.clone(),
+            )
+        } else if let Some(summary) = &payload.summary {
+            (
+                Some(summary.bundle_id.as_str()),
+                summary.app_apple_id,
+                summary.environment.clone(),
+            )
+        } else if let Some(token) = &payload.external_purchase_token {
+            // External purchase tokens carry no environment; sandbox IDs are
+            // prefixed instead.
+            let environment = if token.external_purchase_id.starts_with("SANDBOX") {
+                AppStoreEnvironment::Sandbox
+            } else {
+                AppStoreEnvironment::Production
+            };
+            (
+                Some(token.bundle_id.as_str()),
+                token.app_apple_id,
+                environment,
+            )
+        } else {
+            (None, None, self.environment.clone())
+        };
+        self.check_bundle_id(bundle_id)?;
+        self.check_app_apple_id(app_apple_id)?;
+        self.check_environment(&environment)?;
+        Ok(payload)
     }
 
     pub fn verify_and_decode_app_transaction(
@@ -242,21 +361,113 @@ impl SignedDataVerifier {
         signed_jws: &str,
     ) -> Result<AppTransaction, AppleError> {
         let payload: AppTransaction = self.decode_jws(signed_jws)?;
-        if payload.bundle_id != self.bundle_id {
-            return Err(AppleError::CertificateError(format!(
-                "Bundle ID mismatch: expected {}, got {}",
-                self.bundle_id, payload.bundle_id
-            )));
+        self.check_bundle_id(Some(&payload.bundle_id))?;
+        self.check_app_apple_id(payload.app_apple_id)?;
+        self.check_environment(&payload.environment)?;
+        Ok(payload)
+    }
+
+    fn check_bundle_id(&self, found: Option<&str>) -> Result<(), AppleError> {
+        if found != Some(self.bundle_id.as_str()) {
+            return Err(AppleError::VerificationError(
+                VerificationError::BundleIdMismatch {
+                    expected: self.bundle_id.clone(),
+                    found: found.map(str::to_string),
+                },
+            ));
         }
-        if payload.environment != self.environment {
-            return Err(AppleError::CertificateError(format!(
-                "Environment mismatch: expected {:?}, got {:?}",
-                self.environment, payload.environment
-            )));
+        Ok(())
+    }
+
+    fn check_environment(&self, found: &AppStoreEnvironment) -> Result<(), AppleError> {
+        if *found != self.environment {
+            return Err(AppleError::VerificationError(
+                VerificationError::EnvironmentMismatch {
+                    expected: self.environment.clone(),
+                    found: found.clone(),
+                },
+            ));
         }
+        Ok(())
+    }
+
+    /// Only production payloads are required to name the app's Apple ID;
+    /// sandbox and Xcode builds have none.
+    fn check_app_apple_id(&self, found: Option<i64>) -> Result<(), AppleError> {
+        if self.environment == AppStoreEnvironment::Production && found != self.app_apple_id {
+            return Err(AppleError::VerificationError(
+                VerificationError::AppAppleIdMismatch {
+                    expected: self.app_apple_id,
+                    found,
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn online_checks_enabled(&self) -> bool {
+        self.ocsp.is_some()
+    }
+
+    pub async fn verify_and_decode_transaction_online(
+        &self,
+        signed_jws: &str,
+    ) -> Result<JWSTransactionDecodedPayload, AppleError> {
+        let payload = self.verify_and_decode_transaction(signed_jws)?;
+        self.check_revocation(signed_jws).await?;
+        Ok(payload)
+    }
+
+    pub async fn verify_and_decode_renewal_info_online(
+        &self,
+        signed_jws: &str,
+    ) -> Result<JWSRenewalInfoDecodedPayload, AppleError> {
+        let payload = self.verify_and_decode_renewal_info(signed_jws)?;
+        self.check_revocation(signed_jws).await?;
+        Ok(payload)
+    }
+
+    pub async fn verify_and_decode_notification_online(
+        &self,
+        signed_jws: &str,
+    ) -> Result<super::notifications_v2::ResponseBodyV2DecodedPayload, AppleError> {
+        let payload = self.verify_and_decode_notification(signed_jws)?;
+        self.check_revocation(signed_jws).await?;
         Ok(payload)
     }
 
+    pub async fn verify_and_decode_app_transaction_online(
+        &self,
+        signed_jws: &str,
+    ) -> Result<AppTransaction, AppleError> {
+        let payload = self.verify_and_decode_app_transaction(signed_jws)?;
+        self.check_revocation(signed_jws).await?;
+        Ok(payload)
+    }
+
+    /// Asks OCSP about the leaf and intermediate of a JWS whose chain has
+    /// already been verified.
+    async fn check_revocation(&self, signed_jws: &str) -> Result<(), AppleError> {
+        let checker = self.ocsp.as_ref().ok_or_else(|| {
+            AppleError::CertificateError(
+                "Online checks are not enabled; call with_online_checks".to_string(),
+            )
+        })?;
+        let header = decode_header(signed_jws)?;
+        let chain = header
+            .x5c
+            .iter()
+            .map(|cert| {
+                let der = STANDARD
+                    .decode(cert)
+                    .map_err(|e| AppleError::Base64Error(e.to_string()))?;
+                Certificate::from_der(&der).map_err(|e| AppleError::CertificateError(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        checker.check(&chain[0], &chain[1]).await?;
+        checker.check(&chain[1], &chain[2]).await
+    }
+
     fn decode_jws<T: serde::de::DeserializeOwned>(
         &self,
         jws_string: &str,
@@ -268,107 +479,272 @@ impl SignedDataVerifier {
             ));
         }
 
-        let header_bytes = URL_SAFE_NO_PAD
-            .decode(parts[0])
-            .map_err(|e| AppleError::Base64Error(e.to_string()))?;
-
-        let header: JWSHeader = serde_json::from_slice(&header_bytes)
-            .map_err(|e| AppleError::JsonError(e.to_string()))?;
-
-        if !header.x5c.is_empty() {
-            self.verify_certificate_chain(&header.x5c)?;
+        let header = decode_header(jws_string)?;
+        if header.alg != "ES256" {
+            return Err(AppleError::CertificateError(format!(
+                "Unsupported JWS algorithm: {}",
+                header.alg
+            )));
         }
 
         let payload_bytes = URL_SAFE_NO_PAD
             .decode(parts[1])
             .map_err(|e| AppleError::Base64Error(e.to_string()))?;
 
-        if !header.x5c.is_empty() {
-            let leaf_cert_bytes = STANDARD
-                .decode(&header.x5c[0])
-                .map_err(|e| AppleError::Base64Error(e.to_string()))?;
-
-            let cert = Certificate::from_der(&leaf_cert_bytes)
-                .map_err(|e| AppleError::CertificateError(e.to_string()))?;
-
-            let public_key_bytes = cert
-                .tbs_certificate
-                .subject_public_key_info
-                .subject_public_key
-                .raw_bytes();
-
-            let verifying_key = VerifyingKey::from_sec1_bytes(public_key_bytes)
-                .map_err(|e| AppleError::CertificateError(e.to_string()))?;
-
-            let signature_bytes = URL_SAFE_NO_PAD
-                .decode(parts[2])
-                .map_err(|e| AppleError::Base64Error(e.to_string()))?;
-
-            let signature = Signature::from_der(&signature_bytes)
-                .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+        let effective_date = if self.validate_at_current_time {
+            None
+        } else {
+            serde_json::from_slice::<SignedDate>(&payload_bytes)
+                .map_err(|e| AppleError::JsonError(e.to_string()))?
+                .signed_date
+        };
+        let effective_date = match effective_date {
+            Some(millis) => millis,
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| AppleError::TimeError(e.to_string()))?
+                .as_millis() as i64,
+        };
+        let leaf = self.verify_certificate_chain(&header.x5c, effective_date)?;
+
+        let public_key_bytes = leaf
+            .tbs_certificate
+            .subject_public_key_info
+            .subject_public_key
+            .raw_bytes();
+
+        let verifying_key = p256::ecdsa::VerifyingKey::from_sec1_bytes(public_key_bytes)
+            .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+
+        let signature_bytes = URL_SAFE_NO_PAD
+            .decode(parts[2])
+            .map_err(|e| AppleError::Base64Error(e.to_string()))?;
 
-            let signed_content = format!("{}.{}", parts[0], parts[1]);
-            verifying_key
-                .verify(signed_content.as_bytes(), &signature)
-                .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+        // JWS signatures are raw `r || s`; accept DER as well for older callers.
+        let signature = if signature_bytes.len() == 64 {
+            p256::ecdsa::Signature::from_slice(&signature_bytes)
+        } else {
+            p256::ecdsa::Signature::from_der(&signature_bytes)
         }
+        .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+
+        let signed_content = format!("{}.{}", parts[0], parts[1]);
+        verifying_key
+            .verify(signed_content.as_bytes(), &signature)
+            .map_err(|e| AppleError::CertificateError(e.to_string()))?;
 
         serde_json::from_slice(&payload_bytes).map_err(|e| AppleError::JsonError(e.to_string()))
     }
 
-    fn verify_certificate_chain(&self, x5c_chain: &[String]) -> Result<(), AppleError> {
-        if x5c_chain.is_empty() {
+    /// Verifies `x5c` as leaf, intermediate and a pinned root, all valid at
+    /// `effective_date` (milliseconds since the epoch), and returns the leaf.
+    fn verify_certificate_chain(
+        &self,
+        x5c_chain: &[String],
+        effective_date: i64,
+    ) -> Result<Certificate, AppleError> {
+        if self.root_certificates.is_empty() {
             return Err(AppleError::CertificateError(
-                "Empty certificate chain".to_string(),
+                "No trusted root certificates configured".to_string(),
             ));
         }
+        if x5c_chain.len() != CHAIN_LENGTH {
+            return Err(AppleError::CertificateError(format!(
+                "Expected a certificate chain of {} certificates, got {}",
+                CHAIN_LENGTH,
+                x5c_chain.len()
+            )));
+        }
 
-        // Verify chain from leaf to root
-        for i in 0..x5c_chain.len() - 1 {
-            let cert_bytes = STANDARD
-                .decode(&x5c_chain[i])
-                .map_err(|e| AppleError::Base64Error(e.to_string()))?;
+        let der_chain = x5c_chain
+            .iter()
+            .map(|cert| {
+                STANDARD
+                    .decode(cert)
+                    .map_err(|e| AppleError::Base64Error(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !self.root_certificates.contains(&der_chain[2]) {
+            return Err(AppleError::CertificateError(
+                "Root certificate not trusted".to_string(),
+            ));
+        }
+        let chain = der_chain
+            .iter()
+            .map(|der| {
+                Certificate::from_der(der).map_err(|e| AppleError::CertificateError(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let (leaf, intermediate, root) = (&chain[0], &chain[1], &chain[2]);
+
+        for (name, cert) in [
+            ("Leaf", leaf),
+            ("Intermediate", intermediate),
+            ("Root", root),
+        ] {
+            check_validity(name, cert, effective_date)?;
+        }
+        check_marker(leaf, LEAF_MARKER_OID, "Leaf")?;
+        check_marker(intermediate, INTERMEDIATE_MARKER_OID, "Intermediate")?;
+        if basic_constraints(leaf)?.is_some_and(|bc| bc.ca) {
+            return Err(AppleError::CertificateError(
+                "Leaf certificate must not be a CA".to_string(),
+            ));
+        }
+        check_ca(intermediate, "Intermediate", 0)?;
+        check_ca(root, "Root", 1)?;
 
-            let _cert = Certificate::from_der(&cert_bytes)
-                .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+        verify_issued_by(leaf, intermediate)?;
+        verify_issued_by(intermediate, root)?;
 
-            let issuer_bytes = STANDARD
-                .decode(&x5c_chain[i + 1])
-                .map_err(|e| AppleError::Base64Error(e.to_string()))?;
+        Ok(chain.into_iter().next().unwrap())
+    }
+}
 
-            let issuer_cert = Certificate::from_der(&issuer_bytes)
-                .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+fn decode_header(jws_string: &str) -> Result<JWSHeader, AppleError> {
+    let header_bytes = URL_SAFE_NO_PAD
+        .decode(jws_string.split('.').next().unwrap_or_default())
+        .map_err(|e| AppleError::Base64Error(e.to_string()))?;
+    serde_json::from_slice(&header_bytes).map_err(|e| AppleError::JsonError(e.to_string()))
+}
 
-            let issuer_public_key_bytes = issuer_cert
-                .tbs_certificate
-                .subject_public_key_info
-                .subject_public_key
-                .raw_bytes();
+pub(crate) fn check_validity(name: &str, cert: &Certificate, at: i64) -> Result<(), AppleError> {
+    let validity = &cert.tbs_certificate.validity;
+    let not_before = validity.not_before.to_unix_duration().as_millis() as i64;
+    let not_after = validity.not_after.to_unix_duration().as_millis() as i64;
+    if at < not_before || at > not_after {
+        return Err(AppleError::CertificateError(format!(
+            "{} certificate is not valid at {} (valid from {} to {})",
+            name, at, not_before, not_after
+        )));
+    }
+    Ok(())
+}
 
-            let _issuer_verifying_key = VerifyingKey::from_sec1_bytes(issuer_public_key_bytes)
-                .map_err(|e| AppleError::CertificateError(e.to_string()))?;
-        }
+fn check_marker(cert: &Certificate, oid: ObjectIdentifier, name: &str) -> Result<(), AppleError> {
+    let present = cert
+        .tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .any(|ext| ext.extn_id == oid);
+    if !present {
+        return Err(AppleError::CertificateError(format!(
+            "{} certificate is missing the Apple marker extension {}",
+            name, oid
+        )));
+    }
+    Ok(())
+}
 
-        // Verify root certificate against trusted roots
-        let root_cert_bytes = STANDARD
-            .decode(x5c_chain.last().unwrap())
-            .map_err(|e| AppleError::Base64Error(e.to_string()))?;
+fn basic_constraints(cert: &Certificate) -> Result<Option<BasicConstraints>, AppleError> {
+    cert.tbs_certificate
+        .extensions
+        .iter()
+        .flatten()
+        .find(|ext| ext.extn_id == BasicConstraints::OID)
+        .map(|ext| {
+            BasicConstraints::from_der(ext.extn_value.as_bytes())
+                .map_err(|e| AppleError::CertificateError(e.to_string()))
+        })
+        .transpose()
+}
 
-        if !self.root_certificates.is_empty() {
-            let mut root_trusted = false;
-            for trusted_root in &self.root_certificates {
-                if *trusted_root == root_cert_bytes {
-                    root_trusted = true;
-                    break;
-                }
+/// Requires `cert` to be a CA allowed to have `intermediates_below` CA
+/// certificates under it.
+fn check_ca(cert: &Certificate, name: &str, intermediates_below: u8) -> Result<(), AppleError> {
+    match basic_constraints(cert)? {
+        Some(bc) if bc.ca => match bc.path_len_constraint {
+            Some(limit) if limit < intermediates_below => {
+                Err(AppleError::CertificateError(format!(
+                    "{} certificate path length constraint {} is exceeded",
+                    name, limit
+                )))
             }
-            if !root_trusted {
-                return Err(AppleError::CertificateError(
-                    "Root certificate not trusted".to_string(),
-                ));
-            }
-        }
+            _ => Ok(()),
+        },
+        _ => Err(AppleError::CertificateError(format!(
+            "{} certificate is not a CA",
+            name
+        ))),
+    }
+}
 
-        Ok(())
+/// Checks that `issuer` signed `cert`. Apple's chain mixes P-256 and P-384
+/// keys with SHA-256 and SHA-384 signatures.
+pub(crate) fn verify_issued_by(cert: &Certificate, issuer: &Certificate) -> Result<(), AppleError> {
+    if cert.tbs_certificate.issuer != issuer.tbs_certificate.subject {
+        return Err(AppleError::CertificateError(format!(
+            "Certificate issuer {} does not match {}",
+            cert.tbs_certificate.issuer, issuer.tbs_certificate.subject
+        )));
+    }
+
+    let tbs = cert
+        .tbs_certificate
+        .to_der()
+        .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+    let signature = cert.signature.as_bytes().ok_or_else(|| {
+        AppleError::CertificateError("Certificate signature is not byte aligned".to_string())
+    })?;
+    let verified = verify_signature(
+        &tbs,
+        cert.signature_algorithm.oid,
+        signature,
+        &issuer.tbs_certificate.subject_public_key_info,
+    )?;
+    if !verified {
+        return Err(AppleError::CertificateError(format!(
+            "Signature on {} does not verify against its issuer",
+            cert.tbs_certificate.subject
+        )));
+    }
+    Ok(())
+}
+
+/// Checks an ECDSA `signature` over `data` made with `key_info`'s key.
+/// Returns `Ok(false)` for a well-formed signature that does not verify.
+pub(crate) fn verify_signature(
+    data: &[u8],
+    algorithm: ObjectIdentifier,
+    signature: &[u8],
+    key_info: &SubjectPublicKeyInfoOwned,
+) -> Result<bool, AppleError> {
+    let digest = if algorithm == ECDSA_WITH_SHA256 {
+        Sha256::digest(data).to_vec()
+    } else if algorithm == ECDSA_WITH_SHA384 {
+        Sha384::digest(data).to_vec()
+    } else {
+        return Err(AppleError::CertificateError(format!(
+            "Unsupported signature algorithm {}",
+            algorithm
+        )));
+    };
+
+    let curve: ObjectIdentifier = key_info
+        .algorithm
+        .parameters
+        .as_ref()
+        .ok_or_else(|| AppleError::CertificateError("Signing key has no curve".to_string()))?
+        .decode_as()
+        .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+    let key = key_info.subject_public_key.raw_bytes();
+    if curve == SECP256R1 {
+        let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(key)
+            .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+        let signature = p256::ecdsa::Signature::from_der(signature)
+            .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+        Ok(key.verify_prehash(&digest, &signature).is_ok())
+    } else if curve == SECP384R1 {
+        let key = p384::ecdsa::VerifyingKey::from_sec1_bytes(key)
+            .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+        let signature = p384::ecdsa::Signature::from_der(signature)
+            .map_err(|e| AppleError::CertificateError(e.to_string()))?;
+        Ok(key.verify_prehash(&digest, &signature).is_ok())
+    } else {
+        Err(AppleError::CertificateError(format!(
+            "Unsupported key curve {}",
+            curve
+        )))
     }
 }
@@ -0,0 +1,529 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use p256::ecdsa::signature::Signer;
+use p256::elliptic_curve::rand_core::{OsRng, RngCore};
+use p256::pkcs8::EncodePublicKey;
+use serde::Serialize;
+use x509_cert::certificate::{Certificate, TbsCertificate, Version};
+use x509_cert::der::asn1::{
+    BitString, GeneralizedTime, Ia5String, Null, ObjectIdentifier, OctetString,
+};
+use x509_cert::der::oid::AssociatedOid;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::ext::Extension;
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::pkix::{
+    AccessDescription, AuthorityInfoAccessSyntax, BasicConstraints, ExtendedKeyUsage,
+};
+use x509_cert::name::Name;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned};
+use x509_cert::time::{Time, Validity};
+
+use super::notifications_v2::{NotificationData, ResponseBodyV2, ResponseBodyV2DecodedPayload};
+use super::signed_data::{
+    AppTransaction, INTERMEDIATE_MARKER_OID, JWSRenewalInfoDecodedPayload,
+    JWSTransactionDecodedPayload, LEAF_MARKER_OID, SignedDataVerifier,
+};
+use super::types::{AppStoreEnvironment, NotificationTypeV2, Subtype};
+
+const ECDSA_WITH_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+const ECDSA_WITH_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+const ID_AD_OCSP: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.48.1");
+
+/// 2000-01-01 to 2100-01-01, so payloads with any realistic `signedDate`
+/// verify.
+const VALIDITY_SECS: (u64, u64) = (946_684_800, 4_102_444_800);
+
+fn p256_key() -> p256::ecdsa::SigningKey {
+    p256::ecdsa::SigningKey::random(&mut OsRng)
+}
+
+fn p384_key() -> p384::ecdsa::SigningKey {
+    p384::ecdsa::SigningKey::random(&mut OsRng)
+}
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+fn unique_id() -> String {
+    (OsRng.next_u64() % 1_000_000_000_000_000).to_string()
+}
+
+/// A random version 4 UUID.
+pub fn uuid() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+enum Key {
+    P256(p256::ecdsa::SigningKey),
+    P384(p384::ecdsa::SigningKey),
+}
+
+impl Key {
+    fn public_key(&self) -> SubjectPublicKeyInfoOwned {
+        let der = match self {
+            Key::P256(key) => key.verifying_key().to_public_key_der(),
+            Key::P384(key) => key.verifying_key().to_public_key_der(),
+        }
+        .expect("public key encodes");
+        SubjectPublicKeyInfoOwned::from_der(der.as_bytes()).expect("public key decodes")
+    }
+
+    fn sign(&self, data: &[u8]) -> (ObjectIdentifier, Vec<u8>) {
+        match self {
+            Key::P256(key) => {
+                let signature: p256::ecdsa::DerSignature = key.sign(data);
+                (ECDSA_WITH_SHA256, signature.as_bytes().to_vec())
+            }
+            Key::P384(key) => {
+                let signature: p384::ecdsa::DerSignature = key.sign(data);
+                (ECDSA_WITH_SHA384, signature.as_bytes().to_vec())
+            }
+        }
+    }
+}
+
+/// One certificate issued by a `TestSigner`. The defaults describe a valid
+/// chain; change them through `TestSigner::with_chain` to test how
+/// verification fails.
+#[derive(Debug, Clone)]
+pub struct CertificateSpec {
+    pub subject: String,
+    pub serial: u64,
+    /// The BasicConstraints `cA` flag, or `None` to leave the extension out.
+    pub ca: Option<bool>,
+    /// Apple's marker extension for the certificate's place in the chain.
+    pub marker: Option<ObjectIdentifier>,
+    pub extended_key_usage: Vec<ObjectIdentifier>,
+    /// Validity as seconds since the Unix epoch.
+    pub valid_secs: (u64, u64),
+    /// OCSP responder put in the Authority Information Access extension.
+    pub ocsp_url: Option<String>,
+    /// Signs the certificate with an unrelated key instead of the issuer's.
+    pub forged: bool,
+}
+
+impl CertificateSpec {
+    pub fn new(subject: &str, serial: u64) -> Self {
+        CertificateSpec {
+            subject: subject.to_string(),
+            serial,
+            ca: None,
+            marker: None,
+            extended_key_usage: Vec::new(),
+            valid_secs: VALIDITY_SECS,
+            ocsp_url: None,
+            forged: false,
+        }
+    }
+}
+
+fn extension(extn_id: ObjectIdentifier, critical: bool, value: impl Encode) -> Extension {
+    Extension {
+        extn_id,
+        critical,
+        extn_value: OctetString::new(value.to_der().expect("extension encodes"))
+            .expect("extension fits an octet string"),
+    }
+}
+
+fn issue(
+    spec: &CertificateSpec,
+    public_key: SubjectPublicKeyInfoOwned,
+    issuer: &Issuer,
+) -> Vec<u8> {
+    let mut extensions = Vec::new();
+    if let Some(ca) = spec.ca {
+        extensions.push(extension(
+            BasicConstraints::OID,
+            true,
+            BasicConstraints {
+                ca,
+                path_len_constraint: None,
+            },
+        ));
+    }
+    if !spec.extended_key_usage.is_empty() {
+        extensions.push(extension(
+            ExtendedKeyUsage::OID,
+            false,
+            ExtendedKeyUsage(spec.extended_key_usage.clone()),
+        ));
+    }
+    if let Some(url) = &spec.ocsp_url {
+        extensions.push(extension(
+            AuthorityInfoAccessSyntax::OID,
+            false,
+            AuthorityInfoAccessSyntax(vec![AccessDescription {
+                access_method: ID_AD_OCSP,
+                access_location: GeneralName::UniformResourceIdentifier(
+                    Ia5String::new(url).expect("OCSP URL is ASCII"),
+                ),
+            }]),
+        ));
+    }
+    if let Some(marker) = spec.marker {
+        extensions.push(extension(marker, false, Null));
+    }
+
+    let forger;
+    let signing_key = if spec.forged {
+        forger = Key::P384(p384_key());
+        &forger
+    } else {
+        issuer.key
+    };
+    let time = |secs| {
+        Time::GeneralTime(
+            GeneralizedTime::from_unix_duration(Duration::from_secs(secs))
+                .expect("validity is representable"),
+        )
+    };
+    let algorithm = AlgorithmIdentifierOwned {
+        oid: signing_key.sign(b"").0,
+        parameters: None,
+    };
+    let tbs = TbsCertificate {
+        version: Version::V3,
+        serial_number: SerialNumber::from(spec.serial),
+        signature: algorithm.clone(),
+        issuer: Name::from_str(&format!("CN={}", issuer.name)).expect("issuer name parses"),
+        validity: Validity {
+            not_before: time(spec.valid_secs.0),
+            not_after: time(spec.valid_secs.1),
+        },
+        subject: Name::from_str(&format!("CN={}", spec.subject)).expect("subject name parses"),
+        subject_public_key_info: public_key,
+        issuer_unique_id: None,
+        subject_unique_id: None,
+        extensions: (!extensions.is_empty()).then_some(extensions),
+    };
+    let (_, signature) = signing_key.sign(&tbs.to_der().expect("certificate encodes"));
+    Certificate {
+        tbs_certificate: tbs,
+        signature_algorithm: algorithm,
+        signature: BitString::from_bytes(&signature).expect("signature fits a bit string"),
+    }
+    .to_der()
+    .expect("certificate encodes")
+}
+
+struct Issuer<'a> {
+    name: &'a str,
+    key: &'a Key,
+}
+
+/// A certificate issued by one of a `TestSigner`'s CAs, such as a delegated
+/// OCSP responder, together with its P-384 key.
+pub struct IssuedCertificate {
+    pub der: Vec<u8>,
+    key: Key,
+}
+
+impl IssuedCertificate {
+    /// Signs `data` with the certificate's key, returning the signature
+    /// algorithm and the DER signature.
+    pub fn sign(&self, data: &[u8]) -> (ObjectIdentifier, Vec<u8>) {
+        self.key.sign(data)
+    }
+}
+
+/// A freshly generated root, intermediate and leaf laid out like Apple's
+/// App Store chain: P-384 root and intermediate, a P-256 leaf, and Apple's
+/// marker OIDs. Signs payloads as ES256 JWS with the chain in `x5c`, for
+/// testing code that consumes `SignedDataVerifier`. Never trust its root
+/// outside tests.
+///
+/// ```ignore
+/// let signer = TestSigner::new();
+/// let verifier = signer.verifier("com.example.app", AppStoreEnvironment::Sandbox, None);
+/// let transaction = testing::transaction("com.example.app", "pro.monthly", AppStoreEnvironment::Sandbox);
+/// let decoded = verifier.verify_and_decode_transaction(&signer.sign_transaction(&transaction))?;
+/// ```
+pub struct TestSigner {
+    /// Leaf, intermediate and root keys, in `x5c` order.
+    keys: [Key; 3],
+    names: [String; 3],
+    root: Vec<u8>,
+    x5c: Vec<String>,
+}
+
+impl Default for TestSigner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestSigner {
+    pub fn new() -> Self {
+        Self::with_chain(|_| {})
+    }
+
+    /// Builds the chain from the leaf, intermediate and root specs after
+    /// `customize` has adjusted them. Serial numbers are 1, 2 and 3.
+    ///
+    /// ```ignore
+    /// let expired = TestSigner::with_chain(|[leaf, _, _]| leaf.valid_secs = (0, 1));
+    /// ```
+    pub fn with_chain(customize: impl FnOnce(&mut [CertificateSpec; 3])) -> Self {
+        let mut specs = [
+            CertificateSpec {
+                ca: Some(false),
+                marker: Some(LEAF_MARKER_OID),
+                ..CertificateSpec::new("Test Signing Leaf", 1)
+            },
+            CertificateSpec {
+                ca: Some(true),
+                marker: Some(INTERMEDIATE_MARKER_OID),
+                ..CertificateSpec::new("Test Intermediate CA", 2)
+            },
+            CertificateSpec {
+                ca: Some(true),
+                ..CertificateSpec::new("Test Root CA", 3)
+            },
+        ];
+        customize(&mut specs);
+
+        let keys = [
+            Key::P256(p256_key()),
+            Key::P384(p384_key()),
+            Key::P384(p384_key()),
+        ];
+        let names = specs.clone().map(|spec| spec.subject);
+        let certificates: Vec<Vec<u8>> = (0..3)
+            .map(|i| {
+                let issuer = (i + 1).min(2);
+                issue(
+                    &specs[i],
+                    keys[i].public_key(),
+                    &Issuer {
+                        name: &names[issuer],
+                        key: &keys[issuer],
+                    },
+                )
+            })
+            .collect();
+
+        TestSigner {
+            keys,
+            names,
+            x5c: certificates
+                .iter()
+                .map(|der| STANDARD.encode(der))
+                .collect(),
+            root: certificates[2].clone(),
+        }
+    }
+
+    /// Puts `x5c` in JWS headers in place of the real chain, for testing
+    /// missing, short or substituted chains.
+    pub fn with_certificate_chain(mut self, x5c: Vec<String>) -> Self {
+        self.x5c = x5c;
+        self
+    }
+
+    /// Signs `data` with the key of the certificate at `index` in the chain
+    /// (0 is the leaf), returning the signature algorithm and the DER
+    /// signature. Lets a test answer OCSP requests as the issuer.
+    pub fn sign_with(&self, index: usize, data: &[u8]) -> (ObjectIdentifier, Vec<u8>) {
+        self.keys[index].sign(data)
+    }
+
+    /// Issues a certificate with a new P-384 key from the chain's
+    /// certificate at `issuer` (1 is the intermediate, 2 the root).
+    pub fn issue(&self, issuer: usize, spec: &CertificateSpec) -> IssuedCertificate {
+        let key = Key::P384(p384_key());
+        let der = issue(
+            spec,
+            key.public_key(),
+            &Issuer {
+                name: &self.names[issuer],
+                key: &self.keys[issuer],
+            },
+        );
+        IssuedCertificate { der, key }
+    }
+
+    /// The DER root certificate to trust.
+    pub fn root_certificate(&self) -> &[u8] {
+        &self.root
+    }
+
+    /// The base64 DER leaf, intermediate and root put in each JWS `x5c`.
+    pub fn certificate_chain(&self) -> &[String] {
+        &self.x5c
+    }
+
+    /// A verifier that trusts only this signer's root.
+    pub fn verifier(
+        &self,
+        bundle_id: &str,
+        environment: AppStoreEnvironment,
+        app_apple_id: Option<i64>,
+    ) -> SignedDataVerifier {
+        SignedDataVerifier::new(
+            vec![self.root.clone()],
+            bundle_id,
+            environment,
+            app_apple_id,
+        )
+    }
+
+    /// Signs any payload as a compact ES256 JWS.
+    pub fn sign<T: Serialize>(&self, payload: &T) -> String {
+        let header = serde_json::json!({"alg": "ES256", "x5c": self.x5c});
+        let payload = serde_json::to_vec(payload).expect("payload serializes to JSON");
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(payload)
+        );
+        let Key::P256(leaf_key) = &self.keys[0] else {
+            unreachable!("the leaf key is P-256")
+        };
+        let signature: p256::ecdsa::Signature = leaf_key.sign(signing_input.as_bytes());
+        format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        )
+    }
+
+    pub fn sign_transaction(&self, transaction: &JWSTransactionDecodedPayload) -> String {
+        self.sign(transaction)
+    }
+
+    pub fn sign_renewal_info(&self, renewal_info: &JWSRenewalInfoDecodedPayload) -> String {
+        self.sign(renewal_info)
+    }
+
+    pub fn sign_app_transaction(&self, app_transaction: &AppTransaction) -> String {
+        self.sign(app_transaction)
+    }
+
+    pub fn sign_notification(&self, notification: &ResponseBodyV2DecodedPayload) -> String {
+        self.sign(notification)
+    }
+
+    /// A V2 notification about `transaction`, with it and `renewal_info`
+    /// signed into `data`. Production verifiers also need
+    /// `data.app_apple_id` set.
+    pub fn notification(
+        &self,
+        notification_type: NotificationTypeV2,
+        subtype: Option<Subtype>,
+        transaction: &JWSTransactionDecodedPayload,
+        renewal_info: Option<&JWSRenewalInfoDecodedPayload>,
+    ) -> ResponseBodyV2DecodedPayload {
+        ResponseBodyV2DecodedPayload {
+            notification_type,
+            subtype,
+            data: Some(NotificationData {
+                environment: transaction.environment.clone(),
+                bundle_id: transaction.bundle_id.clone(),
+                app_apple_id: None,
+                bundle_version: None,
+                signed_transaction_info: Some(self.sign_transaction(transaction)),
+                signed_renewal_info: renewal_info.map(|info| self.sign_renewal_info(info)),
+                status: None,
+                consumption_request_reason: None,
+            }),
+            summary: None,
+            external_purchase_token: None,
+            version: "2.0".to_string(),
+            signed_date: now_millis(),
+            notification_uuid: uuid(),
+        }
+    }
+
+    /// The HTTP body Apple would POST to a webhook for `notification`.
+    pub fn webhook_body(&self, notification: &ResponseBodyV2DecodedPayload) -> Vec<u8> {
+        serde_json::to_vec(&ResponseBodyV2 {
+            signed_payload: self.sign_notification(notification),
+        })
+        .expect("body serializes to JSON")
+    }
+}
+
+/// An auto-renewable subscription purchased now and expiring in 30 days.
+pub fn transaction(
+    bundle_id: &str,
+    product_id: &str,
+    environment: AppStoreEnvironment,
+) -> JWSTransactionDecodedPayload {
+    let now = now_millis();
+    let id = unique_id();
+    serde_json::from_value(serde_json::json!({
+        "transactionId": id,
+        "originalTransactionId": id,
+        "webOrderLineItemId": unique_id(),
+        "bundleId": bundle_id,
+        "productId": product_id,
+        "subscriptionGroupIdentifier": "21000000",
+        "purchaseDate": now,
+        "originalPurchaseDate": now,
+        "expiresDate": now + 30 * 24 * 60 * 60 * 1000,
+        "quantity": 1,
+        "type": "Auto-Renewable Subscription",
+        "inAppOwnershipType": "PURCHASED",
+        "signedDate": now,
+        "environment": environment,
+        "transactionReason": "PURCHASE",
+        "storefront": "USA",
+        "storefrontId": "143441",
+        "price": 9990,
+        "currency": "USD",
+    }))
+    .expect("transaction payload is valid")
+}
+
+/// Renewal info for `transaction` with auto-renew on.
+pub fn renewal_info(transaction: &JWSTransactionDecodedPayload) -> JWSRenewalInfoDecodedPayload {
+    serde_json::from_value(serde_json::json!({
+        "originalTransactionId": transaction.original_transaction_id,
+        "autoRenewProductId": transaction.product_id,
+        "productId": transaction.product_id,
+        "autoRenewStatus": 1,
+        "signedDate": now_millis(),
+        "environment": transaction.environment,
+        "recentSubscriptionStartDate": transaction.purchase_date,
+        "renewalDate": transaction.expires_date,
+    }))
+    .expect("renewal info payload is valid")
+}
+
+pub fn app_transaction(
+    bundle_id: &str,
+    environment: AppStoreEnvironment,
+    app_apple_id: Option<i64>,
+) -> AppTransaction {
+    let now = now_millis();
+    serde_json::from_value(serde_json::json!({
+        "appAppleId": app_apple_id,
+        "bundleId": bundle_id,
+        "applicationVersion": "1",
+        "originalApplicationVersion": "1",
+        "originalPurchaseDate": now,
+        "receiptCreationDate": now,
+        "environment": environment,
+        "signedDate": now,
+    }))
+    .expect("app transaction payload is valid")
+}
@@ -0,0 +1,178 @@
+//! Exports a CloudKit database to a backup archive, or imports one.
+//!
+//! ```text
+//! cloudkit-backup export <DIR> --container <ID> [--database public] [--record-type <TYPE>]...
+//! cloudkit-backup import <DIR> --container <ID> [--zone-map <FROM>=<TO>]... [--allow-production]
+//! ```
+//!
+//! The server-to-server key is read from `--key-id`/`--key-file`, falling back
+//! to the `CLOUDKIT_KEY_ID` and `CLOUDKIT_KEY_FILE` environment variables.
+
+use apple::cloudkit::{
+    CloudKitClient, CloudKitConfig, DatabaseType, Environment, ExportOptions, ImportOptions, ZoneID,
+};
+use apple::error::AppleError;
+use apple::signing::AppleKeyPair;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: cloudkit-backup <export|import> <DIR> --container <ID> [options]
+
+Options:
+  --environment <development|production>  Defaults to development
+  --database <public|private|shared>      Defaults to public
+  --key-id <ID>                           Or CLOUDKIT_KEY_ID
+  --key-file <PATH>                       Or CLOUDKIT_KEY_FILE
+  --base-url <URL>                        Send requests somewhere other than CloudKit
+
+Export:
+  --record-type <TYPE>                    Record type to export (repeatable; required for public)
+  --zone <NAME>                           Zone to export (repeatable; defaults to all)
+  --page-size <N>
+  --no-assets                             Do not download asset contents
+
+Import:
+  --zone-map <FROM>=<TO>                  Restore zone FROM into zone TO (repeatable)
+  --no-create-zones                       Fail instead of creating missing zones
+  --allow-production                      Required to import into production";
+
+#[derive(Default)]
+struct Args {
+    command: String,
+    dir: PathBuf,
+    container: Option<String>,
+    environment: Option<String>,
+    database: Option<String>,
+    key_id: Option<String>,
+    key_file: Option<String>,
+    base_url: Option<String>,
+    export: ExportOptions,
+    import: ImportOptions,
+    allow_production: bool,
+}
+
+fn parse_args(mut argv: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut args = Args {
+        command: argv.next().ok_or("Missing command")?,
+        dir: argv.next().ok_or("Missing archive directory")?.into(),
+        ..Default::default()
+    };
+    while let Some(flag) = argv.next() {
+        let mut value = || argv.next().ok_or(format!("{} needs a value", flag));
+        match flag.as_str() {
+            "--container" => args.container = Some(value()?),
+            "--environment" => args.environment = Some(value()?),
+            "--database" => args.database = Some(value()?),
+            "--key-id" => args.key_id = Some(value()?),
+            "--key-file" => args.key_file = Some(value()?),
+            "--base-url" => args.base_url = Some(value()?),
+            "--record-type" => args.export = args.export.with_record_type(&value()?),
+            "--zone" => args.export = args.export.with_zone(ZoneID::new(&value()?)),
+            "--page-size" => {
+                let size = value()?
+                    .parse()
+                    .map_err(|_| "--page-size must be a number".to_string())?;
+                args.export = args.export.with_page_size(size);
+            }
+            "--no-assets" => args.export = args.export.without_assets(),
+            "--zone-map" => {
+                let mapping = value()?;
+                let (from, to) = mapping.split_once('=').ok_or("--zone-map takes FROM=TO")?;
+                args.import = args.import.with_zone_mapping(from, ZoneID::new(to));
+            }
+            "--no-create-zones" => args.import = args.import.with_create_zones(false),
+            "--allow-production" => args.allow_production = true,
+            other => return Err(format!("Unknown option {}", other)),
+        }
+    }
+    Ok(args)
+}
+
+fn client(args: &Args) -> Result<CloudKitClient, String> {
+    let container = args.container.clone().ok_or("--container is required")?;
+    let environment: Environment = args
+        .environment
+        .as_deref()
+        .unwrap_or("development")
+        .parse()
+        .map_err(|e: AppleError| e.to_string())?;
+    let key_id = args
+        .key_id
+        .clone()
+        .or_else(|| std::env::var("CLOUDKIT_KEY_ID").ok())
+        .ok_or("--key-id or CLOUDKIT_KEY_ID is required")?;
+    let key_file = args
+        .key_file
+        .clone()
+        .or_else(|| std::env::var("CLOUDKIT_KEY_FILE").ok())
+        .ok_or("--key-file or CLOUDKIT_KEY_FILE is required")?;
+    let key_pair = AppleKeyPair::from_file(&key_id, &key_file).map_err(|e| e.to_string())?;
+
+    let client = CloudKitClient::new(CloudKitConfig {
+        container,
+        environment,
+        key_pair,
+    })
+    .map_err(|e| e.to_string())?
+    .with_production_writes(args.allow_production);
+    Ok(match &args.base_url {
+        Some(base_url) => client.with_base_url(base_url),
+        None => client,
+    })
+}
+
+fn database(args: &Args) -> Result<DatabaseType, String> {
+    args.database
+        .as_deref()
+        .unwrap_or("public")
+        .parse()
+        .map_err(|e: AppleError| e.to_string())
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    let client = client(&args)?;
+    let db = database(&args)?;
+    let report = |e: AppleError| e.to_string();
+    match args.command.as_str() {
+        "export" => {
+            let manifest = client
+                .export_backup(&db, &args.dir, &args.export)
+                .await
+                .map_err(report)?;
+            for zone in &manifest.zones {
+                println!(
+                    "{}: {} records, {} assets",
+                    zone.zone_id.zone_name, zone.records, zone.assets
+                );
+            }
+        }
+        "import" => {
+            let summary = client
+                .import_backup(&db, &args.dir, &args.import)
+                .await
+                .map_err(report)?;
+            println!(
+                "Imported {} records and {} assets into {} zones",
+                summary.records, summary.assets, summary.zones
+            );
+        }
+        other => return Err(format!("Unknown command {}", other)),
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let result = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => run(args).await,
+        Err(err) => Err(format!("{}\n\n{}", err, USAGE)),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("cloudkit-backup: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,715 @@
+//! Command-line access to CloudKit Web Services.
+//!
+//! ```text
+//! cloudkit [options] records query Note --where 'title BEGINSWITH "a"' --sort title
+//! cloudkit [options] records create Note --field title=Hello --field count:int64=3
+//! cloudkit [options] zones list
+//! cloudkit [options] changes zone Notes --token <TOKEN>
+//! ```
+//!
+//! Credentials and defaults are read from a JSON config file (`--config` or
+//! `CLOUDKIT_CONFIG`), then `CLOUDKIT_*` environment variables, then flags.
+
+use apple::cloudkit::{
+    CloudKitClient, CloudKitConfig, DatabaseType, Environment, FieldValue, FiresOn,
+    NotificationInfo, OperationType, QueryBuilder, Record, ReferenceValue, Subscription,
+    SubscriptionType, ZoneID,
+};
+use apple::error::AppleError;
+use apple::signing::AppleKeyPair;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: cloudkit [options] <command> [arguments]
+
+Commands:
+  records query <TYPE> [--where <EXPR>] [--sort <FIELD>[:desc]] [--limit <N>] [--all]
+  records lookup <NAME>...
+  records create <TYPE> [--name <NAME>] [--field <FIELD>[:<TYPE>]=<VALUE>]...
+  records update <NAME> [--field <FIELD>[:<TYPE>]=<VALUE>]... [--remove <FIELD>]...
+  records delete <NAME>...
+  zones list
+  zones create <NAME>...
+  zones delete <NAME>...
+  subscriptions list
+  subscriptions create-query <ID> <TYPE> [--where <EXPR>] [--fires-on create,update,delete]
+  subscriptions create-zone <ID> <ZONE>
+  subscriptions create-database <ID>
+  subscriptions delete <ID>...
+  changes database [--token <TOKEN>]
+  changes zone <ZONE> [--token <TOKEN>]
+
+Options:
+  --config <PATH>          JSON file with container, environment, keyId, keyFile, database
+  --container <ID>         Or CLOUDKIT_CONTAINER
+  --environment <ENV>      development (default) or production; or CLOUDKIT_ENVIRONMENT
+  --key-id <ID>            Or CLOUDKIT_KEY_ID
+  --key-file <PATH>        Or CLOUDKIT_KEY_FILE
+  --database <DB>          public (default), private or shared; or CLOUDKIT_DATABASE
+  --zone <NAME>            Zone for record commands
+  --output <FORMAT>        table (default) or json
+  --base-url <URL>         Send requests somewhere other than CloudKit
+  --allow-production       Required for writes to production
+
+Field types for --field: string (default), int64, double, timestamp, reference, bytes.";
+
+/// Settings from the config file. Every key is optional.
+#[derive(Debug, Default, Deserialize)]
+struct FileConfig {
+    container: Option<String>,
+    environment: Option<String>,
+    #[serde(rename = "keyId")]
+    key_id: Option<String>,
+    #[serde(rename = "keyFile")]
+    key_file: Option<String>,
+    database: Option<String>,
+    #[serde(rename = "baseURL")]
+    base_url: Option<String>,
+}
+
+#[derive(Default)]
+struct Settings {
+    config: Option<String>,
+    container: Option<String>,
+    environment: Option<String>,
+    key_id: Option<String>,
+    key_file: Option<String>,
+    database: Option<String>,
+    zone: Option<String>,
+    json: bool,
+    base_url: Option<String>,
+    allow_production: bool,
+}
+
+/// Positional arguments and `--flag value` pairs of one command.
+struct Command {
+    words: Vec<String>,
+    flags: Vec<(String, String)>,
+    switches: Vec<String>,
+}
+
+impl Command {
+    fn flag(&self, name: &str) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(flag, _)| flag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn flags(&self, name: &str) -> Vec<&str> {
+        self.flags
+            .iter()
+            .filter(|(flag, _)| flag == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|s| s == name)
+    }
+
+    fn word(&self, index: usize, what: &str) -> Result<&str, String> {
+        self.words
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("Missing {}", what))
+    }
+
+    fn rest(&self, from: usize, what: &str) -> Result<Vec<&str>, String> {
+        let rest: Vec<&str> = self.words.iter().skip(from).map(String::as_str).collect();
+        if rest.is_empty() {
+            return Err(format!("Missing {}", what));
+        }
+        Ok(rest)
+    }
+}
+
+const SWITCHES: &[&str] = &["--all", "--allow-production"];
+
+fn parse_args(mut argv: impl Iterator<Item = String>) -> Result<(Settings, Command), String> {
+    let mut settings = Settings::default();
+    let mut command = Command {
+        words: Vec::new(),
+        flags: Vec::new(),
+        switches: Vec::new(),
+    };
+    while let Some(arg) = argv.next() {
+        if !arg.starts_with("--") {
+            command.words.push(arg);
+            continue;
+        }
+        if SWITCHES.contains(&arg.as_str()) {
+            if arg == "--allow-production" {
+                settings.allow_production = true;
+            }
+            command.switches.push(arg);
+            continue;
+        }
+        let value = argv
+            .next()
+            .ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--config" => settings.config = Some(value),
+            "--container" => settings.container = Some(value),
+            "--environment" => settings.environment = Some(value),
+            "--key-id" => settings.key_id = Some(value),
+            "--key-file" => settings.key_file = Some(value),
+            "--database" => settings.database = Some(value),
+            "--zone" => settings.zone = Some(value),
+            "--base-url" => settings.base_url = Some(value),
+            "--output" => {
+                settings.json = match value.as_str() {
+                    "json" => true,
+                    "table" => false,
+                    other => return Err(format!("Unknown output format {}", other)),
+                }
+            }
+            _ => command.flags.push((arg, value)),
+        }
+    }
+    Ok((settings, command))
+}
+
+/// Flags win over environment variables, which win over the config file.
+fn resolve(settings: &mut Settings) -> Result<(), String> {
+    let path = settings
+        .config
+        .clone()
+        .or_else(|| std::env::var("CLOUDKIT_CONFIG").ok());
+    let file = match path {
+        Some(path) => {
+            let json = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+            serde_json::from_str(&json).map_err(|e| format!("{}: {}", path, e))?
+        }
+        None => FileConfig::default(),
+    };
+    let pick = |flag: &mut Option<String>, var: &str, file: Option<String>| {
+        if flag.is_none() {
+            *flag = std::env::var(var).ok().or(file);
+        }
+    };
+    pick(
+        &mut settings.container,
+        "CLOUDKIT_CONTAINER",
+        file.container,
+    );
+    pick(
+        &mut settings.environment,
+        "CLOUDKIT_ENVIRONMENT",
+        file.environment,
+    );
+    pick(&mut settings.key_id, "CLOUDKIT_KEY_ID", file.key_id);
+    pick(&mut settings.key_file, "CLOUDKIT_KEY_FILE", file.key_file);
+    pick(&mut settings.database, "CLOUDKIT_DATABASE", file.database);
+    pick(&mut settings.base_url, "CLOUDKIT_BASE_URL", file.base_url);
+    Ok(())
+}
+
+fn client(settings: &Settings) -> Result<CloudKitClient, String> {
+    let container = settings
+        .container
+        .clone()
+        .ok_or("No container; pass --container or set CLOUDKIT_CONTAINER")?;
+    let environment: Environment = settings
+        .environment
+        .as_deref()
+        .unwrap_or("development")
+        .parse()
+        .map_err(|e: AppleError| e.to_string())?;
+    let key_id = settings
+        .key_id
+        .as_deref()
+        .ok_or("No key ID; pass --key-id or set CLOUDKIT_KEY_ID")?;
+    let key_file = settings
+        .key_file
+        .as_deref()
+        .ok_or("No key file; pass --key-file or set CLOUDKIT_KEY_FILE")?;
+    let key_pair = AppleKeyPair::from_file(key_id, key_file).map_err(|e| e.to_string())?;
+
+    let client = CloudKitClient::new(CloudKitConfig {
+        container,
+        environment,
+        key_pair,
+    })
+    .map_err(|e| e.to_string())?
+    .with_production_writes(settings.allow_production);
+    Ok(match &settings.base_url {
+        Some(base_url) => client.with_base_url(base_url),
+        None => client,
+    })
+}
+
+/// Parses `name=value` or `name:type=value`.
+fn parse_field(spec: &str) -> Result<(String, FieldValue), String> {
+    let (key, value) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("--field {} must be NAME=VALUE", spec))?;
+    let (name, kind) = key.split_once(':').unwrap_or((key, "string"));
+    let invalid = || format!("{} is not a valid {}", value, kind);
+    let value = match kind {
+        "string" => FieldValue::String(value.to_string()),
+        "int64" => FieldValue::Int64(value.parse().map_err(|_| invalid())?),
+        "double" => FieldValue::Double(value.parse().map_err(|_| invalid())?),
+        "timestamp" => FieldValue::Timestamp(value.parse().map_err(|_| invalid())?),
+        "reference" => FieldValue::Reference(ReferenceValue {
+            record_name: value.to_string(),
+            zone_id: None,
+            action: None,
+        }),
+        "bytes" => FieldValue::Bytes(value.to_string()),
+        other => return Err(format!("Unknown field type {}", other)),
+    };
+    Ok((name.to_string(), value))
+}
+
+fn render(value: &FieldValue) -> String {
+    let join = |items: Vec<String>| items.join(", ");
+    match value {
+        FieldValue::String(s) | FieldValue::Bytes(s) | FieldValue::EncryptedBytes(s) => s.clone(),
+        FieldValue::Int64(n) | FieldValue::Timestamp(n) => n.to_string(),
+        FieldValue::Double(n) => n.to_string(),
+        FieldValue::Reference(r) => r.record_name.clone(),
+        FieldValue::Asset(a) => a.file_checksum.clone().unwrap_or_else(|| "<asset>".into()),
+        FieldValue::AssetList(items) => join(
+            items
+                .iter()
+                .map(|a| a.file_checksum.clone().unwrap_or_else(|| "<asset>".into()))
+                .collect(),
+        ),
+        FieldValue::Location(l) => format!("{},{}", l.latitude, l.longitude),
+        FieldValue::StringList(items) | FieldValue::BytesList(items) => join(items.clone()),
+        FieldValue::Int64List(items) | FieldValue::TimestampList(items) => {
+            join(items.iter().map(|n| n.to_string()).collect())
+        }
+        FieldValue::DoubleList(items) => join(items.iter().map(|n| n.to_string()).collect()),
+        FieldValue::ReferenceList(items) => {
+            join(items.iter().map(|r| r.record_name.clone()).collect())
+        }
+        FieldValue::LocationList(items) => join(
+            items
+                .iter()
+                .map(|l| format!("{},{}", l.latitude, l.longitude))
+                .collect(),
+        ),
+    }
+}
+
+/// Rows of named cells, printed as aligned columns or as JSON.
+#[derive(Default)]
+struct Table {
+    columns: Vec<String>,
+    rows: Vec<Vec<(String, String)>>,
+    json: Vec<Value>,
+}
+
+impl Table {
+    fn push(&mut self, cells: Vec<(String, String)>, json: Value) {
+        for (column, _) in &cells {
+            if !self.columns.contains(column) {
+                self.columns.push(column.clone());
+            }
+        }
+        self.rows.push(cells);
+        self.json.push(json);
+    }
+
+    fn push_record(&mut self, record: &Record) {
+        let mut cells = vec![
+            (
+                "recordName".to_string(),
+                record.record_name.clone().unwrap_or_default(),
+            ),
+            ("recordType".to_string(), record.record_type.clone()),
+        ];
+        let mut fields: Vec<_> = record.fields.iter().collect();
+        fields.sort_by_key(|(name, _)| name.as_str());
+        cells.extend(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.clone(), render(value))),
+        );
+        self.push(cells, serde_json::to_value(record).unwrap_or(Value::Null));
+    }
+
+    fn print(&self, json: bool) {
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&self.json).unwrap_or_default()
+            );
+            return;
+        }
+        let cell = |row: &[(String, String)], column: &str| {
+            row.iter()
+                .find(|(name, _)| name == column)
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default()
+        };
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .map(|column| {
+                self.rows
+                    .iter()
+                    .map(|row| cell(row, column).chars().count())
+                    .chain([column.len()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let line = |values: Vec<String>| {
+            let padded: Vec<String> = values
+                .iter()
+                .zip(&widths)
+                .map(|(value, width)| format!("{:width$}", value, width = width))
+                .collect();
+            println!("{}", padded.join("  ").trim_end());
+        };
+        line(self.columns.clone());
+        for row in &self.rows {
+            line(self.columns.iter().map(|c| cell(row, c)).collect());
+        }
+    }
+}
+
+fn fires_on(spec: Option<&str>) -> Result<Vec<FiresOn>, String> {
+    spec.unwrap_or("create,update,delete")
+        .split(',')
+        .map(|kind| match kind.trim() {
+            "create" => Ok(FiresOn::Create),
+            "update" => Ok(FiresOn::Update),
+            "delete" => Ok(FiresOn::Delete),
+            other => Err(format!("Unknown --fires-on value {}", other)),
+        })
+        .collect()
+}
+
+async fn records(
+    client: &CloudKitClient,
+    db: &DatabaseType,
+    zone: Option<ZoneID>,
+    command: &Command,
+) -> Result<Table, AppleError> {
+    let usage = |msg: String| AppleError::ValidationError(msg);
+    let mut table = Table::default();
+    match command.word(1, "records subcommand").map_err(usage)? {
+        "query" => {
+            let record_type = command.word(2, "record type").map_err(usage)?;
+            let mut builder = QueryBuilder::new(record_type);
+            if let Some(expression) = command.flag("--where") {
+                builder = builder.predicate(expression)?;
+            }
+            for sort in command.flags("--sort") {
+                let (field, descending) = match sort.strip_suffix(":desc") {
+                    Some(field) => (field, true),
+                    None => (sort.strip_suffix(":asc").unwrap_or(sort), false),
+                };
+                builder = builder.sort(field, !descending);
+            }
+            let limit = command
+                .flag("--limit")
+                .map(|n| n.parse::<u32>())
+                .transpose()
+                .map_err(|_| usage("--limit must be a number".to_string()))?;
+            let query = builder.build();
+            let mut marker = None;
+            loop {
+                let response = client
+                    .query_records(db, query.clone(), zone.clone(), limit, marker, None)
+                    .await?;
+                for result in response.records {
+                    table.push_record(&result.into_record()?);
+                }
+                marker = response.continuation_marker;
+                if marker.is_none() || !command.switch("--all") {
+                    break;
+                }
+            }
+        }
+        "lookup" => {
+            let names = command.rest(2, "record names").map_err(usage)?;
+            for result in client.lookup_record_results(db, &names, zone, None).await? {
+                table.push_record(&result.into_record()?);
+            }
+        }
+        "create" => {
+            let record_type = command.word(2, "record type").map_err(usage)?;
+            let mut record = Record::new(record_type);
+            if let Some(name) = command.flag("--name") {
+                record = record.with_name(name);
+            }
+            if let Some(zone) = zone {
+                record = record.with_zone(zone);
+            }
+            for spec in command.flags("--field") {
+                let (name, value) = parse_field(spec).map_err(usage)?;
+                record = record.with_field(&name, value);
+            }
+            table.push_record(&client.create_record(db, record).await?);
+        }
+        "update" => {
+            let name = command.word(2, "record name").map_err(usage)?;
+            let mut record = client
+                .lookup_record_results(db, &[name], zone.clone(), None)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| usage(format!("Record {} not found", name)))?
+                .into_record()?;
+            for spec in command.flags("--field") {
+                let (field, value) = parse_field(spec).map_err(usage)?;
+                record.fields.insert(field, value);
+            }
+            for field in command.flags("--remove") {
+                record.fields.remove(field);
+            }
+            if record.zone_id.is_none() {
+                record.zone_id = zone;
+            }
+            table.push_record(&client.update_record(db, record).await?);
+        }
+        "delete" => {
+            let names = command.rest(2, "record names").map_err(usage)?;
+            let operations = names
+                .iter()
+                .map(|name| {
+                    let mut record = Record::new("");
+                    record.record_name = Some(name.to_string());
+                    (OperationType::Delete, record)
+                })
+                .collect();
+            for result in client
+                .modify_records(db, operations, zone.clone(), None)
+                .await?
+            {
+                if let Some(err) = result.error() {
+                    return Err(err);
+                }
+                let name = result.record_name.unwrap_or_default();
+                table.push(
+                    vec![("deleted".to_string(), name.clone())],
+                    json!({ "recordName": name, "deleted": true }),
+                );
+            }
+        }
+        other => return Err(usage(format!("Unknown records subcommand {}", other))),
+    }
+    Ok(table)
+}
+
+async fn zones(
+    client: &CloudKitClient,
+    db: &DatabaseType,
+    command: &Command,
+) -> Result<Table, AppleError> {
+    let usage = |msg: String| AppleError::ValidationError(msg);
+    let mut table = Table::default();
+    let mut push = |zone_name: &str, sync_token: Option<String>, json: Value| {
+        table.push(
+            vec![
+                ("zoneName".to_string(), zone_name.to_string()),
+                ("syncToken".to_string(), sync_token.unwrap_or_default()),
+            ],
+            json,
+        )
+    };
+    match command.word(1, "zones subcommand").map_err(usage)? {
+        "list" => {
+            for zone in client.list_zones(db).await? {
+                let json = serde_json::to_value(&zone).unwrap_or(Value::Null);
+                push(&zone.zone_id.zone_name, zone.sync_token, json);
+            }
+        }
+        "create" => {
+            for name in command.rest(2, "zone names").map_err(usage)? {
+                let zone = client.create_zone(db, ZoneID::new(name), None).await?;
+                let json = serde_json::to_value(&zone).unwrap_or(Value::Null);
+                push(&zone.zone_id.zone_name, zone.sync_token, json);
+            }
+        }
+        "delete" => {
+            for name in command.rest(2, "zone names").map_err(usage)? {
+                client.delete_zone(db, ZoneID::new(name)).await?;
+                push(name, None, json!({ "zoneName": name, "deleted": true }));
+            }
+        }
+        other => return Err(usage(format!("Unknown zones subcommand {}", other))),
+    }
+    Ok(table)
+}
+
+async fn subscriptions(
+    client: &CloudKitClient,
+    db: &DatabaseType,
+    command: &Command,
+) -> Result<Table, AppleError> {
+    let usage = |msg: String| AppleError::ValidationError(msg);
+    let mut table = Table::default();
+    let mut push = |subscription: &Subscription| {
+        let kind = match subscription.subscription_type {
+            SubscriptionType::Query => "query",
+            SubscriptionType::Zone => "zone",
+            SubscriptionType::Database => "database",
+        };
+        let target = match (&subscription.query, &subscription.zone_id) {
+            (Some(query), _) => query.record_type.clone(),
+            (None, Some(zone_id)) => zone_id.zone_name.clone(),
+            (None, None) => String::new(),
+        };
+        table.push(
+            vec![
+                (
+                    "subscriptionID".to_string(),
+                    subscription.subscription_id.clone().unwrap_or_default(),
+                ),
+                ("type".to_string(), kind.to_string()),
+                ("target".to_string(), target),
+            ],
+            serde_json::to_value(subscription).unwrap_or(Value::Null),
+        )
+    };
+    let created = match command.word(1, "subscriptions subcommand").map_err(usage)? {
+        "list" => {
+            for subscription in client.list_subscriptions(db).await? {
+                push(&subscription);
+            }
+            None
+        }
+        "create-query" => {
+            let id = command.word(2, "subscription ID").map_err(usage)?;
+            let record_type = command.word(3, "record type").map_err(usage)?;
+            let mut builder = QueryBuilder::new(record_type);
+            if let Some(expression) = command.flag("--where") {
+                builder = builder.predicate(expression)?;
+            }
+            let fires_on = fires_on(command.flag("--fires-on")).map_err(usage)?;
+            Some(Subscription::query(id, builder.build(), &fires_on)?)
+        }
+        "create-zone" => {
+            let id = command.word(2, "subscription ID").map_err(usage)?;
+            let zone = command.word(3, "zone name").map_err(usage)?;
+            Some(Subscription::zone(id, ZoneID::new(zone)))
+        }
+        "create-database" => {
+            let id = command.word(2, "subscription ID").map_err(usage)?;
+            Some(Subscription::database(id))
+        }
+        "delete" => {
+            let ids = command.rest(2, "subscription IDs").map_err(usage)?;
+            let existing = client.list_subscriptions(db).await?;
+            for id in ids {
+                let subscription = existing
+                    .iter()
+                    .find(|s| s.subscription_id.as_deref() == Some(id))
+                    .ok_or_else(|| usage(format!("Subscription {} not found", id)))?;
+                client
+                    .delete_subscription(db, id, subscription.subscription_type.clone())
+                    .await?;
+                push(subscription);
+            }
+            None
+        }
+        other => {
+            return Err(usage(format!("Unknown subscriptions subcommand {}", other)));
+        }
+    };
+    if let Some(subscription) = created {
+        let subscription = subscription.with_notification_info(NotificationInfo::silent());
+        push(&client.create_subscription(db, subscription).await?);
+    }
+    Ok(table)
+}
+
+async fn changes(
+    client: &CloudKitClient,
+    db: &DatabaseType,
+    command: &Command,
+) -> Result<Table, AppleError> {
+    let usage = |msg: String| AppleError::ValidationError(msg);
+    let token = command.flag("--token").map(String::from);
+    let mut table = Table::default();
+    let sync_token = match command.word(1, "changes subcommand").map_err(usage)? {
+        "database" => {
+            let response = client.fetch_database_changes(db, token, None).await?;
+            for zone in response.zones {
+                table.push(
+                    vec![
+                        ("zoneName".to_string(), zone.zone_id.zone_name.clone()),
+                        ("deleted".to_string(), zone.deleted.to_string()),
+                    ],
+                    json!({ "zoneID": zone.zone_id, "deleted": zone.deleted }),
+                );
+            }
+            response.sync_token
+        }
+        "zone" => {
+            let zone = command.word(2, "zone name").map_err(usage)?;
+            let response = client
+                .fetch_zone_changes(db, ZoneID::new(zone), token, None)
+                .await?;
+            for result in response.records {
+                if result.deleted {
+                    let name = result.record_name.unwrap_or_default();
+                    table.push(
+                        vec![
+                            ("recordName".to_string(), name.clone()),
+                            ("deleted".to_string(), "true".to_string()),
+                        ],
+                        json!({ "recordName": name, "deleted": true }),
+                    );
+                } else {
+                    table.push_record(&result.into_record()?);
+                }
+            }
+            response.sync_token
+        }
+        other => return Err(usage(format!("Unknown changes subcommand {}", other))),
+    };
+    // The next token goes to stderr so table and JSON output stay parseable.
+    if let Some(sync_token) = sync_token {
+        eprintln!("syncToken: {}", sync_token);
+    }
+    Ok(table)
+}
+
+async fn run(mut settings: Settings, command: Command) -> Result<(), String> {
+    resolve(&mut settings)?;
+    let client = client(&settings)?;
+    let db: DatabaseType = settings
+        .database
+        .as_deref()
+        .unwrap_or("public")
+        .parse()
+        .map_err(|e: AppleError| e.to_string())?;
+    let zone = settings.zone.as_deref().map(ZoneID::new);
+
+    let table = match command.word(0, "command")? {
+        "records" => records(&client, &db, zone, &command).await,
+        "zones" => zones(&client, &db, &command).await,
+        "subscriptions" => subscriptions(&client, &db, &command).await,
+        "changes" => changes(&client, &db, &command).await,
+        other => return Err(format!("Unknown command {}", other)),
+    }
+    .map_err(|e| e.to_string())?;
+    table.print(settings.json);
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let result = match parse_args(std::env::args().skip(1)) {
+        Ok((settings, command)) if !command.words.is_empty() => run(settings, command).await,
+        Ok(_) => Err(USAGE.to_string()),
+        Err(err) => Err(format!("{}\n\n{}", err, USAGE)),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("cloudkit: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
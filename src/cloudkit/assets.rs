@@ -1,5 +1,5 @@
 use crate::cloudkit::client::CloudKitClient;
-use crate::cloudkit::types::DatabaseType;
+use crate::cloudkit::types::{AssetValue, DatabaseType};
 use crate::error::AppleError;
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
@@ -92,13 +92,19 @@ impl CloudKitClient {
             )));
         }
 
-        let result: AssetUploadResult = serde_json::from_str(&response_body)
+        // CloudKit wraps the receipt in `singleFile`; accept it bare as well.
+        let mut body: serde_json::Value = serde_json::from_str(&response_body)
             .map_err(|e| AppleError::JsonError(e.to_string()))?;
+        if let Some(single_file) = body.get_mut("singleFile") {
+            body = single_file.take();
+        }
+        let result: AssetUploadResult =
+            serde_json::from_value(body).map_err(|e| AppleError::JsonError(e.to_string()))?;
 
         Ok(AssetUploadResult {
             file_checksum: result.file_checksum.or(Some(checksum)),
             size: result.size.or(Some(data.len() as u64)),
-            receipt: result.receipt,
+            ..result
         })
     }
 }
@@ -109,4 +115,22 @@ pub struct AssetUploadResult {
     pub file_checksum: Option<String>,
     pub size: Option<u64>,
     pub receipt: Option<String>,
+    #[serde(rename = "wrappingKey")]
+    pub wrapping_key: Option<String>,
+    #[serde(rename = "referenceChecksum")]
+    pub reference_checksum: Option<String>,
+}
+
+/// The asset field value that attaches an uploaded file to a record.
+impl From<AssetUploadResult> for AssetValue {
+    fn from(result: AssetUploadResult) -> Self {
+        AssetValue {
+            file_checksum: result.file_checksum,
+            size: result.size,
+            download_url: None,
+            receipt: result.receipt,
+            wrapping_key: result.wrapping_key,
+            reference_checksum: result.reference_checksum,
+        }
+    }
 }
@@ -0,0 +1,591 @@
+use crate::cloudkit::client::CloudKitClient;
+use crate::cloudkit::query::QueryBuilder;
+use crate::cloudkit::records::RecordResult;
+use crate::cloudkit::types::*;
+use crate::error::AppleError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Written to `manifest.json` as `format`.
+pub const BACKUP_FORMAT: &str = "cloudkit-backup";
+/// Archive layout version. Imports refuse archives from a newer version.
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const ASSETS_DIR: &str = "assets";
+const ZONES_DIR: &str = "zones";
+/// CloudKit accepts at most 200 operations per `records/modify` request.
+const MAX_BATCH_SIZE: usize = 200;
+
+/// Describes a backup archive: a directory holding `manifest.json`, one
+/// JSON Lines file of records per zone under `zones/`, and the downloaded
+/// asset contents under `assets/`, named by their SHA-256.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format: String,
+    pub version: u32,
+    pub container: String,
+    pub environment: String,
+    pub database: String,
+    /// Milliseconds since the Unix epoch.
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    pub zones: Vec<BackupZone>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupZone {
+    #[serde(rename = "zoneID")]
+    pub zone_id: ZoneID,
+    /// Path of the zone's JSON Lines file, relative to the archive.
+    pub file: String,
+    pub records: usize,
+    pub assets: usize,
+}
+
+/// What `export_backup` reads.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Record types to query in default zones, which have no change feed.
+    /// Required for the public database. Without them, the private default
+    /// zone is skipped.
+    pub record_types: Vec<String>,
+    /// Zones to export. Defaults to every zone in the database.
+    pub zones: Option<Vec<ZoneID>>,
+    pub download_assets: bool,
+    pub page_size: Option<u32>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            record_types: Vec::new(),
+            zones: None,
+            download_assets: true,
+            page_size: None,
+        }
+    }
+}
+
+impl ExportOptions {
+    pub fn with_record_type(mut self, record_type: &str) -> Self {
+        self.record_types.push(record_type.to_string());
+        self
+    }
+
+    pub fn with_zone(mut self, zone_id: ZoneID) -> Self {
+        self.zones.get_or_insert_with(Vec::new).push(zone_id);
+        self
+    }
+
+    /// Keeps asset fields in the archive but does not download their
+    /// contents. Such assets are dropped on import.
+    pub fn without_assets(mut self) -> Self {
+        self.download_assets = false;
+        self
+    }
+
+    pub fn with_page_size(mut self, page_size: u32) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+}
+
+/// How `import_backup` writes an archive back.
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Archived zone name to the zone it is restored into. References that
+    /// point into a remapped zone are rewritten too.
+    pub zone_map: HashMap<String, ZoneID>,
+    /// Creates missing target zones first. Has no effect on the public
+    /// database.
+    pub create_zones: bool,
+    pub batch_size: usize,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            zone_map: HashMap::new(),
+            create_zones: true,
+            batch_size: MAX_BATCH_SIZE,
+        }
+    }
+}
+
+impl ImportOptions {
+    pub fn with_zone_mapping(mut self, from: &str, to: ZoneID) -> Self {
+        self.zone_map.insert(from.to_string(), to);
+        self
+    }
+
+    pub fn with_create_zones(mut self, create_zones: bool) -> Self {
+        self.create_zones = create_zones;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.clamp(1, MAX_BATCH_SIZE);
+        self
+    }
+
+    fn target_zone(&self, zone_id: &ZoneID) -> ZoneID {
+        self.zone_map
+            .get(&zone_id.zone_name)
+            .cloned()
+            .unwrap_or_else(|| zone_id.clone())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportSummary {
+    pub zones: usize,
+    pub records: usize,
+    pub assets: usize,
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> AppleError {
+    AppleError::IoError(format!("{}: {}", path.display(), err))
+}
+
+/// Resolves a path read from an archive against `dir`, refusing anything
+/// that could point outside it, such as `..` or absolute paths.
+fn archive_path(dir: &Path, relative: &str) -> Result<PathBuf, AppleError> {
+    let path = Path::new(relative);
+    let contained = path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !contained {
+        return Err(AppleError::ValidationError(format!(
+            "Backup archive path {} escapes the archive",
+            relative
+        )));
+    }
+    Ok(dir.join(path))
+}
+
+fn assets_mut(value: &mut FieldValue) -> Vec<&mut AssetValue> {
+    match value {
+        FieldValue::Asset(asset) => vec![asset],
+        FieldValue::AssetList(assets) => assets.iter_mut().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn is_default_zone(zone_id: &ZoneID) -> bool {
+    zone_id.zone_name == ZoneID::default_zone().zone_name
+}
+
+fn remap_reference(reference: &mut ReferenceValue, options: &ImportOptions) {
+    if let Some(zone_id) = &reference.zone_id {
+        reference.zone_id = Some(options.target_zone(zone_id));
+    }
+}
+
+impl CloudKitClient {
+    /// Writes every record of the selected zones to an archive in `dir`,
+    /// downloading asset contents alongside. The directory is created if
+    /// needed; an existing manifest in it is overwritten.
+    pub async fn export_backup(
+        &self,
+        db: &DatabaseType,
+        dir: &Path,
+        options: &ExportOptions,
+    ) -> Result<BackupManifest, AppleError> {
+        if *db == DatabaseType::Public && options.record_types.is_empty() {
+            return Err(AppleError::ValidationError(
+                "Exporting the public database requires at least one record type".to_string(),
+            ));
+        }
+        for sub in [ZONES_DIR, ASSETS_DIR] {
+            let path = dir.join(sub);
+            fs::create_dir_all(&path).map_err(|e| io_error(&path, e))?;
+        }
+
+        let zones = match (&options.zones, db) {
+            (Some(zones), _) => zones.clone(),
+            (None, DatabaseType::Public) => vec![ZoneID::default_zone()],
+            (None, _) => self
+                .list_zones(db)
+                .await?
+                .into_iter()
+                .map(|zone| zone.zone_id)
+                .filter(|zone_id| !is_default_zone(zone_id) || !options.record_types.is_empty())
+                .collect(),
+        };
+
+        let mut manifest = BackupManifest {
+            format: BACKUP_FORMAT.to_string(),
+            version: BACKUP_FORMAT_VERSION,
+            container: self.config.container.clone(),
+            environment: self.config.environment.to_string(),
+            database: db.to_string(),
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| AppleError::TimeError(e.to_string()))?
+                .as_millis() as i64,
+            zones: Vec::new(),
+        };
+
+        for (index, zone_id) in zones.into_iter().enumerate() {
+            let records = self.export_zone_records(db, &zone_id, options).await?;
+            let file = format!("{}/{:04}.jsonl", ZONES_DIR, index);
+            let path = dir.join(&file);
+            let mut writer =
+                BufWriter::new(fs::File::create(&path).map_err(|e| io_error(&path, e))?);
+            let mut assets = 0;
+            let record_count = records.len();
+            for mut record in records.into_values() {
+                if options.download_assets {
+                    assets += self.archive_assets(dir, &mut record).await?;
+                }
+                let line = serde_json::to_string(&record)
+                    .map_err(|e| AppleError::JsonError(e.to_string()))?;
+                writeln!(writer, "{}", line).map_err(|e| io_error(&path, e))?;
+            }
+            writer.flush().map_err(|e| io_error(&path, e))?;
+            manifest.zones.push(BackupZone {
+                zone_id,
+                file,
+                records: record_count,
+                assets,
+            });
+        }
+
+        let path = dir.join(MANIFEST_FILE);
+        let json = serde_json::to_string_pretty(&manifest)
+            .map_err(|e| AppleError::JsonError(e.to_string()))?;
+        fs::write(&path, json).map_err(|e| io_error(&path, e))?;
+        Ok(manifest)
+    }
+
+    /// The zone's live records by name. Default zones have no change feed
+    /// and are read with queries; custom zones through `changes/zone`.
+    async fn export_zone_records(
+        &self,
+        db: &DatabaseType,
+        zone_id: &ZoneID,
+        options: &ExportOptions,
+    ) -> Result<BTreeMap<String, Record>, AppleError> {
+        let mut records = BTreeMap::new();
+        let mut keep = |result: RecordResult| -> Result<(), AppleError> {
+            if result.deleted {
+                if let Some(name) = &result.record_name {
+                    records.remove(name);
+                }
+                return Ok(());
+            }
+            let record = result.into_record()?;
+            if let Some(name) = record.record_name.clone() {
+                records.insert(name, record);
+            }
+            Ok(())
+        };
+
+        if *db == DatabaseType::Public || is_default_zone(zone_id) {
+            for record_type in &options.record_types {
+                let mut marker = None;
+                loop {
+                    let response = self
+                        .query_records(
+                            db,
+                            QueryBuilder::new(record_type).build(),
+                            Some(zone_id.clone()),
+                            options.page_size,
+                            marker,
+                            None,
+                        )
+                        .await?;
+                    for result in response.records {
+                        keep(result)?;
+                    }
+                    marker = response.continuation_marker;
+                    if marker.is_none() {
+                        break;
+                    }
+                }
+            }
+        } else {
+            let mut sync_token = None;
+            loop {
+                let response = self
+                    .fetch_zone_changes(db, zone_id.clone(), sync_token, options.page_size)
+                    .await?;
+                for result in response.records {
+                    keep(result)?;
+                }
+                sync_token = response.sync_token;
+                if !response.more_coming.unwrap_or(false) || sync_token.is_none() {
+                    break;
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    /// Downloads the record's assets into the archive and points their
+    /// `downloadURL` at the archived file. Returns how many were stored.
+    async fn archive_assets(&self, dir: &Path, record: &mut Record) -> Result<usize, AppleError> {
+        let mut count = 0;
+        for value in record.fields.values_mut() {
+            for asset in assets_mut(value) {
+                let Some(url) = asset.download_url.clone() else {
+                    continue;
+                };
+                let (file, size) = self.download_asset(dir, &url).await?;
+                asset.download_url = Some(file);
+                asset.size = Some(size);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Stores the asset at `url` under `assets/` and returns its archive path
+    /// and size.
+    async fn download_asset(&self, dir: &Path, url: &str) -> Result<(String, u64), AppleError> {
+        // CloudKit download URLs end in a `${f}` placeholder for the name the
+        // file should be served under.
+        let url = url.replace("${f}", "asset");
+        let res = self
+            .http_client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| AppleError::HttpError(e.to_string()))?;
+        if !res.status().is_success() {
+            return Err(AppleError::HttpError(format!(
+                "Asset download failed with status: {}",
+                res.status()
+            )));
+        }
+        let data = res
+            .bytes()
+            .await
+            .map_err(|e| AppleError::HttpError(e.to_string()))?;
+
+        let digest: String = Sha256::digest(&data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let file = format!("{}/{}", ASSETS_DIR, digest);
+        let path = dir.join(&file);
+        if !path.exists() {
+            fs::write(&path, &data).map_err(|e| io_error(&path, e))?;
+        }
+        Ok((file, data.len() as u64))
+    }
+
+    /// Replays an archive written by `export_backup` into `db`. Records are
+    /// written with `forceReplace`, so importing the same archive twice
+    /// leaves one copy of each record.
+    pub async fn import_backup(
+        &self,
+        db: &DatabaseType,
+        dir: &Path,
+        options: &ImportOptions,
+    ) -> Result<ImportSummary, AppleError> {
+        let manifest = read_manifest(dir)?;
+        let mut summary = ImportSummary::default();
+
+        let mut existing_zones = Vec::new();
+        if options.create_zones && *db != DatabaseType::Public {
+            existing_zones = self
+                .list_zones(db)
+                .await?
+                .into_iter()
+                .map(|zone| zone.zone_id.zone_name)
+                .collect();
+        }
+
+        for zone in &manifest.zones {
+            let target = options.target_zone(&zone.zone_id);
+            if options.create_zones
+                && *db != DatabaseType::Public
+                && !existing_zones.contains(&target.zone_name)
+            {
+                self.create_zone(db, target.clone(), None).await?;
+                existing_zones.push(target.zone_name.clone());
+            }
+
+            let path = archive_path(dir, &zone.file)?;
+            let reader = BufReader::new(fs::File::open(&path).map_err(|e| io_error(&path, e))?);
+            let mut batch = Vec::new();
+            for line in reader.lines() {
+                let line = line.map_err(|e| io_error(&path, e))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record: Record = serde_json::from_str(&line)
+                    .map_err(|e| AppleError::JsonError(e.to_string()))?;
+                let record = self
+                    .restore_record(db, dir, record, &target, options, &mut summary)
+                    .await?;
+                batch.push((OperationType::ForceReplace, record));
+                if batch.len() >= options.batch_size {
+                    self.import_batch(db, std::mem::take(&mut batch), &target, &mut summary)
+                        .await?;
+                }
+            }
+            if !batch.is_empty() {
+                self.import_batch(db, batch, &target, &mut summary).await?;
+            }
+            summary.zones += 1;
+        }
+        Ok(summary)
+    }
+
+    /// Strips server-assigned metadata, moves the record and its references
+    /// into the target zones and uploads archived assets.
+    async fn restore_record(
+        &self,
+        db: &DatabaseType,
+        dir: &Path,
+        mut record: Record,
+        target: &ZoneID,
+        options: &ImportOptions,
+        summary: &mut ImportSummary,
+    ) -> Result<Record, AppleError> {
+        record.record_change_tag = None;
+        record.created = None;
+        record.modified = None;
+        record.zone_id = Some(target.clone());
+        if let Some(share) = &mut record.share {
+            remap_reference(share, options);
+        }
+
+        let record_name = record.record_name.clone().unwrap_or_default();
+        let mut dropped = Vec::new();
+        for (field_name, value) in record.fields.iter_mut() {
+            match value {
+                FieldValue::Reference(reference) => remap_reference(reference, options),
+                FieldValue::ReferenceList(references) => {
+                    for reference in references {
+                        remap_reference(reference, options);
+                    }
+                }
+                FieldValue::Asset(asset) => {
+                    let target_field = (
+                        record_name.as_str(),
+                        record.record_type.as_str(),
+                        field_name.as_str(),
+                    );
+                    match self
+                        .restore_asset(db, dir, target_field, target, asset)
+                        .await?
+                    {
+                        Some(restored) => {
+                            *asset = restored;
+                            summary.assets += 1;
+                        }
+                        None => dropped.push(field_name.clone()),
+                    }
+                }
+                FieldValue::AssetList(assets) => {
+                    let target_field = (
+                        record_name.as_str(),
+                        record.record_type.as_str(),
+                        field_name.as_str(),
+                    );
+                    let mut restored = Vec::new();
+                    for asset in assets.iter() {
+                        if let Some(asset) = self
+                            .restore_asset(db, dir, target_field, target, asset)
+                            .await?
+                        {
+                            restored.push(asset);
+                        }
+                    }
+                    summary.assets += restored.len();
+                    *assets = restored;
+                }
+                _ => {}
+            }
+        }
+        for field_name in dropped {
+            record.fields.remove(&field_name);
+        }
+        Ok(record)
+    }
+
+    /// Uploads an archived asset for `(record name, record type, field)`.
+    /// Returns `None` for assets whose contents were not archived.
+    async fn restore_asset(
+        &self,
+        db: &DatabaseType,
+        dir: &Path,
+        (record_name, record_type, field_name): (&str, &str, &str),
+        target: &ZoneID,
+        asset: &AssetValue,
+    ) -> Result<Option<AssetValue>, AppleError> {
+        let Some(file) = asset
+            .download_url
+            .as_deref()
+            .filter(|url| url.starts_with(&format!("{}/", ASSETS_DIR)))
+        else {
+            return Ok(None);
+        };
+        let path = archive_path(dir, file)?;
+        let data = fs::read(&path).map_err(|e| io_error(&path, e))?;
+        let upload = self
+            .request_asset_upload(
+                db,
+                record_name,
+                record_type,
+                field_name,
+                Some(target.clone()),
+            )
+            .await?;
+        let url = upload
+            .tokens
+            .into_iter()
+            .find_map(|token| token.url)
+            .ok_or_else(|| AppleError::JsonError("assets/upload returned no URL".to_string()))?;
+        let uploaded = self.upload_asset(&url, &data).await?;
+        Ok(Some(uploaded.into()))
+    }
+
+    async fn import_batch(
+        &self,
+        db: &DatabaseType,
+        batch: Vec<(OperationType, Record)>,
+        target: &ZoneID,
+        summary: &mut ImportSummary,
+    ) -> Result<(), AppleError> {
+        let results = self
+            .modify_records(db, batch, Some(target.clone()), None)
+            .await?;
+        for result in results {
+            result.into_record()?;
+            summary.records += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Reads and checks an archive's manifest.
+pub fn read_manifest(dir: &Path) -> Result<BackupManifest, AppleError> {
+    let path = dir.join(MANIFEST_FILE);
+    let json = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+    let manifest: BackupManifest =
+        serde_json::from_str(&json).map_err(|e| AppleError::JsonError(e.to_string()))?;
+    if manifest.format != BACKUP_FORMAT {
+        return Err(AppleError::ValidationError(format!(
+            "{} is not a CloudKit backup archive",
+            dir.display()
+        )));
+    }
+    if manifest.version > BACKUP_FORMAT_VERSION {
+        return Err(AppleError::ValidationError(format!(
+            "Backup archive version {} is newer than the supported version {}",
+            manifest.version, BACKUP_FORMAT_VERSION
+        )));
+    }
+    Ok(manifest)
+}
@@ -44,6 +44,8 @@ pub struct DatabaseChangesResponse {
 pub struct ZoneChangeInfo {
     #[serde(rename = "zoneID")]
     pub zone_id: ZoneID,
+    #[serde(default)]
+    pub deleted: bool,
 }
 
 impl CloudKitClient {
@@ -1,10 +1,12 @@
 use crate::cloudkit::error::parse_cloudkit_error;
+use crate::cloudkit::schema::Schema;
 use crate::cloudkit::types::{DatabaseType, Environment};
+use crate::cloudkit::web_auth::{CloudKitAuthMode, UserSession};
 use crate::error::AppleError;
 use crate::signing::AppleKeyPair;
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 use sha2::{Digest, Sha256};
@@ -13,6 +15,30 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const CLOUDKIT_BASE_URL: &str = "https://api.apple-cloudkit.com";
 
+pub(crate) const KEY_ID_HEADER: &str = "X-Apple-CloudKit-Request-KeyID";
+pub(crate) const DATE_HEADER: &str = "X-Apple-CloudKit-Request-ISO8601Date";
+pub(crate) const SIGNATURE_HEADER: &str = "X-Apple-CloudKit-Request-SignatureV1";
+
+/// The `date:body-hash:subpath` string covered by the request signature.
+pub(crate) fn signing_message(date: &str, body: &[u8], subpath: &str) -> String {
+    let body_hash = {
+        let mut hasher = Sha256::new();
+        hasher.update(body);
+        STANDARD.encode(hasher.finalize())
+    };
+    format!("{}:{}:{}", date, body_hash, subpath)
+}
+
+/// Operations that change data, guarded by `with_production_writes`.
+const WRITE_OPERATIONS: &[&str] = &[
+    "/records/modify",
+    "/records/accept",
+    "/zones/modify",
+    "/subscriptions/modify",
+    "/assets/upload",
+];
+
+#[derive(Clone)]
 pub struct CloudKitConfig {
     pub container: String,
     pub environment: Environment,
@@ -20,8 +46,13 @@ pub struct CloudKitConfig {
 }
 
 pub struct CloudKitClient {
-    config: CloudKitConfig,
+    pub(crate) config: CloudKitConfig,
     pub(crate) http_client: Client,
+    pub(crate) auth_mode: CloudKitAuthMode,
+    pub(crate) session: Option<Arc<UserSession>>,
+    pub(crate) base_url: String,
+    pub(crate) schema: Option<Arc<Schema>>,
+    pub(crate) production_writes: bool,
 }
 
 impl CloudKitClient {
@@ -31,10 +62,32 @@ impl CloudKitClient {
             .build()
             .map_err(|e| AppleError::HttpError(e.to_string()))?;
 
-        Ok(CloudKitClient {
+        Ok(CloudKitClient::with_http_client(config, http_client))
+    }
+
+    /// Creates a client that sends requests through an existing HTTP client,
+    /// sharing its connection pool.
+    pub fn with_http_client(config: CloudKitConfig, http_client: Client) -> Self {
+        CloudKitClient {
             config,
             http_client,
-        })
+            auth_mode: CloudKitAuthMode::ServerToServer,
+            session: None,
+            base_url: CLOUDKIT_BASE_URL.to_string(),
+            schema: None,
+            production_writes: true,
+        }
+    }
+
+    /// Sends requests to `base_url` instead of `https://api.apple-cloudkit.com`,
+    /// for example a local emulator or a relay.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
     }
 
     /// Returns a reference to the client's configuration.
@@ -42,21 +95,54 @@ impl CloudKitClient {
         &self.config
     }
 
+    /// Whether writes to a production container are sent. Clients from
+    /// `CloudKitClient::new` allow them; clients handed out by a
+    /// `CloudKitPool` refuse them unless the pool enabled them.
+    pub fn with_production_writes(mut self, allowed: bool) -> Self {
+        self.production_writes = allowed;
+        self
+    }
+
+    pub fn production_writes_allowed(&self) -> bool {
+        self.production_writes || self.config.environment != Environment::Production
+    }
+
+    /// Refuses operations that change data when production writes are off.
+    fn check_write_allowed(&self, subpath: &str) -> Result<(), AppleError> {
+        if self.production_writes_allowed() {
+            return Ok(());
+        }
+        let operation = subpath.split('?').next().unwrap_or(subpath);
+        if WRITE_OPERATIONS.iter().any(|op| operation.ends_with(op)) {
+            return Err(AppleError::EnvironmentGuardError(format!(
+                "Refusing {} on production container {}; production writes are not enabled",
+                operation, self.config.container
+            )));
+        }
+        Ok(())
+    }
+
     pub(crate) fn build_url(&self, db: &DatabaseType, operation: &str) -> String {
         format!(
             "{}/database/1/{}/{}/{}/{}",
-            CLOUDKIT_BASE_URL, self.config.container, self.config.environment, db, operation,
+            self.base_url, self.config.container, self.config.environment, db, operation,
         )
     }
 
     pub(crate) fn build_base_url(&self, path: &str) -> String {
         format!(
             "{}/database/1/{}/{}/{}",
-            CLOUDKIT_BASE_URL, self.config.container, self.config.environment, path,
+            self.base_url, self.config.container, self.config.environment, path,
         )
     }
 
-    fn sign_request(&self, body: &str, subpath: &str) -> Result<Vec<(String, String)>, AppleError> {
+    /// Produces the key ID, date and signature headers for a server-to-server
+    /// request. `subpath` is the request path starting at `/database/`.
+    pub fn sign_request(
+        &self,
+        body: &str,
+        subpath: &str,
+    ) -> Result<Vec<(String, String)>, AppleError> {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map_err(|e| AppleError::TimeError(e.to_string()))?;
@@ -66,31 +152,52 @@ impl CloudKitClient {
             .format("%Y-%m-%dT%H:%M:%SZ")
             .to_string();
 
-        let body_hash = {
-            let mut hasher = Sha256::new();
-            hasher.update(body.as_bytes());
-            STANDARD.encode(hasher.finalize())
-        };
-
-        let message = format!("{}:{}:{}", date, body_hash, subpath);
+        let message = signing_message(&date, body.as_bytes(), subpath);
         let signature = self.config.key_pair.sign(message.as_bytes());
         let signature_b64 = STANDARD.encode(&signature);
 
         Ok(vec![
             (
-                "X-Apple-CloudKit-Request-KeyID".to_string(),
+                KEY_ID_HEADER.to_string(),
                 self.config.key_pair.key_id().to_string(),
             ),
-            ("X-Apple-CloudKit-Request-ISO8601Date".to_string(), date),
-            (
-                "X-Apple-CloudKit-Request-SignatureV1".to_string(),
-                signature_b64,
-            ),
+            (DATE_HEADER.to_string(), date),
+            (SIGNATURE_HEADER.to_string(), signature_b64),
         ])
     }
 
-    pub(crate) fn extract_subpath(url: &str) -> &str {
-        url.strip_prefix(CLOUDKIT_BASE_URL).unwrap_or(url)
+    pub(crate) fn extract_subpath<'a>(&self, url: &'a str) -> &'a str {
+        url.strip_prefix(self.base_url.as_str()).unwrap_or(url)
+    }
+
+    /// Adds the server-to-server signature headers. User-context requests
+    /// carry their tokens in the query string instead.
+    fn authorize(
+        &self,
+        request: RequestBuilder,
+        body: &str,
+        url: &str,
+    ) -> Result<RequestBuilder, AppleError> {
+        match self.auth_mode {
+            CloudKitAuthMode::ServerToServer => {
+                let subpath = self.extract_subpath(url);
+                let headers = self.sign_request(body, subpath)?;
+                Ok(headers
+                    .iter()
+                    .fold(request, |request, (key, value)| request.header(key, value)))
+            }
+            CloudKitAuthMode::ApiToken => Ok(request),
+        }
+    }
+
+    fn response_error(&self, body: &str) -> AppleError {
+        let err = parse_cloudkit_error(body);
+        if let (AppleError::CloudKitAuthenticationRequired(_), Some(session)) =
+            (&err, &self.session)
+        {
+            session.set_web_auth_token(None);
+        }
+        err
     }
 
     pub(crate) async fn signed_post<Req: Serialize, Res: DeserializeOwned>(
@@ -98,20 +205,15 @@ impl CloudKitClient {
         url: &str,
         body: &Req,
     ) -> Result<Res, AppleError> {
+        self.check_write_allowed(self.extract_subpath(url))?;
         let body_str =
             serde_json::to_string(body).map_err(|e| AppleError::JsonError(e.to_string()))?;
 
-        let subpath = Self::extract_subpath(url);
-        let headers = self.sign_request(&body_str, subpath)?;
-
-        let mut request = self
+        let request = self
             .http_client
-            .post(url)
+            .post(self.request_url(url)?)
             .header("Content-Type", "application/json");
-
-        for (key, value) in &headers {
-            request = request.header(key, value);
-        }
+        let request = self.authorize(request, &body_str, url)?;
 
         let res = request
             .body(body_str)
@@ -126,25 +228,19 @@ impl CloudKitClient {
             .map_err(|e| AppleError::HttpError(e.to_string()))?;
 
         if !status.is_success() {
-            return Err(parse_cloudkit_error(&response_body));
+            return Err(self.response_error(&response_body));
         }
+        self.capture_web_auth_token(&response_body);
 
         serde_json::from_str(&response_body).map_err(|e| AppleError::JsonError(e.to_string()))
     }
 
-    #[allow(dead_code)]
     pub(crate) async fn signed_get<Res: DeserializeOwned>(
         &self,
         url: &str,
     ) -> Result<Res, AppleError> {
-        let subpath = Self::extract_subpath(url);
-        let headers = self.sign_request("", subpath)?;
-
-        let mut request = self.http_client.get(url);
-
-        for (key, value) in &headers {
-            request = request.header(key, value);
-        }
+        let request = self.http_client.get(self.request_url(url)?);
+        let request = self.authorize(request, "", url)?;
 
         let res = request
             .send()
@@ -158,8 +254,9 @@ impl CloudKitClient {
             .map_err(|e| AppleError::HttpError(e.to_string()))?;
 
         if !status.is_success() {
-            return Err(parse_cloudkit_error(&response_body));
+            return Err(self.response_error(&response_body));
         }
+        self.capture_web_auth_token(&response_body);
 
         serde_json::from_str(&response_body).map_err(|e| AppleError::JsonError(e.to_string()))
     }
@@ -0,0 +1,628 @@
+mod query;
+mod store;
+
+use crate::cloudkit::client::{CloudKitClient, CloudKitConfig};
+use crate::cloudkit::query::Query;
+use crate::cloudkit::request_verifier::{CloudKitRequestVerifier, SignedRequestHeaders};
+use crate::cloudkit::types::*;
+use crate::error::{AppleError, CloudKitErrorCode};
+use crate::signing::AppleKeyPair;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use store::{ApiError, EmulatorState};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const DEFAULT_PAGE_SIZE: u32 = 200;
+/// How long a WebCourier poll waits for a notification before returning an
+/// empty list.
+const COURIER_WAIT: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize)]
+struct ModifyRecordsBody {
+    operations: Vec<RecordOperationBody>,
+    #[serde(rename = "zoneID")]
+    zone_id: Option<ZoneID>,
+    atomic: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct RecordOperationBody {
+    #[serde(rename = "operationType")]
+    operation_type: OperationType,
+    record: RecordPayload,
+}
+
+#[derive(Deserialize)]
+struct RecordPayload {
+    #[serde(rename = "recordName")]
+    record_name: Option<String>,
+    #[serde(rename = "recordType")]
+    record_type: Option<String>,
+    #[serde(rename = "recordChangeTag")]
+    record_change_tag: Option<String>,
+    fields: Option<HashMap<String, FieldValue>>,
+    share: Option<ReferenceValue>,
+}
+
+#[derive(Deserialize)]
+struct LookupRecordsBody {
+    records: Vec<RecordNameBody>,
+    #[serde(rename = "zoneID")]
+    zone_id: Option<ZoneID>,
+    #[serde(rename = "desiredKeys")]
+    desired_keys: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct RecordNameBody {
+    #[serde(rename = "recordName")]
+    record_name: String,
+}
+
+#[derive(Deserialize)]
+struct QueryRecordsBody {
+    query: Query,
+    #[serde(rename = "zoneID")]
+    zone_id: Option<ZoneID>,
+    #[serde(rename = "resultsLimit")]
+    results_limit: Option<u32>,
+    #[serde(rename = "continuationMarker")]
+    continuation_marker: Option<String>,
+    #[serde(rename = "desiredKeys")]
+    desired_keys: Option<Vec<String>>,
+    #[serde(rename = "zoneWide", default)]
+    zone_wide: bool,
+}
+
+#[derive(Deserialize)]
+struct ModifyZonesBody {
+    operations: Vec<ZoneOperationBody>,
+}
+
+#[derive(Deserialize)]
+struct ZoneOperationBody {
+    #[serde(rename = "operationType")]
+    operation_type: String,
+    zone: ZoneBody,
+}
+
+#[derive(Deserialize)]
+struct ZoneBody {
+    #[serde(rename = "zoneID")]
+    zone_id: ZoneID,
+    atomic: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct LookupZonesBody {
+    zones: Vec<ZoneLookupBody>,
+}
+
+#[derive(Deserialize)]
+struct ZoneLookupBody {
+    #[serde(rename = "zoneID")]
+    zone_id: ZoneID,
+}
+
+#[derive(Deserialize)]
+struct ZonesChangesBody {
+    #[serde(rename = "syncToken")]
+    sync_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ZoneChangesBody {
+    #[serde(rename = "zoneID")]
+    zone_id: ZoneID,
+    #[serde(rename = "syncToken")]
+    sync_token: Option<String>,
+    #[serde(rename = "resultsLimit")]
+    results_limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct DatabaseChangesBody {
+    #[serde(rename = "syncToken")]
+    sync_token: Option<String>,
+    #[serde(rename = "resultsLimit")]
+    results_limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ModifySubscriptionsBody {
+    operations: Vec<SubscriptionOperationBody>,
+}
+
+#[derive(Deserialize)]
+struct SubscriptionOperationBody {
+    #[serde(rename = "operationType")]
+    operation_type: String,
+    subscription: Subscription,
+}
+
+#[derive(Deserialize)]
+struct LookupSubscriptionsBody {
+    subscriptions: Vec<SubscriptionLookupBody>,
+}
+
+#[derive(Deserialize)]
+struct SubscriptionLookupBody {
+    #[serde(rename = "subscriptionID")]
+    subscription_id: String,
+}
+
+#[derive(Deserialize)]
+struct RegisterTokenBody {
+    #[serde(rename = "apnsToken")]
+    apns_token: String,
+}
+
+#[derive(Deserialize)]
+struct AssetUploadBody {
+    tokens: Vec<AssetUploadTokenBody>,
+}
+
+#[derive(Deserialize)]
+struct AssetUploadTokenBody {
+    #[serde(rename = "recordName")]
+    record_name: String,
+    #[serde(rename = "fieldName")]
+    field_name: String,
+}
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(value: Value) -> Self {
+        Reply {
+            status: 200,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(err: ApiError) -> Self {
+        Reply {
+            status: err.status(),
+            content_type: "application/json",
+            body: err.to_json().to_string().into_bytes(),
+        }
+    }
+}
+
+/// How the emulator authenticates requests. With neither a verifier nor an
+/// API token configured, every request is accepted; `start` only allows
+/// that after `allow_unauthenticated`.
+#[derive(Default)]
+struct Auth {
+    verifier: Option<CloudKitRequestVerifier>,
+    api_token: Option<String>,
+    unauthenticated: bool,
+}
+
+pub struct CloudKitEmulatorBuilder {
+    auth: Auth,
+    page_size: u32,
+}
+
+impl CloudKitEmulatorBuilder {
+    /// Requires every server-to-server request to carry a signature the
+    /// verifier accepts.
+    pub fn with_verifier(mut self, verifier: CloudKitRequestVerifier) -> Self {
+        self.auth.verifier = Some(verifier);
+        self
+    }
+
+    /// Shorthand for `with_verifier` trusting a single key pair.
+    pub fn with_key_pair(mut self, key_pair: &AppleKeyPair) -> Self {
+        let verifier = self.auth.verifier.take().unwrap_or_default();
+        self.auth.verifier = Some(verifier.with_key_pair(key_pair));
+        self
+    }
+
+    /// Accepts user-context requests whose `ckAPIToken` matches `api_token`.
+    /// Once any authentication is configured, requests with another token
+    /// are rejected.
+    pub fn with_api_token(mut self, api_token: &str) -> Self {
+        self.auth.api_token = Some(api_token.to_string());
+        self
+    }
+
+    /// Serves requests without checking signatures or API tokens. `start`
+    /// fails unless either this or a key pair, verifier or API token is set.
+    pub fn allow_unauthenticated(mut self) -> Self {
+        self.auth.unauthenticated = true;
+        self
+    }
+
+    /// Caps the number of records, changes or zones returned per page.
+    /// Defaults to 200, like CloudKit.
+    pub fn with_page_size(mut self, page_size: u32) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Binds to a free port on 127.0.0.1 and starts serving requests on a
+    /// background thread.
+    pub fn start(self) -> Result<CloudKitEmulator, AppleError> {
+        if self.auth.verifier.is_none()
+            && self.auth.api_token.is_none()
+            && !self.auth.unauthenticated
+        {
+            return Err(AppleError::ValidationError(
+                "CloudKit emulator needs with_key_pair, with_verifier, with_api_token or allow_unauthenticated"
+                    .to_string(),
+            ));
+        }
+        let server =
+            Server::http("127.0.0.1:0").map_err(|e| AppleError::HttpError(e.to_string()))?;
+        let port = server
+            .server_addr()
+            .to_ip()
+            .map(|addr| addr.port())
+            .ok_or_else(|| AppleError::HttpError("Emulator is not bound to a port".to_string()))?;
+        let base_url = format!("http://127.0.0.1:{}", port);
+
+        let server = Arc::new(server);
+        let state = Arc::new(Mutex::new(EmulatorState::new(&base_url, self.page_size)));
+        let worker = {
+            let server = Arc::clone(&server);
+            let state = Arc::clone(&state);
+            let auth = self.auth;
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    if let Some(token) = request.url().strip_prefix("/webcourier/") {
+                        let token = token.to_string();
+                        let state = Arc::clone(&state);
+                        thread::spawn(move || serve_courier(request, &token, &state));
+                        continue;
+                    }
+                    handle(request, &state, &auth);
+                }
+            })
+        };
+
+        Ok(CloudKitEmulator {
+            base_url,
+            server,
+            state,
+            worker: Some(worker),
+        })
+    }
+}
+
+/// A local stand-in for CloudKit Web Services. Serves the records, zones,
+/// changes, subscriptions, assets and tokens endpoints, plus WebCourier long
+/// polls, from memory until dropped.
+pub struct CloudKitEmulator {
+    base_url: String,
+    server: Arc<Server>,
+    state: Arc<Mutex<EmulatorState>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl CloudKitEmulator {
+    pub fn builder() -> CloudKitEmulatorBuilder {
+        CloudKitEmulatorBuilder {
+            auth: Auth::default(),
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+
+    /// Starts an emulator that accepts requests signed with `key_pair`.
+    pub fn start(key_pair: &AppleKeyPair) -> Result<Self, AppleError> {
+        Self::builder().with_key_pair(key_pair).start()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Creates a client whose requests go to this emulator.
+    pub fn client(&self, config: CloudKitConfig) -> Result<CloudKitClient, AppleError> {
+        Ok(CloudKitClient::new(config)?.with_base_url(&self.base_url))
+    }
+
+    /// Drops every database, zone, record, subscription, asset and token.
+    pub fn reset(&self) {
+        self.state().reset();
+    }
+
+    /// Queues an APNs-style payload (`{"ck": {...}}`) for every token
+    /// created through `tokens/create`.
+    pub fn notify(&self, payload: Value) {
+        self.state().notify(&payload);
+    }
+
+    /// Invalidates every WebCourier URL handed out so far. Polls on them get a
+    /// 404 until a new token is created.
+    pub fn expire_tokens(&self) {
+        self.state().expire_tokens();
+    }
+
+    fn state(&self) -> MutexGuard<'_, EmulatorState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for CloudKitEmulator {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn handle(mut request: Request, state: &Mutex<EmulatorState>, auth: &Auth) {
+    let mut body = Vec::new();
+    let reply = match request.as_reader().read_to_end(&mut body) {
+        Ok(_) => {
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            dispatch(&request, &body, &mut state, auth).unwrap_or_else(Reply::error)
+        }
+        Err(e) => Reply::error(ApiError::new(CloudKitErrorCode::BadRequest, e.to_string())),
+    };
+    respond(request, reply);
+}
+
+/// Holds a WebCourier poll open until a notification arrives or
+/// `COURIER_WAIT` passes. Runs on its own thread so other requests proceed.
+fn serve_courier(request: Request, token: &str, state: &Mutex<EmulatorState>) {
+    let deadline = Instant::now() + COURIER_WAIT;
+    let reply = loop {
+        let taken = state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take_notifications(token);
+        match taken {
+            None => {
+                break Reply::error(ApiError::new(
+                    CloudKitErrorCode::NotFound,
+                    "WebCourier token has expired",
+                ));
+            }
+            Some(items) if !items.is_empty() || Instant::now() >= deadline => {
+                break Reply::json(json!({ "notifications": items }));
+            }
+            Some(_) => thread::sleep(Duration::from_millis(20)),
+        }
+    };
+    respond(request, reply);
+}
+
+fn respond(request: Request, reply: Reply) {
+    let mut response = Response::from_data(reply.body).with_status_code(reply.status);
+    if let Ok(header) = Header::from_bytes("Content-Type", reply.content_type) {
+        response = response.with_header(header);
+    }
+    let _ = request.respond(response);
+}
+
+fn dispatch(
+    request: &Request,
+    body: &[u8],
+    state: &mut EmulatorState,
+    auth: &Auth,
+) -> Result<Reply, ApiError> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    if let Some(upload_id) = path.strip_prefix("/assets/upload/") {
+        return Ok(Reply::json(state.store_asset(upload_id, body.to_vec())));
+    }
+    if let Some(key) = path.strip_prefix("/assets/download/") {
+        let key = key.split('/').next().unwrap_or_default();
+        let data = state
+            .asset(key)
+            .ok_or_else(|| ApiError::new(CloudKitErrorCode::NotFound, "Asset not found"))?;
+        return Ok(Reply {
+            status: 200,
+            content_type: "application/octet-stream",
+            body: data.to_vec(),
+        });
+    }
+
+    let not_found = || {
+        ApiError::new(
+            CloudKitErrorCode::NotFound,
+            format!("Unknown endpoint: {}", path),
+        )
+    };
+    let rest = path.strip_prefix("/database/1/").ok_or_else(not_found)?;
+    let parts: Vec<&str> = rest.splitn(4, '/').collect();
+    if let [_, _, "tokens", operation] = parts[..] {
+        authenticate(request, query, body, path, auth)?;
+        let response = match operation {
+            "create" => state.create_token(),
+            "register" => {
+                let req: RegisterTokenBody = parse(body)?;
+                state.register_token(&req.apns_token)?
+            }
+            _ => return Err(not_found()),
+        };
+        return Ok(Reply::json(response));
+    }
+    let [container, environment, database, operation] = parts[..] else {
+        return Err(not_found());
+    };
+    let db = match database {
+        "public" => DatabaseType::Public,
+        "private" => DatabaseType::Private,
+        "shared" => DatabaseType::Shared,
+        _ => return Err(not_found()),
+    };
+    if *request.method() != Method::Post {
+        return Err(ApiError::new(
+            CloudKitErrorCode::BadRequest,
+            "Only POST is supported",
+        ));
+    }
+
+    authenticate(request, query, body, path, auth)?;
+
+    let key = format!("{}/{}/{}", container, environment, database);
+    let page_size = state.page_size;
+    let response = match operation {
+        "records/modify" => {
+            let mut req: ModifyRecordsBody = parse(body)?;
+            for op in &mut req.operations {
+                if let Some(fields) = &mut op.record.fields {
+                    state.resolve_assets(fields)?;
+                }
+            }
+            let zone_id = req.zone_id.unwrap_or_else(ZoneID::default_zone);
+            let records = state.database(key, &db).modify_records(
+                &zone_id,
+                req.operations,
+                req.atomic.unwrap_or(false),
+            );
+            json!({ "records": records })
+        }
+        "records/lookup" => {
+            let req: LookupRecordsBody = parse(body)?;
+            let zone_id = req.zone_id.unwrap_or_else(ZoneID::default_zone);
+            let names: Vec<String> = req.records.into_iter().map(|r| r.record_name).collect();
+            let records = state.database(key, &db).lookup_records(
+                &zone_id,
+                &names,
+                req.desired_keys.as_deref(),
+            );
+            json!({ "records": records })
+        }
+        "records/query" => {
+            let req: QueryRecordsBody = parse(body)?;
+            let zone_id = req.zone_id.unwrap_or_else(ZoneID::default_zone);
+            state.database(key, &db).query_records(
+                (!req.zone_wide).then_some(&zone_id),
+                &req.query,
+                req.results_limit,
+                req.continuation_marker.as_deref(),
+                req.desired_keys.as_deref(),
+                page_size,
+            )?
+        }
+        "zones/list" => json!({ "zones": state.database(key, &db).list_zones() }),
+        "zones/modify" => {
+            let req: ModifyZonesBody = parse(body)?;
+            json!({ "zones": state.database(key, &db).modify_zones(req.operations) })
+        }
+        "zones/lookup" => {
+            let req: LookupZonesBody = parse(body)?;
+            let zone_ids: Vec<ZoneID> = req.zones.into_iter().map(|z| z.zone_id).collect();
+            json!({ "zones": state.database(key, &db).lookup_zones(&zone_ids) })
+        }
+        "zones/changes" => {
+            let req: ZonesChangesBody = parse(body)?;
+            state
+                .database(key, &db)
+                .zones_changes(req.sync_token.as_deref(), page_size)?
+        }
+        "changes/zone" => {
+            let req: ZoneChangesBody = parse(body)?;
+            state.database(key, &db).zone_changes(
+                &req.zone_id,
+                req.sync_token.as_deref(),
+                req.results_limit,
+                page_size,
+            )?
+        }
+        "changes/database" => {
+            let req: DatabaseChangesBody = parse(body)?;
+            state.database(key, &db).database_changes(
+                req.sync_token.as_deref(),
+                req.results_limit,
+                page_size,
+            )?
+        }
+        "subscriptions/list" => {
+            json!({ "subscriptions": state.database(key, &db).list_subscriptions() })
+        }
+        "subscriptions/modify" => {
+            let req: ModifySubscriptionsBody = parse(body)?;
+            json!({
+                "subscriptions": state.database(key, &db).modify_subscriptions(req.operations),
+            })
+        }
+        "subscriptions/lookup" => {
+            let req: LookupSubscriptionsBody = parse(body)?;
+            let ids: Vec<String> = req
+                .subscriptions
+                .into_iter()
+                .map(|s| s.subscription_id)
+                .collect();
+            json!({ "subscriptions": state.database(key, &db).lookup_subscriptions(&ids) })
+        }
+        "assets/upload" => {
+            let req: AssetUploadBody = parse(body)?;
+            let tokens: Vec<Value> = req
+                .tokens
+                .into_iter()
+                .map(|token| {
+                    json!({
+                        "recordName": token.record_name,
+                        "fieldName": token.field_name,
+                        "url": state.upload_url(),
+                    })
+                })
+                .collect();
+            json!({ "tokens": tokens })
+        }
+        _ => return Err(not_found()),
+    };
+
+    Ok(Reply::json(response))
+}
+
+fn authenticate(
+    request: &Request,
+    query: &str,
+    body: &[u8],
+    path: &str,
+    auth: &Auth,
+) -> Result<(), ApiError> {
+    if auth.verifier.is_none() && auth.api_token.is_none() {
+        return Ok(());
+    }
+    let failed = |reason: &str| ApiError::new(CloudKitErrorCode::AuthenticationFailed, reason);
+    if let Some((_, token)) =
+        url::form_urlencoded::parse(query.as_bytes()).find(|(key, _)| key == "ckAPIToken")
+    {
+        return match &auth.api_token {
+            Some(expected) if *expected == token => Ok(()),
+            _ => Err(failed("Unknown API token")),
+        };
+    }
+    let Some(verifier) = &auth.verifier else {
+        return Err(failed("Request carries no API token"));
+    };
+
+    let headers: Vec<(&str, &str)> = request
+        .headers()
+        .iter()
+        .map(|h| (h.field.as_str().as_str(), h.value.as_str()))
+        .collect();
+    SignedRequestHeaders::from_pairs(&headers)
+        .and_then(|headers| verifier.verify(&headers, body, path))
+        .map_err(|e| ApiError::new(CloudKitErrorCode::AuthenticationFailed, e.to_string()))
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(body)
+        .map_err(|e| ApiError::new(CloudKitErrorCode::BadRequest, e.to_string()))
+}
+
+impl Default for CloudKitEmulatorBuilder {
+    fn default() -> Self {
+        CloudKitEmulator::builder()
+    }
+}
@@ -0,0 +1,186 @@
+use crate::cloudkit::query::{Comparator, Filter, SortDescriptor};
+use crate::cloudkit::types::{FieldValue, LocationValue, Record};
+use std::cmp::Ordering;
+
+/// A single comparable value. Lists expand to several scalars.
+#[derive(Debug, Clone, PartialEq)]
+enum Scalar {
+    Number(f64),
+    Text(String),
+}
+
+impl Scalar {
+    fn compare(&self, other: &Scalar) -> Option<Ordering> {
+        match (self, other) {
+            (Scalar::Number(a), Scalar::Number(b)) => a.partial_cmp(b),
+            (Scalar::Text(a), Scalar::Text(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+fn scalars(value: &FieldValue) -> Vec<Scalar> {
+    match value {
+        FieldValue::String(s) | FieldValue::Bytes(s) | FieldValue::EncryptedBytes(s) => {
+            vec![Scalar::Text(s.clone())]
+        }
+        FieldValue::Int64(n) | FieldValue::Timestamp(n) => vec![Scalar::Number(*n as f64)],
+        FieldValue::Double(n) => vec![Scalar::Number(*n)],
+        FieldValue::Reference(r) => vec![Scalar::Text(r.record_name.clone())],
+        FieldValue::StringList(list) | FieldValue::BytesList(list) => {
+            list.iter().cloned().map(Scalar::Text).collect()
+        }
+        FieldValue::Int64List(list) | FieldValue::TimestampList(list) => {
+            list.iter().map(|n| Scalar::Number(*n as f64)).collect()
+        }
+        FieldValue::DoubleList(list) => list.iter().copied().map(Scalar::Number).collect(),
+        FieldValue::ReferenceList(list) => list
+            .iter()
+            .map(|r| Scalar::Text(r.record_name.clone()))
+            .collect(),
+        FieldValue::Asset(_)
+        | FieldValue::AssetList(_)
+        | FieldValue::Location(_)
+        | FieldValue::LocationList(_) => Vec::new(),
+    }
+}
+
+/// Field values as seen by filters and sorts, including the `___recordID`,
+/// `___createTime`, `___modTime` and `___createdBy` system fields.
+fn record_values(record: &Record, field_name: &str) -> Option<Vec<Scalar>> {
+    match field_name {
+        "___recordID" => record
+            .record_name
+            .clone()
+            .map(|name| vec![Scalar::Text(name)]),
+        "___createTime" => record
+            .created
+            .as_ref()
+            .map(|t| vec![Scalar::Number(t.timestamp as f64)]),
+        "___modTime" => record
+            .modified
+            .as_ref()
+            .map(|t| vec![Scalar::Number(t.timestamp as f64)]),
+        "___createdBy" => record
+            .created
+            .as_ref()
+            .and_then(|t| t.user_record_name.clone())
+            .map(|name| vec![Scalar::Text(name)]),
+        _ => record.fields.get(field_name).map(scalars),
+    }
+}
+
+fn location<'a>(record: &'a Record, field_name: &str) -> Option<&'a LocationValue> {
+    match record.fields.get(field_name) {
+        Some(FieldValue::Location(location)) => Some(location),
+        _ => None,
+    }
+}
+
+/// Great-circle distance in meters.
+fn distance(a: &LocationValue, b: &LocationValue) -> f64 {
+    const EARTH_RADIUS: f64 = 6_371_000.0;
+    let (lat1, lat2) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.longitude - a.longitude).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+fn tokens(values: &[Scalar]) -> Vec<String> {
+    values
+        .iter()
+        .filter_map(|v| match v {
+            Scalar::Text(s) => Some(s),
+            Scalar::Number(_) => None,
+        })
+        .flat_map(|s| s.split(|c: char| !c.is_alphanumeric()))
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+fn ordered(values: &[Scalar], target: &[Scalar], accept: fn(Ordering) -> bool) -> bool {
+    match (values, target) {
+        ([value], [target]) => value.compare(target).is_some_and(accept),
+        _ => false,
+    }
+}
+
+/// Evaluates one filter. Records missing the field never match.
+pub(super) fn matches(record: &Record, filter: &Filter) -> Result<bool, String> {
+    if let Comparator::Near = filter.comparator {
+        let (FieldValue::Location(center), Some(radius)) = (&filter.field_value, filter.distance)
+        else {
+            return Err("NEAR filters need a location and a distance".to_string());
+        };
+        return Ok(
+            location(record, &filter.field_name).is_some_and(|l| distance(l, center) <= radius)
+        );
+    }
+
+    let Some(values) = record_values(record, &filter.field_name) else {
+        return Ok(false);
+    };
+    let target = scalars(&filter.field_value);
+
+    let matched = match filter.comparator {
+        Comparator::Equals => values == target,
+        Comparator::NotEquals => values != target,
+        Comparator::GreaterThan => ordered(&values, &target, Ordering::is_gt),
+        Comparator::GreaterThanOrEquals => ordered(&values, &target, Ordering::is_ge),
+        Comparator::LessThan => ordered(&values, &target, Ordering::is_lt),
+        Comparator::LessThanOrEquals => ordered(&values, &target, Ordering::is_le),
+        Comparator::In => values.len() == 1 && target.contains(&values[0]),
+        Comparator::NotIn => values.len() == 1 && !target.contains(&values[0]),
+        Comparator::BeginsWith => match (values.as_slice(), target.as_slice()) {
+            ([Scalar::Text(value)], [Scalar::Text(prefix)]) => value.starts_with(prefix.as_str()),
+            _ => false,
+        },
+        Comparator::ContainsAllTokens => {
+            let have = tokens(&values);
+            tokens(&target).iter().all(|t| have.contains(t))
+        }
+        Comparator::ContainsAnyTokens => {
+            let have = tokens(&values);
+            tokens(&target).iter().any(|t| have.contains(t))
+        }
+        Comparator::ListContains => target.len() == 1 && values.contains(&target[0]),
+        Comparator::ListNotContains => target.len() == 1 && !values.contains(&target[0]),
+        Comparator::ListContainsAll => target.iter().all(|t| values.contains(t)),
+        Comparator::ListContainsAny => target.iter().any(|t| values.contains(t)),
+        Comparator::Near => unreachable!("handled above"),
+    };
+    Ok(matched)
+}
+
+/// Orders records by the sort descriptors. Records missing a sort field go
+/// last.
+pub(super) fn compare(a: &Record, b: &Record, sorts: &[SortDescriptor]) -> Ordering {
+    for sort in sorts {
+        let value = |record: &Record| match &sort.relative_location {
+            Some(origin) => {
+                location(record, &sort.field_name).map(|l| Scalar::Number(distance(l, origin)))
+            }
+            None => record_values(record, &sort.field_name).and_then(|v| v.into_iter().next()),
+        };
+        let (left, right) = (value(a), value(b));
+        let ordering = match (left, right) {
+            (Some(l), Some(r)) => {
+                let ordering = l.compare(&r).unwrap_or(Ordering::Equal);
+                if sort.ascending.unwrap_or(true) {
+                    ordering
+                } else {
+                    ordering.reverse()
+                }
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
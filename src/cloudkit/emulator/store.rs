@@ -0,0 +1,934 @@
+use super::query;
+use super::{RecordOperationBody, SubscriptionOperationBody, ZoneOperationBody};
+use crate::cloudkit::query::Query;
+use crate::cloudkit::types::*;
+use crate::error::CloudKitErrorCode;
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_ZONE: &str = "_defaultZone";
+const EMULATOR_USER: &str = "_emulator";
+const SYNC_TOKEN_PREFIX: &str = "sync:";
+const CONTINUATION_PREFIX: &str = "offset:";
+
+/// A request-level failure, sent as the whole response body.
+#[derive(Debug)]
+pub(super) struct ApiError {
+    pub(super) code: CloudKitErrorCode,
+    pub(super) reason: String,
+}
+
+impl ApiError {
+    pub(super) fn new(code: CloudKitErrorCode, reason: impl Into<String>) -> Self {
+        ApiError {
+            code,
+            reason: reason.into(),
+        }
+    }
+
+    pub(super) fn status(&self) -> u16 {
+        match self.code {
+            CloudKitErrorCode::BadRequest => 400,
+            CloudKitErrorCode::AuthenticationFailed => 401,
+            CloudKitErrorCode::AccessDenied => 403,
+            CloudKitErrorCode::NotFound | CloudKitErrorCode::ZoneNotFound => 404,
+            CloudKitErrorCode::Conflict | CloudKitErrorCode::Exists => 409,
+            CloudKitErrorCode::QuotaExceeded => 413,
+            CloudKitErrorCode::AuthenticationRequired => 421,
+            CloudKitErrorCode::Throttled => 429,
+            CloudKitErrorCode::TryAgainLater => 503,
+            CloudKitErrorCode::InternalError | CloudKitErrorCode::Unknown(_) => 500,
+        }
+    }
+
+    pub(super) fn to_json(&self) -> Value {
+        json!({
+            "serverErrorCode": self.code.to_string(),
+            "reason": self.reason,
+        })
+    }
+}
+
+/// A per-item failure, reported in place of the record, zone or subscription.
+struct ItemError(CloudKitErrorCode, String);
+
+impl ItemError {
+    fn new(code: CloudKitErrorCode, reason: &str) -> Self {
+        ItemError(code, reason.to_string())
+    }
+}
+
+fn encode_marker(prefix: &str, n: u64) -> String {
+    URL_SAFE_NO_PAD.encode(format!("{}{}", prefix, n))
+}
+
+fn decode_marker(prefix: &str, marker: &str) -> Option<u64> {
+    let decoded = URL_SAFE_NO_PAD.decode(marker).ok()?;
+    String::from_utf8(decoded)
+        .ok()?
+        .strip_prefix(prefix)?
+        .parse()
+        .ok()
+}
+
+fn decode_sync_token(token: Option<&str>) -> Result<u64, ApiError> {
+    match token {
+        None => Ok(0),
+        Some(token) => decode_marker(SYNC_TOKEN_PREFIX, token)
+            .ok_or_else(|| ApiError::new(CloudKitErrorCode::BadRequest, "Invalid sync token")),
+    }
+}
+
+fn page_limit(results_limit: Option<u32>, page_size: u32) -> usize {
+    results_limit.map_or(page_size, |l| l.min(page_size)).max(1) as usize
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn zone_json(zone_name: &str) -> Value {
+    json!({ "zoneName": zone_name })
+}
+
+fn record_json(record: &Record, desired_keys: Option<&[String]>) -> Value {
+    let mut record = record.clone();
+    if let Some(keys) = desired_keys {
+        record.fields.retain(|name, _| keys.contains(name));
+    }
+    serde_json::to_value(&record).unwrap_or(Value::Null)
+}
+
+fn record_error(record_name: Option<&str>, error: ItemError) -> Value {
+    json!({
+        "recordName": record_name,
+        "serverErrorCode": error.0.to_string(),
+        "reason": error.1,
+    })
+}
+
+#[derive(Clone)]
+struct StoredRecord {
+    record: Record,
+    change: u64,
+    deleted: bool,
+}
+
+impl StoredRecord {
+    fn to_json(&self, desired_keys: Option<&[String]>) -> Value {
+        if self.deleted {
+            json!({
+                "recordName": self.record.record_name,
+                "recordType": self.record.record_type,
+                "zoneID": self.record.zone_id,
+                "deleted": true,
+            })
+        } else {
+            record_json(&self.record, desired_keys)
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct ZoneState {
+    atomic: Option<bool>,
+    records: BTreeMap<String, StoredRecord>,
+    last_change: u64,
+}
+
+impl ZoneState {
+    fn live(&self, record_name: &str) -> Option<&StoredRecord> {
+        self.records.get(record_name).filter(|r| !r.deleted)
+    }
+}
+
+/// One container/environment/database triple.
+pub(super) struct Database {
+    public: bool,
+    zones: BTreeMap<String, ZoneState>,
+    deleted_zones: BTreeMap<String, u64>,
+    subscriptions: BTreeMap<String, Subscription>,
+    counter: u64,
+    generated_names: u64,
+}
+
+impl Database {
+    fn new(public: bool) -> Self {
+        let mut zones = BTreeMap::new();
+        zones.insert(DEFAULT_ZONE.to_string(), ZoneState::default());
+        Database {
+            public,
+            zones,
+            deleted_zones: BTreeMap::new(),
+            subscriptions: BTreeMap::new(),
+            counter: 0,
+            generated_names: 0,
+        }
+    }
+
+    fn zone(&self, zone_id: &ZoneID) -> Result<&ZoneState, ApiError> {
+        self.zones.get(&zone_id.zone_name).ok_or_else(|| {
+            ApiError::new(
+                CloudKitErrorCode::ZoneNotFound,
+                format!("Zone {} does not exist", zone_id.zone_name),
+            )
+        })
+    }
+
+    fn zone_state_json(&self, zone_name: &str, zone: &ZoneState) -> Value {
+        let zone = Zone {
+            zone_id: ZoneID::new(zone_name),
+            sync_token: Some(encode_marker(SYNC_TOKEN_PREFIX, zone.last_change)),
+            atomic: zone.atomic,
+        };
+        serde_json::to_value(&zone).unwrap_or(Value::Null)
+    }
+
+    pub(super) fn list_zones(&self) -> Vec<Value> {
+        self.zones
+            .iter()
+            .map(|(name, zone)| self.zone_state_json(name, zone))
+            .collect()
+    }
+
+    pub(super) fn modify_zones(&mut self, operations: Vec<ZoneOperationBody>) -> Vec<Value> {
+        operations
+            .into_iter()
+            .map(|op| {
+                let name = op.zone.zone_id.zone_name;
+                match self.modify_zone(&op.operation_type, &name, op.zone.atomic) {
+                    Ok(value) => value,
+                    Err(err) => json!({
+                        "zoneID": zone_json(&name),
+                        "serverErrorCode": err.0.to_string(),
+                        "reason": err.1,
+                    }),
+                }
+            })
+            .collect()
+    }
+
+    fn modify_zone(
+        &mut self,
+        operation_type: &str,
+        name: &str,
+        atomic: Option<bool>,
+    ) -> Result<Value, ItemError> {
+        match operation_type {
+            "create" => {
+                if self.public && name != DEFAULT_ZONE {
+                    return Err(ItemError::new(
+                        CloudKitErrorCode::BadRequest,
+                        "The public database only supports the default zone",
+                    ));
+                }
+                if !self.zones.contains_key(name) {
+                    self.counter += 1;
+                    self.zones.insert(
+                        name.to_string(),
+                        ZoneState {
+                            atomic,
+                            records: BTreeMap::new(),
+                            last_change: self.counter,
+                        },
+                    );
+                    self.deleted_zones.remove(name);
+                }
+                Ok(self.zone_state_json(name, &self.zones[name]))
+            }
+            "delete" => {
+                if name == DEFAULT_ZONE {
+                    return Err(ItemError::new(
+                        CloudKitErrorCode::BadRequest,
+                        "The default zone cannot be deleted",
+                    ));
+                }
+                if self.zones.remove(name).is_none() {
+                    return Err(ItemError::new(
+                        CloudKitErrorCode::ZoneNotFound,
+                        "Zone does not exist",
+                    ));
+                }
+                self.counter += 1;
+                self.deleted_zones.insert(name.to_string(), self.counter);
+                Ok(json!({ "zoneID": zone_json(name), "deleted": true }))
+            }
+            other => Err(ItemError(
+                CloudKitErrorCode::BadRequest,
+                format!("Unsupported zone operation: {}", other),
+            )),
+        }
+    }
+
+    /// Applies a batch against a copy of the zone and only keeps it when the
+    /// batch is not atomic or every operation succeeded.
+    pub(super) fn modify_records(
+        &mut self,
+        zone_id: &ZoneID,
+        operations: Vec<RecordOperationBody>,
+        atomic: bool,
+    ) -> Vec<Value> {
+        let Some(zone) = self.zones.get(&zone_id.zone_name) else {
+            return operations
+                .iter()
+                .map(|op| {
+                    record_error(
+                        op.record.record_name.as_deref(),
+                        ItemError::new(CloudKitErrorCode::ZoneNotFound, "Zone does not exist"),
+                    )
+                })
+                .collect();
+        };
+
+        let mut scratch = zone.clone();
+        let mut counter = self.counter;
+        let mut generated_names = self.generated_names;
+        let now = now_millis();
+
+        let mut results = Vec::with_capacity(operations.len());
+        for op in operations {
+            let name = match &op.record.record_name {
+                Some(name) => name.clone(),
+                None => {
+                    generated_names += 1;
+                    format!("emulator-record-{}", generated_names)
+                }
+            };
+            let result =
+                apply_record_operation(&mut scratch, zone_id, &name, op, &mut counter, now)
+                    .map_err(|err| record_error(Some(&name), err));
+            results.push(result);
+        }
+
+        let failed = results.iter().any(Result::is_err);
+        if atomic && failed {
+            return results
+                .into_iter()
+                .map(|result| match result {
+                    Ok(value) => record_error(
+                        value["recordName"].as_str(),
+                        ItemError::new(
+                            CloudKitErrorCode::Unknown("ATOMIC_ERROR".to_string()),
+                            "Another operation in the atomic batch failed",
+                        ),
+                    ),
+                    Err(err) => err,
+                })
+                .collect();
+        }
+
+        if counter != self.counter {
+            scratch.last_change = counter;
+        }
+        self.zones.insert(zone_id.zone_name.clone(), scratch);
+        self.counter = counter;
+        self.generated_names = generated_names;
+
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|err| err))
+            .collect()
+    }
+
+    pub(super) fn lookup_records(
+        &self,
+        zone_id: &ZoneID,
+        record_names: &[String],
+        desired_keys: Option<&[String]>,
+    ) -> Vec<Value> {
+        record_names
+            .iter()
+            .map(|name| {
+                let Some(zone) = self.zones.get(&zone_id.zone_name) else {
+                    return record_error(
+                        Some(name),
+                        ItemError::new(CloudKitErrorCode::ZoneNotFound, "Zone does not exist"),
+                    );
+                };
+                match zone.live(name) {
+                    Some(stored) => stored.to_json(desired_keys),
+                    None => record_error(
+                        Some(name),
+                        ItemError::new(CloudKitErrorCode::NotFound, "Record not found"),
+                    ),
+                }
+            })
+            .collect()
+    }
+
+    /// Queries one zone, or every zone when `zone_id` is `None`.
+    pub(super) fn query_records(
+        &self,
+        zone_id: Option<&ZoneID>,
+        query: &Query,
+        results_limit: Option<u32>,
+        continuation_marker: Option<&str>,
+        desired_keys: Option<&[String]>,
+        page_size: u32,
+    ) -> Result<Value, ApiError> {
+        let zones: Vec<&ZoneState> = match zone_id {
+            Some(zone_id) => vec![self.zone(zone_id)?],
+            None => self.zones.values().collect(),
+        };
+        let offset = match continuation_marker {
+            None => 0,
+            Some(marker) => decode_marker(CONTINUATION_PREFIX, marker).ok_or_else(|| {
+                ApiError::new(CloudKitErrorCode::BadRequest, "Invalid continuation marker")
+            })? as usize,
+        };
+
+        let filters = query.filter_by.as_deref().unwrap_or_default();
+        let mut matched = Vec::new();
+        for stored in zones.iter().flat_map(|zone| zone.records.values()) {
+            if stored.deleted || stored.record.record_type != query.record_type {
+                continue;
+            }
+            let mut keep = true;
+            for filter in filters {
+                if !query::matches(&stored.record, filter)
+                    .map_err(|reason| ApiError::new(CloudKitErrorCode::BadRequest, reason))?
+                {
+                    keep = false;
+                    break;
+                }
+            }
+            if keep {
+                matched.push(&stored.record);
+            }
+        }
+        if let Some(sorts) = &query.sort_by {
+            matched.sort_by(|a, b| query::compare(a, b, sorts));
+        }
+
+        let limit = page_limit(results_limit, page_size);
+        let end = (offset + limit).min(matched.len());
+        let records: Vec<Value> = matched
+            .get(offset..end)
+            .unwrap_or_default()
+            .iter()
+            .map(|record| record_json(record, desired_keys))
+            .collect();
+        let continuation_marker =
+            (end < matched.len()).then(|| encode_marker(CONTINUATION_PREFIX, end as u64));
+
+        Ok(json!({
+            "records": records,
+            "continuationMarker": continuation_marker,
+        }))
+    }
+
+    pub(super) fn zone_changes(
+        &self,
+        zone_id: &ZoneID,
+        sync_token: Option<&str>,
+        results_limit: Option<u32>,
+        page_size: u32,
+    ) -> Result<Value, ApiError> {
+        self.reject_public("changes/zone")?;
+        let zone = self.zone(zone_id)?;
+        let since = decode_sync_token(sync_token)?;
+
+        let mut changed: Vec<&StoredRecord> =
+            zone.records.values().filter(|r| r.change > since).collect();
+        changed.sort_by_key(|r| r.change);
+
+        let limit = page_limit(results_limit, page_size);
+        let more_coming = changed.len() > limit;
+        changed.truncate(limit);
+        let token = match (more_coming, changed.last()) {
+            (true, Some(last)) => last.change,
+            _ => zone.last_change.max(since),
+        };
+
+        Ok(json!({
+            "zoneID": zone_json(&zone_id.zone_name),
+            "records": changed.iter().map(|r| r.to_json(None)).collect::<Vec<_>>(),
+            "syncToken": encode_marker(SYNC_TOKEN_PREFIX, token),
+            "moreComing": more_coming,
+        }))
+    }
+
+    pub(super) fn lookup_zones(&self, zone_ids: &[ZoneID]) -> Vec<Value> {
+        zone_ids
+            .iter()
+            .map(|zone_id| match self.zones.get(&zone_id.zone_name) {
+                Some(zone) => self.zone_state_json(&zone_id.zone_name, zone),
+                None => json!({
+                    "zoneID": zone_json(&zone_id.zone_name),
+                    "serverErrorCode": CloudKitErrorCode::ZoneNotFound.to_string(),
+                    "reason": "Zone does not exist",
+                }),
+            })
+            .collect()
+    }
+
+    /// Zones changed since the token, with their current sync tokens.
+    pub(super) fn zones_changes(
+        &self,
+        sync_token: Option<&str>,
+        page_size: u32,
+    ) -> Result<Value, ApiError> {
+        self.reject_public("zones/changes")?;
+        let since = decode_sync_token(sync_token)?;
+        let (changed, token, more_coming) = self.changed_zones(since, None, page_size);
+
+        let zones: Vec<Value> = changed
+            .iter()
+            .map(|(_, name, deleted)| {
+                if *deleted {
+                    json!({ "zoneID": zone_json(name), "deleted": true })
+                } else {
+                    self.zone_state_json(name, &self.zones[*name])
+                }
+            })
+            .collect();
+
+        Ok(json!({
+            "zones": zones,
+            "syncToken": encode_marker(SYNC_TOKEN_PREFIX, token),
+            "moreComing": more_coming,
+        }))
+    }
+
+    /// Live and deleted zones whose last change is after `since`, oldest
+    /// first, plus the token to resume from and whether more remain.
+    fn changed_zones(
+        &self,
+        since: u64,
+        results_limit: Option<u32>,
+        page_size: u32,
+    ) -> (Vec<(u64, &str, bool)>, u64, bool) {
+        let mut changed: Vec<(u64, &str, bool)> = self
+            .zones
+            .iter()
+            .map(|(name, zone)| (zone.last_change, name.as_str(), false))
+            .chain(
+                self.deleted_zones
+                    .iter()
+                    .map(|(name, change)| (*change, name.as_str(), true)),
+            )
+            .filter(|(change, _, _)| *change > since)
+            .collect();
+        changed.sort();
+
+        let limit = page_limit(results_limit, page_size);
+        let more_coming = changed.len() > limit;
+        changed.truncate(limit);
+        let token = match (more_coming, changed.last()) {
+            (true, Some((change, _, _))) => *change,
+            _ => self.counter.max(since),
+        };
+        (changed, token, more_coming)
+    }
+
+    pub(super) fn database_changes(
+        &self,
+        sync_token: Option<&str>,
+        results_limit: Option<u32>,
+        page_size: u32,
+    ) -> Result<Value, ApiError> {
+        self.reject_public("changes/database")?;
+        let since = decode_sync_token(sync_token)?;
+        let (changed, token, more_coming) = self.changed_zones(since, results_limit, page_size);
+
+        let zones: Vec<Value> = changed
+            .iter()
+            .map(|(_, name, deleted)| {
+                if *deleted {
+                    json!({ "zoneID": zone_json(name), "deleted": true })
+                } else {
+                    json!({ "zoneID": zone_json(name) })
+                }
+            })
+            .collect();
+
+        Ok(json!({
+            "zones": zones,
+            "syncToken": encode_marker(SYNC_TOKEN_PREFIX, token),
+            "moreComing": more_coming,
+        }))
+    }
+
+    fn reject_public(&self, operation: &str) -> Result<(), ApiError> {
+        if self.public {
+            return Err(ApiError::new(
+                CloudKitErrorCode::BadRequest,
+                format!("{} is not supported in the public database", operation),
+            ));
+        }
+        Ok(())
+    }
+
+    pub(super) fn list_subscriptions(&self) -> Vec<Value> {
+        self.subscriptions
+            .values()
+            .map(|s| serde_json::to_value(s).unwrap_or(Value::Null))
+            .collect()
+    }
+
+    pub(super) fn lookup_subscriptions(&self, subscription_ids: &[String]) -> Vec<Value> {
+        subscription_ids
+            .iter()
+            .map(|id| match self.subscriptions.get(id) {
+                Some(s) => serde_json::to_value(s).unwrap_or(Value::Null),
+                None => json!({
+                    "subscriptionID": id,
+                    "serverErrorCode": CloudKitErrorCode::NotFound.to_string(),
+                    "reason": "Subscription not found",
+                }),
+            })
+            .collect()
+    }
+
+    pub(super) fn modify_subscriptions(
+        &mut self,
+        operations: Vec<SubscriptionOperationBody>,
+    ) -> Vec<Value> {
+        operations
+            .into_iter()
+            .map(|op| {
+                let subscription_type = op.subscription.subscription_type.clone();
+                let subscription_id = op.subscription.subscription_id.clone();
+                match self.modify_subscription(&op.operation_type, op.subscription) {
+                    Ok(value) => value,
+                    Err(err) => json!({
+                        "subscriptionID": subscription_id,
+                        "subscriptionType": subscription_type,
+                        "serverErrorCode": err.0.to_string(),
+                        "reason": err.1,
+                    }),
+                }
+            })
+            .collect()
+    }
+
+    fn modify_subscription(
+        &mut self,
+        operation_type: &str,
+        mut subscription: Subscription,
+    ) -> Result<Value, ItemError> {
+        match operation_type {
+            "create" | "update" => {
+                if let Some(zone_id) = &subscription.zone_id
+                    && !self.zones.contains_key(&zone_id.zone_name)
+                {
+                    return Err(ItemError::new(
+                        CloudKitErrorCode::ZoneNotFound,
+                        "Zone does not exist",
+                    ));
+                }
+                let id = match subscription.subscription_id.clone() {
+                    Some(id) => id,
+                    None if operation_type == "create" => {
+                        self.generated_names += 1;
+                        format!("emulator-subscription-{}", self.generated_names)
+                    }
+                    None => {
+                        return Err(ItemError::new(
+                            CloudKitErrorCode::BadRequest,
+                            "subscriptionID is required for update",
+                        ));
+                    }
+                };
+                if operation_type == "update" && !self.subscriptions.contains_key(&id) {
+                    return Err(ItemError::new(
+                        CloudKitErrorCode::NotFound,
+                        "Subscription not found",
+                    ));
+                }
+                subscription.subscription_id = Some(id.clone());
+                let value = serde_json::to_value(&subscription).unwrap_or(Value::Null);
+                self.subscriptions.insert(id, subscription);
+                Ok(value)
+            }
+            "delete" => {
+                let id = subscription.subscription_id.clone().unwrap_or_default();
+                let removed = self.subscriptions.remove(&id).ok_or_else(|| {
+                    ItemError::new(CloudKitErrorCode::NotFound, "Subscription not found")
+                })?;
+                Ok(serde_json::to_value(&removed).unwrap_or(Value::Null))
+            }
+            other => Err(ItemError(
+                CloudKitErrorCode::BadRequest,
+                format!("Unsupported subscription operation: {}", other),
+            )),
+        }
+    }
+}
+
+fn apply_record_operation(
+    zone: &mut ZoneState,
+    zone_id: &ZoneID,
+    record_name: &str,
+    op: RecordOperationBody,
+    counter: &mut u64,
+    now: i64,
+) -> Result<Value, ItemError> {
+    let payload = op.record;
+    let live = zone.live(record_name).cloned();
+    let check_tag = |stored: &StoredRecord| match payload.record_change_tag.as_deref() {
+        None => Err(ItemError::new(
+            CloudKitErrorCode::BadRequest,
+            "recordChangeTag is required",
+        )),
+        Some(tag) if Some(tag) != stored.record.record_change_tag.as_deref() => {
+            Err(ItemError::new(
+                CloudKitErrorCode::Conflict,
+                "Record has been modified since it was fetched",
+            ))
+        }
+        Some(_) => Ok(()),
+    };
+    let not_found = || ItemError::new(CloudKitErrorCode::NotFound, "Record not found");
+
+    let (base, merge) = match op.operation_type {
+        OperationType::Create => {
+            if live.is_some() {
+                return Err(ItemError::new(
+                    CloudKitErrorCode::Exists,
+                    "Record already exists",
+                ));
+            }
+            (None, false)
+        }
+        OperationType::Update | OperationType::Replace => {
+            let stored = live.ok_or_else(not_found)?;
+            check_tag(&stored)?;
+            let merge = matches!(op.operation_type, OperationType::Update);
+            (Some(stored), merge)
+        }
+        OperationType::ForceUpdate => (Some(live.ok_or_else(not_found)?), true),
+        OperationType::ForceReplace => (live, false),
+        OperationType::Delete | OperationType::ForceDelete => {
+            let stored = live.ok_or_else(not_found)?;
+            if matches!(op.operation_type, OperationType::Delete)
+                && payload.record_change_tag.is_some()
+            {
+                check_tag(&stored)?;
+            }
+            *counter += 1;
+            let tombstone = StoredRecord {
+                record: Record {
+                    fields: HashMap::new(),
+                    ..stored.record
+                },
+                change: *counter,
+                deleted: true,
+            };
+            let value = tombstone.to_json(None);
+            zone.records.insert(record_name.to_string(), tombstone);
+            return Ok(value);
+        }
+    };
+
+    let record_type = match (&base, payload.record_type) {
+        (_, Some(record_type)) => record_type,
+        (Some(stored), None) => stored.record.record_type.clone(),
+        (None, None) => {
+            return Err(ItemError::new(
+                CloudKitErrorCode::BadRequest,
+                "recordType is required",
+            ));
+        }
+    };
+    let mut fields = match (&base, merge) {
+        (Some(stored), true) => stored.record.fields.clone(),
+        _ => HashMap::new(),
+    };
+    fields.extend(payload.fields.unwrap_or_default());
+    let share = match (&base, merge) {
+        (Some(stored), true) => payload.share.or_else(|| stored.record.share.clone()),
+        _ => payload.share,
+    };
+
+    *counter += 1;
+    let timestamp = RecordTimestamp {
+        timestamp: now,
+        user_record_name: Some(EMULATOR_USER.to_string()),
+        device_id: None,
+    };
+    let record = Record {
+        record_name: Some(record_name.to_string()),
+        record_type,
+        record_change_tag: Some(format!("{:x}", *counter)),
+        fields,
+        zone_id: Some(ZoneID::new(&zone_id.zone_name)),
+        created: base
+            .and_then(|stored| stored.record.created)
+            .or_else(|| Some(timestamp.clone())),
+        modified: Some(timestamp),
+        share,
+    };
+    let value = record_json(&record, None);
+    zone.records.insert(
+        record_name.to_string(),
+        StoredRecord {
+            record,
+            change: *counter,
+            deleted: false,
+        },
+    );
+    Ok(value)
+}
+
+/// Everything the emulator serves, shared between the server thread and the
+/// `CloudKitEmulator` handle.
+pub(super) struct EmulatorState {
+    base_url: String,
+    pub(super) page_size: u32,
+    databases: HashMap<String, Database>,
+    assets: HashMap<String, Vec<u8>>,
+    /// Checksum of the file each upload receipt was issued for.
+    receipts: HashMap<String, String>,
+    uploads: u64,
+    /// Pending WebCourier notifications per token.
+    couriers: HashMap<String, VecDeque<Value>>,
+    tokens: u64,
+}
+
+impl EmulatorState {
+    pub(super) fn new(base_url: &str, page_size: u32) -> Self {
+        EmulatorState {
+            base_url: base_url.to_string(),
+            page_size,
+            databases: HashMap::new(),
+            assets: HashMap::new(),
+            receipts: HashMap::new(),
+            uploads: 0,
+            couriers: HashMap::new(),
+            tokens: 0,
+        }
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.databases.clear();
+        self.assets.clear();
+        self.receipts.clear();
+        self.uploads = 0;
+        self.couriers.clear();
+    }
+
+    pub(super) fn create_token(&mut self) -> Value {
+        self.tokens += 1;
+        let token = format!("emulator-token-{}", self.tokens);
+        self.couriers.insert(token.clone(), VecDeque::new());
+        json!({
+            "apnsToken": token,
+            "webcourierURL": format!("{}/webcourier/{}", self.base_url, token),
+        })
+    }
+
+    pub(super) fn register_token(&self, token: &str) -> Result<Value, ApiError> {
+        if !self.couriers.contains_key(token) {
+            return Err(ApiError::new(
+                CloudKitErrorCode::BadRequest,
+                "Unknown APNs token",
+            ));
+        }
+        Ok(json!({}))
+    }
+
+    pub(super) fn notify(&mut self, payload: &Value) {
+        for queue in self.couriers.values_mut() {
+            queue.push_back(payload.clone());
+        }
+    }
+
+    pub(super) fn expire_tokens(&mut self) {
+        self.couriers.clear();
+    }
+
+    /// Drains the token's queue, or `None` once the token has expired.
+    pub(super) fn take_notifications(&mut self, token: &str) -> Option<Vec<Value>> {
+        self.couriers
+            .get_mut(token)
+            .map(|queue| queue.drain(..).collect())
+    }
+
+    pub(super) fn database(&mut self, key: String, db: &DatabaseType) -> &mut Database {
+        self.databases
+            .entry(key)
+            .or_insert_with(|| Database::new(*db == DatabaseType::Public))
+    }
+
+    pub(super) fn upload_url(&mut self) -> String {
+        self.uploads += 1;
+        format!("{}/assets/upload/{}", self.base_url, self.uploads)
+    }
+
+    pub(super) fn store_asset(&mut self, upload_id: &str, data: Vec<u8>) -> Value {
+        let checksum = STANDARD.encode(Sha256::digest(&data));
+        let size = data.len();
+        let receipt = format!("emulator-receipt-{}", upload_id);
+        self.assets.insert(checksum.clone(), data);
+        self.receipts.insert(receipt.clone(), checksum.clone());
+        json!({
+            "fileChecksum": checksum,
+            "size": size,
+            "receipt": receipt,
+        })
+    }
+
+    /// Looks up an asset by the URL-safe form of its checksum used in
+    /// download URLs.
+    pub(super) fn asset(&self, download_key: &str) -> Option<&[u8]> {
+        let digest = URL_SAFE_NO_PAD.decode(download_key).ok()?;
+        self.assets
+            .get(&STANDARD.encode(digest))
+            .map(|data| data.as_slice())
+    }
+
+    /// Checks that every asset field carries the receipt of an upload with
+    /// the same checksum, then fills in size and download URL in place of
+    /// the receipt.
+    pub(super) fn resolve_assets(
+        &self,
+        fields: &mut HashMap<String, FieldValue>,
+    ) -> Result<(), ApiError> {
+        for (field_name, value) in fields.iter_mut() {
+            let assets = match value {
+                FieldValue::Asset(asset) => vec![asset],
+                FieldValue::AssetList(assets) => assets.iter_mut().collect(),
+                _ => continue,
+            };
+            for asset in assets {
+                let checksum = asset
+                    .receipt
+                    .take()
+                    .and_then(|receipt| self.receipts.get(&receipt))
+                    .filter(|checksum| {
+                        asset.file_checksum.is_none()
+                            || asset.file_checksum.as_ref() == Some(checksum)
+                    })
+                    .ok_or_else(|| {
+                        ApiError::new(
+                            CloudKitErrorCode::BadRequest,
+                            format!("Asset field {} needs a valid upload receipt", field_name),
+                        )
+                    })?;
+                let (Some(data), Ok(digest)) =
+                    (self.assets.get(checksum), STANDARD.decode(checksum))
+                else {
+                    continue;
+                };
+                asset.file_checksum = Some(checksum.clone());
+                asset.size = Some(data.len() as u64);
+                asset.download_url = Some(format!(
+                    "{}/assets/download/{}/${{f}}",
+                    self.base_url,
+                    URL_SAFE_NO_PAD.encode(digest)
+                ));
+                asset.wrapping_key = None;
+                asset.reference_checksum = None;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
-use crate::error::{AppleError, CloudKitErrorCode, CloudKitErrorResponse};
+use crate::error::{
+    AppleError, CloudKitAuthenticationRequired, CloudKitErrorCode, CloudKitErrorResponse,
+};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -9,14 +11,26 @@ pub(crate) struct RawCloudKitErrorWrapper {
     pub uuid: Option<String>,
     #[serde(rename = "retryAfter")]
     pub retry_after: Option<u64>,
+    #[serde(rename = "redirectURL")]
+    pub redirect_url: Option<String>,
 }
 
 pub(crate) fn parse_cloudkit_error(body: &str) -> AppleError {
     if let Ok(wrapper) = serde_json::from_str::<RawCloudKitErrorWrapper>(body)
         && let Some(code) = wrapper.server_error_code
     {
+        let server_error_code = CloudKitErrorCode::parse(&code);
+        if server_error_code == CloudKitErrorCode::AuthenticationRequired
+            && let Some(redirect_url) = wrapper.redirect_url
+        {
+            return AppleError::CloudKitAuthenticationRequired(CloudKitAuthenticationRequired {
+                redirect_url,
+                reason: wrapper.reason.unwrap_or_default(),
+                uuid: wrapper.uuid,
+            });
+        }
         return AppleError::CloudKitError(CloudKitErrorResponse {
-            server_error_code: CloudKitErrorCode::parse(&code),
+            server_error_code,
             reason: wrapper.reason.unwrap_or_default(),
             uuid: wrapper.uuid,
             retry_after: wrapper.retry_after,
@@ -24,3 +38,17 @@ pub(crate) fn parse_cloudkit_error(body: &str) -> AppleError {
     }
     AppleError::JsonError(format!("Failed to parse CloudKit error response: {}", body))
 }
+
+/// The error a `serverErrorCode` on one item of a batch response stands
+/// for, or `None` when the item succeeded.
+pub(crate) fn item_error(
+    server_error_code: Option<&String>,
+    reason: Option<&String>,
+) -> Option<AppleError> {
+    Some(AppleError::CloudKitError(CloudKitErrorResponse {
+        server_error_code: CloudKitErrorCode::parse(server_error_code?),
+        reason: reason.cloned().unwrap_or_default(),
+        uuid: None,
+        retry_after: None,
+    }))
+}
@@ -0,0 +1,451 @@
+use crate::cloudkit::records::RecordResult;
+use crate::cloudkit::types::{AssetValue, FieldValue, LocationValue, Record, ReferenceValue};
+use crate::error::AppleError;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A field held a different CloudKit type than the one asked for, or was
+/// missing. `field` is set when the value was read through a record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldTypeError {
+    pub field: Option<String>,
+    pub expected: &'static str,
+    pub found: Option<&'static str>,
+}
+
+impl fmt::Display for FieldTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.field, self.found) {
+            (Some(field), Some(found)) => write!(
+                f,
+                "Field `{}` is {}, expected {}",
+                field, found, self.expected
+            ),
+            (Some(field), None) => {
+                write!(
+                    f,
+                    "Field `{}` is missing, expected {}",
+                    field, self.expected
+                )
+            }
+            (None, Some(found)) => write!(f, "Expected {}, found {}", self.expected, found),
+            (None, None) => write!(f, "Expected {}", self.expected),
+        }
+    }
+}
+
+impl FieldValue {
+    /// The CloudKit type tag, e.g. `STRING` or `ASSET_LIST`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            FieldValue::String(_) => "STRING",
+            FieldValue::Int64(_) => "INT64",
+            FieldValue::Double(_) => "DOUBLE",
+            FieldValue::Timestamp(_) => "TIMESTAMP",
+            FieldValue::Reference(_) => "REFERENCE",
+            FieldValue::Asset(_) => "ASSET",
+            FieldValue::Location(_) => "LOCATION",
+            FieldValue::Bytes(_) => "BYTES",
+            FieldValue::EncryptedBytes(_) => "ENCRYPTED_BYTES",
+            FieldValue::StringList(_) => "STRING_LIST",
+            FieldValue::Int64List(_) => "INT64_LIST",
+            FieldValue::DoubleList(_) => "DOUBLE_LIST",
+            FieldValue::TimestampList(_) => "TIMESTAMP_LIST",
+            FieldValue::ReferenceList(_) => "REFERENCE_LIST",
+            FieldValue::LocationList(_) => "LOCATION_LIST",
+            FieldValue::AssetList(_) => "ASSET_LIST",
+            FieldValue::BytesList(_) => "BYTES_LIST",
+        }
+    }
+
+    /// Bytes that CloudKit stores encrypted at rest.
+    pub fn encrypted_bytes(data: &[u8]) -> Self {
+        FieldValue::EncryptedBytes(STANDARD.encode(data))
+    }
+}
+
+/// A Rust type that can be read out of a `FieldValue`.
+pub trait FromFieldValue: Sized {
+    /// The CloudKit type named in mismatch errors.
+    const EXPECTED: &'static str;
+
+    fn from_field_value(value: &FieldValue) -> Result<Self, AppleError>;
+}
+
+fn mismatch(expected: &'static str, value: &FieldValue) -> AppleError {
+    AppleError::FieldTypeError(FieldTypeError {
+        field: None,
+        expected,
+        found: Some(value.type_name()),
+    })
+}
+
+fn timestamp(millis: i64) -> Result<DateTime<Utc>, AppleError> {
+    DateTime::from_timestamp_millis(millis)
+        .ok_or_else(|| AppleError::TimeError(format!("Timestamp {} is out of range", millis)))
+}
+
+fn decode(data: &str) -> Result<Vec<u8>, AppleError> {
+    STANDARD
+        .decode(data)
+        .map_err(|e| AppleError::Base64Error(e.to_string()))
+}
+
+macro_rules! field_conversion {
+    ($ty:ty, $expected:literal, |$value:ident| $read:expr) => {
+        impl FromFieldValue for $ty {
+            const EXPECTED: &'static str = $expected;
+
+            fn from_field_value($value: &FieldValue) -> Result<Self, AppleError> {
+                $read
+            }
+        }
+
+        impl TryFrom<&FieldValue> for $ty {
+            type Error = AppleError;
+
+            fn try_from(value: &FieldValue) -> Result<Self, AppleError> {
+                <$ty as FromFieldValue>::from_field_value(value)
+            }
+        }
+
+        impl TryFrom<FieldValue> for $ty {
+            type Error = AppleError;
+
+            fn try_from(value: FieldValue) -> Result<Self, AppleError> {
+                <$ty as FromFieldValue>::from_field_value(&value)
+            }
+        }
+    };
+}
+
+field_conversion!(String, "STRING", |value| match value {
+    FieldValue::String(s) => Ok(s.clone()),
+    other => Err(mismatch("STRING", other)),
+});
+
+field_conversion!(i64, "INT64", |value| match value {
+    FieldValue::Int64(n) => Ok(*n),
+    other => Err(mismatch("INT64", other)),
+});
+
+// CloudKit has no boolean type; Core Data and CKRecord store them as INT64.
+field_conversion!(bool, "INT64", |value| match value {
+    FieldValue::Int64(n) => Ok(*n != 0),
+    other => Err(mismatch("INT64", other)),
+});
+
+field_conversion!(f64, "DOUBLE", |value| match value {
+    FieldValue::Double(n) => Ok(*n),
+    FieldValue::Int64(n) => Ok(*n as f64),
+    other => Err(mismatch("DOUBLE", other)),
+});
+
+field_conversion!(DateTime<Utc>, "TIMESTAMP", |value| match value {
+    FieldValue::Timestamp(millis) => timestamp(*millis),
+    other => Err(mismatch("TIMESTAMP", other)),
+});
+
+field_conversion!(Vec<u8>, "BYTES", |value| match value {
+    FieldValue::Bytes(data) | FieldValue::EncryptedBytes(data) => decode(data),
+    other => Err(mismatch("BYTES", other)),
+});
+
+field_conversion!(ReferenceValue, "REFERENCE", |value| match value {
+    FieldValue::Reference(reference) => Ok(reference.clone()),
+    other => Err(mismatch("REFERENCE", other)),
+});
+
+field_conversion!(AssetValue, "ASSET", |value| match value {
+    FieldValue::Asset(asset) => Ok(asset.clone()),
+    other => Err(mismatch("ASSET", other)),
+});
+
+field_conversion!(LocationValue, "LOCATION", |value| match value {
+    FieldValue::Location(location) => Ok(location.clone()),
+    other => Err(mismatch("LOCATION", other)),
+});
+
+field_conversion!((f64, f64), "LOCATION", |value| match value {
+    FieldValue::Location(location) => Ok((location.latitude, location.longitude)),
+    other => Err(mismatch("LOCATION", other)),
+});
+
+field_conversion!(Vec<String>, "STRING_LIST", |value| match value {
+    FieldValue::StringList(items) => Ok(items.clone()),
+    other => Err(mismatch("STRING_LIST", other)),
+});
+
+field_conversion!(Vec<i64>, "INT64_LIST", |value| match value {
+    FieldValue::Int64List(items) => Ok(items.clone()),
+    other => Err(mismatch("INT64_LIST", other)),
+});
+
+field_conversion!(Vec<f64>, "DOUBLE_LIST", |value| match value {
+    FieldValue::DoubleList(items) => Ok(items.clone()),
+    other => Err(mismatch("DOUBLE_LIST", other)),
+});
+
+field_conversion!(Vec<DateTime<Utc>>, "TIMESTAMP_LIST", |value| match value {
+    FieldValue::TimestampList(items) => items.iter().map(|millis| timestamp(*millis)).collect(),
+    other => Err(mismatch("TIMESTAMP_LIST", other)),
+});
+
+field_conversion!(Vec<ReferenceValue>, "REFERENCE_LIST", |value| match value {
+    FieldValue::ReferenceList(items) => Ok(items.clone()),
+    other => Err(mismatch("REFERENCE_LIST", other)),
+});
+
+field_conversion!(Vec<LocationValue>, "LOCATION_LIST", |value| match value {
+    FieldValue::LocationList(items) => Ok(items.clone()),
+    other => Err(mismatch("LOCATION_LIST", other)),
+});
+
+field_conversion!(Vec<AssetValue>, "ASSET_LIST", |value| match value {
+    FieldValue::AssetList(items) => Ok(items.clone()),
+    other => Err(mismatch("ASSET_LIST", other)),
+});
+
+field_conversion!(Vec<Vec<u8>>, "BYTES_LIST", |value| match value {
+    FieldValue::BytesList(items) => items.iter().map(|data| decode(data)).collect(),
+    other => Err(mismatch("BYTES_LIST", other)),
+});
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        FieldValue::String(value)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::String(value.to_string())
+    }
+}
+
+impl From<i64> for FieldValue {
+    fn from(value: i64) -> Self {
+        FieldValue::Int64(value)
+    }
+}
+
+impl From<i32> for FieldValue {
+    fn from(value: i32) -> Self {
+        FieldValue::Int64(value.into())
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        FieldValue::Int64(value.into())
+    }
+}
+
+impl From<f64> for FieldValue {
+    fn from(value: f64) -> Self {
+        FieldValue::Double(value)
+    }
+}
+
+impl From<DateTime<Utc>> for FieldValue {
+    fn from(value: DateTime<Utc>) -> Self {
+        FieldValue::Timestamp(value.timestamp_millis())
+    }
+}
+
+impl From<&[u8]> for FieldValue {
+    fn from(value: &[u8]) -> Self {
+        FieldValue::Bytes(STANDARD.encode(value))
+    }
+}
+
+impl From<Vec<u8>> for FieldValue {
+    fn from(value: Vec<u8>) -> Self {
+        FieldValue::Bytes(STANDARD.encode(value))
+    }
+}
+
+impl From<ReferenceValue> for FieldValue {
+    fn from(value: ReferenceValue) -> Self {
+        FieldValue::Reference(value)
+    }
+}
+
+impl From<AssetValue> for FieldValue {
+    fn from(value: AssetValue) -> Self {
+        FieldValue::Asset(value)
+    }
+}
+
+impl From<LocationValue> for FieldValue {
+    fn from(value: LocationValue) -> Self {
+        FieldValue::Location(value)
+    }
+}
+
+/// A `(latitude, longitude)` pair.
+impl From<(f64, f64)> for FieldValue {
+    fn from((latitude, longitude): (f64, f64)) -> Self {
+        FieldValue::Location(LocationValue {
+            latitude,
+            longitude,
+            altitude: None,
+            horizontal_accuracy: None,
+            vertical_accuracy: None,
+            course: None,
+            speed: None,
+            timestamp: None,
+        })
+    }
+}
+
+impl From<Vec<String>> for FieldValue {
+    fn from(value: Vec<String>) -> Self {
+        FieldValue::StringList(value)
+    }
+}
+
+impl From<Vec<&str>> for FieldValue {
+    fn from(value: Vec<&str>) -> Self {
+        FieldValue::StringList(value.into_iter().map(str::to_string).collect())
+    }
+}
+
+impl From<Vec<i64>> for FieldValue {
+    fn from(value: Vec<i64>) -> Self {
+        FieldValue::Int64List(value)
+    }
+}
+
+impl From<Vec<f64>> for FieldValue {
+    fn from(value: Vec<f64>) -> Self {
+        FieldValue::DoubleList(value)
+    }
+}
+
+impl From<Vec<DateTime<Utc>>> for FieldValue {
+    fn from(value: Vec<DateTime<Utc>>) -> Self {
+        FieldValue::TimestampList(value.iter().map(DateTime::timestamp_millis).collect())
+    }
+}
+
+impl From<Vec<ReferenceValue>> for FieldValue {
+    fn from(value: Vec<ReferenceValue>) -> Self {
+        FieldValue::ReferenceList(value)
+    }
+}
+
+impl From<Vec<LocationValue>> for FieldValue {
+    fn from(value: Vec<LocationValue>) -> Self {
+        FieldValue::LocationList(value)
+    }
+}
+
+impl From<Vec<AssetValue>> for FieldValue {
+    fn from(value: Vec<AssetValue>) -> Self {
+        FieldValue::AssetList(value)
+    }
+}
+
+impl From<Vec<Vec<u8>>> for FieldValue {
+    fn from(value: Vec<Vec<u8>>) -> Self {
+        FieldValue::BytesList(value.iter().map(|data| STANDARD.encode(data)).collect())
+    }
+}
+
+/// Typed access to the fields of a `Record` or `RecordResult`.
+///
+/// ```ignore
+/// let title: String = record.get("title")?;
+/// let rating = record.get_optional::<i64>("rating")?.unwrap_or(0);
+/// record.set("published", chrono::Utc::now());
+/// ```
+pub trait RecordFields {
+    fn fields(&self) -> &HashMap<String, FieldValue>;
+
+    fn fields_mut(&mut self) -> &mut HashMap<String, FieldValue>;
+
+    /// Reads `name` as `T`, failing with a `FieldTypeError` if the field is
+    /// missing or holds another type.
+    fn get<T: FromFieldValue>(&self, name: &str) -> Result<T, AppleError> {
+        self.get_optional(name)?.ok_or_else(|| {
+            AppleError::FieldTypeError(FieldTypeError {
+                field: Some(name.to_string()),
+                expected: T::EXPECTED,
+                found: None,
+            })
+        })
+    }
+
+    /// Reads `name` as `T`, returning `None` if the field is not set.
+    fn get_optional<T: FromFieldValue>(&self, name: &str) -> Result<Option<T>, AppleError> {
+        let Some(value) = self.fields().get(name) else {
+            return Ok(None);
+        };
+        T::from_field_value(value)
+            .map(Some)
+            .map_err(|err| match err {
+                AppleError::FieldTypeError(err) => AppleError::FieldTypeError(FieldTypeError {
+                    field: Some(name.to_string()),
+                    ..err
+                }),
+                other => other,
+            })
+    }
+
+    fn get_string(&self, name: &str) -> Result<String, AppleError> {
+        self.get(name)
+    }
+
+    fn get_i64(&self, name: &str) -> Result<i64, AppleError> {
+        self.get(name)
+    }
+
+    fn get_f64(&self, name: &str) -> Result<f64, AppleError> {
+        self.get(name)
+    }
+
+    fn get_bool(&self, name: &str) -> Result<bool, AppleError> {
+        self.get(name)
+    }
+
+    fn get_timestamp(&self, name: &str) -> Result<DateTime<Utc>, AppleError> {
+        self.get(name)
+    }
+
+    fn get_bytes(&self, name: &str) -> Result<Vec<u8>, AppleError> {
+        self.get(name)
+    }
+
+    fn set(&mut self, name: &str, value: impl Into<FieldValue>) {
+        self.fields_mut().insert(name.to_string(), value.into());
+    }
+
+    /// Removes `name`, returning its previous value.
+    fn remove(&mut self, name: &str) -> Option<FieldValue> {
+        self.fields_mut().remove(name)
+    }
+}
+
+impl RecordFields for Record {
+    fn fields(&self) -> &HashMap<String, FieldValue> {
+        &self.fields
+    }
+
+    fn fields_mut(&mut self) -> &mut HashMap<String, FieldValue> {
+        &mut self.fields
+    }
+}
+
+impl RecordFields for RecordResult {
+    fn fields(&self) -> &HashMap<String, FieldValue> {
+        &self.fields
+    }
+
+    fn fields_mut(&mut self) -> &mut HashMap<String, FieldValue> {
+        &mut self.fields
+    }
+}
@@ -1,27 +1,64 @@
 pub mod assets;
+#[cfg(feature = "cloudkit-backup")]
+pub mod backup;
 pub mod changes;
 pub mod client;
+#[cfg(feature = "cloudkit-emulator")]
+pub mod emulator;
 pub(crate) mod error;
+pub mod fields;
 pub mod notifications;
+pub mod pool;
+pub mod predicate;
 pub mod query;
 pub mod records;
+pub mod request_verifier;
+pub mod schema;
+pub mod sharing;
 pub mod subscriptions;
+pub mod sync;
 pub mod tokens;
 pub mod types;
 pub mod users;
+pub mod web_auth;
+pub mod webcourier;
 pub mod zones;
 
 pub use assets::{AssetTokenInfo, AssetUploadResponse, AssetUploadResult};
+#[cfg(feature = "cloudkit-backup")]
+pub use backup::{
+    BackupManifest, BackupZone, ExportOptions, ImportOptions, ImportSummary, read_manifest,
+};
 pub use changes::{DatabaseChangesResponse, ZoneChangeInfo, ZoneChangesResponse};
 pub use client::{CloudKitClient, CloudKitConfig};
+pub use fields::{FieldTypeError, FromFieldValue, RecordFields};
 pub use notifications::{
     APNsCloudKitPayload, CKDatabaseNotification, CKNotification, CKQueryNotification,
     CKRecordZoneNotification, DatabaseScope, QueryNotificationReason,
 };
-pub use query::{Comparator, Filter, Query, QueryBuilder, SortDescriptor};
+pub use pool::{CloudKitPool, ContainerHandle};
+pub use predicate::{PredicateError, parse_predicate};
+pub use query::{Comparator, Filter, Query, QueryBuilder, SortDescriptor, SystemField};
 pub use records::{ModifyRecordsResponse, QueryResponse, RecordResult};
-pub use subscriptions::{ListSubscriptionsResponse, ModifySubscriptionsResponse};
+pub use request_verifier::{CloudKitRequestVerifier, SignedRequestHeaders};
+pub use schema::{
+    FieldSchema, Grant, Permission, RecordTypeSchema, Schema, SchemaFieldType, SchemaViolation,
+};
+pub use sharing::{
+    NameComponents, ParticipantAcceptanceStatus, ParticipantPermission, ParticipantType, Share,
+    ShareMetadata, ShareParticipant, UserIdentity, UserLookupInfo,
+};
+pub use subscriptions::{
+    ListSubscriptionsResponse, LookupSubscriptionsResponse, ModifySubscriptionsResponse,
+    SubscriptionOperationType, SubscriptionReconciliation, SubscriptionResult,
+};
+pub use sync::{SyncDispatcher, SyncEvent};
 pub use tokens::TokenCreateResponse;
 pub use types::*;
 pub use users::CloudKitUser;
-pub use zones::{ListZonesResponse, ModifyZonesResponse};
+pub use web_auth::CloudKitAuthMode;
+pub use webcourier::{NotificationStream, NotificationStreamOptions};
+pub use zones::{
+    ListZonesResponse, LookupZonesResponse, ModifyZonesResponse, ZoneOperationType, ZoneResult,
+    ZonesChangesResponse,
+};
@@ -1,5 +1,4 @@
 use crate::error::AppleError;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -201,18 +200,37 @@ pub fn parse_notification(json: &str) -> Result<CKNotification, AppleError> {
     }
 }
 
+/// Splits a WebCourier response into its notifications, keeping parse
+/// failures as errors in place.
+pub(crate) fn parse_webcourier_response(
+    body: &str,
+) -> Result<Vec<Result<CKNotification, AppleError>>, AppleError> {
+    let response: serde_json::Value =
+        serde_json::from_str(body).map_err(|e| AppleError::JsonError(e.to_string()))?;
+
+    Ok(response
+        .get("notifications")
+        .and_then(|v| v.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .map(|item| parse_notification(&item.to_string()))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
 impl super::client::CloudKitClient {
+    /// Performs a single long-poll and drops notifications that fail to
+    /// parse. See `notification_stream` for a reconnecting alternative.
     pub async fn poll_notifications(
         &self,
         webcourier_url: &str,
     ) -> Result<Vec<CKNotification>, AppleError> {
-        let long_poll_client = Client::builder()
-            .timeout(Duration::from_secs(120))
-            .build()
-            .map_err(|e| AppleError::HttpError(e.to_string()))?;
-
-        let res = long_poll_client
+        let res = self
+            .http_client
             .get(webcourier_url)
+            .timeout(Duration::from_secs(120))
             .send()
             .await
             .map_err(|e| AppleError::HttpError(e.to_string()))?;
@@ -230,22 +248,9 @@ impl super::client::CloudKitClient {
             )));
         }
 
-        // Parse the response - the webcourier returns a JSON object
-        let response: serde_json::Value =
-            serde_json::from_str(&body).map_err(|e| AppleError::JsonError(e.to_string()))?;
-
-        let mut notifications = Vec::new();
-
-        if let Some(items) = response.get("notifications").and_then(|v| v.as_array()) {
-            for item in items {
-                let item_str = serde_json::to_string(item)
-                    .map_err(|e| AppleError::JsonError(e.to_string()))?;
-                if let Ok(notification) = parse_notification(&item_str) {
-                    notifications.push(notification);
-                }
-            }
-        }
-
-        Ok(notifications)
+        Ok(parse_webcourier_response(&body)?
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect())
     }
 }
@@ -0,0 +1,116 @@
+use crate::cloudkit::client::{CloudKitClient, CloudKitConfig};
+use crate::cloudkit::types::Environment;
+use crate::error::AppleError;
+use crate::signing::AppleKeyPair;
+use reqwest::Client;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A container and the environment to address it in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerHandle {
+    pub container: String,
+    pub environment: Environment,
+}
+
+impl ContainerHandle {
+    pub fn new(container: &str, environment: Environment) -> Self {
+        ContainerHandle {
+            container: container.to_string(),
+            environment,
+        }
+    }
+
+    pub fn development(container: &str) -> Self {
+        ContainerHandle::new(container, Environment::Development)
+    }
+
+    pub fn production(container: &str) -> Self {
+        ContainerHandle::new(container, Environment::Production)
+    }
+}
+
+impl fmt::Display for ContainerHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.container, self.environment)
+    }
+}
+
+/// Hands out clients for several containers and environments that share one
+/// HTTP client and one set of server-to-server keys.
+///
+/// Writes to production are refused unless enabled per container with
+/// `with_production_writes`, so a handle pointed at the wrong environment
+/// fails instead of changing live data. Reads are always allowed.
+pub struct CloudKitPool {
+    http_client: Client,
+    default_key: Arc<AppleKeyPair>,
+    container_keys: HashMap<String, Arc<AppleKeyPair>>,
+    production_writes: HashSet<String>,
+    base_url: Option<String>,
+}
+
+impl CloudKitPool {
+    /// Creates a pool that signs for every container with `key_pair` unless
+    /// `with_container_key` overrides it.
+    pub fn new(key_pair: Arc<AppleKeyPair>) -> Result<Self, AppleError> {
+        let http_client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| AppleError::HttpError(e.to_string()))?;
+
+        Ok(CloudKitPool {
+            http_client,
+            default_key: key_pair,
+            container_keys: HashMap::new(),
+            production_writes: HashSet::new(),
+            base_url: None,
+        })
+    }
+
+    /// Signs requests for `container` with its own key.
+    pub fn with_container_key(mut self, container: &str, key_pair: Arc<AppleKeyPair>) -> Self {
+        self.container_keys.insert(container.to_string(), key_pair);
+        self
+    }
+
+    /// Allows record, zone, subscription and asset writes to `container` in
+    /// production.
+    pub fn with_production_writes(mut self, container: &str) -> Self {
+        self.production_writes.insert(container.to_string());
+        self
+    }
+
+    /// Sends every client's requests to `base_url`, for example an emulator.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    pub fn production_writes_allowed(&self, container: &str) -> bool {
+        self.production_writes.contains(container)
+    }
+
+    /// A client for one container and environment. Clients are cheap to
+    /// create and share the pool's connections.
+    pub fn client(&self, handle: &ContainerHandle) -> CloudKitClient {
+        let key_pair = self
+            .container_keys
+            .get(&handle.container)
+            .unwrap_or(&self.default_key)
+            .clone();
+        let config = CloudKitConfig {
+            container: handle.container.clone(),
+            environment: handle.environment.clone(),
+            key_pair,
+        };
+        let client = CloudKitClient::with_http_client(config, self.http_client.clone())
+            .with_production_writes(self.production_writes_allowed(&handle.container));
+        match &self.base_url {
+            Some(base_url) => client.with_base_url(base_url),
+            None => client,
+        }
+    }
+}
@@ -0,0 +1,550 @@
+use crate::cloudkit::query::{Comparator, Filter};
+use crate::cloudkit::types::{FieldValue, LocationValue, ReferenceValue};
+use crate::error::AppleError;
+use std::fmt;
+
+/// A syntax or type error in a predicate expression. `column` is 1-based
+/// and points at `token`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PredicateError {
+    pub message: String,
+    pub column: usize,
+    pub token: String,
+}
+
+impl PredicateError {
+    /// Renders the expression with a caret line under the offending token.
+    pub fn pointer(&self, expression: &str) -> String {
+        let width = self.token.chars().count().max(1);
+        format!(
+            "{}\n{}{}",
+            expression,
+            " ".repeat(self.column.saturating_sub(1)),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Display for PredicateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.token.is_empty() {
+            write!(f, "{} at end of expression", self.message)
+        } else {
+            write!(
+                f,
+                "{} at column {} (`{}`)",
+                self.message, self.column, self.token
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word,
+    Str,
+    Number,
+    Symbol,
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    /// The unescaped contents of a string literal.
+    value: String,
+    column: usize,
+}
+
+impl Token {
+    fn is_word(&self, word: &str) -> bool {
+        self.kind == TokenKind::Word && self.text.eq_ignore_ascii_case(word)
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        self.kind == TokenKind::Symbol && self.text == symbol
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, PredicateError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let token = |kind, text: String, value: String| Token {
+            kind,
+            text,
+            value,
+            column: start + 1,
+        };
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => {
+                        return Err(PredicateError {
+                            message: "Unterminated string".to_string(),
+                            column: start + 1,
+                            token: chars[start..].iter().collect(),
+                        });
+                    }
+                    Some('\\') if i + 1 < chars.len() => {
+                        value.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&ch) if ch == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&ch) => {
+                        value.push(ch);
+                        i += 1;
+                    }
+                }
+            }
+            let text = chars[start..i].iter().collect();
+            tokens.push(token(TokenKind::Str, text, value));
+        } else if c.is_ascii_digit()
+            || (c == '-' || c == '.') && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit())
+        {
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(token(TokenKind::Number, text, String::new()));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(token(TokenKind::Word, text, String::new()));
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let text = if matches!(two.as_str(), "<=" | ">=" | "!=" | "==" | "<>") {
+                two
+            } else if "=<>()[],".contains(c) {
+                c.to_string()
+            } else {
+                return Err(PredicateError {
+                    message: "Unexpected character".to_string(),
+                    column: start + 1,
+                    token: c.to_string(),
+                });
+            };
+            i += text.chars().count();
+            tokens.push(token(TokenKind::Symbol, text, String::new()));
+        }
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        text: String::new(),
+        value: String::new(),
+        column: chars.len() + 1,
+    });
+    Ok(tokens)
+}
+
+/// A parsed literal before it is matched against a comparator.
+enum Literal {
+    Scalar(FieldValue),
+    List(FieldValue),
+    Location(LocationValue, Option<f64>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error_at(token: &Token, message: &str) -> PredicateError {
+        PredicateError {
+            message: message.to_string(),
+            column: token.column,
+            token: token.text.clone(),
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<Token, PredicateError> {
+        let token = self.next();
+        if token.is_symbol(symbol) {
+            Ok(token)
+        } else {
+            Err(Self::error_at(&token, &format!("Expected `{}`", symbol)))
+        }
+    }
+
+    fn filters(&mut self) -> Result<Vec<Filter>, PredicateError> {
+        let mut filters = vec![self.clause()?];
+        loop {
+            let token = self.next();
+            match token.kind {
+                TokenKind::End => return Ok(filters),
+                _ if token.is_word("AND") => filters.push(self.clause()?),
+                _ if token.is_word("OR") => {
+                    return Err(Self::error_at(
+                        &token,
+                        "CloudKit queries only support AND between conditions",
+                    ));
+                }
+                _ => return Err(Self::error_at(&token, "Expected AND")),
+            }
+        }
+    }
+
+    fn clause(&mut self) -> Result<Filter, PredicateError> {
+        let field = self.next();
+        let field_name = match field.kind {
+            TokenKind::Word if !is_reserved(&field.text) => field.text.clone(),
+            TokenKind::Str => field.value.clone(),
+            _ => return Err(Self::error_at(&field, "Expected a field name")),
+        };
+
+        let (comparator, comparator_token) = self.comparator()?;
+        let value_token = self.peek().clone();
+        let literal = self.literal()?;
+        let (field_value, distance) = check(&comparator, &comparator_token, &value_token, literal)?;
+
+        Ok(Filter {
+            field_name,
+            comparator,
+            field_value,
+            distance,
+        })
+    }
+
+    fn comparator(&mut self) -> Result<(Comparator, Token), PredicateError> {
+        let token = self.next();
+        let comparator = match token.text.to_ascii_uppercase().as_str() {
+            "=" | "==" => Comparator::Equals,
+            "!=" | "<>" => Comparator::NotEquals,
+            ">" => Comparator::GreaterThan,
+            ">=" => Comparator::GreaterThanOrEquals,
+            "<" => Comparator::LessThan,
+            "<=" => Comparator::LessThanOrEquals,
+            "IN" => Comparator::In,
+            "NOT" => {
+                let next = self.next();
+                if next.is_word("IN") {
+                    Comparator::NotIn
+                } else {
+                    return Err(Self::error_at(&next, "Expected IN after NOT"));
+                }
+            }
+            "NEAR" => Comparator::Near,
+            "BEGINSWITH" | "BEGINS_WITH" => Comparator::BeginsWith,
+            "CONTAINS_ALL_TOKENS" => Comparator::ContainsAllTokens,
+            "CONTAINS_ANY_TOKENS" => Comparator::ContainsAnyTokens,
+            "LIST_CONTAINS" => Comparator::ListContains,
+            "LIST_NOT_CONTAINS" => Comparator::ListNotContains,
+            "LIST_CONTAINS_ALL" => Comparator::ListContainsAll,
+            "LIST_CONTAINS_ANY" => Comparator::ListContainsAny,
+            _ => return Err(Self::error_at(&token, "Expected a comparator")),
+        };
+        Ok((comparator, token))
+    }
+
+    fn literal(&mut self) -> Result<Literal, PredicateError> {
+        let token = self.peek().clone();
+        if token.is_symbol("[") {
+            return self.list();
+        }
+        if token.is_symbol("(") {
+            return self.location();
+        }
+        self.scalar().map(Literal::Scalar)
+    }
+
+    fn scalar(&mut self) -> Result<FieldValue, PredicateError> {
+        let token = self.next();
+        match token.kind {
+            TokenKind::Str => Ok(FieldValue::String(token.value)),
+            TokenKind::Number => number(&token),
+            TokenKind::Word if token.is_word("timestamp") || token.is_word("reference") => {
+                self.expect_symbol("(")?;
+                let arg = self.next();
+                if arg.kind != TokenKind::Str {
+                    return Err(Self::error_at(&arg, "Expected a quoted string"));
+                }
+                self.expect_symbol(")")?;
+                if token.is_word("reference") {
+                    return Ok(FieldValue::Reference(ReferenceValue {
+                        record_name: arg.value,
+                        zone_id: None,
+                        action: None,
+                    }));
+                }
+                chrono::DateTime::parse_from_rfc3339(&arg.value)
+                    .map(|t| FieldValue::Timestamp(t.timestamp_millis()))
+                    .map_err(|_| Self::error_at(&arg, "Expected an RFC 3339 timestamp"))
+            }
+            _ => Err(Self::error_at(&token, "Expected a value")),
+        }
+    }
+
+    fn list(&mut self) -> Result<Literal, PredicateError> {
+        let open = self.expect_symbol("[")?;
+        let mut items: Vec<(FieldValue, Token)> = Vec::new();
+        if !self.peek().is_symbol("]") {
+            loop {
+                let token = self.peek().clone();
+                items.push((self.scalar()?, token));
+                if self.peek().is_symbol("]") {
+                    break;
+                }
+                self.expect_symbol(",")?;
+            }
+        }
+        self.expect_symbol("]")?;
+
+        let Some((first, _)) = items.first() else {
+            return Err(Self::error_at(&open, "Lists must not be empty"));
+        };
+        let expected = value_kind(first);
+        if let Some((_, token)) = items.iter().find(|(v, _)| value_kind(v) != expected) {
+            return Err(Self::error_at(
+                token,
+                &format!("Expected {} in list", expected),
+            ));
+        }
+
+        let values = items.into_iter().map(|(v, _)| v);
+        let list = match expected {
+            "a number" => {
+                let values: Vec<FieldValue> = values.collect();
+                if values.iter().all(|v| matches!(v, FieldValue::Int64(_))) {
+                    FieldValue::Int64List(values.iter().filter_map(as_i64).collect())
+                } else {
+                    FieldValue::DoubleList(values.iter().filter_map(as_f64).collect())
+                }
+            }
+            "a string" => FieldValue::StringList(
+                values
+                    .filter_map(|v| match v {
+                        FieldValue::String(s) => Some(s),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            "a timestamp" => FieldValue::TimestampList(
+                values
+                    .filter_map(|v| match v {
+                        FieldValue::Timestamp(t) => Some(t),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            _ => FieldValue::ReferenceList(
+                values
+                    .filter_map(|v| match v {
+                        FieldValue::Reference(r) => Some(r),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+        };
+        Ok(Literal::List(list))
+    }
+
+    /// `(latitude, longitude)` or `(latitude, longitude, distance[unit])`.
+    fn location(&mut self) -> Result<Literal, PredicateError> {
+        self.expect_symbol("(")?;
+        let latitude = self.coordinate(90.0, "Latitude")?;
+        self.expect_symbol(",")?;
+        let longitude = self.coordinate(180.0, "Longitude")?;
+
+        let mut distance = None;
+        if self.peek().is_symbol(",") {
+            self.next();
+            let token = self.next();
+            let value = match number(&token) {
+                Ok(value) => as_f64(&value).unwrap_or_default(),
+                Err(_) => return Err(Self::error_at(&token, "Expected a distance")),
+            };
+            if value < 0.0 {
+                return Err(Self::error_at(&token, "Distance must not be negative"));
+            }
+            let unit = self.peek().clone();
+            let meters_per_unit = if unit.kind == TokenKind::Word {
+                self.next();
+                match unit.text.to_ascii_lowercase().as_str() {
+                    "m" => 1.0,
+                    "km" => 1000.0,
+                    "mi" => 1609.344,
+                    "ft" => 0.3048,
+                    _ => return Err(Self::error_at(&unit, "Expected a unit: m, km, mi or ft")),
+                }
+            } else {
+                1.0
+            };
+            distance = Some(value * meters_per_unit);
+        }
+        self.expect_symbol(")")?;
+
+        Ok(Literal::Location(
+            LocationValue {
+                latitude,
+                longitude,
+                altitude: None,
+                horizontal_accuracy: None,
+                vertical_accuracy: None,
+                course: None,
+                speed: None,
+                timestamp: None,
+            },
+            distance,
+        ))
+    }
+
+    fn coordinate(&mut self, limit: f64, name: &str) -> Result<f64, PredicateError> {
+        let token = self.next();
+        let value = number(&token)
+            .ok()
+            .and_then(|v| as_f64(&v))
+            .ok_or_else(|| {
+                Self::error_at(&token, &format!("Expected a {}", name.to_lowercase()))
+            })?;
+        if value.abs() > limit {
+            return Err(Self::error_at(
+                &token,
+                &format!("{} must be between -{} and {}", name, limit, limit),
+            ));
+        }
+        Ok(value)
+    }
+}
+
+fn is_reserved(word: &str) -> bool {
+    ["AND", "OR", "NOT", "IN", "NEAR"]
+        .iter()
+        .any(|r| r.eq_ignore_ascii_case(word))
+}
+
+fn number(token: &Token) -> Result<FieldValue, PredicateError> {
+    if token.kind != TokenKind::Number {
+        return Err(Parser::error_at(token, "Expected a number"));
+    }
+    if token.text.contains('.') {
+        token.text.parse().map(FieldValue::Double)
+    } else {
+        token
+            .text
+            .parse()
+            .map(FieldValue::Int64)
+            .or_else(|_| token.text.parse().map(FieldValue::Double))
+    }
+    .map_err(|_| Parser::error_at(token, "Invalid number"))
+}
+
+fn as_i64(value: &FieldValue) -> Option<i64> {
+    match value {
+        FieldValue::Int64(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn as_f64(value: &FieldValue) -> Option<f64> {
+    match value {
+        FieldValue::Int64(n) => Some(*n as f64),
+        FieldValue::Double(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn value_kind(value: &FieldValue) -> &'static str {
+    match value {
+        FieldValue::String(_) => "a string",
+        FieldValue::Int64(_) | FieldValue::Double(_) => "a number",
+        FieldValue::Timestamp(_) => "a timestamp",
+        FieldValue::Reference(_) => "a reference",
+        _ => "a value",
+    }
+}
+
+/// Checks that the literal is the kind of value the comparator accepts.
+fn check(
+    comparator: &Comparator,
+    comparator_token: &Token,
+    value_token: &Token,
+    literal: Literal,
+) -> Result<(FieldValue, Option<f64>), PredicateError> {
+    let name = comparator_token.text.to_ascii_uppercase();
+    let fail = |needs: &str| {
+        Err(Parser::error_at(
+            value_token,
+            &format!("{} needs {}", name, needs),
+        ))
+    };
+
+    match comparator {
+        Comparator::Near => match literal {
+            Literal::Location(location, Some(distance)) => {
+                Ok((FieldValue::Location(location), Some(distance)))
+            }
+            Literal::Location(_, None) => fail("a distance, like (37.3, -122.0, 5km)"),
+            _ => fail("a location and distance, like (37.3, -122.0, 5km)"),
+        },
+        Comparator::In
+        | Comparator::NotIn
+        | Comparator::ListContainsAll
+        | Comparator::ListContainsAny => match literal {
+            Literal::List(list) => Ok((list, None)),
+            _ => fail("a list, like [\"a\", \"b\"]"),
+        },
+        Comparator::BeginsWith | Comparator::ContainsAllTokens | Comparator::ContainsAnyTokens => {
+            match literal {
+                Literal::Scalar(value @ FieldValue::String(_)) => Ok((value, None)),
+                _ => fail("a string"),
+            }
+        }
+        Comparator::GreaterThan
+        | Comparator::GreaterThanOrEquals
+        | Comparator::LessThan
+        | Comparator::LessThanOrEquals => match literal {
+            Literal::Scalar(FieldValue::Reference(_)) => fail("a string, number or timestamp"),
+            Literal::Scalar(value) => Ok((value, None)),
+            _ => fail("a string, number or timestamp"),
+        },
+        Comparator::Equals
+        | Comparator::NotEquals
+        | Comparator::ListContains
+        | Comparator::ListNotContains => match literal {
+            Literal::Scalar(value) => Ok((value, None)),
+            _ => fail("a single value"),
+        },
+    }
+}
+
+/// Parses an NSPredicate-like expression into CloudKit filters. Conditions
+/// are joined with `AND`; values are strings, numbers, `[lists]`,
+/// `timestamp("2024-01-01T00:00:00Z")`, `reference("recordName")` and, for
+/// `NEAR`, `(latitude, longitude, distance)` with an optional m, km, mi or
+/// ft unit.
+pub fn parse_predicate(expression: &str) -> Result<Vec<Filter>, AppleError> {
+    let tokens = tokenize(expression).map_err(AppleError::PredicateError)?;
+    Parser { tokens, pos: 0 }
+        .filters()
+        .map_err(AppleError::PredicateError)
+}
@@ -1,4 +1,6 @@
-use crate::cloudkit::types::FieldValue;
+use crate::cloudkit::predicate::parse_predicate;
+use crate::cloudkit::types::{FieldValue, LocationValue, ReferenceValue, ZoneID};
+use crate::error::AppleError;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,6 +11,37 @@ pub struct Query {
     pub filter_by: Option<Vec<Filter>>,
     #[serde(rename = "sortBy", skip_serializing_if = "Option::is_none")]
     pub sort_by: Option<Vec<SortDescriptor>>,
+    /// Request-level options. `query_records` sends these alongside the
+    /// query unless overridden by its own arguments.
+    #[serde(skip)]
+    pub zone_id: Option<ZoneID>,
+    #[serde(skip)]
+    pub zone_wide: bool,
+    #[serde(skip)]
+    pub results_limit: Option<u32>,
+    #[serde(skip)]
+    pub desired_keys: Option<Vec<String>>,
+}
+
+/// Fields CloudKit maintains on every record. They can be filtered and sorted
+/// on once marked queryable or sortable in the schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemField {
+    RecordId,
+    CreateTime,
+    ModTime,
+    CreatedBy,
+}
+
+impl SystemField {
+    pub fn field_name(&self) -> &'static str {
+        match self {
+            SystemField::RecordId => "___recordID",
+            SystemField::CreateTime => "___createTime",
+            SystemField::ModTime => "___modTime",
+            SystemField::CreatedBy => "___createdBy",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +51,9 @@ pub struct Filter {
     pub comparator: Comparator,
     #[serde(rename = "fieldValue")]
     pub field_value: FieldValue,
+    /// Radius in meters for `Comparator::Near`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,12 +98,40 @@ pub struct SortDescriptor {
     pub field_name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ascending: Option<bool>,
+    /// Sorts by distance from this point. Only valid on LOCATION fields.
+    #[serde(rename = "relativeLocation", skip_serializing_if = "Option::is_none")]
+    pub relative_location: Option<LocationValue>,
 }
 
 pub struct QueryBuilder {
     record_type: String,
     filters: Vec<Filter>,
     sorts: Vec<SortDescriptor>,
+    zone_id: Option<ZoneID>,
+    zone_wide: bool,
+    results_limit: Option<u32>,
+    desired_keys: Option<Vec<String>>,
+}
+
+fn point(latitude: f64, longitude: f64) -> LocationValue {
+    LocationValue {
+        latitude,
+        longitude,
+        altitude: None,
+        horizontal_accuracy: None,
+        vertical_accuracy: None,
+        course: None,
+        speed: None,
+        timestamp: None,
+    }
+}
+
+fn reference(record_name: &str) -> FieldValue {
+    FieldValue::Reference(ReferenceValue {
+        record_name: record_name.to_string(),
+        zone_id: None,
+        action: None,
+    })
 }
 
 impl QueryBuilder {
@@ -76,6 +140,10 @@ impl QueryBuilder {
             record_type: record_type.to_string(),
             filters: Vec::new(),
             sorts: Vec::new(),
+            zone_id: None,
+            zone_wide: false,
+            results_limit: None,
+            desired_keys: None,
         }
     }
 
@@ -84,18 +152,105 @@ impl QueryBuilder {
             field_name: field_name.to_string(),
             comparator,
             field_value: value,
+            distance: None,
         });
         self
     }
 
+    /// Matches records within `meters` of the given point.
+    pub fn near(mut self, field_name: &str, latitude: f64, longitude: f64, meters: f64) -> Self {
+        self.filters.push(Filter {
+            field_name: field_name.to_string(),
+            comparator: Comparator::Near,
+            field_value: FieldValue::Location(point(latitude, longitude)),
+            distance: Some(meters),
+        });
+        self
+    }
+
+    /// Filters on the record name via `___recordID`.
+    pub fn record_id(self, comparator: Comparator, record_name: &str) -> Self {
+        let field = SystemField::RecordId.field_name();
+        self.filter(field, comparator, reference(record_name))
+    }
+
+    /// Filters on the creating user's record name via `___createdBy`.
+    pub fn created_by(self, comparator: Comparator, user_record_name: &str) -> Self {
+        let field = SystemField::CreatedBy.field_name();
+        self.filter(field, comparator, reference(user_record_name))
+    }
+
+    /// Filters on `___createTime`, in milliseconds since the epoch.
+    pub fn created(self, comparator: Comparator, timestamp: i64) -> Self {
+        let field = SystemField::CreateTime.field_name();
+        self.filter(field, comparator, FieldValue::Timestamp(timestamp))
+    }
+
+    /// Filters on `___modTime`, in milliseconds since the epoch.
+    pub fn modified(self, comparator: Comparator, timestamp: i64) -> Self {
+        let field = SystemField::ModTime.field_name();
+        self.filter(field, comparator, FieldValue::Timestamp(timestamp))
+    }
+
+    /// Appends the filters from a predicate expression such as
+    /// `age >= 21 AND location NEAR (37.3, -122.0, 5km)`.
+    pub fn predicate(mut self, expression: &str) -> Result<Self, AppleError> {
+        self.filters.extend(parse_predicate(expression)?);
+        Ok(self)
+    }
+
     pub fn sort(mut self, field_name: &str, ascending: bool) -> Self {
         self.sorts.push(SortDescriptor {
             field_name: field_name.to_string(),
             ascending: Some(ascending),
+            relative_location: None,
+        });
+        self
+    }
+
+    pub fn sort_by_system(self, field: SystemField, ascending: bool) -> Self {
+        self.sort(field.field_name(), ascending)
+    }
+
+    /// Sorts by distance from the given point, nearest first when ascending.
+    pub fn sort_by_distance(
+        mut self,
+        field_name: &str,
+        latitude: f64,
+        longitude: f64,
+        ascending: bool,
+    ) -> Self {
+        self.sorts.push(SortDescriptor {
+            field_name: field_name.to_string(),
+            ascending: Some(ascending),
+            relative_location: Some(point(latitude, longitude)),
         });
         self
     }
 
+    /// Queries a single zone instead of the default zone.
+    pub fn zone(mut self, zone_id: ZoneID) -> Self {
+        self.zone_id = Some(zone_id);
+        self
+    }
+
+    /// Queries across every zone in the database.
+    pub fn zone_wide(mut self, zone_wide: bool) -> Self {
+        self.zone_wide = zone_wide;
+        self
+    }
+
+    pub fn results_limit(mut self, limit: u32) -> Self {
+        self.results_limit = Some(limit);
+        self
+    }
+
+    /// Limits the fields returned on each record.
+    pub fn desired_keys(mut self, keys: &[&str]) -> Self {
+        self.desired_keys = Some(keys.iter().map(|k| k.to_string()).collect());
+        self
+    }
+
     pub fn build(self) -> Query {
         Query {
             record_type: self.record_type,
@@ -109,6 +264,10 @@ impl QueryBuilder {
             } else {
                 Some(self.sorts)
             },
+            zone_id: self.zone_id,
+            zone_wide: self.zone_wide,
+            results_limit: self.results_limit,
+            desired_keys: self.desired_keys,
         }
     }
 }
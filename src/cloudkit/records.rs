@@ -1,36 +1,44 @@
 use crate::cloudkit::client::CloudKitClient;
+use crate::cloudkit::error::item_error;
 use crate::cloudkit::query::Query;
+use crate::cloudkit::sharing::{ParticipantPermission, ShareParticipant};
 use crate::cloudkit::types::*;
 use crate::error::AppleError;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[derive(Debug, Serialize)]
-struct ModifyRecordsRequest {
-    operations: Vec<RecordOperation>,
+pub(crate) struct ModifyRecordsRequest {
+    pub(crate) operations: Vec<RecordOperation>,
     #[serde(rename = "zoneID", skip_serializing_if = "Option::is_none")]
-    zone_id: Option<ZoneID>,
+    pub(crate) zone_id: Option<ZoneID>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    atomic: Option<bool>,
+    pub(crate) atomic: Option<bool>,
 }
 
 #[derive(Debug, Serialize)]
-struct RecordOperation {
+pub(crate) struct RecordOperation {
     #[serde(rename = "operationType")]
-    operation_type: OperationType,
-    record: RecordBody,
+    pub(crate) operation_type: OperationType,
+    pub(crate) record: RecordBody,
 }
 
-#[derive(Debug, Serialize)]
-struct RecordBody {
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct RecordBody {
     #[serde(rename = "recordName", skip_serializing_if = "Option::is_none")]
-    record_name: Option<String>,
+    pub(crate) record_name: Option<String>,
     #[serde(rename = "recordType", skip_serializing_if = "Option::is_none")]
-    record_type: Option<String>,
+    pub(crate) record_type: Option<String>,
     #[serde(rename = "recordChangeTag", skip_serializing_if = "Option::is_none")]
-    record_change_tag: Option<String>,
+    pub(crate) record_change_tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) fields: Option<HashMap<String, FieldValue>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    fields: Option<HashMap<String, FieldValue>>,
+    pub(crate) share: Option<ReferenceValue>,
+    #[serde(rename = "publicPermission", skip_serializing_if = "Option::is_none")]
+    pub(crate) public_permission: Option<ParticipantPermission>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) participants: Option<Vec<ShareParticipant>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,7 +46,7 @@ pub struct ModifyRecordsResponse {
     pub records: Vec<RecordResult>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct RecordResult {
     #[serde(rename = "recordName")]
     pub record_name: Option<String>,
@@ -52,11 +60,46 @@ pub struct RecordResult {
     pub zone_id: Option<ZoneID>,
     pub created: Option<RecordTimestamp>,
     pub modified: Option<RecordTimestamp>,
+    pub share: Option<ReferenceValue>,
+    #[serde(rename = "shortGUID")]
+    pub short_guid: Option<String>,
+    #[serde(rename = "publicPermission")]
+    pub public_permission: Option<ParticipantPermission>,
+    #[serde(default)]
+    pub participants: Vec<ShareParticipant>,
+    pub owner: Option<ShareParticipant>,
+    #[serde(rename = "currentUserParticipant")]
+    pub current_user_participant: Option<ShareParticipant>,
+    /// Set on tombstones returned by `changes/zone`.
+    #[serde(default)]
+    pub deleted: bool,
     #[serde(rename = "serverErrorCode")]
     pub server_error_code: Option<String>,
     pub reason: Option<String>,
 }
 
+impl RecordResult {
+    pub fn error(&self) -> Option<AppleError> {
+        item_error(self.server_error_code.as_ref(), self.reason.as_ref())
+    }
+
+    pub fn into_record(self) -> Result<Record, AppleError> {
+        if let Some(err) = self.error() {
+            return Err(err);
+        }
+        Ok(Record {
+            record_name: self.record_name,
+            record_type: self.record_type.unwrap_or_default(),
+            record_change_tag: self.record_change_tag,
+            fields: self.fields,
+            zone_id: self.zone_id,
+            created: self.created,
+            modified: self.modified,
+            share: self.share,
+        })
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct QueryRecordsRequest {
     query: Query,
@@ -68,6 +111,8 @@ struct QueryRecordsRequest {
     continuation_marker: Option<String>,
     #[serde(rename = "desiredKeys", skip_serializing_if = "Option::is_none")]
     desired_keys: Option<Vec<String>>,
+    #[serde(rename = "zoneWide", skip_serializing_if = "Option::is_none")]
+    zone_wide: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -98,6 +143,7 @@ impl CloudKitClient {
         db: &DatabaseType,
         record: Record,
     ) -> Result<Record, AppleError> {
+        self.check_record(&OperationType::Create, &record)?;
         let url = self.build_url(db, "records/modify");
         let fields = if record.fields.is_empty() {
             None
@@ -112,6 +158,8 @@ impl CloudKitClient {
                     record_type: Some(record.record_type),
                     record_change_tag: None,
                     fields,
+                    share: record.share,
+                    ..Default::default()
                 },
             }],
             zone_id: record.zone_id,
@@ -125,26 +173,7 @@ impl CloudKitClient {
             .next()
             .ok_or_else(|| AppleError::JsonError("Empty response from CloudKit".to_string()))?;
 
-        if let Some(ref err_code) = result.server_error_code {
-            return Err(AppleError::CloudKitError(
-                crate::error::CloudKitErrorResponse {
-                    server_error_code: crate::error::CloudKitErrorCode::parse(err_code),
-                    reason: result.reason.unwrap_or_default(),
-                    uuid: None,
-                    retry_after: None,
-                },
-            ));
-        }
-
-        Ok(Record {
-            record_name: result.record_name,
-            record_type: result.record_type.unwrap_or_default(),
-            record_change_tag: result.record_change_tag,
-            fields: result.fields,
-            zone_id: result.zone_id,
-            created: result.created,
-            modified: result.modified,
-        })
+        result.into_record()
     }
 
     pub async fn update_record(
@@ -152,6 +181,7 @@ impl CloudKitClient {
         db: &DatabaseType,
         record: Record,
     ) -> Result<Record, AppleError> {
+        self.check_record(&OperationType::Update, &record)?;
         let url = self.build_url(db, "records/modify");
         let fields = if record.fields.is_empty() {
             None
@@ -166,6 +196,8 @@ impl CloudKitClient {
                     record_type: Some(record.record_type),
                     record_change_tag: record.record_change_tag,
                     fields,
+                    share: record.share,
+                    ..Default::default()
                 },
             }],
             zone_id: record.zone_id,
@@ -179,26 +211,7 @@ impl CloudKitClient {
             .next()
             .ok_or_else(|| AppleError::JsonError("Empty response from CloudKit".to_string()))?;
 
-        if let Some(ref err_code) = result.server_error_code {
-            return Err(AppleError::CloudKitError(
-                crate::error::CloudKitErrorResponse {
-                    server_error_code: crate::error::CloudKitErrorCode::parse(err_code),
-                    reason: result.reason.unwrap_or_default(),
-                    uuid: None,
-                    retry_after: None,
-                },
-            ));
-        }
-
-        Ok(Record {
-            record_name: result.record_name,
-            record_type: result.record_type.unwrap_or_default(),
-            record_change_tag: result.record_change_tag,
-            fields: result.fields,
-            zone_id: result.zone_id,
-            created: result.created,
-            modified: result.modified,
-        })
+        result.into_record()
     }
 
     pub async fn delete_record(
@@ -217,6 +230,7 @@ impl CloudKitClient {
                     record_type: Some(record_type.to_string()),
                     record_change_tag: None,
                     fields: None,
+                    ..Default::default()
                 },
             }],
             zone_id,
@@ -224,17 +238,8 @@ impl CloudKitClient {
         };
 
         let response: ModifyRecordsResponse = self.signed_post(&url, &request).await?;
-        if let Some(result) = response.records.into_iter().next()
-            && let Some(ref err_code) = result.server_error_code
-        {
-            return Err(AppleError::CloudKitError(
-                crate::error::CloudKitErrorResponse {
-                    server_error_code: crate::error::CloudKitErrorCode::parse(err_code),
-                    reason: result.reason.unwrap_or_default(),
-                    uuid: None,
-                    retry_after: None,
-                },
-            ));
+        if let Some(err) = response.records.first().and_then(RecordResult::error) {
+            return Err(err);
         }
 
         Ok(())
@@ -249,18 +254,22 @@ impl CloudKitClient {
         continuation_marker: Option<String>,
         desired_keys: Option<Vec<String>>,
     ) -> Result<QueryResponse, AppleError> {
+        self.check_query(&query)?;
         let url = self.build_url(db, "records/query");
         let request = QueryRecordsRequest {
-            query,
-            zone_id,
-            results_limit,
+            zone_id: zone_id.or_else(|| query.zone_id.clone()),
+            results_limit: results_limit.or(query.results_limit),
             continuation_marker,
-            desired_keys,
+            desired_keys: desired_keys.or_else(|| query.desired_keys.clone()),
+            zone_wide: query.zone_wide.then_some(true),
+            query,
         };
 
         self.signed_post(&url, &request).await
     }
 
+    /// Fails with the first per-record error, such as `NotFound` for a
+    /// name that does not exist; `lookup_record_results` reports each one.
     pub async fn lookup_records(
         &self,
         db: &DatabaseType,
@@ -268,6 +277,21 @@ impl CloudKitClient {
         zone_id: Option<ZoneID>,
         desired_keys: Option<Vec<String>>,
     ) -> Result<Vec<Record>, AppleError> {
+        self.lookup_record_results(db, record_names, zone_id, desired_keys)
+            .await?
+            .into_iter()
+            .map(RecordResult::into_record)
+            .collect()
+    }
+
+    /// Looks records up, returning one result per name in request order.
+    pub async fn lookup_record_results(
+        &self,
+        db: &DatabaseType,
+        record_names: &[&str],
+        zone_id: Option<ZoneID>,
+        desired_keys: Option<Vec<String>>,
+    ) -> Result<Vec<RecordResult>, AppleError> {
         let url = self.build_url(db, "records/lookup");
         let request = LookupRecordsRequest {
             records: record_names
@@ -281,19 +305,7 @@ impl CloudKitClient {
         };
 
         let response: ModifyRecordsResponse = self.signed_post(&url, &request).await?;
-        Ok(response
-            .records
-            .into_iter()
-            .map(|r| Record {
-                record_name: r.record_name,
-                record_type: r.record_type.unwrap_or_default(),
-                record_change_tag: r.record_change_tag,
-                fields: r.fields,
-                zone_id: r.zone_id,
-                created: r.created,
-                modified: r.modified,
-            })
-            .collect())
+        Ok(response.records)
     }
 
     pub async fn modify_records(
@@ -303,6 +315,9 @@ impl CloudKitClient {
         zone_id: Option<ZoneID>,
         atomic: Option<bool>,
     ) -> Result<Vec<RecordResult>, AppleError> {
+        for (op_type, record) in &operations {
+            self.check_record(op_type, record)?;
+        }
         let url = self.build_url(db, "records/modify");
         let ops: Vec<RecordOperation> = operations
             .into_iter()
@@ -312,13 +327,17 @@ impl CloudKitClient {
                 } else {
                     Some(record.fields)
                 };
+                // Deletes only need the record name.
+                let record_type = Some(record.record_type).filter(|t| !t.is_empty());
                 RecordOperation {
                     operation_type: op_type,
                     record: RecordBody {
                         record_name: record.record_name,
-                        record_type: Some(record.record_type),
+                        record_type,
                         record_change_tag: record.record_change_tag,
                         fields,
+                        share: record.share,
+                        ..Default::default()
                     },
                 }
             })
@@ -0,0 +1,144 @@
+use crate::cloudkit::client::{DATE_HEADER, KEY_ID_HEADER, SIGNATURE_HEADER, signing_message};
+use crate::error::AppleError;
+use crate::signing::AppleKeyPair;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use p256::ecdsa::{Signature, VerifyingKey, signature::Verifier};
+use p256::pkcs8::DecodePublicKey;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_MAX_CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// The three headers `CloudKitClient::sign_request` attaches to a request.
+#[derive(Debug, Clone)]
+pub struct SignedRequestHeaders {
+    pub key_id: String,
+    pub date: String,
+    pub signature: String,
+}
+
+impl SignedRequestHeaders {
+    /// Picks the CloudKit signature headers out of a header list. Header
+    /// names are matched case-insensitively.
+    pub fn from_pairs<K: AsRef<str>, V: AsRef<str>>(
+        headers: &[(K, V)],
+    ) -> Result<Self, AppleError> {
+        let find = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key.as_ref().eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_ref().to_string())
+                .ok_or_else(|| AppleError::SignatureError(format!("Missing {} header", name)))
+        };
+        Ok(SignedRequestHeaders {
+            key_id: find(KEY_ID_HEADER)?,
+            date: find(DATE_HEADER)?,
+            signature: find(SIGNATURE_HEADER)?,
+        })
+    }
+}
+
+/// Verifies CloudKit-style server-to-server request signatures against a set
+/// of registered public keys.
+pub struct CloudKitRequestVerifier {
+    keys: HashMap<String, VerifyingKey>,
+    max_clock_skew: Duration,
+}
+
+impl CloudKitRequestVerifier {
+    pub fn new() -> Self {
+        CloudKitRequestVerifier {
+            keys: HashMap::new(),
+            max_clock_skew: DEFAULT_MAX_CLOCK_SKEW,
+        }
+    }
+
+    /// How far the request date may drift from the verifier's clock, in
+    /// either direction. Defaults to five minutes.
+    pub fn with_max_clock_skew(mut self, max_clock_skew: Duration) -> Self {
+        self.max_clock_skew = max_clock_skew;
+        self
+    }
+
+    pub fn with_key(mut self, key_id: &str, key: VerifyingKey) -> Self {
+        self.keys.insert(key_id.to_string(), key);
+        self
+    }
+
+    /// Trusts the public half of a key pair under its own key ID.
+    pub fn with_key_pair(self, key_pair: &AppleKeyPair) -> Self {
+        let key_id = key_pair.key_id().to_string();
+        self.with_key(&key_id, key_pair.verifying_key())
+    }
+
+    /// Trusts a SubjectPublicKeyInfo PEM (`-----BEGIN PUBLIC KEY-----`).
+    pub fn with_public_key_pem(self, key_id: &str, pem: &str) -> Result<Self, AppleError> {
+        let key = VerifyingKey::from_public_key_pem(pem)
+            .map_err(|e| AppleError::KeyParseError(e.to_string()))?;
+        Ok(self.with_key(key_id, key))
+    }
+
+    pub fn remove_key(&mut self, key_id: &str) -> Option<VerifyingKey> {
+        self.keys.remove(key_id)
+    }
+
+    pub fn verify(
+        &self,
+        headers: &SignedRequestHeaders,
+        body: &[u8],
+        subpath: &str,
+    ) -> Result<(), AppleError> {
+        self.verify_at(headers, body, subpath, SystemTime::now())
+    }
+
+    /// Like `verify`, but evaluates the clock-skew window against `now`.
+    pub fn verify_at(
+        &self,
+        headers: &SignedRequestHeaders,
+        body: &[u8],
+        subpath: &str,
+        now: SystemTime,
+    ) -> Result<(), AppleError> {
+        let key = self.keys.get(&headers.key_id).ok_or_else(|| {
+            AppleError::SignatureError(format!("Unknown key ID: {}", headers.key_id))
+        })?;
+
+        self.check_date(&headers.date, now)?;
+
+        let signature_bytes = STANDARD
+            .decode(&headers.signature)
+            .map_err(|e| AppleError::Base64Error(e.to_string()))?;
+        let signature = Signature::from_der(&signature_bytes)
+            .map_err(|e| AppleError::SignatureError(e.to_string()))?;
+
+        let message = signing_message(&headers.date, body, subpath);
+        key.verify(message.as_bytes(), &signature)
+            .map_err(|_| AppleError::SignatureError("Signature does not match".to_string()))
+    }
+
+    fn check_date(&self, date: &str, now: SystemTime) -> Result<(), AppleError> {
+        let signed_at = chrono::DateTime::parse_from_rfc3339(date)
+            .map_err(|e| AppleError::SignatureError(format!("Invalid request date: {}", e)))?
+            .timestamp();
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| AppleError::TimeError(e.to_string()))?
+            .as_secs() as i64;
+
+        let skew = signed_at.abs_diff(now);
+        if skew > self.max_clock_skew.as_secs() {
+            return Err(AppleError::SignatureError(format!(
+                "Request date {} is {}s away from the current time",
+                date, skew
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for CloudKitRequestVerifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
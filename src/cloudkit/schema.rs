@@ -0,0 +1,748 @@
+use crate::cloudkit::client::CloudKitClient;
+use crate::cloudkit::query::{Comparator, Filter, Query};
+use crate::cloudkit::types::{FieldValue, OperationType, Record};
+use crate::error::AppleError;
+use std::fmt;
+use std::sync::Arc;
+
+/// A field type as written in a `.ckdb` schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaFieldType {
+    String,
+    Int64,
+    Double,
+    Timestamp,
+    Reference,
+    Asset,
+    Location,
+    Bytes,
+    List(Box<SchemaFieldType>),
+}
+
+impl SchemaFieldType {
+    fn parse_scalar(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "STRING" => Some(SchemaFieldType::String),
+            "INT64" => Some(SchemaFieldType::Int64),
+            "DOUBLE" => Some(SchemaFieldType::Double),
+            "TIMESTAMP" => Some(SchemaFieldType::Timestamp),
+            "REFERENCE" => Some(SchemaFieldType::Reference),
+            "ASSET" => Some(SchemaFieldType::Asset),
+            "LOCATION" => Some(SchemaFieldType::Location),
+            "BYTES" => Some(SchemaFieldType::Bytes),
+            _ => None,
+        }
+    }
+
+    /// The schema type a `FieldValue` variant is stored as.
+    pub fn of(value: &FieldValue) -> Self {
+        let list = |t: SchemaFieldType| SchemaFieldType::List(Box::new(t));
+        match value {
+            FieldValue::String(_) => SchemaFieldType::String,
+            FieldValue::Int64(_) => SchemaFieldType::Int64,
+            FieldValue::Double(_) => SchemaFieldType::Double,
+            FieldValue::Timestamp(_) => SchemaFieldType::Timestamp,
+            FieldValue::Reference(_) => SchemaFieldType::Reference,
+            FieldValue::Asset(_) => SchemaFieldType::Asset,
+            FieldValue::Location(_) => SchemaFieldType::Location,
+            FieldValue::Bytes(_) | FieldValue::EncryptedBytes(_) => SchemaFieldType::Bytes,
+            FieldValue::StringList(_) => list(SchemaFieldType::String),
+            FieldValue::Int64List(_) => list(SchemaFieldType::Int64),
+            FieldValue::DoubleList(_) => list(SchemaFieldType::Double),
+            FieldValue::TimestampList(_) => list(SchemaFieldType::Timestamp),
+            FieldValue::ReferenceList(_) => list(SchemaFieldType::Reference),
+            FieldValue::LocationList(_) => list(SchemaFieldType::Location),
+            FieldValue::AssetList(_) => list(SchemaFieldType::Asset),
+            FieldValue::BytesList(_) => list(SchemaFieldType::Bytes),
+        }
+    }
+}
+
+impl fmt::Display for SchemaFieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaFieldType::String => write!(f, "STRING"),
+            SchemaFieldType::Int64 => write!(f, "INT64"),
+            SchemaFieldType::Double => write!(f, "DOUBLE"),
+            SchemaFieldType::Timestamp => write!(f, "TIMESTAMP"),
+            SchemaFieldType::Reference => write!(f, "REFERENCE"),
+            SchemaFieldType::Asset => write!(f, "ASSET"),
+            SchemaFieldType::Location => write!(f, "LOCATION"),
+            SchemaFieldType::Bytes => write!(f, "BYTES"),
+            SchemaFieldType::List(inner) => write!(f, "LIST<{}>", inner),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSchema {
+    pub name: String,
+    pub field_type: SchemaFieldType,
+    pub encrypted: bool,
+    pub queryable: bool,
+    pub searchable: bool,
+    pub sortable: bool,
+}
+
+impl FieldSchema {
+    pub fn new(name: &str, field_type: SchemaFieldType) -> Self {
+        FieldSchema {
+            name: name.to_string(),
+            field_type,
+            encrypted: false,
+            queryable: false,
+            searchable: false,
+            sortable: false,
+        }
+    }
+
+    pub fn queryable(mut self) -> Self {
+        self.queryable = true;
+        self
+    }
+
+    pub fn searchable(mut self) -> Self {
+        self.searchable = true;
+        self
+    }
+
+    pub fn sortable(mut self) -> Self {
+        self.sortable = true;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    Create,
+    Read,
+    Write,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::Create => write!(f, "CREATE"),
+            Permission::Read => write!(f, "READ"),
+            Permission::Write => write!(f, "WRITE"),
+        }
+    }
+}
+
+/// `GRANT READ, WRITE TO "role"`. The built-in roles are `_world`,
+/// `_icloud` and `_creator`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grant {
+    pub permissions: Vec<Permission>,
+    pub role: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordTypeSchema {
+    pub name: String,
+    pub fields: Vec<FieldSchema>,
+    pub grants: Vec<Grant>,
+}
+
+impl RecordTypeSchema {
+    pub fn new(name: &str) -> Self {
+        RecordTypeSchema {
+            name: name.to_string(),
+            fields: Vec::new(),
+            grants: Vec::new(),
+        }
+    }
+
+    pub fn with_field(mut self, field: FieldSchema) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    pub fn with_grant(mut self, permissions: &[Permission], role: &str) -> Self {
+        self.grants.push(Grant {
+            permissions: permissions.to_vec(),
+            role: role.to_string(),
+        });
+        self
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+/// A problem found by `Schema::record_violations` or
+/// `Schema::query_violations`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaViolation {
+    UnknownRecordType(String),
+    UnknownField {
+        record_type: String,
+        field: String,
+    },
+    TypeMismatch {
+        record_type: String,
+        field: String,
+        expected: SchemaFieldType,
+        found: SchemaFieldType,
+    },
+    NotQueryable {
+        record_type: String,
+        field: String,
+    },
+    NotSearchable {
+        record_type: String,
+        field: String,
+    },
+    NotSortable {
+        record_type: String,
+        field: String,
+    },
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaViolation::UnknownRecordType(name) => write!(f, "unknown record type {}", name),
+            SchemaViolation::UnknownField { record_type, field } => {
+                write!(f, "{}.{} is not in the schema", record_type, field)
+            }
+            SchemaViolation::TypeMismatch {
+                record_type,
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}.{} expects {} but got {}",
+                record_type, field, expected, found
+            ),
+            SchemaViolation::NotQueryable { record_type, field } => {
+                write!(f, "{}.{} is not QUERYABLE", record_type, field)
+            }
+            SchemaViolation::NotSearchable { record_type, field } => {
+                write!(f, "{}.{} is not SEARCHABLE", record_type, field)
+            }
+            SchemaViolation::NotSortable { record_type, field } => {
+                write!(f, "{}.{} is not SORTABLE", record_type, field)
+            }
+        }
+    }
+}
+
+/// A container schema in the `.ckdb` format used by `cktool export-schema`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Schema {
+    pub roles: Vec<String>,
+    pub record_types: Vec<RecordTypeSchema>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.roles.push(role.to_string());
+        self
+    }
+
+    pub fn with_record_type(mut self, record_type: RecordTypeSchema) -> Self {
+        self.record_types.push(record_type);
+        self
+    }
+
+    pub fn record_type(&self, name: &str) -> Option<&RecordTypeSchema> {
+        self.record_types.iter().find(|r| r.name == name)
+    }
+
+    pub fn parse(input: &str) -> Result<Self, AppleError> {
+        Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+        }
+        .schema()
+    }
+
+    pub fn to_ckdb(&self) -> String {
+        let mut out = String::from("DEFINE SCHEMA\n");
+        for role in &self.roles {
+            out.push_str(&format!("\n    CREATE ROLE {};\n", identifier(role)));
+        }
+        for record_type in &self.record_types {
+            let mut items: Vec<String> = record_type
+                .fields
+                .iter()
+                .map(|field| {
+                    let mut line = format!("{} ", identifier(&field.name));
+                    if field.encrypted {
+                        line.push_str("ENCRYPTED ");
+                    }
+                    line.push_str(&field.field_type.to_string());
+                    for (set, option) in [
+                        (field.queryable, " QUERYABLE"),
+                        (field.searchable, " SEARCHABLE"),
+                        (field.sortable, " SORTABLE"),
+                    ] {
+                        if set {
+                            line.push_str(option);
+                        }
+                    }
+                    line
+                })
+                .collect();
+            items.extend(record_type.grants.iter().map(|grant| {
+                let permissions: Vec<String> =
+                    grant.permissions.iter().map(|p| p.to_string()).collect();
+                format!("GRANT {} TO \"{}\"", permissions.join(", "), grant.role)
+            }));
+
+            out.push_str(&format!(
+                "\n    RECORD TYPE {} (\n",
+                identifier(&record_type.name)
+            ));
+            out.push_str(&format!("        {}\n", items.join(",\n        ")));
+            out.push_str("    );\n");
+        }
+        out
+    }
+
+    /// Unknown record types, unknown fields and values whose `FieldValue`
+    /// variant does not match the schema.
+    pub fn record_violations(&self, record: &Record) -> Vec<SchemaViolation> {
+        let Some(schema) = self.record_type(&record.record_type) else {
+            return vec![SchemaViolation::UnknownRecordType(
+                record.record_type.clone(),
+            )];
+        };
+
+        let mut names: Vec<&String> = record.fields.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let value = &record.fields[name];
+                match schema.field(name) {
+                    None => Some(SchemaViolation::UnknownField {
+                        record_type: schema.name.clone(),
+                        field: name.clone(),
+                    }),
+                    Some(field) => type_violation(schema, field, value, &field.field_type),
+                }
+            })
+            .collect()
+    }
+
+    /// Filters on fields that are not QUERYABLE (or SEARCHABLE for token
+    /// comparators), sorts on fields that are not SORTABLE, and filter values
+    /// of the wrong type.
+    pub fn query_violations(&self, query: &Query) -> Vec<SchemaViolation> {
+        let Some(schema) = self.record_type(&query.record_type) else {
+            return vec![SchemaViolation::UnknownRecordType(
+                query.record_type.clone(),
+            )];
+        };
+        let unknown = |field: &str| SchemaViolation::UnknownField {
+            record_type: schema.name.clone(),
+            field: field.to_string(),
+        };
+
+        let mut violations = Vec::new();
+        for filter in query.filter_by.iter().flatten() {
+            match schema.field(&filter.field_name) {
+                None => violations.push(unknown(&filter.field_name)),
+                Some(field) => violations.extend(filter_violations(schema, field, filter)),
+            }
+        }
+        for sort in query.sort_by.iter().flatten() {
+            match schema.field(&sort.field_name) {
+                None => violations.push(unknown(&sort.field_name)),
+                Some(field) if !field.sortable => violations.push(SchemaViolation::NotSortable {
+                    record_type: schema.name.clone(),
+                    field: field.name.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        violations
+    }
+
+    pub fn check_record(&self, record: &Record) -> Result<(), AppleError> {
+        into_result(self.record_violations(record))
+    }
+
+    pub fn check_query(&self, query: &Query) -> Result<(), AppleError> {
+        into_result(self.query_violations(query))
+    }
+}
+
+fn into_result(violations: Vec<SchemaViolation>) -> Result<(), AppleError> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let messages: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+    Err(AppleError::ValidationError(messages.join("; ")))
+}
+
+fn type_violation(
+    schema: &RecordTypeSchema,
+    field: &FieldSchema,
+    value: &FieldValue,
+    expected: &SchemaFieldType,
+) -> Option<SchemaViolation> {
+    let found = SchemaFieldType::of(value);
+    (found != *expected).then(|| SchemaViolation::TypeMismatch {
+        record_type: schema.name.clone(),
+        field: field.name.clone(),
+        expected: expected.clone(),
+        found,
+    })
+}
+
+fn filter_violations(
+    schema: &RecordTypeSchema,
+    field: &FieldSchema,
+    filter: &Filter,
+) -> Vec<SchemaViolation> {
+    let mut violations = Vec::new();
+    let token_search = matches!(
+        filter.comparator,
+        Comparator::ContainsAllTokens | Comparator::ContainsAnyTokens
+    );
+    if token_search && !field.searchable {
+        violations.push(SchemaViolation::NotSearchable {
+            record_type: schema.name.clone(),
+            field: field.name.clone(),
+        });
+    } else if !token_search && !field.queryable {
+        violations.push(SchemaViolation::NotQueryable {
+            record_type: schema.name.clone(),
+            field: field.name.clone(),
+        });
+    }
+
+    let expected = match (&filter.comparator, &field.field_type) {
+        (Comparator::In | Comparator::NotIn, scalar) => {
+            SchemaFieldType::List(Box::new(scalar.clone()))
+        }
+        (
+            Comparator::ListContains | Comparator::ListNotContains,
+            SchemaFieldType::List(element),
+        ) => (**element).clone(),
+        (_, field_type) => field_type.clone(),
+    };
+    violations.extend(type_violation(
+        schema,
+        field,
+        &filter.field_value,
+        &expected,
+    ));
+    violations
+}
+
+/// Quotes names that are not plain identifiers, such as the `___` system
+/// fields.
+fn identifier(name: &str) -> String {
+    let plain = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Symbol(char),
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, AppleError> {
+    let mut tokens = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line_number = index + 1;
+        let mut chars = line.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            match c {
+                c if c.is_whitespace() => {
+                    chars.next();
+                }
+                '/' | '-' => {
+                    chars.next();
+                    if chars.peek() == Some(&c) {
+                        break;
+                    }
+                    return Err(schema_error(line_number, &format!("unexpected '{}'", c)));
+                }
+                '"' => {
+                    chars.next();
+                    let mut value = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(ch) => value.push(ch),
+                            None => {
+                                return Err(schema_error(line_number, "unterminated quoted name"));
+                            }
+                        }
+                    }
+                    tokens.push((Token::Quoted(value), line_number));
+                }
+                '(' | ')' | ',' | ';' | '<' | '>' => {
+                    chars.next();
+                    tokens.push((Token::Symbol(c), line_number));
+                }
+                c if c.is_alphanumeric() || c == '_' => {
+                    let mut word = String::new();
+                    while let Some(&ch) = chars.peek() {
+                        if !(ch.is_alphanumeric() || ch == '_' || ch == '.') {
+                            break;
+                        }
+                        word.push(ch);
+                        chars.next();
+                    }
+                    tokens.push((Token::Word(word), line_number));
+                }
+                other => {
+                    return Err(schema_error(
+                        line_number,
+                        &format!("unexpected '{}'", other),
+                    ));
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn schema_error(line: usize, message: &str) -> AppleError {
+    AppleError::SchemaError(format!("line {}: {}", line, message))
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|(t, _)| t)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self, message: &str) -> AppleError {
+        schema_error(self.line(), message)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(t, _)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), AppleError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}", keyword)))
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<(), AppleError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", symbol)))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, AppleError> {
+        match self.next() {
+            Some(Token::Word(w)) | Some(Token::Quoted(w)) => Ok(w),
+            _ => {
+                self.pos -= 1;
+                Err(self.error("expected a name"))
+            }
+        }
+    }
+
+    fn schema(mut self) -> Result<Schema, AppleError> {
+        if self.eat_keyword("DEFINE") {
+            self.expect_keyword("SCHEMA")?;
+        }
+
+        let mut schema = Schema::new();
+        while self.peek().is_some() {
+            if self.eat_keyword("CREATE") {
+                self.expect_keyword("ROLE")?;
+                schema.roles.push(self.name()?);
+                self.expect_symbol(';')?;
+            } else if self.eat_keyword("RECORD") {
+                self.expect_keyword("TYPE")?;
+                schema.record_types.push(self.record_type()?);
+            } else {
+                return Err(self.error("expected CREATE ROLE or RECORD TYPE"));
+            }
+        }
+        Ok(schema)
+    }
+
+    fn record_type(&mut self) -> Result<RecordTypeSchema, AppleError> {
+        let mut record_type = RecordTypeSchema::new(&self.name()?);
+        self.expect_symbol('(')?;
+        if !self.eat_symbol(')') {
+            loop {
+                if self.eat_keyword("GRANT") {
+                    record_type.grants.push(self.grant()?);
+                } else {
+                    record_type.fields.push(self.field()?);
+                }
+                if self.eat_symbol(')') {
+                    break;
+                }
+                self.expect_symbol(',')?;
+            }
+        }
+        self.expect_symbol(';')?;
+        Ok(record_type)
+    }
+
+    fn permission(&mut self) -> Option<Permission> {
+        let permission = match self.peek() {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("CREATE") => Permission::Create,
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("READ") => Permission::Read,
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("WRITE") => Permission::Write,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(permission)
+    }
+
+    fn grant(&mut self) -> Result<Grant, AppleError> {
+        let mut permissions = vec![
+            self.permission()
+                .ok_or_else(|| self.error("expected CREATE, READ or WRITE"))?,
+        ];
+        while self.peek() == Some(&Token::Symbol(','))
+            && matches!(self.peek_at(1), Some(Token::Word(_)))
+        {
+            self.pos += 1;
+            match self.permission() {
+                Some(permission) => permissions.push(permission),
+                None => {
+                    self.pos -= 1;
+                    break;
+                }
+            }
+        }
+        self.expect_keyword("TO")?;
+        Ok(Grant {
+            permissions,
+            role: self.name()?,
+        })
+    }
+
+    fn field_type(&mut self) -> Result<SchemaFieldType, AppleError> {
+        if self.eat_keyword("LIST") {
+            self.expect_symbol('<')?;
+            let inner = self.field_type()?;
+            self.expect_symbol('>')?;
+            return Ok(SchemaFieldType::List(Box::new(inner)));
+        }
+        match self.next() {
+            Some(Token::Word(w)) => SchemaFieldType::parse_scalar(&w).ok_or_else(|| {
+                self.pos -= 1;
+                self.error(&format!("unknown field type {}", w))
+            }),
+            _ => {
+                self.pos -= 1;
+                Err(self.error("expected a field type"))
+            }
+        }
+    }
+
+    fn field(&mut self) -> Result<FieldSchema, AppleError> {
+        let name = self.name()?;
+        let encrypted = self.eat_keyword("ENCRYPTED");
+        let mut field = FieldSchema::new(&name, self.field_type()?);
+        field.encrypted = encrypted;
+        loop {
+            if self.eat_keyword("QUERYABLE") {
+                field.queryable = true;
+            } else if self.eat_keyword("SEARCHABLE") {
+                field.searchable = true;
+            } else if self.eat_keyword("SORTABLE") {
+                field.sortable = true;
+            } else {
+                break;
+            }
+        }
+        Ok(field)
+    }
+}
+
+impl CloudKitClient {
+    /// Validates records and queries against `schema` before they are sent.
+    /// Violations surface as `AppleError::ValidationError` instead of a
+    /// BAD_REQUEST from CloudKit.
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(Arc::new(schema));
+        self
+    }
+
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_deref()
+    }
+
+    pub(crate) fn check_record(
+        &self,
+        operation: &OperationType,
+        record: &Record,
+    ) -> Result<(), AppleError> {
+        match (&self.schema, operation) {
+            (_, OperationType::Delete | OperationType::ForceDelete) | (None, _) => Ok(()),
+            (Some(schema), _) => schema.check_record(record),
+        }
+    }
+
+    pub(crate) fn check_query(&self, query: &Query) -> Result<(), AppleError> {
+        match &self.schema {
+            Some(schema) => schema.check_query(query),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,556 @@
+use crate::cloudkit::client::CloudKitClient;
+use crate::cloudkit::records::{
+    ModifyRecordsRequest, ModifyRecordsResponse, RecordBody, RecordOperation, RecordResult,
+};
+use crate::cloudkit::types::*;
+use crate::error::AppleError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const SHARE_URL_PREFIX: &str = "https://www.icloud.com/share/";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ParticipantPermission {
+    #[serde(rename = "UNKNOWN")]
+    Unknown,
+    #[serde(rename = "NONE")]
+    None,
+    #[serde(rename = "READ_ONLY")]
+    ReadOnly,
+    #[serde(rename = "READ_WRITE")]
+    ReadWrite,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ParticipantType {
+    #[serde(rename = "UNKNOWN")]
+    Unknown,
+    #[serde(rename = "OWNER")]
+    Owner,
+    #[serde(rename = "ADMINISTRATOR")]
+    Administrator,
+    #[serde(rename = "USER")]
+    User,
+    #[serde(rename = "PUBLIC_USER")]
+    PublicUser,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ParticipantAcceptanceStatus {
+    #[serde(rename = "UNKNOWN")]
+    Unknown,
+    #[serde(rename = "PENDING")]
+    Pending,
+    #[serde(rename = "ACCEPTED")]
+    Accepted,
+    #[serde(rename = "REMOVED")]
+    Removed,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserLookupInfo {
+    #[serde(rename = "emailAddress", skip_serializing_if = "Option::is_none")]
+    pub email_address: Option<String>,
+    #[serde(rename = "phoneNumber", skip_serializing_if = "Option::is_none")]
+    pub phone_number: Option<String>,
+    #[serde(rename = "userRecordName", skip_serializing_if = "Option::is_none")]
+    pub user_record_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NameComponents {
+    #[serde(rename = "givenName", skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    #[serde(rename = "familyName", skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+    #[serde(rename = "middleName", skip_serializing_if = "Option::is_none")]
+    pub middle_name: Option<String>,
+    #[serde(rename = "namePrefix", skip_serializing_if = "Option::is_none")]
+    pub name_prefix: Option<String>,
+    #[serde(rename = "nameSuffix", skip_serializing_if = "Option::is_none")]
+    pub name_suffix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserIdentity {
+    #[serde(rename = "userRecordName", skip_serializing_if = "Option::is_none")]
+    pub user_record_name: Option<String>,
+    #[serde(rename = "lookupInfo", skip_serializing_if = "Option::is_none")]
+    pub lookup_info: Option<UserLookupInfo>,
+    #[serde(rename = "nameComponents", skip_serializing_if = "Option::is_none")]
+    pub name_components: Option<NameComponents>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareParticipant {
+    #[serde(rename = "participantId", skip_serializing_if = "Option::is_none")]
+    pub participant_id: Option<String>,
+    #[serde(rename = "userIdentity", default)]
+    pub user_identity: UserIdentity,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub participant_type: Option<ParticipantType>,
+    #[serde(rename = "acceptanceStatus", skip_serializing_if = "Option::is_none")]
+    pub acceptance_status: Option<ParticipantAcceptanceStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permission: Option<ParticipantPermission>,
+}
+
+impl ShareParticipant {
+    fn invite(lookup_info: UserLookupInfo, permission: ParticipantPermission) -> Self {
+        ShareParticipant {
+            participant_id: None,
+            user_identity: UserIdentity {
+                user_record_name: None,
+                lookup_info: Some(lookup_info),
+                name_components: None,
+            },
+            participant_type: Some(ParticipantType::User),
+            acceptance_status: None,
+            permission: Some(permission),
+        }
+    }
+
+    /// Invite a participant by the email address of their Apple Account.
+    pub fn by_email(email_address: &str, permission: ParticipantPermission) -> Self {
+        Self::invite(
+            UserLookupInfo {
+                email_address: Some(email_address.to_string()),
+                ..Default::default()
+            },
+            permission,
+        )
+    }
+
+    /// Invite a participant by the phone number of their Apple Account.
+    pub fn by_phone_number(phone_number: &str, permission: ParticipantPermission) -> Self {
+        Self::invite(
+            UserLookupInfo {
+                phone_number: Some(phone_number.to_string()),
+                ..Default::default()
+            },
+            permission,
+        )
+    }
+
+    /// Invite a participant by their CloudKit user record name.
+    pub fn by_user_record_name(user_record_name: &str, permission: ParticipantPermission) -> Self {
+        Self::invite(
+            UserLookupInfo {
+                user_record_name: Some(user_record_name.to_string()),
+                ..Default::default()
+            },
+            permission,
+        )
+    }
+}
+
+/// A `cloudkit.share` record, either sharing a single record hierarchy or a
+/// whole zone.
+#[derive(Debug, Clone)]
+pub struct Share {
+    pub record_name: Option<String>,
+    pub record_change_tag: Option<String>,
+    pub zone_id: Option<ZoneID>,
+    pub short_guid: Option<String>,
+    pub public_permission: Option<ParticipantPermission>,
+    pub participants: Vec<ShareParticipant>,
+    pub owner: Option<ShareParticipant>,
+    pub current_user_participant: Option<ShareParticipant>,
+    pub fields: HashMap<String, FieldValue>,
+}
+
+impl Share {
+    pub const RECORD_TYPE: &'static str = "cloudkit.share";
+    pub const ZONE_WIDE_RECORD_NAME: &'static str = "cloudkit.zoneshare";
+    pub const TITLE_FIELD: &'static str = "cloudkit.title";
+
+    pub fn new() -> Self {
+        Share {
+            record_name: None,
+            record_change_tag: None,
+            zone_id: None,
+            short_guid: None,
+            public_permission: None,
+            participants: Vec::new(),
+            owner: None,
+            current_user_participant: None,
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.record_name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.fields.insert(
+            Self::TITLE_FIELD.to_string(),
+            FieldValue::String(title.to_string()),
+        );
+        self
+    }
+
+    pub fn with_public_permission(mut self, permission: ParticipantPermission) -> Self {
+        self.public_permission = Some(permission);
+        self
+    }
+
+    pub fn with_participant(mut self, participant: ShareParticipant) -> Self {
+        self.participants.push(participant);
+        self
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        match self.fields.get(Self::TITLE_FIELD) {
+            Some(FieldValue::String(title)) => Some(title),
+            _ => None,
+        }
+    }
+
+    pub fn is_zone_wide(&self) -> bool {
+        self.record_name.as_deref() == Some(Self::ZONE_WIDE_RECORD_NAME)
+    }
+
+    /// The `https://www.icloud.com/share/...` URL participants open to accept
+    /// the share. Only available once the server has assigned a short GUID.
+    pub fn url(&self) -> Option<String> {
+        self.short_guid
+            .as_ref()
+            .map(|guid| format!("{}{}", SHARE_URL_PREFIX, guid))
+    }
+
+    fn into_body(self) -> RecordBody {
+        RecordBody {
+            record_name: self.record_name,
+            record_type: Some(Self::RECORD_TYPE.to_string()),
+            record_change_tag: self.record_change_tag,
+            fields: if self.fields.is_empty() {
+                None
+            } else {
+                Some(self.fields)
+            },
+            share: None,
+            public_permission: self.public_permission,
+            participants: Some(self.participants),
+        }
+    }
+
+    fn from_result(result: RecordResult) -> Result<Self, AppleError> {
+        if let Some(err) = result.error() {
+            return Err(err);
+        }
+        Ok(Share {
+            record_name: result.record_name,
+            record_change_tag: result.record_change_tag,
+            zone_id: result.zone_id,
+            short_guid: result.short_guid,
+            public_permission: result.public_permission,
+            participants: result.participants,
+            owner: result.owner,
+            current_user_participant: result.current_user_participant,
+            fields: result.fields,
+        })
+    }
+}
+
+impl Default for Share {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Extracts the short GUID from a share URL such as
+/// `https://www.icloud.com/share/0abcDEF#Title`.
+pub fn short_guid_from_url(share_url: &str) -> Option<String> {
+    let rest = share_url.strip_prefix(SHARE_URL_PREFIX)?;
+    let guid = rest.split(['#', '?', '/']).next()?;
+    if guid.is_empty() {
+        None
+    } else {
+        Some(guid.to_string())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ShortGUIDsRequest {
+    #[serde(rename = "shortGUIDs")]
+    short_guids: Vec<ShortGUID>,
+}
+
+#[derive(Debug, Serialize)]
+struct ShortGUID {
+    value: String,
+    #[serde(
+        rename = "shouldFetchRootRecord",
+        skip_serializing_if = "Option::is_none"
+    )]
+    should_fetch_root_record: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShareMetadataResponse {
+    results: Vec<ShareMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShareMetadata {
+    #[serde(rename = "shortGUID")]
+    pub short_guid: Option<String>,
+    #[serde(rename = "containerIdentifier")]
+    pub container_identifier: Option<String>,
+    pub environment: Option<String>,
+    #[serde(rename = "databaseScope")]
+    pub database_scope: Option<String>,
+    pub share: Option<RecordResult>,
+    #[serde(rename = "rootRecordName")]
+    pub root_record_name: Option<String>,
+    #[serde(rename = "rootRecord")]
+    pub root_record: Option<RecordResult>,
+    #[serde(rename = "zoneID")]
+    pub zone_id: Option<ZoneID>,
+    #[serde(rename = "ownerIdentity")]
+    pub owner_identity: Option<UserIdentity>,
+    #[serde(rename = "participantType")]
+    pub participant_type: Option<ParticipantType>,
+    #[serde(rename = "participantStatus")]
+    pub participant_status: Option<ParticipantAcceptanceStatus>,
+    #[serde(rename = "participantPermission")]
+    pub participant_permission: Option<ParticipantPermission>,
+    #[serde(rename = "serverErrorCode")]
+    pub server_error_code: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl ShareMetadata {
+    /// The zone to use with `DatabaseType::Shared` to read the shared records.
+    pub fn shared_zone_id(&self) -> Option<ZoneID> {
+        let zone_id = self.zone_id.clone()?;
+        if zone_id.owner_record_name.is_some() {
+            return Some(zone_id);
+        }
+        let owner = self.owner_identity.as_ref()?.user_record_name.as_deref()?;
+        Some(ZoneID::with_owner(&zone_id.zone_name, owner))
+    }
+}
+
+fn require_shareable_database(db: &DatabaseType) -> Result<(), AppleError> {
+    if *db == DatabaseType::Public {
+        return Err(AppleError::ValidationError(
+            "Shares cannot be stored in the public database".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl CloudKitClient {
+    /// Shares `root` and its child records. The share and the root record's
+    /// `share` reference are saved atomically in the root's private zone.
+    pub async fn share_record(&self, root: Record, share: Share) -> Result<Share, AppleError> {
+        let zone_id = root.zone_id.clone().ok_or_else(|| {
+            AppleError::ValidationError("Shared records must live in a custom zone".to_string())
+        })?;
+        let root_name = root.record_name.clone().ok_or_else(|| {
+            AppleError::ValidationError("Root record must have a record name".to_string())
+        })?;
+        if zone_id.zone_name == ZoneID::default_zone().zone_name {
+            return Err(AppleError::ValidationError(
+                "Records in the default zone cannot be shared".to_string(),
+            ));
+        }
+
+        let mut share = share;
+        let share_name = share
+            .record_name
+            .get_or_insert_with(|| format!("share-{}", root_name))
+            .clone();
+        share.zone_id = Some(zone_id.clone());
+
+        let url = self.build_url(&DatabaseType::Private, "records/modify");
+        let request = ModifyRecordsRequest {
+            operations: vec![
+                RecordOperation {
+                    operation_type: OperationType::Create,
+                    record: share.into_body(),
+                },
+                RecordOperation {
+                    operation_type: OperationType::Update,
+                    record: RecordBody {
+                        record_name: Some(root_name),
+                        record_type: Some(root.record_type),
+                        record_change_tag: root.record_change_tag,
+                        share: Some(ReferenceValue {
+                            record_name: share_name,
+                            zone_id: Some(zone_id.clone()),
+                            action: None,
+                        }),
+                        ..Default::default()
+                    },
+                },
+            ],
+            zone_id: Some(zone_id),
+            atomic: Some(true),
+        };
+
+        let response: ModifyRecordsResponse = self.signed_post(&url, &request).await?;
+        Self::find_share(response.records)
+    }
+
+    /// Shares every record in `zone_id` through a zone-wide share.
+    pub async fn share_zone(&self, zone_id: ZoneID, share: Share) -> Result<Share, AppleError> {
+        let mut share = share.with_name(Share::ZONE_WIDE_RECORD_NAME);
+        share.zone_id = Some(zone_id.clone());
+
+        let url = self.build_url(&DatabaseType::Private, "records/modify");
+        let request = ModifyRecordsRequest {
+            operations: vec![RecordOperation {
+                operation_type: OperationType::Create,
+                record: share.into_body(),
+            }],
+            zone_id: Some(zone_id),
+            atomic: None,
+        };
+
+        let response: ModifyRecordsResponse = self.signed_post(&url, &request).await?;
+        Self::find_share(response.records)
+    }
+
+    /// Fetches a share from the owner's private database or, for a
+    /// participant, from the shared database.
+    pub async fn lookup_share(
+        &self,
+        db: &DatabaseType,
+        share_record_name: &str,
+        zone_id: ZoneID,
+    ) -> Result<Share, AppleError> {
+        require_shareable_database(db)?;
+
+        #[derive(Serialize)]
+        struct LookupRequest {
+            records: Vec<Lookup>,
+            #[serde(rename = "zoneID")]
+            zone_id: ZoneID,
+        }
+
+        #[derive(Serialize)]
+        struct Lookup {
+            #[serde(rename = "recordName")]
+            record_name: String,
+        }
+
+        let url = self.build_url(db, "records/lookup");
+        let request = LookupRequest {
+            records: vec![Lookup {
+                record_name: share_record_name.to_string(),
+            }],
+            zone_id,
+        };
+
+        let response: ModifyRecordsResponse = self.signed_post(&url, &request).await?;
+        Self::find_share(response.records)
+    }
+
+    /// Saves changes to a share's participants or public permission. The
+    /// share must carry the change tag it was fetched with.
+    pub async fn update_share(&self, db: &DatabaseType, share: Share) -> Result<Share, AppleError> {
+        require_shareable_database(db)?;
+        if share.record_name.is_none() {
+            return Err(AppleError::ValidationError(
+                "Share must have a record name".to_string(),
+            ));
+        }
+
+        let url = self.build_url(db, "records/modify");
+        let zone_id = share.zone_id.clone();
+        let request = ModifyRecordsRequest {
+            operations: vec![RecordOperation {
+                operation_type: OperationType::Update,
+                record: share.into_body(),
+            }],
+            zone_id,
+            atomic: None,
+        };
+
+        let response: ModifyRecordsResponse = self.signed_post(&url, &request).await?;
+        Self::find_share(response.records)
+    }
+
+    /// Stops sharing. Deleting a share from the shared database removes the
+    /// current user as a participant instead.
+    pub async fn delete_share(
+        &self,
+        db: &DatabaseType,
+        share_record_name: &str,
+        zone_id: ZoneID,
+    ) -> Result<(), AppleError> {
+        require_shareable_database(db)?;
+        self.delete_record(db, share_record_name, Share::RECORD_TYPE, Some(zone_id))
+            .await
+    }
+
+    /// Resolves share URLs (or their short GUIDs) to share metadata without
+    /// accepting them.
+    pub async fn resolve_shares(
+        &self,
+        short_guids: &[&str],
+        should_fetch_root_record: bool,
+    ) -> Result<Vec<ShareMetadata>, AppleError> {
+        let url = self.build_url(&DatabaseType::Public, "records/resolve");
+        let request = ShortGUIDsRequest {
+            short_guids: short_guids
+                .iter()
+                .map(|guid| ShortGUID {
+                    value: guid.to_string(),
+                    should_fetch_root_record: Some(should_fetch_root_record),
+                })
+                .collect(),
+        };
+
+        let response: ShareMetadataResponse = self.signed_post(&url, &request).await?;
+        Ok(response.results)
+    }
+
+    /// Accepts shares on behalf of the current user. Accepted zones then
+    /// appear in `DatabaseType::Shared` under the owner's zone ID.
+    pub async fn accept_shares(
+        &self,
+        short_guids: &[&str],
+    ) -> Result<Vec<ShareMetadata>, AppleError> {
+        let url = self.build_url(&DatabaseType::Public, "records/accept");
+        let request = ShortGUIDsRequest {
+            short_guids: short_guids
+                .iter()
+                .map(|guid| ShortGUID {
+                    value: guid.to_string(),
+                    should_fetch_root_record: None,
+                })
+                .collect(),
+        };
+
+        let response: ShareMetadataResponse = self.signed_post(&url, &request).await?;
+        Ok(response.results)
+    }
+
+    /// Lists the zones other users have shared with the current user.
+    pub async fn list_shared_zones(&self) -> Result<Vec<Zone>, AppleError> {
+        self.list_zones(&DatabaseType::Shared).await
+    }
+
+    fn find_share(records: Vec<RecordResult>) -> Result<Share, AppleError> {
+        let mut first_error = None;
+        for result in records {
+            if let Some(err) = result.error() {
+                first_error.get_or_insert(err);
+                continue;
+            }
+            if result.record_type.as_deref() == Some(Share::RECORD_TYPE) {
+                return Share::from_result(result);
+            }
+        }
+        Err(first_error.unwrap_or_else(|| {
+            AppleError::JsonError("No share record in CloudKit response".to_string())
+        }))
+    }
+}
@@ -1,7 +1,9 @@
 use crate::cloudkit::client::CloudKitClient;
+use crate::cloudkit::error::item_error;
+use crate::cloudkit::query::Query;
 use crate::cloudkit::types::*;
 use crate::error::AppleError;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Debug, Serialize)]
 struct ModifySubscriptionsRequest {
@@ -11,13 +13,77 @@ struct ModifySubscriptionsRequest {
 #[derive(Debug, Serialize)]
 struct SubscriptionOperation {
     #[serde(rename = "operationType")]
-    operation_type: String,
+    operation_type: SubscriptionOperationType,
     subscription: Subscription,
 }
 
+#[derive(Debug, Serialize)]
+struct LookupSubscriptionsRequest {
+    subscriptions: Vec<SubscriptionLookup>,
+}
+
+#[derive(Debug, Serialize)]
+struct SubscriptionLookup {
+    #[serde(rename = "subscriptionID")]
+    subscription_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SubscriptionOperationType {
+    #[serde(rename = "create")]
+    Create,
+    #[serde(rename = "update")]
+    Update,
+    #[serde(rename = "delete")]
+    Delete,
+}
+
+/// One entry of a subscription response. Failed operations carry
+/// `server_error_code` instead of a subscription.
+#[derive(Debug, Clone)]
+pub struct SubscriptionResult {
+    pub subscription_id: Option<String>,
+    pub subscription: Option<Subscription>,
+    pub server_error_code: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for SubscriptionResult {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let text = |key: &str| value.get(key).and_then(|v| v.as_str()).map(String::from);
+        let server_error_code = text("serverErrorCode");
+        let subscription = match server_error_code {
+            Some(_) => None,
+            None => Some(serde_json::from_value(value.clone()).map_err(serde::de::Error::custom)?),
+        };
+        Ok(SubscriptionResult {
+            subscription_id: text("subscriptionID"),
+            subscription,
+            reason: text("reason"),
+            server_error_code,
+        })
+    }
+}
+
+impl SubscriptionResult {
+    pub fn error(&self) -> Option<AppleError> {
+        item_error(self.server_error_code.as_ref(), self.reason.as_ref())
+    }
+
+    pub fn into_subscription(self) -> Result<Subscription, AppleError> {
+        if let Some(err) = self.error() {
+            return Err(err);
+        }
+        self.subscription.ok_or_else(|| {
+            AppleError::JsonError("Subscription result without a subscription".to_string())
+        })
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ModifySubscriptionsResponse {
-    pub subscriptions: Vec<Subscription>,
+    pub subscriptions: Vec<SubscriptionResult>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -25,26 +91,243 @@ pub struct ListSubscriptionsResponse {
     pub subscriptions: Vec<Subscription>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LookupSubscriptionsResponse {
+    pub subscriptions: Vec<SubscriptionResult>,
+}
+
+/// What `ensure_subscriptions` changed, by subscription ID.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubscriptionReconciliation {
+    pub created: Vec<String>,
+    /// Subscriptions whose definition changed. They are updated in place.
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+impl Subscription {
+    /// Fires when records matching `query` are created, updated or deleted,
+    /// as selected by `fires_on`.
+    pub fn query(
+        subscription_id: &str,
+        query: Query,
+        fires_on: &[FiresOn],
+    ) -> Result<Self, AppleError> {
+        let subscription = Subscription {
+            fires_on: Some(fires_on.to_vec()),
+            query: Some(query),
+            ..Subscription::empty(subscription_id, SubscriptionType::Query)
+        };
+        subscription.validate()?;
+        Ok(subscription)
+    }
+
+    /// Fires on any change within a custom zone.
+    pub fn zone(subscription_id: &str, zone_id: ZoneID) -> Self {
+        Subscription {
+            zone_id: Some(zone_id),
+            ..Subscription::empty(subscription_id, SubscriptionType::Zone)
+        }
+    }
+
+    /// Fires on any change in the private or shared database.
+    pub fn database(subscription_id: &str) -> Self {
+        Subscription::empty(subscription_id, SubscriptionType::Database)
+    }
+
+    fn empty(subscription_id: &str, subscription_type: SubscriptionType) -> Self {
+        Subscription {
+            subscription_id: Some(subscription_id.to_string()),
+            subscription_type,
+            query: None,
+            fires_on: None,
+            fires_on_record_creation: None,
+            fires_on_record_update: None,
+            fires_on_record_deletion: None,
+            notification_info: None,
+            zone_id: None,
+        }
+    }
+
+    pub fn with_notification_info(mut self, notification_info: NotificationInfo) -> Self {
+        self.notification_info = Some(notification_info);
+        self
+    }
+
+    /// Limits a query subscription to one zone.
+    pub fn with_zone(mut self, zone_id: ZoneID) -> Self {
+        self.zone_id = Some(zone_id);
+        self
+    }
+
+    /// Checks that the fields set match the subscription type.
+    pub fn validate(&self) -> Result<(), AppleError> {
+        let invalid = |msg: &str| Err(AppleError::ValidationError(msg.to_string()));
+        let has_fires_on = self.fires_on.as_ref().is_some_and(|f| !f.is_empty())
+            || self.fires_on_record_creation.is_some()
+            || self.fires_on_record_update.is_some()
+            || self.fires_on_record_deletion.is_some();
+
+        match self.subscription_type {
+            SubscriptionType::Query => match &self.query {
+                None => invalid("Query subscriptions require a query"),
+                Some(query) if query.record_type.is_empty() => {
+                    invalid("Query subscriptions require a record type")
+                }
+                Some(_) if !has_fires_on => {
+                    invalid("Query subscriptions must fire on at least one change")
+                }
+                Some(_) => Ok(()),
+            },
+            SubscriptionType::Zone if self.zone_id.is_none() => {
+                invalid("Zone subscriptions require a zone")
+            }
+            SubscriptionType::Zone | SubscriptionType::Database if self.query.is_some() => {
+                invalid("Only query subscriptions take a query")
+            }
+            SubscriptionType::Zone | SubscriptionType::Database if has_fires_on => {
+                invalid("Only query subscriptions take firesOn")
+            }
+            SubscriptionType::Database if self.zone_id.is_some() => {
+                invalid("Database subscriptions cannot be limited to a zone")
+            }
+            SubscriptionType::Zone | SubscriptionType::Database => Ok(()),
+        }
+    }
+
+    /// The definition without order-only differences in `firesOn`.
+    fn definition(&self) -> serde_json::Value {
+        let mut normalized = self.clone();
+        if let Some(fires_on) = &mut normalized.fires_on {
+            fires_on.sort();
+            fires_on.dedup();
+        }
+        serde_json::to_value(&normalized).unwrap_or_default()
+    }
+
+    /// Whether `current` already has every field set on `self`. Fields the
+    /// caller left unset, and anything the server filled in, are ignored.
+    fn is_satisfied_by(&self, current: &Subscription) -> bool {
+        fn covers(wanted: &serde_json::Value, current: &serde_json::Value) -> bool {
+            use serde_json::Value;
+            match (wanted, current) {
+                (Value::Object(wanted), Value::Object(current)) => wanted
+                    .iter()
+                    .all(|(key, value)| current.get(key).is_some_and(|c| covers(value, c))),
+                (Value::Array(wanted), Value::Array(current)) => {
+                    wanted.len() == current.len()
+                        && wanted.iter().zip(current).all(|(w, c)| covers(w, c))
+                }
+                _ => wanted == current,
+            }
+        }
+        covers(&self.definition(), &current.definition())
+    }
+}
+
+impl NotificationInfo {
+    /// A background push with `content-available` and no alert.
+    pub fn silent() -> Self {
+        NotificationInfo {
+            should_send_content_available: Some(true),
+            ..Default::default()
+        }
+    }
+
+    pub fn alert(body: &str) -> Self {
+        NotificationInfo {
+            alert_body: Some(body.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// An alert looked up from the app's localized strings.
+    pub fn localized_alert(key: &str, args: &[&str]) -> Self {
+        NotificationInfo {
+            alert_localization_key: Some(key.to_string()),
+            alert_localization_args: if args.is_empty() {
+                None
+            } else {
+                Some(args.iter().map(|a| a.to_string()).collect())
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn with_sound(mut self, sound_name: &str) -> Self {
+        self.sound_name = Some(sound_name.to_string());
+        self
+    }
+
+    pub fn with_badge(mut self) -> Self {
+        self.should_badge = Some(true);
+        self
+    }
+
+    pub fn with_content_available(mut self) -> Self {
+        self.should_send_content_available = Some(true);
+        self
+    }
+
+    pub fn with_mutable_content(mut self) -> Self {
+        self.should_send_mutable_content = Some(true);
+        self
+    }
+
+    pub fn with_category(mut self, category: &str) -> Self {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    pub fn with_collapse_id(mut self, collapse_id_key: &str) -> Self {
+        self.collapse_id_key = Some(collapse_id_key.to_string());
+        self
+    }
+
+    /// Record fields to include in the notification payload.
+    pub fn with_desired_keys(mut self, keys: &[&str]) -> Self {
+        self.desired_keys = Some(keys.iter().map(|k| k.to_string()).collect());
+        self
+    }
+}
+
+fn first_subscription(results: Vec<SubscriptionResult>) -> Result<Subscription, AppleError> {
+    results
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppleError::JsonError("Empty subscription response".to_string()))?
+        .into_subscription()
+}
+
 impl CloudKitClient {
     pub async fn create_subscription(
         &self,
         db: &DatabaseType,
         subscription: Subscription,
     ) -> Result<Subscription, AppleError> {
-        let url = self.build_url(db, "subscriptions/modify");
-        let request = ModifySubscriptionsRequest {
-            operations: vec![SubscriptionOperation {
-                operation_type: "create".to_string(),
-                subscription,
-            }],
-        };
+        let results = self
+            .modify_subscriptions(db, vec![(SubscriptionOperationType::Create, subscription)])
+            .await?;
+        first_subscription(results)
+    }
 
-        let response: ModifySubscriptionsResponse = self.signed_post(&url, &request).await?;
-        response
-            .subscriptions
-            .into_iter()
-            .next()
-            .ok_or_else(|| AppleError::JsonError("Empty subscription response".to_string()))
+    /// Replaces an existing subscription's definition. The subscription must
+    /// carry its ID.
+    pub async fn update_subscription(
+        &self,
+        db: &DatabaseType,
+        subscription: Subscription,
+    ) -> Result<Subscription, AppleError> {
+        if subscription.subscription_id.is_none() {
+            return Err(AppleError::ValidationError(
+                "Updating a subscription requires its subscriptionID".to_string(),
+            ));
+        }
+        let results = self
+            .modify_subscriptions(db, vec![(SubscriptionOperationType::Update, subscription)])
+            .await?;
+        first_subscription(results)
     }
 
     pub async fn delete_subscription(
@@ -53,26 +336,42 @@ impl CloudKitClient {
         subscription_id: &str,
         subscription_type: SubscriptionType,
     ) -> Result<(), AppleError> {
+        let subscription = Subscription::empty(subscription_id, subscription_type);
+        let results = self
+            .modify_subscriptions(db, vec![(SubscriptionOperationType::Delete, subscription)])
+            .await?;
+        match results.first().and_then(SubscriptionResult::error) {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends several subscription operations in one request and returns a
+    /// result per operation, in order. Creates and updates are validated
+    /// first.
+    pub async fn modify_subscriptions(
+        &self,
+        db: &DatabaseType,
+        operations: Vec<(SubscriptionOperationType, Subscription)>,
+    ) -> Result<Vec<SubscriptionResult>, AppleError> {
+        for (operation_type, subscription) in &operations {
+            if *operation_type != SubscriptionOperationType::Delete {
+                subscription.validate()?;
+            }
+        }
         let url = self.build_url(db, "subscriptions/modify");
         let request = ModifySubscriptionsRequest {
-            operations: vec![SubscriptionOperation {
-                operation_type: "delete".to_string(),
-                subscription: Subscription {
-                    subscription_id: Some(subscription_id.to_string()),
-                    subscription_type,
-                    query: None,
-                    fires_on: None,
-                    fires_on_record_creation: None,
-                    fires_on_record_update: None,
-                    fires_on_record_deletion: None,
-                    notification_info: None,
-                    zone_id: None,
-                },
-            }],
+            operations: operations
+                .into_iter()
+                .map(|(operation_type, subscription)| SubscriptionOperation {
+                    operation_type,
+                    subscription,
+                })
+                .collect(),
         };
 
-        let _response: ModifySubscriptionsResponse = self.signed_post(&url, &request).await?;
-        Ok(())
+        let response: ModifySubscriptionsResponse = self.signed_post(&url, &request).await?;
+        Ok(response.subscriptions)
     }
 
     pub async fn list_subscriptions(
@@ -87,4 +386,91 @@ impl CloudKitClient {
         let response: ListSubscriptionsResponse = self.signed_post(&url, &EmptyBody {}).await?;
         Ok(response.subscriptions)
     }
+
+    /// Fetches specific subscriptions. Unknown IDs come back as `NOT_FOUND`
+    /// results.
+    pub async fn lookup_subscriptions(
+        &self,
+        db: &DatabaseType,
+        subscription_ids: &[&str],
+    ) -> Result<Vec<SubscriptionResult>, AppleError> {
+        let url = self.build_url(db, "subscriptions/lookup");
+        let request = LookupSubscriptionsRequest {
+            subscriptions: subscription_ids
+                .iter()
+                .map(|id| SubscriptionLookup {
+                    subscription_id: id.to_string(),
+                })
+                .collect(),
+        };
+
+        let response: LookupSubscriptionsResponse = self.signed_post(&url, &request).await?;
+        Ok(response.subscriptions)
+    }
+
+    /// Makes the database's subscriptions match `desired`: missing ones are
+    /// created, changed ones updated, and any others deleted. Only the fields
+    /// set on a desired subscription are compared. Running it
+    /// again with the same set changes nothing. Every desired subscription
+    /// needs an ID.
+    pub async fn ensure_subscriptions(
+        &self,
+        db: &DatabaseType,
+        desired: Vec<Subscription>,
+    ) -> Result<SubscriptionReconciliation, AppleError> {
+        let mut ids = Vec::new();
+        for subscription in &desired {
+            subscription.validate()?;
+            let id = subscription.subscription_id.clone().ok_or_else(|| {
+                AppleError::ValidationError(
+                    "ensure_subscriptions requires every subscription to have an ID".to_string(),
+                )
+            })?;
+            if ids.contains(&id) {
+                return Err(AppleError::ValidationError(format!(
+                    "Duplicate subscription ID: {}",
+                    id
+                )));
+            }
+            ids.push(id);
+        }
+
+        let existing = self.list_subscriptions(db).await?;
+        let mut plan = SubscriptionReconciliation::default();
+        let mut operations = Vec::new();
+
+        for current in existing {
+            let id = current.subscription_id.clone().unwrap_or_default();
+            match desired
+                .iter()
+                .find(|d| d.subscription_id.as_deref() == Some(id.as_str()))
+            {
+                Some(wanted) if wanted.is_satisfied_by(&current) => {
+                    plan.unchanged.push(id);
+                }
+                Some(_) => plan.updated.push(id),
+                None => {
+                    plan.deleted.push(id);
+                    operations.push((SubscriptionOperationType::Delete, current));
+                }
+            }
+        }
+        for wanted in desired {
+            let id = wanted.subscription_id.clone().unwrap_or_default();
+            if plan.updated.contains(&id) {
+                operations.push((SubscriptionOperationType::Update, wanted));
+            } else if !plan.unchanged.contains(&id) {
+                plan.created.push(id);
+                operations.push((SubscriptionOperationType::Create, wanted));
+            }
+        }
+
+        if !operations.is_empty() {
+            let results = self.modify_subscriptions(db, operations).await?;
+            if let Some(err) = results.iter().find_map(SubscriptionResult::error) {
+                return Err(err);
+            }
+        }
+        Ok(plan)
+    }
 }
@@ -0,0 +1,455 @@
+use crate::cloudkit::client::CloudKitClient;
+use crate::cloudkit::notifications::{
+    CKNotification, CKQueryNotification, DatabaseScope, QueryNotificationReason,
+};
+use crate::cloudkit::records::RecordResult;
+use crate::cloudkit::types::{DatabaseType, Record, ZoneID};
+use crate::error::{AppleError, CloudKitErrorCode};
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// What the dispatcher found while following up on notifications.
+#[derive(Debug, Clone)]
+pub enum SyncEvent {
+    /// One page of `changes/zone` results, already applied to the stored
+    /// sync token.
+    ZoneChanged {
+        database: DatabaseType,
+        zone_id: ZoneID,
+        records: Vec<RecordResult>,
+    },
+    ZoneDeleted {
+        database: DatabaseType,
+        zone_id: ZoneID,
+    },
+    /// The current state of a record named by a query notification.
+    RecordChanged {
+        database: DatabaseType,
+        record: Box<Record>,
+    },
+    RecordDeleted {
+        database: DatabaseType,
+        record_name: String,
+    },
+    /// Notifications were pruned in a database without change tracking, so
+    /// the application has to re-run its own queries.
+    CatchUpRequired { database: DatabaseType },
+    /// `database` is `None` when the notification stream itself failed.
+    Failed {
+        database: Option<DatabaseType>,
+        zone_id: Option<ZoneID>,
+        error: AppleError,
+    },
+}
+
+type Handler = Box<dyn Fn(&SyncEvent) + Send + Sync>;
+
+fn same_zone(a: &ZoneID, b: &ZoneID) -> bool {
+    a.zone_name == b.zone_name && a.owner_record_name == b.owner_record_name
+}
+
+/// Follow-up work collected from notifications. Repeats of the same zone,
+/// database or record collapse into one entry.
+#[derive(Default)]
+struct PendingWork {
+    catch_up: Vec<DatabaseType>,
+    databases: Vec<DatabaseType>,
+    zones: Vec<(DatabaseType, ZoneID)>,
+    /// Record name, zone and whether the last notification was a deletion.
+    records: Vec<(DatabaseType, String, Option<ZoneID>, bool)>,
+}
+
+impl PendingWork {
+    fn is_empty(&self) -> bool {
+        self.catch_up.is_empty()
+            && self.databases.is_empty()
+            && self.zones.is_empty()
+            && self.records.is_empty()
+    }
+
+    fn add_database(list: &mut Vec<DatabaseType>, database: DatabaseType) {
+        if !list.contains(&database) {
+            list.push(database);
+        }
+    }
+
+    fn add_zone(&mut self, database: DatabaseType, zone_id: ZoneID) {
+        if !self
+            .zones
+            .iter()
+            .any(|(db, zone)| *db == database && same_zone(zone, &zone_id))
+        {
+            self.zones.push((database, zone_id));
+        }
+    }
+
+    fn add_record(&mut self, notification: CKQueryNotification, database: DatabaseType) {
+        let Some(record_name) = notification
+            .record_id
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+        else {
+            return;
+        };
+        let zone_id = notification
+            .zone_id
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .map(|name| match &notification.zone_owner {
+                Some(owner) => ZoneID::with_owner(name, owner),
+                None => ZoneID::new(name),
+            });
+        let deleted = notification.reason == Some(QueryNotificationReason::RecordDeleted);
+
+        self.records
+            .retain(|(db, name, _, _)| !(*db == database && *name == record_name));
+        self.records.push((database, record_name, zone_id, deleted));
+    }
+}
+
+fn database_for(scope: &Option<DatabaseScope>) -> DatabaseType {
+    match scope {
+        Some(DatabaseScope::Public) => DatabaseType::Public,
+        Some(DatabaseScope::Shared) => DatabaseType::Shared,
+        Some(DatabaseScope::Private) | None => DatabaseType::Private,
+    }
+}
+
+fn zone_key(database: &DatabaseType, zone_id: &ZoneID) -> String {
+    format!(
+        "{}/{}/{}",
+        database,
+        zone_id.owner_record_name.as_deref().unwrap_or_default(),
+        zone_id.zone_name
+    )
+}
+
+/// Turns CloudKit notifications into incremental syncs. Notifications are
+/// queued with `enqueue` and coalesced until `flush`:
+///
+/// - zone notifications fetch `changes/zone` for that zone,
+/// - database notifications fetch `changes/database`, then each changed zone,
+/// - query notifications look up (or report the deletion of) the record,
+/// - pruned notifications sync every zone in the database.
+///
+/// Sync tokens are kept in memory; seed them with `with_database_token` and
+/// `with_zone_token` and read them back to persist progress.
+pub struct SyncDispatcher<'a> {
+    client: &'a CloudKitClient,
+    handlers: Vec<Handler>,
+    pending: Mutex<PendingWork>,
+    database_tokens: Mutex<HashMap<String, String>>,
+    zone_tokens: Mutex<HashMap<String, String>>,
+}
+
+impl<'a> SyncDispatcher<'a> {
+    pub fn new(client: &'a CloudKitClient) -> Self {
+        SyncDispatcher {
+            client,
+            handlers: Vec::new(),
+            pending: Mutex::new(PendingWork::default()),
+            database_tokens: Mutex::new(HashMap::new()),
+            zone_tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Registers a handler. Every handler sees every event, in registration
+    /// order.
+    pub fn with_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&SyncEvent) + Send + Sync + 'static,
+    {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    pub fn with_database_token(self, database: &DatabaseType, sync_token: &str) -> Self {
+        lock(&self.database_tokens).insert(database.to_string(), sync_token.to_string());
+        self
+    }
+
+    pub fn with_zone_token(
+        self,
+        database: &DatabaseType,
+        zone_id: &ZoneID,
+        sync_token: &str,
+    ) -> Self {
+        lock(&self.zone_tokens).insert(zone_key(database, zone_id), sync_token.to_string());
+        self
+    }
+
+    pub fn database_token(&self, database: &DatabaseType) -> Option<String> {
+        lock(&self.database_tokens)
+            .get(&database.to_string())
+            .cloned()
+    }
+
+    pub fn zone_token(&self, database: &DatabaseType, zone_id: &ZoneID) -> Option<String> {
+        lock(&self.zone_tokens)
+            .get(&zone_key(database, zone_id))
+            .cloned()
+    }
+
+    /// Queues the follow-up for a notification without running it.
+    pub fn enqueue(&self, notification: CKNotification) {
+        let mut pending = lock(&self.pending);
+        match notification {
+            CKNotification::Query(n) => {
+                let database = database_for(&n.database_scope);
+                if n.is_pruned == Some(true) {
+                    PendingWork::add_database(&mut pending.catch_up, database);
+                } else {
+                    pending.add_record(n, database);
+                }
+            }
+            CKNotification::RecordZone(n) => {
+                let database = database_for(&n.database_scope);
+                let zone_name = n.zone_id.as_ref().and_then(|v| v.as_str());
+                match zone_name {
+                    Some(name) if n.is_pruned != Some(true) => {
+                        let zone_id = match &n.zone_owner {
+                            Some(owner) => ZoneID::with_owner(name, owner),
+                            None => ZoneID::new(name),
+                        };
+                        pending.add_zone(database, zone_id);
+                    }
+                    _ => PendingWork::add_database(&mut pending.catch_up, database),
+                }
+            }
+            CKNotification::Database(n) => {
+                let database = database_for(&n.database_scope);
+                if n.is_pruned == Some(true) {
+                    PendingWork::add_database(&mut pending.catch_up, database);
+                } else {
+                    PendingWork::add_database(&mut pending.databases, database);
+                }
+            }
+        }
+    }
+
+    /// Runs everything queued so far. Failures are reported to the handlers
+    /// as `SyncEvent::Failed` and do not stop the remaining work.
+    pub async fn flush(&self) {
+        let work = std::mem::take(&mut *lock(&self.pending));
+        let mut zones: Vec<(DatabaseType, ZoneID)> = Vec::new();
+        let mut push_zone = |database: &DatabaseType, zone_id: ZoneID| {
+            if !zones
+                .iter()
+                .any(|(db, zone)| db == database && same_zone(zone, &zone_id))
+            {
+                zones.push((database.clone(), zone_id));
+            }
+        };
+
+        for database in &work.catch_up {
+            if *database == DatabaseType::Public {
+                self.emit(SyncEvent::CatchUpRequired {
+                    database: database.clone(),
+                });
+                continue;
+            }
+            match self.client.list_zones(database).await {
+                Ok(listed) => listed
+                    .into_iter()
+                    .for_each(|zone| push_zone(database, zone.zone_id)),
+                Err(error) => self.fail(database, None, error),
+            }
+        }
+
+        for database in work
+            .databases
+            .iter()
+            .filter(|db| !work.catch_up.contains(db))
+        {
+            for zone_id in self.changed_zones(database).await {
+                push_zone(database, zone_id);
+            }
+        }
+
+        for (database, zone_id) in work.zones {
+            if !work.catch_up.contains(&database) {
+                push_zone(&database, zone_id);
+            }
+        }
+
+        for (database, zone_id) in zones {
+            self.sync_zone(&database, zone_id).await;
+        }
+
+        for (database, record_name, zone_id, deleted) in work.records {
+            if work.catch_up.contains(&database) {
+                continue;
+            }
+            if deleted {
+                self.emit(SyncEvent::RecordDeleted {
+                    database,
+                    record_name,
+                });
+                continue;
+            }
+            let results = match self
+                .client
+                .lookup_record_results(&database, &[&record_name], zone_id.clone(), None)
+                .await
+            {
+                Ok(results) => results,
+                Err(error) => {
+                    self.fail(&database, zone_id, error);
+                    continue;
+                }
+            };
+            for result in results {
+                match result.into_record() {
+                    Ok(record) => self.emit(SyncEvent::RecordChanged {
+                        database: database.clone(),
+                        record: Box::new(record),
+                    }),
+                    // Deleted again before the follow-up lookup.
+                    Err(AppleError::CloudKitError(error))
+                        if error.server_error_code == CloudKitErrorCode::NotFound =>
+                    {
+                        self.emit(SyncEvent::RecordDeleted {
+                            database: database.clone(),
+                            record_name: record_name.clone(),
+                        })
+                    }
+                    Err(error) => self.fail(&database, zone_id.clone(), error),
+                }
+            }
+        }
+    }
+
+    /// Queues the notifications and flushes them as one batch.
+    pub async fn dispatch<I>(&self, notifications: I)
+    where
+        I: IntoIterator<Item = CKNotification>,
+    {
+        for notification in notifications {
+            self.enqueue(notification);
+        }
+        self.flush().await;
+    }
+
+    /// Consumes a notification stream, such as
+    /// `CloudKitClient::notification_stream`, flushing once no notification
+    /// has arrived for `quiet_period`. Returns when the stream ends.
+    pub async fn run<S>(&self, mut notifications: S, quiet_period: Duration)
+    where
+        S: Stream<Item = Result<CKNotification, AppleError>> + Unpin,
+    {
+        loop {
+            let next = if lock(&self.pending).is_empty() {
+                notifications.next().await
+            } else {
+                match tokio::time::timeout(quiet_period, notifications.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        self.flush().await;
+                        continue;
+                    }
+                }
+            };
+            match next {
+                Some(Ok(notification)) => self.enqueue(notification),
+                Some(Err(error)) => self.emit(SyncEvent::Failed {
+                    database: None,
+                    zone_id: None,
+                    error,
+                }),
+                None => {
+                    self.flush().await;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Pages through `changes/database` and returns the zones that still
+    /// exist. Deleted zones are reported and forgotten.
+    async fn changed_zones(&self, database: &DatabaseType) -> Vec<ZoneID> {
+        let mut zones = Vec::new();
+        loop {
+            let token = self.database_token(database);
+            let response = match self
+                .client
+                .fetch_database_changes(database, token, None)
+                .await
+            {
+                Ok(response) => response,
+                Err(error) => {
+                    self.fail(database, None, error);
+                    return zones;
+                }
+            };
+            for change in response.zones {
+                if change.deleted {
+                    lock(&self.zone_tokens).remove(&zone_key(database, &change.zone_id));
+                    self.emit(SyncEvent::ZoneDeleted {
+                        database: database.clone(),
+                        zone_id: change.zone_id,
+                    });
+                } else {
+                    zones.push(change.zone_id);
+                }
+            }
+            if let Some(token) = response.sync_token {
+                lock(&self.database_tokens).insert(database.to_string(), token);
+            }
+            if response.more_coming != Some(true) {
+                return zones;
+            }
+        }
+    }
+
+    async fn sync_zone(&self, database: &DatabaseType, zone_id: ZoneID) {
+        let key = zone_key(database, &zone_id);
+        loop {
+            let token = lock(&self.zone_tokens).get(&key).cloned();
+            let response = match self
+                .client
+                .fetch_zone_changes(database, zone_id.clone(), token, None)
+                .await
+            {
+                Ok(response) => response,
+                Err(error) => {
+                    self.fail(database, Some(zone_id), error);
+                    return;
+                }
+            };
+            if let Some(token) = response.sync_token {
+                lock(&self.zone_tokens).insert(key.clone(), token);
+            }
+            if !response.records.is_empty() {
+                self.emit(SyncEvent::ZoneChanged {
+                    database: database.clone(),
+                    zone_id: zone_id.clone(),
+                    records: response.records,
+                });
+            }
+            if response.more_coming != Some(true) {
+                return;
+            }
+        }
+    }
+
+    fn fail(&self, database: &DatabaseType, zone_id: Option<ZoneID>, error: AppleError) {
+        self.emit(SyncEvent::Failed {
+            database: Some(database.clone()),
+            zone_id,
+            error,
+        });
+    }
+
+    fn emit(&self, event: SyncEvent) {
+        for handler in &self.handlers {
+            handler(&event);
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Environment {
@@ -17,6 +18,21 @@ impl fmt::Display for Environment {
     }
 }
 
+impl FromStr for Environment {
+    type Err = crate::error::AppleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "development" => Ok(Environment::Development),
+            "production" => Ok(Environment::Production),
+            other => Err(crate::error::AppleError::ValidationError(format!(
+                "Unknown environment: {}",
+                other
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum DatabaseType {
     Public,
@@ -34,6 +50,22 @@ impl fmt::Display for DatabaseType {
     }
 }
 
+impl FromStr for DatabaseType {
+    type Err = crate::error::AppleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(DatabaseType::Public),
+            "private" => Ok(DatabaseType::Private),
+            "shared" => Ok(DatabaseType::Shared),
+            other => Err(crate::error::AppleError::ValidationError(format!(
+                "Unknown database: {}",
+                other
+            ))),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZoneID {
     #[serde(rename = "zoneName")]
@@ -56,6 +88,14 @@ impl ZoneID {
             owner_record_name: None,
         }
     }
+
+    /// A zone owned by another user, as seen from the shared database.
+    pub fn with_owner(zone_name: &str, owner_record_name: &str) -> Self {
+        ZoneID {
+            zone_name: zone_name.to_string(),
+            owner_record_name: Some(owner_record_name.to_string()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,6 +124,8 @@ pub struct Record {
     pub created: Option<RecordTimestamp>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub modified: Option<RecordTimestamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share: Option<ReferenceValue>,
 }
 
 impl Record {
@@ -96,6 +138,7 @@ impl Record {
             zone_id: None,
             created: None,
             modified: None,
+            share: None,
         }
     }
 
@@ -109,8 +152,8 @@ impl Record {
         self
     }
 
-    pub fn with_field(mut self, name: &str, value: FieldValue) -> Self {
-        self.fields.insert(name.to_string(), value);
+    pub fn with_field(mut self, name: &str, value: impl Into<FieldValue>) -> Self {
+        self.fields.insert(name.to_string(), value.into());
         self
     }
 }
@@ -134,6 +177,9 @@ pub enum FieldValue {
     Location(LocationValue),
     #[serde(rename = "BYTES")]
     Bytes(String),
+    /// Base64 bytes that CloudKit stores encrypted.
+    #[serde(rename = "ENCRYPTED_BYTES")]
+    EncryptedBytes(String),
     #[serde(rename = "STRING_LIST")]
     StringList(Vec<String>),
     #[serde(rename = "INT64_LIST")]
@@ -146,6 +192,10 @@ pub enum FieldValue {
     ReferenceList(Vec<ReferenceValue>),
     #[serde(rename = "LOCATION_LIST")]
     LocationList(Vec<LocationValue>),
+    #[serde(rename = "ASSET_LIST")]
+    AssetList(Vec<AssetValue>),
+    #[serde(rename = "BYTES_LIST")]
+    BytesList(Vec<String>),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -168,7 +218,7 @@ pub enum ReferenceAction {
     Validate,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AssetValue {
     #[serde(rename = "fileChecksum", skip_serializing_if = "Option::is_none")]
     pub file_checksum: Option<String>,
@@ -176,6 +226,12 @@ pub struct AssetValue {
     pub size: Option<u64>,
     #[serde(rename = "downloadURL", skip_serializing_if = "Option::is_none")]
     pub download_url: Option<String>,
+    #[serde(rename = "receipt", skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<String>,
+    #[serde(rename = "wrappingKey", skip_serializing_if = "Option::is_none")]
+    pub wrapping_key: Option<String>,
+    #[serde(rename = "referenceChecksum", skip_serializing_if = "Option::is_none")]
+    pub reference_checksum: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -254,7 +310,7 @@ pub struct Subscription {
     pub zone_id: Option<ZoneID>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SubscriptionType {
     #[serde(rename = "query")]
     Query,
@@ -264,7 +320,7 @@ pub enum SubscriptionType {
     Database,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum FiresOn {
     #[serde(rename = "create")]
     Create,
@@ -274,7 +330,7 @@ pub enum FiresOn {
     Delete,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NotificationInfo {
     #[serde(rename = "alertBody", skip_serializing_if = "Option::is_none")]
     pub alert_body: Option<String>,
@@ -0,0 +1,172 @@
+use crate::cloudkit::client::CloudKitClient;
+use crate::cloudkit::types::DatabaseType;
+use crate::error::AppleError;
+use serde::Deserialize;
+use std::sync::{Arc, RwLock};
+
+const API_TOKEN_PARAM: &str = "ckAPIToken";
+const WEB_AUTH_TOKEN_PARAM: &str = "ckWebAuthToken";
+
+/// How a request authenticates against CloudKit Web Services.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CloudKitAuthMode {
+    /// Requests signed with the server-to-server key in `CloudKitConfig`.
+    /// Can only reach the public database.
+    ServerToServer,
+    /// Requests carrying the container API token and, once the user has
+    /// signed in, their `ckWebAuthToken`. Required for private and shared
+    /// databases.
+    ApiToken,
+}
+
+/// The API token and the user's current web auth token, shared by every
+/// client handle derived from the same `CloudKitClient`.
+#[derive(Debug)]
+pub(crate) struct UserSession {
+    api_token: String,
+    web_auth_token: RwLock<Option<String>>,
+}
+
+impl UserSession {
+    pub(crate) fn web_auth_token(&self) -> Option<String> {
+        self.web_auth_token
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub(crate) fn set_web_auth_token(&self, token: Option<String>) {
+        *self
+            .web_auth_token
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = token;
+    }
+}
+
+#[derive(Deserialize)]
+struct WebAuthTokenEnvelope {
+    #[serde(rename = "ckWebAuthToken")]
+    web_auth_token: Option<String>,
+}
+
+/// Reads the `ckWebAuthToken` CloudKit appends to the sign-in callback URL
+/// configured for the API token.
+pub fn web_auth_token_from_callback(callback_url: &str) -> Option<String> {
+    let url = url::Url::parse(callback_url).ok()?;
+    let from_query = url
+        .query_pairs()
+        .find(|(key, _)| key == WEB_AUTH_TOKEN_PARAM)
+        .map(|(_, value)| value.into_owned());
+    from_query.or_else(|| {
+        url::form_urlencoded::parse(url.fragment()?.as_bytes())
+            .find(|(key, _)| key == WEB_AUTH_TOKEN_PARAM)
+            .map(|(_, value)| value.into_owned())
+    })
+}
+
+impl CloudKitClient {
+    /// Enables user-context requests with the container's API token. The
+    /// client itself keeps signing with the server key; use `as_user` to get
+    /// a handle whose requests authenticate as the user.
+    pub fn with_api_token(mut self, api_token: &str) -> Self {
+        let previous = self.session.as_ref().and_then(|s| s.web_auth_token());
+        self.session = Some(Arc::new(UserSession {
+            api_token: api_token.to_string(),
+            web_auth_token: RwLock::new(previous),
+        }));
+        self
+    }
+
+    /// Seeds the session with a web auth token obtained earlier, for example
+    /// from `web_auth_token_from_callback`.
+    pub fn with_web_auth_token(self, web_auth_token: &str) -> Self {
+        self.set_web_auth_token(Some(web_auth_token));
+        self
+    }
+
+    pub fn auth_mode(&self) -> CloudKitAuthMode {
+        self.auth_mode
+    }
+
+    /// The user's current web auth token. CloudKit rotates the token on
+    /// responses, so persist this value after each call if the session must
+    /// survive a restart.
+    pub fn web_auth_token(&self) -> Option<String> {
+        self.session.as_ref().and_then(|s| s.web_auth_token())
+    }
+
+    pub fn set_web_auth_token(&self, web_auth_token: Option<&str>) {
+        if let Some(session) = &self.session {
+            session.set_web_auth_token(web_auth_token.map(|t| t.to_string()));
+        }
+    }
+
+    /// Returns a handle that authenticates as the signed-in user. The handle
+    /// shares the HTTP client and the web auth token with `self`.
+    pub fn as_user(&self) -> Result<CloudKitClient, AppleError> {
+        if self.session.is_none() {
+            return Err(AppleError::ValidationError(
+                "User-context requests need an API token; call with_api_token first".to_string(),
+            ));
+        }
+        Ok(self.with_auth_mode(CloudKitAuthMode::ApiToken))
+    }
+
+    /// Returns a handle that signs requests with the server-to-server key.
+    pub fn as_server(&self) -> CloudKitClient {
+        self.with_auth_mode(CloudKitAuthMode::ServerToServer)
+    }
+
+    fn with_auth_mode(&self, auth_mode: CloudKitAuthMode) -> CloudKitClient {
+        CloudKitClient {
+            config: self.config().clone(),
+            http_client: self.http_client.clone(),
+            auth_mode,
+            session: self.session.clone(),
+            base_url: self.base_url.clone(),
+            schema: self.schema.clone(),
+            production_writes: self.production_writes,
+        }
+    }
+
+    /// Asks CloudKit where to send the user to sign in. Returns `None` when
+    /// the current web auth token is still valid.
+    pub async fn fetch_auth_redirect_url(&self) -> Result<Option<String>, AppleError> {
+        let user = self.as_user()?;
+        let url = user.build_url(&DatabaseType::Public, "users/current");
+        match user.signed_get::<serde_json::Value>(&url).await {
+            Ok(_) => Ok(None),
+            Err(AppleError::CloudKitAuthenticationRequired(err)) => Ok(Some(err.redirect_url)),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub(crate) fn request_url(&self, url: &str) -> Result<String, AppleError> {
+        let session = match (&self.auth_mode, &self.session) {
+            (CloudKitAuthMode::ApiToken, Some(session)) => session,
+            _ => return Ok(url.to_string()),
+        };
+
+        let mut parsed = url::Url::parse(url).map_err(|e| AppleError::HttpError(e.to_string()))?;
+        {
+            let mut query = parsed.query_pairs_mut();
+            query.append_pair(API_TOKEN_PARAM, &session.api_token);
+            if let Some(token) = session.web_auth_token() {
+                query.append_pair(WEB_AUTH_TOKEN_PARAM, &token);
+            }
+        }
+        Ok(parsed.to_string())
+    }
+
+    pub(crate) fn capture_web_auth_token(&self, response_body: &str) {
+        if self.auth_mode != CloudKitAuthMode::ApiToken {
+            return;
+        }
+        if let Some(session) = &self.session
+            && let Ok(envelope) = serde_json::from_str::<WebAuthTokenEnvelope>(response_body)
+            && let Some(token) = envelope.web_auth_token
+        {
+            session.set_web_auth_token(Some(token));
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use crate::cloudkit::client::CloudKitClient;
+use crate::cloudkit::notifications::{CKNotification, parse_webcourier_response};
+use crate::error::AppleError;
+use futures::stream::{self, AbortHandle, Abortable, BoxStream, Stream, StreamExt};
+use reqwest::StatusCode;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Reconnection settings for `CloudKitClient::notification_stream`.
+#[derive(Debug, Clone)]
+pub struct NotificationStreamOptions {
+    /// How long a single long-poll may stay open. Defaults to 120 seconds.
+    pub poll_timeout: Duration,
+    /// First delay after a failed poll. Doubles on each consecutive failure.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Passed to `create_token` and `register_token` when re-registering.
+    pub apns_environment: Option<String>,
+}
+
+impl Default for NotificationStreamOptions {
+    fn default() -> Self {
+        NotificationStreamOptions {
+            poll_timeout: Duration::from_secs(120),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            apns_environment: None,
+        }
+    }
+}
+
+impl NotificationStreamOptions {
+    pub fn with_poll_timeout(mut self, timeout: Duration) -> Self {
+        self.poll_timeout = timeout;
+        self
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    pub fn with_apns_environment(mut self, environment: &str) -> Self {
+        self.apns_environment = Some(environment.to_string());
+        self
+    }
+}
+
+/// A never-ending stream of WebCourier notifications. Notifications that
+/// fail to parse and failed polls are yielded as `Err` items; the stream
+/// keeps reconnecting after them until it is cancelled or dropped.
+pub struct NotificationStream<'a> {
+    inner: Abortable<BoxStream<'a, Result<CKNotification, AppleError>>>,
+    abort: AbortHandle,
+}
+
+impl NotificationStream<'_> {
+    /// A handle that ends the stream from elsewhere, including while a poll
+    /// is in flight.
+    pub fn cancel_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+
+    pub fn cancel(&self) {
+        self.abort.abort();
+    }
+}
+
+impl Stream for NotificationStream<'_> {
+    type Item = Result<CKNotification, AppleError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+enum LongPoll {
+    Delivered(Vec<Result<CKNotification, AppleError>>),
+    TimedOut,
+    Expired,
+}
+
+struct Courier<'a> {
+    client: &'a CloudKitClient,
+    options: NotificationStreamOptions,
+    url: Option<String>,
+    /// Whether `url` came from a registration that has not delivered yet.
+    fresh: bool,
+    backoff: Duration,
+    delay: Option<Duration>,
+    pending: VecDeque<Result<CKNotification, AppleError>>,
+}
+
+impl<'a> Courier<'a> {
+    async fn next(mut self) -> Option<(Result<CKNotification, AppleError>, Self)> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some((item, self));
+            }
+            if let Some(delay) = self.delay.take() {
+                tokio::time::sleep(delay).await;
+            }
+
+            let url = match self.url.clone() {
+                Some(url) => url,
+                None => match self.register().await {
+                    Ok(url) => url,
+                    Err(err) => return Some((Err(err), self.backing_off())),
+                },
+            };
+
+            match self.poll(&url).await {
+                Ok(LongPoll::Delivered(items)) => {
+                    self.fresh = false;
+                    self.backoff = self.options.initial_backoff;
+                    self.pending.extend(items);
+                }
+                Ok(LongPoll::TimedOut) => {}
+                Ok(LongPoll::Expired) if self.fresh => {
+                    self.url = None;
+                    let err = AppleError::HttpError(
+                        "Newly registered WebCourier URL was rejected".to_string(),
+                    );
+                    return Some((Err(err), self.backing_off()));
+                }
+                Ok(LongPoll::Expired) => self.url = None,
+                Err(err) => return Some((Err(err), self.backing_off())),
+            }
+        }
+    }
+
+    fn backing_off(mut self) -> Self {
+        self.delay = Some(self.backoff);
+        self.backoff = (self.backoff * 2).min(self.options.max_backoff);
+        self
+    }
+
+    /// Creates a new token, registers it for the current user and returns its
+    /// WebCourier URL.
+    async fn register(&mut self) -> Result<String, AppleError> {
+        let environment = self.options.apns_environment.as_deref();
+        let token = self.client.create_token(environment).await?;
+        if let Some(apns_token) = &token.apns_token {
+            self.client.register_token(apns_token, environment).await?;
+        }
+        let url = token.webcourier_url.ok_or_else(|| {
+            AppleError::JsonError("tokens/create returned no webcourierURL".to_string())
+        })?;
+        self.url = Some(url.clone());
+        self.fresh = true;
+        Ok(url)
+    }
+
+    async fn poll(&self, url: &str) -> Result<LongPoll, AppleError> {
+        let res = match self
+            .client
+            .http_client
+            .get(url)
+            .timeout(self.options.poll_timeout)
+            .send()
+            .await
+        {
+            Ok(res) => res,
+            Err(e) if e.is_timeout() => return Ok(LongPoll::TimedOut),
+            Err(e) => return Err(AppleError::HttpError(e.to_string())),
+        };
+
+        let status = res.status();
+        if status == StatusCode::NOT_FOUND || status == StatusCode::GONE {
+            return Ok(LongPoll::Expired);
+        }
+        let body = match res.text().await {
+            Ok(body) => body,
+            Err(e) if e.is_timeout() => return Ok(LongPoll::TimedOut),
+            Err(e) => return Err(AppleError::HttpError(e.to_string())),
+        };
+        if !status.is_success() {
+            return Err(AppleError::HttpError(format!(
+                "WebCourier polling failed with status: {}",
+                status
+            )));
+        }
+
+        parse_webcourier_response(&body).map(LongPoll::Delivered)
+    }
+}
+
+impl CloudKitClient {
+    /// Long-polls WebCourier in a loop, reconnecting after every response or
+    /// timeout and backing off after failures. Without a `webcourier_url`,
+    /// or once the current one expires, a new token is created and
+    /// registered.
+    pub fn notification_stream(
+        &self,
+        webcourier_url: Option<&str>,
+        options: NotificationStreamOptions,
+    ) -> NotificationStream<'_> {
+        let courier = Courier {
+            client: self,
+            url: webcourier_url.map(|url| url.to_string()),
+            fresh: false,
+            backoff: options.initial_backoff,
+            delay: None,
+            pending: VecDeque::new(),
+            options,
+        };
+        let (inner, abort) = stream::abortable(stream::unfold(courier, Courier::next).boxed());
+        NotificationStream { inner, abort }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::cloudkit::client::CloudKitClient;
+use crate::cloudkit::error::item_error;
 use crate::cloudkit::types::*;
 use crate::error::AppleError;
 use serde::{Deserialize, Serialize};
@@ -11,7 +12,7 @@ struct ModifyZonesRequest {
 #[derive(Debug, Serialize)]
 struct ZoneOperation {
     #[serde(rename = "operationType")]
-    operation_type: String,
+    operation_type: ZoneOperationType,
     zone: ZoneBody,
 }
 
@@ -23,9 +24,71 @@ struct ZoneBody {
     atomic: Option<bool>,
 }
 
+#[derive(Debug, Serialize)]
+struct LookupZonesRequest {
+    zones: Vec<ZoneLookup>,
+}
+
+#[derive(Debug, Serialize)]
+struct ZoneLookup {
+    #[serde(rename = "zoneID")]
+    zone_id: ZoneID,
+}
+
+#[derive(Debug, Serialize)]
+struct FetchZonesChangesRequest {
+    #[serde(rename = "syncToken", skip_serializing_if = "Option::is_none")]
+    sync_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ZoneOperationType {
+    #[serde(rename = "create")]
+    Create,
+    #[serde(rename = "delete")]
+    Delete,
+}
+
+/// One entry of a zone response. Failed operations carry `server_error_code`
+/// instead of zone state.
+#[derive(Debug, Deserialize)]
+pub struct ZoneResult {
+    #[serde(rename = "zoneID")]
+    pub zone_id: Option<ZoneID>,
+    #[serde(rename = "syncToken")]
+    pub sync_token: Option<String>,
+    pub atomic: Option<bool>,
+    /// Set on zones removed since the last `zones/changes` sync token.
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(rename = "serverErrorCode")]
+    pub server_error_code: Option<String>,
+    pub reason: Option<String>,
+}
+
+impl ZoneResult {
+    pub fn error(&self) -> Option<AppleError> {
+        item_error(self.server_error_code.as_ref(), self.reason.as_ref())
+    }
+
+    pub fn into_zone(self) -> Result<Zone, AppleError> {
+        if let Some(err) = self.error() {
+            return Err(err);
+        }
+        let zone_id = self
+            .zone_id
+            .ok_or_else(|| AppleError::JsonError("Zone result without zoneID".to_string()))?;
+        Ok(Zone {
+            zone_id,
+            sync_token: self.sync_token,
+            atomic: self.atomic,
+        })
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ModifyZonesResponse {
-    pub zones: Vec<Zone>,
+    pub zones: Vec<ZoneResult>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,43 +96,83 @@ pub struct ListZonesResponse {
     pub zones: Vec<Zone>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LookupZonesResponse {
+    pub zones: Vec<ZoneResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ZonesChangesResponse {
+    pub zones: Vec<ZoneResult>,
+    #[serde(rename = "syncToken")]
+    pub sync_token: Option<String>,
+    #[serde(rename = "moreComing")]
+    pub more_coming: Option<bool>,
+}
+
 impl CloudKitClient {
+    /// Creates a zone. Pass `atomic: Some(true)` to get all-or-nothing
+    /// record batches; shared zones take a `ZoneID::with_owner`.
     pub async fn create_zone(
         &self,
         db: &DatabaseType,
         zone_id: ZoneID,
         atomic: Option<bool>,
     ) -> Result<Zone, AppleError> {
-        let url = self.build_url(db, "zones/modify");
-        let request = ModifyZonesRequest {
-            operations: vec![ZoneOperation {
-                operation_type: "create".to_string(),
-                zone: ZoneBody { zone_id, atomic },
-            }],
+        let zone = Zone {
+            zone_id,
+            sync_token: None,
+            atomic,
         };
-
-        let response: ModifyZonesResponse = self.signed_post(&url, &request).await?;
-        response
-            .zones
+        self.modify_zones(db, vec![(ZoneOperationType::Create, zone)])
+            .await?
             .into_iter()
             .next()
-            .ok_or_else(|| AppleError::JsonError("Empty zone response".to_string()))
+            .ok_or_else(|| AppleError::JsonError("Empty zone response".to_string()))?
+            .into_zone()
     }
 
     pub async fn delete_zone(&self, db: &DatabaseType, zone_id: ZoneID) -> Result<(), AppleError> {
+        let zone = Zone {
+            zone_id,
+            sync_token: None,
+            atomic: None,
+        };
+        let result = self
+            .modify_zones(db, vec![(ZoneOperationType::Delete, zone)])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppleError::JsonError("Empty zone response".to_string()))?;
+        match result.error() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends several zone operations in one request and returns a result per
+    /// operation, in order.
+    pub async fn modify_zones(
+        &self,
+        db: &DatabaseType,
+        operations: Vec<(ZoneOperationType, Zone)>,
+    ) -> Result<Vec<ZoneResult>, AppleError> {
         let url = self.build_url(db, "zones/modify");
         let request = ModifyZonesRequest {
-            operations: vec![ZoneOperation {
-                operation_type: "delete".to_string(),
-                zone: ZoneBody {
-                    zone_id,
-                    atomic: None,
-                },
-            }],
+            operations: operations
+                .into_iter()
+                .map(|(operation_type, zone)| ZoneOperation {
+                    operation_type,
+                    zone: ZoneBody {
+                        zone_id: zone.zone_id,
+                        atomic: zone.atomic,
+                    },
+                })
+                .collect(),
         };
 
-        let _response: ModifyZonesResponse = self.signed_post(&url, &request).await?;
-        Ok(())
+        let response: ModifyZonesResponse = self.signed_post(&url, &request).await?;
+        Ok(response.zones)
     }
 
     pub async fn list_zones(&self, db: &DatabaseType) -> Result<Vec<Zone>, AppleError> {
@@ -81,4 +184,41 @@ impl CloudKitClient {
         let response: ListZonesResponse = self.signed_post(&url, &EmptyBody {}).await?;
         Ok(response.zones)
     }
+
+    /// Fetches specific zones. Missing zones come back as `ZONE_NOT_FOUND`
+    /// results rather than failing the whole request.
+    pub async fn lookup_zones(
+        &self,
+        db: &DatabaseType,
+        zone_ids: Vec<ZoneID>,
+    ) -> Result<Vec<ZoneResult>, AppleError> {
+        let url = self.build_url(db, "zones/lookup");
+        let request = LookupZonesRequest {
+            zones: zone_ids
+                .into_iter()
+                .map(|zone_id| ZoneLookup { zone_id })
+                .collect(),
+        };
+
+        let response: LookupZonesResponse = self.signed_post(&url, &request).await?;
+        Ok(response.zones)
+    }
+
+    /// Fetches zones created, changed or deleted since `sync_token`. Only the
+    /// private database tracks zone changes.
+    pub async fn fetch_zones_changes(
+        &self,
+        db: &DatabaseType,
+        sync_token: Option<String>,
+    ) -> Result<ZonesChangesResponse, AppleError> {
+        if !matches!(db, DatabaseType::Private) {
+            return Err(AppleError::ValidationError(
+                "zones/changes is only available in the private database".to_string(),
+            ));
+        }
+        let url = self.build_url(db, "zones/changes");
+        let request = FetchZonesChangesRequest { sync_token };
+
+        self.signed_post(&url, &request).await
+    }
 }
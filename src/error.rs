@@ -11,15 +11,28 @@ pub enum AppleError {
     JsonError(String),
     TimeError(String),
     UnrecognizedError(String),
+    ValidationError(String),
     ResponseError(ErrorResponse),
     #[cfg(feature = "cloudkit")]
     CloudKitError(CloudKitErrorResponse),
     #[cfg(feature = "cloudkit")]
+    CloudKitAuthenticationRequired(CloudKitAuthenticationRequired),
+    #[cfg(feature = "cloudkit")]
     SignatureError(String),
+    #[cfg(feature = "cloudkit")]
+    SchemaError(String),
+    #[cfg(feature = "cloudkit")]
+    PredicateError(crate::cloudkit::predicate::PredicateError),
+    #[cfg(feature = "cloudkit")]
+    EnvironmentGuardError(String),
+    #[cfg(feature = "cloudkit")]
+    FieldTypeError(crate::cloudkit::fields::FieldTypeError),
     #[cfg(feature = "appstore")]
     AppStoreError(AppStoreErrorResponse),
     #[cfg(feature = "appstore")]
     CertificateError(String),
+    #[cfg(feature = "appstore")]
+    VerificationError(crate::appstore::signed_data::VerificationError),
 }
 
 #[derive(Debug, Clone)]
@@ -79,6 +92,16 @@ pub struct CloudKitErrorResponse {
     pub retry_after: Option<u64>,
 }
 
+/// Returned when a user-context request needs the user to sign in. Send the
+/// user to `redirect_url`; CloudKit then hands back a new `ckWebAuthToken`.
+#[cfg(feature = "cloudkit")]
+#[derive(Debug, Clone)]
+pub struct CloudKitAuthenticationRequired {
+    pub redirect_url: String,
+    pub reason: String,
+    pub uuid: Option<String>,
+}
+
 #[cfg(feature = "cloudkit")]
 #[derive(Debug, Clone, PartialEq)]
 pub enum CloudKitErrorCode {
@@ -150,6 +173,17 @@ impl fmt::Display for CloudKitErrorResponse {
     }
 }
 
+#[cfg(feature = "cloudkit")]
+impl fmt::Display for CloudKitAuthenticationRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CloudKit authentication required: {} (sign in at {})",
+            self.reason, self.redirect_url
+        )
+    }
+}
+
 #[cfg(feature = "appstore")]
 #[derive(Debug, Clone)]
 pub struct AppStoreErrorResponse {
@@ -199,15 +233,28 @@ impl fmt::Display for AppleError {
             AppleError::JsonError(msg) => write!(f, "JSON error: {}", msg),
             AppleError::TimeError(msg) => write!(f, "Time error: {}", msg),
             AppleError::UnrecognizedError(msg) => write!(f, "Unrecognized error: {}", msg),
+            AppleError::ValidationError(msg) => write!(f, "Validation error: {}", msg),
             AppleError::ResponseError(err) => write!(f, "{}", err),
             #[cfg(feature = "cloudkit")]
             AppleError::CloudKitError(err) => write!(f, "{}", err),
             #[cfg(feature = "cloudkit")]
+            AppleError::CloudKitAuthenticationRequired(err) => write!(f, "{}", err),
+            #[cfg(feature = "cloudkit")]
             AppleError::SignatureError(msg) => write!(f, "Signature error: {}", msg),
+            #[cfg(feature = "cloudkit")]
+            AppleError::SchemaError(msg) => write!(f, "Schema error: {}", msg),
+            #[cfg(feature = "cloudkit")]
+            AppleError::PredicateError(err) => write!(f, "Predicate error: {}", err),
+            #[cfg(feature = "cloudkit")]
+            AppleError::EnvironmentGuardError(msg) => write!(f, "Environment guard: {}", msg),
+            #[cfg(feature = "cloudkit")]
+            AppleError::FieldTypeError(err) => write!(f, "Field type error: {}", err),
             #[cfg(feature = "appstore")]
             AppleError::AppStoreError(err) => write!(f, "{}", err),
             #[cfg(feature = "appstore")]
             AppleError::CertificateError(msg) => write!(f, "Certificate error: {}", msg),
+            #[cfg(feature = "appstore")]
+            AppleError::VerificationError(err) => write!(f, "Verification error: {}", err),
         }
     }
 }
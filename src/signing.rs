@@ -1,7 +1,7 @@
 use crate::error::AppleError;
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD;
-use p256::ecdsa::{Signature, SigningKey, signature::Signer};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey, signature::Signer};
 use std::sync::Arc;
 
 /// A shared ECDSA P-256 key pair used for both Apple Sign-In (JWT signing)
@@ -48,6 +48,11 @@ impl AppleKeyPair {
         &self.signing_key
     }
 
+    /// Returns the public half of the key pair.
+    pub fn verifying_key(&self) -> VerifyingKey {
+        *self.signing_key.verifying_key()
+    }
+
     /// Sign arbitrary bytes and return the DER-encoded signature.
     pub fn sign(&self, message: &[u8]) -> Vec<u8> {
         let sig: Signature = self.signing_key.sign(message);
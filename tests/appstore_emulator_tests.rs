@@ -0,0 +1,477 @@
+#[cfg(feature = "appstore-emulator")]
+mod appstore_emulator_tests {
+    use apple::appstore::emulator::{AppStoreEmulator, Fault, Scenario};
+    use apple::appstore::testing::{self, TestSigner};
+    use apple::appstore::*;
+    use apple::error::AppleError;
+    use apple::signing::AppleKeyPair;
+    use futures::StreamExt;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const BUNDLE_ID: &str = "com.example.app";
+    const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+    const SIGNED_DATE: i64 = 1_700_000_000_000;
+
+    fn key_pair(seed: u8) -> Arc<AppleKeyPair> {
+        use p256::ecdsa::SigningKey;
+
+        let sk = SigningKey::from_slice(&[seed; 32]).unwrap();
+        let pem_obj = pem::Pem::new("EC PRIVATE KEY", sk.to_bytes().as_slice());
+        AppleKeyPair::from_pem_bytes("test-key", pem::encode(&pem_obj).as_bytes()).unwrap()
+    }
+
+    fn config(issuer_id: &str, bundle_id: &str, key_pair: Arc<AppleKeyPair>) -> AppStoreConfig {
+        AppStoreConfig {
+            issuer_id: issuer_id.to_string(),
+            bundle_id: bundle_id.to_string(),
+            key_pair,
+            environment: AppStoreEnvironment::Sandbox,
+        }
+    }
+
+    fn client(emulator: &AppStoreEmulator) -> AppStoreServerClient {
+        emulator
+            .client(config("issuer", BUNDLE_ID, key_pair(7)))
+            .unwrap()
+    }
+
+    fn subscription(product_id: &str) -> JWSTransactionDecodedPayload {
+        testing::transaction(BUNDLE_ID, product_id, AppStoreEnvironment::Sandbox)
+    }
+
+    fn error_code(result: Result<impl std::fmt::Debug, AppleError>) -> i64 {
+        match result {
+            Err(AppleError::AppStoreError(err)) => err.error_code,
+            other => panic!("expected an App Store error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token_is_checked() {
+        let emulator = AppStoreEmulator::builder(BUNDLE_ID)
+            .with_key_pair("issuer", &key_pair(7))
+            .with_scenario(Scenario::new().with_transaction(subscription("pro.monthly")))
+            .start()
+            .unwrap();
+        let transaction_id = emulator.scenario().transactions[0].transaction_id.clone();
+
+        assert!(
+            client(&emulator)
+                .get_transaction_info(&transaction_id)
+                .await
+                .is_ok()
+        );
+
+        let unauthorized = AppStoreErrorCode::UnauthorizedAccess.code();
+        for config in [
+            config("other-issuer", BUNDLE_ID, key_pair(7)),
+            config("issuer", "com.example.other", key_pair(7)),
+            config("issuer", BUNDLE_ID, key_pair(8)),
+        ] {
+            let client = emulator.client(config).unwrap();
+            assert_eq!(
+                error_code(client.get_transaction_info(&transaction_id).await),
+                unauthorized
+            );
+        }
+
+        let response = reqwest::get(format!(
+            "{}/inApps/v1/transactions/{}",
+            emulator.base_url(),
+            transaction_id
+        ))
+        .await
+        .unwrap();
+        assert_eq!(response.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn test_unauthenticated_access_is_opt_in() {
+        assert!(matches!(
+            AppStoreEmulator::builder(BUNDLE_ID).start(),
+            Err(AppleError::ValidationError(_))
+        ));
+
+        let emulator = AppStoreEmulator::builder(BUNDLE_ID)
+            .allow_unauthenticated()
+            .with_scenario(Scenario::new().with_transaction(subscription("pro.monthly")))
+            .start()
+            .unwrap();
+        let transaction_id = emulator.scenario().transactions[0].transaction_id.clone();
+        let client = emulator
+            .client(config("other-issuer", BUNDLE_ID, key_pair(8)))
+            .unwrap();
+        assert!(client.get_transaction_info(&transaction_id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_history_pages_and_filters_signed_transactions() {
+        let mut scenario = Scenario::new();
+        for (offset, product_id) in ["a", "b", "c"].iter().enumerate() {
+            let mut transaction = subscription(product_id);
+            transaction.purchase_date += offset as i64;
+            scenario = scenario.with_transaction(transaction);
+        }
+        let emulator = AppStoreEmulator::builder(BUNDLE_ID)
+            .with_key_pair("issuer", &key_pair(7))
+            .with_page_size(2)
+            .with_scenario(scenario)
+            .start()
+            .unwrap();
+        let client = client(&emulator);
+        let verifier = emulator.verifier();
+        let transaction_id = emulator.scenario().transactions[0].transaction_id.clone();
+
+        let products: Vec<String> = client
+            .transaction_history_stream(&transaction_id, None, None, Some(&verifier))
+            .map(|item| item.unwrap().decoded.unwrap().product_id)
+            .collect()
+            .await;
+        assert_eq!(products, ["a", "b", "c"]);
+
+        let request = TransactionHistoryRequest {
+            product_id: Some(vec!["a".to_string(), "c".to_string()]),
+            sort: Some(Order::DESCENDING),
+            ..Default::default()
+        };
+        let page = client
+            .get_transaction_history(&transaction_id, None, Some(&request))
+            .await
+            .unwrap();
+        assert!(!page.has_more);
+        let products: Vec<String> = page
+            .signed_transactions
+            .iter()
+            .map(|jws| {
+                verifier
+                    .verify_and_decode_transaction(jws)
+                    .unwrap()
+                    .product_id
+            })
+            .collect();
+        assert_eq!(products, ["c", "a"]);
+
+        assert_eq!(
+            error_code(
+                client
+                    .get_transaction_history(&transaction_id, Some("99"), None)
+                    .await
+            ),
+            AppStoreErrorCode::InvalidRequestRevision.code()
+        );
+        assert_eq!(
+            error_code(client.get_transaction_history("unknown", None, None).await),
+            AppStoreErrorCode::TransactionIdNotFound.code()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookups_statuses_and_refunds() {
+        let active = subscription("pro.monthly");
+        let mut refunded = subscription("pro.yearly");
+        refunded.revocation_date = Some(refunded.purchase_date + 1);
+        let mut expired = subscription("lite.monthly");
+        expired.expires_date = Some(expired.purchase_date - 1);
+        let mut renewal_info = testing::renewal_info(&active);
+        renewal_info.auto_renew_status = AutoRenewStatus::Off;
+
+        let emulator = AppStoreEmulator::start(
+            BUNDLE_ID,
+            "issuer",
+            &key_pair(7),
+            Scenario::new()
+                .with_transaction(active.clone())
+                .with_transaction(refunded.clone())
+                .with_transaction(expired.clone())
+                .with_renewal_info(renewal_info)
+                .with_status(
+                    &expired.original_transaction_id,
+                    SubscriptionStatus::BillingRetryPeriod,
+                )
+                .with_order(
+                    "ORDER1",
+                    &[&active.transaction_id, &refunded.transaction_id],
+                ),
+        )
+        .unwrap();
+        let client = client(&emulator);
+        let verifier = emulator.verifier();
+
+        let order = client.look_up_order_id("ORDER1").await.unwrap();
+        assert_eq!(order.status, OrderLookupStatus::Valid);
+        assert_eq!(order.signed_transactions.len(), 2);
+        let order = client.look_up_order_id("ORDER2").await.unwrap();
+        assert_eq!(order.status, OrderLookupStatus::Invalid);
+
+        let refunds = client
+            .get_refund_history(&active.transaction_id, None)
+            .await
+            .unwrap();
+        assert_eq!(refunds.signed_transactions.len(), 1);
+        assert_eq!(
+            verifier
+                .verify_and_decode_transaction(&refunds.signed_transactions[0])
+                .unwrap()
+                .transaction_id,
+            refunded.transaction_id
+        );
+
+        let statuses = client
+            .get_all_subscription_statuses(&active.transaction_id)
+            .await
+            .unwrap();
+        let mut found: Vec<(String, SubscriptionStatus, AutoRenewStatus)> = statuses.data[0]
+            .last_transactions
+            .iter()
+            .map(|item| {
+                let renewal = verifier
+                    .verify_and_decode_renewal_info(&item.signed_renewal_info)
+                    .unwrap();
+                (
+                    renewal.product_id,
+                    item.status.clone(),
+                    renewal.auto_renew_status,
+                )
+            })
+            .collect();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            found,
+            [
+                (
+                    "lite.monthly".to_string(),
+                    SubscriptionStatus::BillingRetryPeriod,
+                    AutoRenewStatus::On
+                ),
+                (
+                    "pro.monthly".to_string(),
+                    SubscriptionStatus::Active,
+                    AutoRenewStatus::Off
+                ),
+                (
+                    "pro.yearly".to_string(),
+                    SubscriptionStatus::Revoked,
+                    AutoRenewStatus::On
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extensions_and_consumption_update_the_scenario() {
+        let transaction = subscription("pro.monthly");
+        let original_id = transaction.original_transaction_id.clone();
+        let expires_date = transaction.expires_date.unwrap();
+        let emulator = AppStoreEmulator::start(
+            BUNDLE_ID,
+            "issuer",
+            &key_pair(7),
+            Scenario::new().with_transaction(transaction.clone()),
+        )
+        .unwrap();
+        let client = client(&emulator);
+
+        let extend = |days, request_identifier: &str| ExtendRenewalDateRequest {
+            extend_by_days: days,
+            extend_reason_code: ExtendReasonCode::CustomerSatisfaction,
+            request_identifier: request_identifier.to_string(),
+        };
+        let response = client
+            .extend_renewal_date(&original_id, &extend(3, "r1"))
+            .await
+            .unwrap();
+        assert!(response.success);
+        assert_eq!(response.effective_date, expires_date + 3 * DAY_MILLIS);
+        // Retrying a request does not extend twice.
+        client
+            .extend_renewal_date(&original_id, &extend(3, "r1"))
+            .await
+            .unwrap();
+        assert_eq!(
+            error_code(
+                client
+                    .extend_renewal_date(&original_id, &extend(91, "r2"))
+                    .await
+            ),
+            AppStoreErrorCode::InvalidExtendByDays.code()
+        );
+        client
+            .extend_renewal_date(&original_id, &extend(1, "r3"))
+            .await
+            .unwrap();
+        assert_eq!(
+            error_code(
+                client
+                    .extend_renewal_date(&original_id, &extend(1, "r4"))
+                    .await
+            ),
+            AppStoreErrorCode::SubscriptionMaxExtension.code()
+        );
+
+        let mass = MassExtendRenewalDateRequest {
+            extend_by_days: 2,
+            extend_reason_code: ExtendReasonCode::OtherReason,
+            request_identifier: "m1".to_string(),
+            storefront_country_codes: Some(vec!["USA".to_string()]),
+            product_id: "pro.monthly".to_string(),
+        };
+        client.mass_extend_renewal_date(&mass).await.unwrap();
+        let status = client
+            .get_mass_extension_status("pro.monthly", "m1")
+            .await
+            .unwrap();
+        assert!(status.complete);
+        assert_eq!(status.succeeded_count, Some(1));
+        assert_eq!(
+            emulator
+                .scenario()
+                .latest_transaction(&original_id)
+                .unwrap()
+                .expires_date,
+            Some(expires_date + 6 * DAY_MILLIS)
+        );
+
+        let consumption: ConsumptionRequest = serde_json::from_value(serde_json::json!({
+            "accountTenure": 1,
+            "appAccountToken": "",
+            "consumptionStatus": 1,
+            "customerConsented": true,
+            "platform": 1,
+            "userStatus": 1,
+        }))
+        .unwrap();
+        client
+            .send_consumption_data(&transaction.transaction_id, &consumption)
+            .await
+            .unwrap();
+        assert_eq!(
+            emulator.scenario().consumption[0].0,
+            transaction.transaction_id
+        );
+        assert_eq!(
+            error_code(client.send_consumption_data("unknown", &consumption).await),
+            AppStoreErrorCode::TransactionIdNotFound.code()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notification_history_feeds_the_reconciler() {
+        let signer = TestSigner::new();
+        let transaction = subscription("pro.monthly");
+        let failed = vec![SendAttemptItem {
+            attempt_date: 0,
+            send_attempt_result: SendAttemptResult::TIMED_OUT,
+        }];
+        let mut scenario = Scenario::new().with_transaction(transaction.clone());
+        for (offset, notification_type) in [
+            NotificationTypeV2::DID_RENEW,
+            NotificationTypeV2::REFUND,
+            NotificationTypeV2::DID_RENEW,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut payload = signer.notification(notification_type, None, &transaction, None);
+            payload.signed_date = SIGNED_DATE + offset as i64;
+            scenario = scenario.with_notification(
+                payload,
+                if offset == 1 {
+                    failed.clone()
+                } else {
+                    Vec::new()
+                },
+            );
+        }
+        let emulator = AppStoreEmulator::builder(BUNDLE_ID)
+            .with_key_pair("issuer", &key_pair(7))
+            .with_signer(signer)
+            .with_scenario(scenario)
+            .with_page_size(2)
+            .start()
+            .unwrap();
+        let client = client(&emulator);
+
+        let mut request = NotificationHistoryRequest {
+            start_date: SIGNED_DATE,
+            end_date: SIGNED_DATE + 3,
+            notification_type: None,
+            notification_subtype: None,
+            only_failures: None,
+            transaction_id: Some(transaction.transaction_id.clone()),
+        };
+        let handler = NotificationHandler::new(emulator.verifier(), MemoryIdempotencyStore::new());
+        let report = Reconciler::new(&client, &handler)
+            .backfill(&request, None, |_| async { Ok(()) })
+            .await;
+        assert!(report.is_complete());
+        let kinds: Vec<NotificationKind> = report.missed.iter().map(|m| m.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [
+                NotificationKind::Renewed,
+                NotificationKind::Refunded,
+                NotificationKind::Renewed
+            ]
+        );
+
+        request.only_failures = Some(true);
+        let page = client
+            .get_notification_history(&request, None)
+            .await
+            .unwrap();
+        assert_eq!(page.notification_history.len(), 1);
+        assert!(!page.has_more);
+        request.only_failures = None;
+        request.end_date = SIGNED_DATE + 1;
+        let page = client
+            .get_notification_history(&request, None)
+            .await
+            .unwrap();
+        assert_eq!(page.notification_history.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_faults_and_rate_limits() {
+        let transaction = subscription("pro.monthly");
+        let emulator = AppStoreEmulator::builder(BUNDLE_ID)
+            .with_key_pair("issuer", &key_pair(7))
+            .with_scenario(Scenario::new().with_transaction(transaction.clone()))
+            .with_rate_limit(3, Duration::from_secs(60))
+            .start()
+            .unwrap();
+        let client = client(&emulator);
+        let id = transaction.transaction_id.as_str();
+
+        emulator.inject(
+            Fault::rate_limited()
+                .on_path("/inApps/v1/transactions/")
+                .times(1),
+        );
+        emulator.inject(Fault::error(
+            500,
+            AppStoreErrorCode::GeneralInternalRetryable,
+        ));
+        assert_eq!(
+            error_code(client.get_transaction_info(id).await),
+            AppStoreErrorCode::RateLimitExceeded.code()
+        );
+        assert_eq!(
+            error_code(client.get_transaction_info(id).await),
+            AppStoreErrorCode::GeneralInternalRetryable.code()
+        );
+        emulator.clear_faults();
+        assert!(client.get_transaction_info(id).await.is_ok());
+
+        // The fourth request within the window is throttled.
+        assert_eq!(
+            error_code(client.get_transaction_info(id).await),
+            AppStoreErrorCode::RateLimitExceeded.code()
+        );
+        assert_eq!(emulator.requests().len(), 4);
+        assert_eq!(
+            emulator.requests()[0],
+            format!("GET /inApps/v1/transactions/{}", id)
+        );
+    }
+}
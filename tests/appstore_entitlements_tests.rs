@@ -0,0 +1,244 @@
+#[cfg(feature = "appstore")]
+mod appstore_entitlements_tests {
+    use apple::appstore::*;
+
+    const DAY: i64 = 24 * 60 * 60 * 1000;
+    const NOW: i64 = 1_700_000_000_000;
+
+    fn transaction(
+        original_transaction_id: &str,
+        product_id: &str,
+        expires_date: Option<i64>,
+        extra: serde_json::Value,
+    ) -> JWSTransactionDecodedPayload {
+        let mut value = serde_json::json!({
+            "transactionId": format!("{}-1", original_transaction_id),
+            "originalTransactionId": original_transaction_id,
+            "bundleId": "com.example.app",
+            "productId": product_id,
+            "purchaseDate": NOW - 30 * DAY,
+            "expiresDate": expires_date,
+            "quantity": 1,
+            "type": "Auto-Renewable Subscription",
+            "inAppOwnershipType": "PURCHASED",
+            "signedDate": NOW - DAY,
+            "environment": "Sandbox",
+            "transactionReason": "PURCHASE",
+            "subscriptionGroupIdentifier": "group.pro",
+        });
+        value
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn renewal_info(
+        original_transaction_id: &str,
+        extra: serde_json::Value,
+    ) -> JWSRenewalInfoDecodedPayload {
+        let mut value = serde_json::json!({
+            "originalTransactionId": original_transaction_id,
+            "autoRenewProductId": "pro.monthly",
+            "productId": "pro.monthly",
+            "autoRenewStatus": 1,
+            "signedDate": NOW - DAY,
+            "environment": "Sandbox",
+        });
+        value
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn event(
+        notification_type: &str,
+        subtype: Option<&str>,
+        transaction: JWSTransactionDecodedPayload,
+    ) -> NotificationEvent {
+        let payload: ResponseBodyV2DecodedPayload = serde_json::from_value(serde_json::json!({
+            "notificationType": notification_type,
+            "subtype": subtype,
+            "version": "2.0",
+            "signedDate": NOW,
+            "notificationUUID": "uuid",
+            "data": {"environment": "Sandbox", "bundleId": "com.example.app"},
+        }))
+        .unwrap();
+        NotificationEvent {
+            kind: NotificationKind::new(&payload.notification_type, payload.subtype.as_ref()),
+            notification_uuid: payload.notification_uuid.clone(),
+            signed_date: payload.signed_date,
+            transaction: Some(transaction),
+            renewal_info: None,
+            payload,
+        }
+    }
+
+    #[test]
+    fn test_active_and_expired_by_date() {
+        let mut engine = EntitlementEngine::new();
+        engine.apply(
+            transaction("1", "pro.monthly", Some(NOW + DAY), serde_json::json!({})),
+            Some(renewal_info("1", serde_json::json!({}))),
+            Some(SubscriptionStatus::Active),
+        );
+
+        let entitlement = engine.for_product("pro.monthly", NOW).unwrap();
+        assert!(entitlement.has_access);
+        assert_eq!(entitlement.reason, EntitlementReason::Active);
+        assert_eq!(entitlement.expires_date, Some(NOW + DAY));
+        assert_eq!(entitlement.will_auto_renew, Some(true));
+
+        let later = engine.for_product("pro.monthly", NOW + 2 * DAY).unwrap();
+        assert!(!later.has_access);
+        assert_eq!(later.reason, EntitlementReason::Expired);
+        assert!(engine.for_product("pro.yearly", NOW).is_none());
+    }
+
+    #[test]
+    fn test_grace_period_grants_access_until_it_ends() {
+        let mut engine = EntitlementEngine::new();
+        engine.apply(
+            transaction("1", "pro.monthly", Some(NOW - DAY), serde_json::json!({})),
+            Some(renewal_info(
+                "1",
+                serde_json::json!({"gracePeriodExpiresDate": NOW + 3 * DAY}),
+            )),
+            Some(SubscriptionStatus::BillingGracePeriod),
+        );
+
+        let entitlement = engine.for_group("group.pro", NOW).unwrap();
+        assert!(entitlement.has_access);
+        assert_eq!(entitlement.reason, EntitlementReason::GracePeriod);
+        assert_eq!(entitlement.expires_date, Some(NOW + 3 * DAY));
+
+        let after = engine.for_group("group.pro", NOW + 4 * DAY).unwrap();
+        assert!(!after.has_access);
+        assert_eq!(after.reason, EntitlementReason::BillingRetry);
+    }
+
+    #[test]
+    fn test_revoked_and_upgraded_deny_access() {
+        let mut engine = EntitlementEngine::new();
+        engine.apply(
+            transaction(
+                "1",
+                "pro.monthly",
+                Some(NOW + DAY),
+                serde_json::json!({"revocationDate": NOW - DAY, "revocationReason": 1}),
+            ),
+            None,
+            None,
+        );
+        engine.apply(
+            transaction(
+                "2",
+                "basic.monthly",
+                Some(NOW + DAY),
+                serde_json::json!({"isUpgraded": true}),
+            ),
+            None,
+            None,
+        );
+
+        let revoked = engine.entitlement("1", NOW).unwrap();
+        assert!(!revoked.has_access);
+        assert_eq!(revoked.reason, EntitlementReason::Revoked);
+        assert_eq!(
+            engine.entitlement("2", NOW).unwrap().reason,
+            EntitlementReason::Upgraded
+        );
+        assert!(!engine.has_access("basic.monthly", NOW));
+    }
+
+    #[test]
+    fn test_group_prefers_entitlements_with_access() {
+        let mut engine = EntitlementEngine::new();
+        engine.apply(
+            transaction("1", "pro.monthly", Some(NOW - DAY), serde_json::json!({})),
+            None,
+            Some(SubscriptionStatus::Expired),
+        );
+        engine.apply(
+            transaction(
+                "2",
+                "pro.monthly",
+                Some(NOW + DAY),
+                serde_json::json!({"inAppOwnershipType": "FAMILY_SHARED"}),
+            ),
+            None,
+            Some(SubscriptionStatus::Active),
+        );
+
+        let entitlement = engine.for_group("group.pro", NOW).unwrap();
+        assert_eq!(entitlement.original_transaction_id, "2");
+        assert!(entitlement.has_access);
+        assert!(entitlement.family_shared);
+        assert_eq!(engine.entitlements(NOW).len(), 2);
+    }
+
+    #[test]
+    fn test_notifications_update_incrementally() {
+        let mut engine = EntitlementEngine::new();
+        engine.apply(
+            transaction("1", "pro.monthly", Some(NOW + DAY), serde_json::json!({})),
+            None,
+            Some(SubscriptionStatus::Active),
+        );
+
+        let refund = transaction(
+            "1",
+            "pro.monthly",
+            Some(NOW + DAY),
+            serde_json::json!({"signedDate": NOW}),
+        );
+        engine.apply_notification(&event("REFUND", None, refund));
+        assert_eq!(
+            engine.entitlement("1", NOW).unwrap().reason,
+            EntitlementReason::Revoked
+        );
+
+        // A delayed notification signed before the refund does not undo it.
+        let stale = transaction(
+            "1",
+            "pro.monthly",
+            Some(NOW + DAY),
+            serde_json::json!({"signedDate": NOW - 2 * DAY}),
+        );
+        engine.apply_notification(&event("DID_RENEW", None, stale));
+        assert!(!engine.has_access("pro.monthly", NOW));
+
+        let renewed = transaction(
+            "1",
+            "pro.yearly",
+            Some(NOW + 365 * DAY),
+            serde_json::json!({"signedDate": NOW + DAY}),
+        );
+        engine.apply_notification(&event("DID_RENEW", None, renewed));
+        assert!(engine.has_access("pro.yearly", NOW + DAY));
+        assert!(engine.for_product("pro.monthly", NOW + DAY).is_none());
+
+        engine.remove("1");
+        assert!(engine.entitlements(NOW).is_empty());
+    }
+
+    #[test]
+    fn test_non_expiring_purchases_stay_active() {
+        let mut engine = EntitlementEngine::new();
+        engine.apply(
+            transaction(
+                "1",
+                "lifetime",
+                None,
+                serde_json::json!({"type": "Non-Consumable"}),
+            ),
+            None,
+            None,
+        );
+        let entitlement = engine.for_product("lifetime", NOW + 1000 * DAY).unwrap();
+        assert!(entitlement.has_access);
+        assert_eq!(entitlement.expires_date, None);
+    }
+}
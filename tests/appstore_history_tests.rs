@@ -0,0 +1,171 @@
+mod common;
+
+#[cfg(feature = "appstore")]
+mod appstore_history_tests {
+    use crate::common::StubServer;
+    use apple::appstore::*;
+    use apple::error::AppleError;
+    use apple::signing::AppleKeyPair;
+    use futures::StreamExt;
+
+    fn test_pem_bytes() -> Vec<u8> {
+        use p256::ecdsa::SigningKey;
+
+        let sk = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let pem_obj = pem::Pem::new("EC PRIVATE KEY", sk.to_bytes().as_slice());
+        pem::encode(&pem_obj).into_bytes()
+    }
+
+    fn client(server: &StubServer) -> AppStoreServerClient {
+        let key_pair = AppleKeyPair::from_pem_bytes("test-key", &test_pem_bytes()).unwrap();
+        AppStoreServerClient::new(AppStoreConfig {
+            issuer_id: "issuer".to_string(),
+            bundle_id: "com.example.app".to_string(),
+            key_pair,
+            environment: AppStoreEnvironment::Sandbox,
+        })
+        .unwrap()
+        .with_base_url(&server.base_url)
+    }
+
+    fn history_page(transactions: &[&str], revision: &str, has_more: bool) -> serde_json::Value {
+        serde_json::json!({
+            "signedTransactions": transactions,
+            "revision": revision,
+            "bundleId": "com.example.app",
+            "environment": "Sandbox",
+            "hasMore": has_more,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_transaction_history_follows_revisions() {
+        let server = StubServer::routes(vec![
+            (
+                "/inApps/v2/history/1000",
+                200,
+                history_page(&["a", "b"], "r1", true),
+            ),
+            (
+                "/inApps/v2/history/1000?revision=r1",
+                200,
+                history_page(&["c"], "r2", false),
+            ),
+        ]);
+        let client = client(&server);
+
+        let items: Vec<TransactionHistoryItem> = client
+            .transaction_history_stream("1000", None, None, None)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        let raw: Vec<&str> = items.iter().map(|item| item.raw.as_str()).collect();
+        assert_eq!(raw, ["a", "b", "c"]);
+        assert_eq!(items[1].cursor, None);
+        assert_eq!(items[1].next_cursor.as_deref(), Some("r1"));
+        assert_eq!(items[2].cursor.as_deref(), Some("r1"));
+        assert_eq!(items[2].next_cursor.as_deref(), Some("r2"));
+        assert!(items.iter().all(|item| item.decoded.is_none()));
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_streams_resume_from_a_stored_cursor() {
+        let server = StubServer::routes(vec![
+            (
+                "/inApps/v2/refund/lookup/1000?revision=r1",
+                200,
+                history_page(&["c"], "r2", false),
+            ),
+            (
+                "/inApps/v1/notifications/history?paginationToken=t1",
+                200,
+                serde_json::json!({
+                    "notificationHistory": [{"signedPayload": "jws", "sendAttempts": []}],
+                    "hasMore": false,
+                }),
+            ),
+        ]);
+        let client = client(&server);
+
+        let refunds: Vec<_> = client
+            .refund_history_stream("1000", Some("r1".to_string()), None)
+            .collect()
+            .await;
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].as_ref().unwrap().raw, "c");
+
+        let request = NotificationHistoryRequest {
+            start_date: 0,
+            end_date: 1,
+            notification_type: None,
+            notification_subtype: None,
+            only_failures: None,
+            transaction_id: None,
+        };
+        let notifications: Vec<_> = client
+            .notification_history_stream(&request, Some("t1".to_string()), None)
+            .collect()
+            .await;
+        let item = notifications[0].as_ref().unwrap();
+        assert_eq!(item.raw.signed_payload, "jws");
+        assert_eq!(item.next_cursor, None);
+        assert_eq!(
+            server.requests(),
+            [
+                "/inApps/v2/refund/lookup/1000?revision=r1",
+                "/inApps/v1/notifications/history?paginationToken=t1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_errors_end_the_stream() {
+        let server = StubServer::routes(vec![
+            (
+                "/inApps/v2/history/1000",
+                200,
+                history_page(&["a"], "r1", true),
+            ),
+            (
+                "/inApps/v2/history/1000?revision=r1",
+                500,
+                serde_json::json!({"errorCode": 5000000, "errorMessage": "An unknown error occurred."}),
+            ),
+        ]);
+        let client = client(&server);
+
+        let items: Vec<_> = client
+            .transaction_history_stream("1000", None, None, None)
+            .collect()
+            .await;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap().raw, "a");
+        assert!(items[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verifier_decodes_each_entry() {
+        let server = StubServer::routes(vec![(
+            "/inApps/v2/history/1000",
+            200,
+            history_page(&["not-a-jws"], "r1", false),
+        )]);
+        let client = client(&server);
+        let verifier = SignedDataVerifier::new(
+            vec![],
+            "com.example.app",
+            AppStoreEnvironment::Sandbox,
+            None,
+        );
+
+        let items: Vec<_> = client
+            .transaction_history_stream("1000", None, None, Some(&verifier))
+            .collect()
+            .await;
+        assert!(matches!(
+            items.as_slice(),
+            [Err(AppleError::CertificateError(_))]
+        ));
+    }
+}
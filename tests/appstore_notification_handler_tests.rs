@@ -0,0 +1,141 @@
+mod common;
+
+#[cfg(feature = "appstore-testing")]
+mod appstore_notification_handler_tests {
+    use crate::common::{SIGNED_DATE, notification, transaction};
+    use apple::appstore::testing::TestSigner;
+    use apple::appstore::*;
+    use apple::error::AppleError;
+
+    fn verifier(roots: Vec<Vec<u8>>) -> SignedDataVerifier {
+        SignedDataVerifier::new(roots, "com.example.app", AppStoreEnvironment::Sandbox, None)
+    }
+
+    fn webhook_body(chain: &TestSigner, notification_type: &str, subtype: Option<&str>) -> Vec<u8> {
+        let renewal_info = serde_json::json!({
+            "originalTransactionId": "1000",
+            "autoRenewProductId": "pro.monthly",
+            "productId": "pro.monthly",
+            "autoRenewStatus": 1,
+            "signedDate": SIGNED_DATE,
+            "environment": "Sandbox",
+        });
+        let mut payload = notification(serde_json::json!({
+            "notificationType": notification_type,
+            "data": {
+                "environment": "Sandbox",
+                "bundleId": "com.example.app",
+                "signedTransactionInfo": chain.sign(&transaction(SIGNED_DATE)),
+                "signedRenewalInfo": chain.sign(&renewal_info),
+            },
+        }));
+        if let Some(subtype) = subtype {
+            payload["subtype"] = subtype.into();
+        }
+        serde_json::json!({"signedPayload": chain.sign(&payload)})
+            .to_string()
+            .into_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_handler_decodes_nested_payloads_once() {
+        let chain = TestSigner::new();
+        let handler = NotificationHandler::new(
+            verifier(vec![chain.root_certificate().to_vec()]),
+            MemoryIdempotencyStore::new(),
+        );
+        let body = webhook_body(
+            &chain,
+            "DID_CHANGE_RENEWAL_STATUS",
+            Some("AUTO_RENEW_DISABLED"),
+        );
+
+        let event = handler.handle(&body).await.unwrap().unwrap();
+        assert_eq!(event.kind, NotificationKind::AutoRenewDisabled);
+        assert_eq!(
+            event.notification_uuid,
+            "8c3b5a2e-1111-2222-3333-444455556666"
+        );
+        assert_eq!(event.transaction.unwrap().transaction_id, "1000");
+        assert_eq!(
+            event.renewal_info.unwrap().auto_renew_product_id,
+            "pro.monthly"
+        );
+        assert!(handler.store().contains(&event.notification_uuid));
+
+        assert!(handler.handle(&body).await.unwrap().is_none());
+        assert!(handler.decode(&body).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_handler_releases_claim_when_processing_fails() {
+        let chain = TestSigner::new();
+        let handler = NotificationHandler::new(
+            verifier(vec![chain.root_certificate().to_vec()]),
+            MemoryIdempotencyStore::new(),
+        );
+        let body = webhook_body(&chain, "DID_RENEW", None);
+
+        let result: Result<Option<()>, AppleError> = handler
+            .process(&body, |_| async {
+                Err(AppleError::HttpError("database unavailable".to_string()))
+            })
+            .await;
+        assert!(result.is_err());
+
+        let kind = handler
+            .process(&body, |event| async move { Ok(event.kind) })
+            .await
+            .unwrap();
+        assert_eq!(kind, Some(NotificationKind::Renewed));
+        let again = handler.process(&body, |_| async { Ok(()) }).await.unwrap();
+        assert!(again.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handler_rejects_unsigned_and_mismatched_payloads() {
+        let chain = TestSigner::new();
+        let handler = NotificationHandler::new(
+            verifier(vec![chain.root_certificate().to_vec()]),
+            MemoryIdempotencyStore::new(),
+        );
+        assert!(matches!(
+            handler.handle(b"not json").await,
+            Err(AppleError::JsonError(_))
+        ));
+
+        let other = SignedDataVerifier::new(
+            vec![chain.root_certificate().to_vec()],
+            "com.example.other",
+            AppStoreEnvironment::Sandbox,
+            None,
+        );
+        let handler = NotificationHandler::new(other, MemoryIdempotencyStore::new());
+        let body = webhook_body(&chain, "SUBSCRIBED", Some("INITIAL_BUY"));
+        assert!(matches!(
+            handler.handle(&body).await,
+            Err(AppleError::VerificationError(_))
+        ));
+        assert!(
+            !handler
+                .store()
+                .contains("8c3b5a2e-1111-2222-3333-444455556666")
+        );
+    }
+
+    #[test]
+    fn test_notification_kind_combines_type_and_subtype() {
+        assert_eq!(
+            NotificationKind::new(&NotificationTypeV2::SUBSCRIBED, Some(&Subtype::RESUBSCRIBE)),
+            NotificationKind::Resubscribed
+        );
+        assert_eq!(
+            NotificationKind::new(&NotificationTypeV2::DID_CHANGE_RENEWAL_PREF, None),
+            NotificationKind::DowngradeCanceled
+        );
+        assert_eq!(
+            NotificationKind::new(&NotificationTypeV2::REFUND, Some(&Subtype::SUMMARY)),
+            NotificationKind::Other(NotificationTypeV2::REFUND, Some(Subtype::SUMMARY))
+        );
+    }
+}
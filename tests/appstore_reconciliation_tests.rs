@@ -0,0 +1,211 @@
+mod common;
+
+#[cfg(feature = "appstore-testing")]
+mod appstore_reconciliation_tests {
+    use crate::common::{SIGNED_DATE, StubServer, notification, transaction};
+    use apple::appstore::testing::TestSigner;
+    use apple::appstore::*;
+    use apple::error::AppleError;
+
+    fn verifier(roots: Vec<Vec<u8>>) -> SignedDataVerifier {
+        SignedDataVerifier::new(roots, "com.example.app", AppStoreEnvironment::Sandbox, None)
+    }
+
+    /// A client for a local App Store Server API that answers by request
+    /// target, with 404 for anything else.
+    fn api_server(routes: Vec<(&str, u16, serde_json::Value)>) -> AppStoreServerClient {
+        let server = StubServer::routes(routes);
+        let key = p256::ecdsa::SigningKey::from_slice(&[7; 32]).unwrap();
+        let pem = pem::encode(&pem::Pem::new("EC PRIVATE KEY", key.to_bytes().as_slice()));
+        AppStoreServerClient::new(AppStoreConfig {
+            issuer_id: "issuer".to_string(),
+            bundle_id: "com.example.app".to_string(),
+            key_pair: apple::signing::AppleKeyPair::from_pem_bytes("key", pem.as_bytes()).unwrap(),
+            environment: AppStoreEnvironment::Sandbox,
+        })
+        .unwrap()
+        .with_base_url(&server.base_url)
+    }
+
+    fn history_notification(chain: &TestSigner, uuid: &str) -> serde_json::Value {
+        let payload = notification(serde_json::json!({
+            "notificationType": "DID_RENEW",
+            "notificationUUID": uuid,
+            "data": {
+                "environment": "Sandbox",
+                "bundleId": "com.example.app",
+                "signedTransactionInfo": chain.sign(&transaction(SIGNED_DATE)),
+            },
+        }));
+        serde_json::json!({
+            "signedPayload": chain.sign(&payload),
+            "sendAttempts": [{"attemptDate": SIGNED_DATE, "sendAttemptResult": "TIMED_OUT"}],
+        })
+    }
+
+    fn history_request() -> NotificationHistoryRequest {
+        NotificationHistoryRequest {
+            start_date: SIGNED_DATE - 1000,
+            end_date: SIGNED_DATE + 1000,
+            notification_type: None,
+            notification_subtype: None,
+            only_failures: None,
+            transaction_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backfill_replays_missed_notifications_once() {
+        let chain = TestSigner::new();
+        let client = api_server(vec![(
+            "/inApps/v1/notifications/history",
+            200,
+            serde_json::json!({
+                "notificationHistory": [
+                    history_notification(&chain, "delivered"),
+                    history_notification(&chain, "missed"),
+                    history_notification(&chain, "failing"),
+                    {"signedPayload": "forged", "sendAttempts": []},
+                ],
+                "hasMore": false,
+            }),
+        )]);
+        let handler = NotificationHandler::new(
+            verifier(vec![chain.root_certificate().to_vec()]),
+            MemoryIdempotencyStore::new(),
+        );
+        handler.store().claim("delivered").await.unwrap();
+        let reconciler = Reconciler::new(&client, &handler);
+
+        let mut replayed = Vec::new();
+        let report = reconciler
+            .backfill(&history_request(), None, |event| {
+                replayed.push(event.notification_uuid.clone());
+                async move {
+                    if event.notification_uuid == "failing" {
+                        Err(AppleError::HttpError("database unavailable".to_string()))
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(replayed, ["missed", "failing"]);
+        assert_eq!(report.fetched, 4);
+        assert_eq!(report.duplicates, 1);
+        assert_eq!(report.missed.len(), 1);
+        assert_eq!(report.missed[0].notification_uuid, "missed");
+        assert_eq!(report.missed[0].kind, NotificationKind::Renewed);
+        assert_eq!(
+            report.missed[0].send_attempts[0].send_attempt_result,
+            SendAttemptResult::TIMED_OUT
+        );
+        let failed: Vec<_> = report
+            .failures
+            .iter()
+            .map(|failure| failure.notification_uuid.as_deref())
+            .collect();
+        assert_eq!(failed, [Some("failing"), None]);
+        assert!(!report.interrupted);
+        assert!(!report.is_complete());
+        assert!(!handler.store().contains("failing"));
+
+        // A second pass only retries what failed.
+        let report = reconciler
+            .backfill(&history_request(), None, |_| async { Ok(()) })
+            .await;
+        assert_eq!(report.duplicates, 2);
+        assert_eq!(report.missed[0].notification_uuid, "failing");
+    }
+
+    #[tokio::test]
+    async fn test_backfill_reports_where_history_was_interrupted() {
+        let chain = TestSigner::new();
+        let client = api_server(vec![
+            (
+                "/inApps/v1/notifications/history",
+                200,
+                serde_json::json!({
+                    "notificationHistory": [history_notification(&chain, "first")],
+                    "paginationToken": "t1",
+                    "hasMore": true,
+                }),
+            ),
+            (
+                "/inApps/v1/notifications/history?paginationToken=t1",
+                500,
+                serde_json::json!({"errorCode": 5000000, "errorMessage": "An unknown error occurred."}),
+            ),
+        ]);
+        let handler = NotificationHandler::new(
+            verifier(vec![chain.root_certificate().to_vec()]),
+            MemoryIdempotencyStore::new(),
+        );
+        let report = Reconciler::new(&client, &handler)
+            .backfill(&history_request(), None, |_| async { Ok(()) })
+            .await;
+        assert_eq!(report.missed.len(), 1);
+        assert!(report.interrupted);
+        assert_eq!(report.cursor.as_deref(), Some("t1"));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_reports_missing_and_outdated_transactions() {
+        let chain = TestSigner::new();
+        let signed = |original: &str, id: &str, purchase_date: i64| {
+            let mut payload = transaction(SIGNED_DATE);
+            payload["originalTransactionId"] = original.into();
+            payload["transactionId"] = id.into();
+            payload["purchaseDate"] = purchase_date.into();
+            payload["expiresDate"] = (purchase_date + 1000).into();
+            payload
+        };
+        let client = api_server(vec![(
+            "/inApps/v2/history/1000",
+            200,
+            serde_json::json!({
+                "signedTransactions": [
+                    chain.sign(&signed("1000", "1000", SIGNED_DATE)),
+                    chain.sign(&signed("1000", "1001", SIGNED_DATE + 1000)),
+                    chain.sign(&signed("2000", "2000", SIGNED_DATE)),
+                    chain.sign(&signed("3000", "3000", SIGNED_DATE)),
+                ],
+                "revision": "r1",
+                "bundleId": "com.example.app",
+                "environment": "Sandbox",
+                "hasMore": false,
+            }),
+        )]);
+        let handler = NotificationHandler::new(
+            verifier(vec![chain.root_certificate().to_vec()]),
+            MemoryIdempotencyStore::new(),
+        );
+        let mut engine = EntitlementEngine::new();
+        for (original, id) in [("1000", "1000"), ("3000", "3000")] {
+            engine.apply(
+                serde_json::from_value(signed(original, id, SIGNED_DATE)).unwrap(),
+                None,
+                None,
+            );
+        }
+
+        let report = Reconciler::new(&client, &handler)
+            .reconcile_transactions("1000", &engine)
+            .await
+            .unwrap();
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.divergences.len(), 2);
+        match &report.divergences[0] {
+            Divergence::Outdated { ours, apple } => {
+                assert_eq!(ours.transaction_id, "1000");
+                assert_eq!(apple.transaction_id, "1001");
+            }
+            other => panic!("Expected Outdated, got {:?}", other),
+        }
+        assert!(matches!(
+            &report.divergences[1],
+            Divergence::Missing { apple } if apple.original_transaction_id == "2000"
+        ));
+    }
+}
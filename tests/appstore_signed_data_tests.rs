@@ -0,0 +1,591 @@
+mod common;
+
+#[cfg(feature = "appstore-testing")]
+mod appstore_signed_data_tests {
+    use crate::common::{SIGNED_DATE, StubResponse, StubServer, notification, transaction};
+    use apple::appstore::signed_data::LEAF_MARKER_OID;
+    use apple::appstore::testing::{CertificateSpec, TestSigner};
+    use apple::appstore::*;
+    use apple::error::AppleError;
+    use base64::Engine;
+    use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+    use sha2::{Digest, Sha256};
+    use std::sync::{Arc, OnceLock};
+    use std::time::{Duration, SystemTime};
+    use x509_cert::certificate::Certificate;
+    use x509_cert::der::asn1::{BitString, GeneralizedTime, ObjectIdentifier};
+    use x509_cert::der::{Decode, Encode};
+    use x509_cert::spki::AlgorithmIdentifierOwned;
+    use x509_ocsp::{
+        BasicOcspResponse, CertStatus, OcspGeneralizedTime, OcspRequest, OcspResponse, ResponderId,
+        ResponseData, RevokedInfo, SingleResponse,
+    };
+
+    const ID_KP_OCSP_SIGNING: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.9");
+
+    fn verifier(roots: Vec<Vec<u8>>) -> SignedDataVerifier {
+        SignedDataVerifier::new(roots, "com.example.app", AppStoreEnvironment::Sandbox, None)
+    }
+
+    fn certificate_error(result: Result<JWSTransactionDecodedPayload, AppleError>) -> String {
+        match result {
+            Err(AppleError::CertificateError(msg)) => msg,
+            other => panic!("Expected CertificateError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_valid_chain_verifies() {
+        let chain = TestSigner::new();
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let payload = verifier(vec![chain.root_certificate().to_vec()])
+            .verify_and_decode_transaction(&jws)
+            .unwrap();
+        assert_eq!(payload.product_id, "pro.monthly");
+    }
+
+    #[test]
+    fn test_fails_closed_without_trusted_roots() {
+        let chain = TestSigner::new();
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let err = certificate_error(verifier(Vec::new()).verify_and_decode_transaction(&jws));
+        assert!(err.contains("No trusted root"), "{}", err);
+
+        let other_root = TestSigner::with_chain(|specs| specs[2].subject = "Other Root".into());
+        let err = certificate_error(
+            verifier(vec![other_root.root_certificate().to_vec()])
+                .verify_and_decode_transaction(&jws),
+        );
+        assert!(err.contains("not trusted"), "{}", err);
+    }
+
+    #[test]
+    fn test_rejects_missing_or_short_chain() {
+        let chain = TestSigner::new();
+        let root = chain.root_certificate().to_vec();
+        let short = chain.certificate_chain()[..2].to_vec();
+        let chain = chain.with_certificate_chain(short);
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let err =
+            certificate_error(verifier(vec![root.clone()]).verify_and_decode_transaction(&jws));
+        assert!(err.contains("chain of 3"), "{}", err);
+
+        let chain = chain.with_certificate_chain(Vec::new());
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        assert!(
+            verifier(vec![root])
+                .verify_and_decode_transaction(&jws)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rejects_forged_certificate_signature() {
+        // Same subject and issuer names, but signed by a key that is not the
+        // intermediate's.
+        let chain = TestSigner::with_chain(|specs| specs[0].forged = true);
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let err = certificate_error(
+            verifier(vec![chain.root_certificate().to_vec()]).verify_and_decode_transaction(&jws),
+        );
+        assert!(err.contains("does not verify"), "{}", err);
+    }
+
+    #[test]
+    fn test_rejects_missing_marker_oids() {
+        let chain = TestSigner::with_chain(|specs| specs[0].marker = None);
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let err = certificate_error(
+            verifier(vec![chain.root_certificate().to_vec()]).verify_and_decode_transaction(&jws),
+        );
+        assert!(err.contains("Leaf certificate is missing"), "{}", err);
+
+        let chain = TestSigner::with_chain(|specs| specs[1].marker = Some(LEAF_MARKER_OID));
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let err = certificate_error(
+            verifier(vec![chain.root_certificate().to_vec()]).verify_and_decode_transaction(&jws),
+        );
+        assert!(
+            err.contains("Intermediate certificate is missing"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_enforces_basic_constraints() {
+        let chain = TestSigner::with_chain(|specs| specs[1].ca = Some(false));
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let err = certificate_error(
+            verifier(vec![chain.root_certificate().to_vec()]).verify_and_decode_transaction(&jws),
+        );
+        assert_eq!(err, "Intermediate certificate is not a CA");
+
+        let chain = TestSigner::with_chain(|specs| specs[0].ca = Some(true));
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let err = certificate_error(
+            verifier(vec![chain.root_certificate().to_vec()]).verify_and_decode_transaction(&jws),
+        );
+        assert_eq!(err, "Leaf certificate must not be a CA");
+    }
+
+    #[test]
+    fn test_validity_is_checked_at_signed_date() {
+        // The leaf expired in 2023 but signed this payload while valid.
+        let chain =
+            TestSigner::with_chain(|specs| specs[0].valid_secs = (1_600_000_000, 1_690_000_000));
+        let verifier = verifier(vec![chain.root_certificate().to_vec()]);
+
+        let jws = chain.sign(&transaction(1_680_000_000_000));
+        assert!(verifier.verify_and_decode_transaction(&jws).is_ok());
+
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let err = certificate_error(verifier.verify_and_decode_transaction(&jws));
+        assert!(err.starts_with("Leaf certificate is not valid"), "{}", err);
+
+        let jws = chain.sign(&transaction(1_680_000_000_000));
+        let verifier = verifier.with_current_time_validation();
+        assert!(verifier.verify_and_decode_transaction(&jws).is_err());
+    }
+
+    #[test]
+    fn test_rejects_tampered_payload() {
+        let chain = TestSigner::new();
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let mut parts: Vec<String> = jws.split('.').map(str::to_string).collect();
+        let mut payload = transaction(SIGNED_DATE);
+        payload["productId"] = "pro.lifetime".into();
+        parts[1] = URL_SAFE_NO_PAD.encode(payload.to_string());
+        assert!(
+            verifier(vec![chain.root_certificate().to_vec()])
+                .verify_and_decode_transaction(&parts.join("."))
+                .is_err()
+        );
+    }
+
+    #[derive(Default)]
+    struct ResponderOptions {
+        revoked: Vec<u8>,
+        forged: bool,
+        stale: bool,
+        /// Signs with a delegated OCSP signer the issuer certified.
+        delegated: bool,
+        /// Names the issuer as the responder even when a delegate signs.
+        misnamed: bool,
+    }
+
+    /// A local OCSP responder for the chain `build` creates, signing as the
+    /// intermediate or root. `build` gets the responder's URL.
+    struct Responder {
+        url: String,
+        server: StubServer,
+        signer: Arc<TestSigner>,
+    }
+
+    fn ocsp_responder(
+        options: ResponderOptions,
+        build: impl FnOnce(&str) -> TestSigner,
+    ) -> Responder {
+        let chain: Arc<OnceLock<Arc<TestSigner>>> = Arc::default();
+        let signing = chain.clone();
+        let server = StubServer::start(move |request| StubResponse {
+            status: 200,
+            content_type: "application/ocsp-response",
+            body: ocsp_response(
+                &OcspRequest::from_der(&request.body).unwrap(),
+                signing.get().unwrap(),
+                &options,
+            ),
+        });
+        let url = format!("{}/ocsp", server.base_url);
+        let signer = Arc::new(build(&url));
+        let _ = chain.set(signer.clone());
+        Responder {
+            url,
+            server,
+            signer,
+        }
+    }
+
+    fn ocsp_time(offset_secs: i64) -> OcspGeneralizedTime {
+        let now = SystemTime::now();
+        let time = if offset_secs >= 0 {
+            now + Duration::from_secs(offset_secs as u64)
+        } else {
+            now - Duration::from_secs(offset_secs.unsigned_abs())
+        };
+        OcspGeneralizedTime(GeneralizedTime::from_system_time(time).unwrap())
+    }
+
+    fn ocsp_response(
+        request: &OcspRequest,
+        chain: &TestSigner,
+        options: &ResponderOptions,
+    ) -> Vec<u8> {
+        let cert_id = &request.tbs_request.request_list[0].req_cert;
+        let (index, issuer) = (1..3)
+            .map(|index| {
+                let der = STANDARD.decode(&chain.certificate_chain()[index]).unwrap();
+                (index, Certificate::from_der(&der).unwrap())
+            })
+            .find(|(_, cert)| {
+                let key = &cert
+                    .tbs_certificate
+                    .subject_public_key_info
+                    .subject_public_key;
+                Sha256::digest(key.raw_bytes()).as_slice() == cert_id.issuer_key_hash.as_bytes()
+            })
+            .unwrap();
+        let serial = cert_id.serial_number.as_bytes()[0];
+        let cert_status = if options.revoked.contains(&serial) {
+            CertStatus::revoked(RevokedInfo {
+                revocation_time: ocsp_time(-3600),
+                revocation_reason: None,
+            })
+        } else {
+            CertStatus::good()
+        };
+        let next_update = if options.stale { -60 } else { 3600 };
+        let delegate = options.delegated.then(|| {
+            let mut spec = CertificateSpec::new("Test OCSP Responder", 10);
+            spec.extended_key_usage = vec![ID_KP_OCSP_SIGNING];
+            chain.issue(index, &spec)
+        });
+        let delegate_cert = delegate
+            .as_ref()
+            .map(|issued| Certificate::from_der(&issued.der).unwrap());
+        let responder = match &delegate_cert {
+            Some(cert) if !options.misnamed => &cert.tbs_certificate.subject,
+            _ => &issuer.tbs_certificate.subject,
+        };
+        let data = ResponseData {
+            version: Default::default(),
+            responder_id: ResponderId::ByName(responder.clone()),
+            produced_at: ocsp_time(-60),
+            responses: vec![SingleResponse {
+                cert_id: cert_id.clone(),
+                cert_status,
+                this_update: ocsp_time(-120),
+                next_update: Some(ocsp_time(next_update)),
+                single_extensions: None,
+            }],
+            response_extensions: None,
+        };
+        let (algorithm, signature) = match &delegate {
+            Some(issued) => issued.sign(&data.to_der().unwrap()),
+            None if options.forged => TestSigner::new().sign_with(index, &data.to_der().unwrap()),
+            None => chain.sign_with(index, &data.to_der().unwrap()),
+        };
+        OcspResponse::successful(BasicOcspResponse {
+            tbs_response_data: data,
+            signature_algorithm: AlgorithmIdentifierOwned {
+                oid: algorithm,
+                parameters: None,
+            },
+            signature: BitString::from_bytes(&signature).unwrap(),
+            certs: delegate_cert.map(|cert| vec![cert]),
+        })
+        .unwrap()
+        .to_der()
+        .unwrap()
+    }
+
+    fn online_verifier(responder: &Responder) -> SignedDataVerifier {
+        verifier(vec![responder.signer.root_certificate().to_vec()]).with_online_checks(
+            OcspChecker::new()
+                .unwrap()
+                .with_responder_url(&responder.url),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_online_checks_query_ocsp_and_cache_good_responses() {
+        let responder = ocsp_responder(ResponderOptions::default(), |_| TestSigner::new());
+        let verifier = online_verifier(&responder);
+        let jws = responder.signer.sign(&transaction(SIGNED_DATE));
+
+        for _ in 0..2 {
+            verifier
+                .verify_and_decode_transaction_online(&jws)
+                .await
+                .unwrap();
+        }
+        // One request each for the leaf and the intermediate; the second
+        // verification is served from the cache.
+        assert_eq!(responder.server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_status_responses_are_checked_online() {
+        let responder = ocsp_responder(ResponderOptions::default(), |_| TestSigner::new());
+        let chain = &responder.signer;
+        let renewal_info = serde_json::json!({
+            "originalTransactionId": "1000",
+            "autoRenewProductId": "pro.monthly",
+            "productId": "pro.monthly",
+            "autoRenewStatus": 1,
+            "signedDate": SIGNED_DATE,
+            "environment": "Sandbox",
+        });
+        let response: StatusResponse = serde_json::from_value(serde_json::json!({
+            "environment": "Sandbox",
+            "bundleId": "com.example.app",
+            "data": [{
+                "subscriptionGroupIdentifier": "group",
+                "lastTransactions": [{
+                    "status": 1,
+                    "originalTransactionId": "1000",
+                    "signedTransactionInfo": chain.sign(&transaction(SIGNED_DATE)),
+                    "signedRenewalInfo": chain.sign(&renewal_info),
+                }],
+            }],
+        }))
+        .unwrap();
+
+        let mut engine = EntitlementEngine::new();
+        engine
+            .apply_status_response(&response, &online_verifier(&responder))
+            .await
+            .unwrap();
+        assert!(engine.transaction("1000").is_some());
+        assert_eq!(responder.server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_online_checks_use_the_certificate_responder() {
+        let responder = ocsp_responder(ResponderOptions::default(), |url| {
+            TestSigner::with_chain(|specs| {
+                specs[0].ocsp_url = Some(url.to_string());
+                specs[1].ocsp_url = Some(url.to_string());
+            })
+        });
+        let chain = &responder.signer;
+        let verifier = verifier(vec![chain.root_certificate().to_vec()])
+            .with_online_checks(OcspChecker::new().unwrap());
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        verifier
+            .verify_and_decode_transaction_online(&jws)
+            .await
+            .unwrap();
+        assert_eq!(responder.server.requests().len(), 2);
+
+        // Without an override or AIA extension there is nowhere to ask.
+        let chain = TestSigner::new();
+        let verifier = self::verifier(vec![chain.root_certificate().to_vec()])
+            .with_online_checks(OcspChecker::new().unwrap());
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let err = certificate_error(verifier.verify_and_decode_transaction_online(&jws).await);
+        assert!(err.contains("names no OCSP responder"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_online_checks_reject_revoked_forged_and_stale_responses() {
+        for (options, expected) in [
+            (
+                ResponderOptions {
+                    revoked: vec![1],
+                    ..Default::default()
+                },
+                "revoked",
+            ),
+            (
+                ResponderOptions {
+                    revoked: vec![2],
+                    ..Default::default()
+                },
+                "revoked",
+            ),
+            (
+                ResponderOptions {
+                    forged: true,
+                    ..Default::default()
+                },
+                "signature does not verify",
+            ),
+            (
+                ResponderOptions {
+                    stale: true,
+                    ..Default::default()
+                },
+                "not current",
+            ),
+        ] {
+            let responder = ocsp_responder(options, |_| TestSigner::new());
+            let verifier = online_verifier(&responder);
+            let jws = responder.signer.sign(&transaction(SIGNED_DATE));
+            let err = certificate_error(verifier.verify_and_decode_transaction_online(&jws).await);
+            assert!(err.contains(expected), "{}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_online_checks_accept_only_the_named_delegate() {
+        let responder = ocsp_responder(
+            ResponderOptions {
+                delegated: true,
+                ..Default::default()
+            },
+            |_| TestSigner::new(),
+        );
+        let verifier = online_verifier(&responder);
+        let jws = responder.signer.sign(&transaction(SIGNED_DATE));
+        verifier
+            .verify_and_decode_transaction_online(&jws)
+            .await
+            .unwrap();
+
+        let responder = ocsp_responder(
+            ResponderOptions {
+                delegated: true,
+                misnamed: true,
+                ..Default::default()
+            },
+            |_| TestSigner::new(),
+        );
+        let verifier = online_verifier(&responder);
+        let jws = responder.signer.sign(&transaction(SIGNED_DATE));
+        let err = certificate_error(verifier.verify_and_decode_transaction_online(&jws).await);
+        assert!(err.contains("signature does not verify"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_online_methods_require_online_checks() {
+        let chain = TestSigner::new();
+        let jws = chain.sign(&transaction(SIGNED_DATE));
+        let err = certificate_error(
+            verifier(vec![chain.root_certificate().to_vec()])
+                .verify_and_decode_transaction_online(&jws)
+                .await,
+        );
+        assert!(err.contains("not enabled"), "{}", err);
+    }
+
+    fn verification_error<T: std::fmt::Debug>(result: Result<T, AppleError>) -> VerificationError {
+        match result {
+            Err(AppleError::VerificationError(err)) => err,
+            other => panic!("Expected VerificationError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_transaction_mismatches_are_typed() {
+        let chain = TestSigner::new();
+        let verifier = verifier(vec![chain.root_certificate().to_vec()]);
+
+        let mut payload = transaction(SIGNED_DATE);
+        payload["bundleId"] = "com.example.other".into();
+        let err = verification_error(verifier.verify_and_decode_transaction(&chain.sign(&payload)));
+        assert_eq!(
+            err,
+            VerificationError::BundleIdMismatch {
+                expected: "com.example.app".into(),
+                found: Some("com.example.other".into()),
+            }
+        );
+
+        let mut payload = transaction(SIGNED_DATE);
+        payload["environment"] = "Production".into();
+        let err = verification_error(verifier.verify_and_decode_transaction(&chain.sign(&payload)));
+        assert_eq!(
+            err.to_string(),
+            "Environment mismatch: expected Sandbox, got Production"
+        );
+    }
+
+    #[test]
+    fn test_notification_checks_data_summary_and_token() {
+        let chain = TestSigner::new();
+        let sandbox = verifier(vec![chain.root_certificate().to_vec()]);
+        let production = SignedDataVerifier::new(
+            vec![chain.root_certificate().to_vec()],
+            "com.example.app",
+            AppStoreEnvironment::Production,
+            Some(42),
+        );
+        let data = |environment: &str, app_apple_id: Option<i64>| {
+            notification(serde_json::json!({"data": {
+                "environment": environment,
+                "bundleId": "com.example.app",
+                "appAppleId": app_apple_id,
+            }}))
+        };
+
+        // Sandbox notifications carry no appAppleId and none is required.
+        let jws = chain.sign(&data("Sandbox", None));
+        assert!(sandbox.verify_and_decode_notification(&jws).is_ok());
+        let err = verification_error(production.verify_and_decode_notification(&jws));
+        assert!(matches!(err, VerificationError::AppAppleIdMismatch { .. }));
+
+        let jws = chain.sign(&data("Production", Some(42)));
+        assert!(production.verify_and_decode_notification(&jws).is_ok());
+        let jws = chain.sign(&data("Production", Some(7)));
+        assert_eq!(
+            verification_error(production.verify_and_decode_notification(&jws)),
+            VerificationError::AppAppleIdMismatch {
+                expected: Some(42),
+                found: Some(7),
+            }
+        );
+
+        let summary = notification(serde_json::json!({"summary": {
+            "environment": "Sandbox",
+            "bundleId": "com.example.other",
+            "requestIdentifier": "req",
+            "productId": "pro.monthly",
+            "succeededCount": 1,
+            "failedCount": 0,
+        }}));
+        let err = verification_error(sandbox.verify_and_decode_notification(&chain.sign(&summary)));
+        assert!(matches!(err, VerificationError::BundleIdMismatch { .. }));
+
+        let token = |id: &str| {
+            notification(serde_json::json!({"externalPurchaseToken": {
+                "externalPurchaseId": id,
+                "tokenCreationDate": SIGNED_DATE,
+                "bundleId": "com.example.app",
+            }}))
+        };
+        let jws = chain.sign(&token("SANDBOX_abc"));
+        assert!(sandbox.verify_and_decode_notification(&jws).is_ok());
+        let jws = chain.sign(&token("abc"));
+        assert!(matches!(
+            verification_error(sandbox.verify_and_decode_notification(&jws)),
+            VerificationError::EnvironmentMismatch { .. }
+        ));
+
+        let err = verification_error(
+            sandbox
+                .verify_and_decode_notification(&chain.sign(&notification(serde_json::json!({})))),
+        );
+        assert_eq!(
+            err.to_string(),
+            "Bundle ID mismatch: expected com.example.app, got none"
+        );
+    }
+
+    #[test]
+    fn test_app_transaction_checks_app_apple_id_in_production() {
+        let chain = TestSigner::new();
+        let production = SignedDataVerifier::new(
+            vec![chain.root_certificate().to_vec()],
+            "com.example.app",
+            AppStoreEnvironment::Production,
+            Some(42),
+        );
+        let app_transaction = |app_apple_id: i64| {
+            serde_json::json!({
+                "appAppleId": app_apple_id,
+                "bundleId": "com.example.app",
+                "environment": "Production",
+                "signedDate": SIGNED_DATE,
+            })
+        };
+        let jws = chain.sign(&app_transaction(42));
+        assert!(production.verify_and_decode_app_transaction(&jws).is_ok());
+        let jws = chain.sign(&app_transaction(43));
+        assert!(matches!(
+            verification_error(production.verify_and_decode_app_transaction(&jws)),
+            VerificationError::AppAppleIdMismatch { .. }
+        ));
+    }
+}
@@ -0,0 +1,235 @@
+#[cfg(feature = "appstore-testing")]
+mod appstore_testing_tests {
+    use apple::appstore::testing::{self, TestSigner};
+    use apple::appstore::*;
+    use apple::error::AppleError;
+
+    const BUNDLE_ID: &str = "com.example.app";
+
+    #[test]
+    fn test_signed_payloads_verify() {
+        let signer = TestSigner::new();
+        let verifier = signer.verifier(BUNDLE_ID, AppStoreEnvironment::Sandbox, None);
+
+        let transaction =
+            testing::transaction(BUNDLE_ID, "pro.monthly", AppStoreEnvironment::Sandbox);
+        let decoded = verifier
+            .verify_and_decode_transaction(&signer.sign_transaction(&transaction))
+            .unwrap();
+        assert_eq!(decoded.transaction_id, transaction.transaction_id);
+        assert_eq!(decoded.product_id, "pro.monthly");
+
+        let renewal_info = testing::renewal_info(&transaction);
+        let decoded = verifier
+            .verify_and_decode_renewal_info(&signer.sign_renewal_info(&renewal_info))
+            .unwrap();
+        assert_eq!(
+            decoded.original_transaction_id,
+            transaction.original_transaction_id
+        );
+
+        let app_transaction =
+            testing::app_transaction(BUNDLE_ID, AppStoreEnvironment::Sandbox, None);
+        let decoded = verifier
+            .verify_and_decode_app_transaction(&signer.sign_app_transaction(&app_transaction))
+            .unwrap();
+        assert_eq!(decoded.bundle_id, BUNDLE_ID);
+    }
+
+    #[test]
+    fn test_production_payloads_need_app_apple_id() {
+        let signer = TestSigner::new();
+        let verifier = signer.verifier(BUNDLE_ID, AppStoreEnvironment::Production, Some(42));
+
+        let app_transaction =
+            testing::app_transaction(BUNDLE_ID, AppStoreEnvironment::Production, Some(42));
+        assert!(
+            verifier
+                .verify_and_decode_app_transaction(&signer.sign_app_transaction(&app_transaction))
+                .is_ok()
+        );
+
+        let transaction =
+            testing::transaction(BUNDLE_ID, "pro.monthly", AppStoreEnvironment::Production);
+        let mut notification =
+            signer.notification(NotificationTypeV2::DID_RENEW, None, &transaction, None);
+        assert!(matches!(
+            verifier.verify_and_decode_notification(&signer.sign_notification(&notification)),
+            Err(AppleError::VerificationError(
+                VerificationError::AppAppleIdMismatch { .. }
+            ))
+        ));
+        notification.data.as_mut().unwrap().app_apple_id = Some(42);
+        assert!(
+            verifier
+                .verify_and_decode_notification(&signer.sign_notification(&notification))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_other_signers_are_not_trusted() {
+        let signer = TestSigner::new();
+        let other = TestSigner::new();
+        assert_ne!(signer.root_certificate(), other.root_certificate());
+        assert_eq!(signer.certificate_chain().len(), 3);
+
+        let transaction =
+            testing::transaction(BUNDLE_ID, "pro.monthly", AppStoreEnvironment::Sandbox);
+        let verifier = other.verifier(BUNDLE_ID, AppStoreEnvironment::Sandbox, None);
+        assert!(
+            verifier
+                .verify_and_decode_transaction(&signer.sign_transaction(&transaction))
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_every_notification_kind_reaches_the_handler() {
+        use NotificationTypeV2 as T;
+
+        let signer = TestSigner::new();
+        let handler = NotificationHandler::new(
+            signer.verifier(BUNDLE_ID, AppStoreEnvironment::Sandbox, None),
+            MemoryIdempotencyStore::new(),
+        );
+        let transaction =
+            testing::transaction(BUNDLE_ID, "pro.monthly", AppStoreEnvironment::Sandbox);
+        let renewal_info = testing::renewal_info(&transaction);
+
+        let cases = [
+            (
+                T::SUBSCRIBED,
+                Some(Subtype::INITIAL_BUY),
+                NotificationKind::InitialBuy,
+            ),
+            (
+                T::SUBSCRIBED,
+                Some(Subtype::RESUBSCRIBE),
+                NotificationKind::Resubscribed,
+            ),
+            (T::DID_RENEW, None, NotificationKind::Renewed),
+            (
+                T::DID_RENEW,
+                Some(Subtype::BILLING_RECOVERY),
+                NotificationKind::BillingRecovered,
+            ),
+            (
+                T::DID_CHANGE_RENEWAL_PREF,
+                Some(Subtype::UPGRADE),
+                NotificationKind::Upgraded,
+            ),
+            (
+                T::DID_CHANGE_RENEWAL_PREF,
+                Some(Subtype::DOWNGRADE),
+                NotificationKind::Downgraded,
+            ),
+            (
+                T::DID_CHANGE_RENEWAL_PREF,
+                None,
+                NotificationKind::DowngradeCanceled,
+            ),
+            (
+                T::DID_CHANGE_RENEWAL_STATUS,
+                Some(Subtype::AUTO_RENEW_ENABLED),
+                NotificationKind::AutoRenewEnabled,
+            ),
+            (
+                T::DID_CHANGE_RENEWAL_STATUS,
+                Some(Subtype::AUTO_RENEW_DISABLED),
+                NotificationKind::AutoRenewDisabled,
+            ),
+            (T::DID_FAIL_TO_RENEW, None, NotificationKind::RenewalFailed),
+            (
+                T::DID_FAIL_TO_RENEW,
+                Some(Subtype::GRACE_PERIOD),
+                NotificationKind::RenewalFailedInGracePeriod,
+            ),
+            (
+                T::EXPIRED,
+                Some(Subtype::VOLUNTARY),
+                NotificationKind::ExpiredVoluntarily,
+            ),
+            (
+                T::EXPIRED,
+                Some(Subtype::BILLING_RETRY),
+                NotificationKind::ExpiredAfterBillingRetry,
+            ),
+            (
+                T::EXPIRED,
+                Some(Subtype::PRICE_INCREASE),
+                NotificationKind::ExpiredAfterPriceIncrease,
+            ),
+            (
+                T::EXPIRED,
+                Some(Subtype::PRODUCT_NOT_FOR_SALE),
+                NotificationKind::ExpiredProductNotForSale,
+            ),
+            (
+                T::GRACE_PERIOD_EXPIRED,
+                None,
+                NotificationKind::GracePeriodExpired,
+            ),
+            (
+                T::OFFER_REDEEMED,
+                None,
+                NotificationKind::OfferRedeemed(None),
+            ),
+            (
+                T::PRICE_INCREASE,
+                Some(Subtype::PENDING),
+                NotificationKind::PriceIncreasePending,
+            ),
+            (
+                T::PRICE_INCREASE,
+                Some(Subtype::ACCEPTED),
+                NotificationKind::PriceIncreaseAccepted,
+            ),
+            (T::REFUND, None, NotificationKind::Refunded),
+            (T::REFUND_DECLINED, None, NotificationKind::RefundDeclined),
+            (T::REFUND_REVERSED, None, NotificationKind::RefundReversed),
+            (T::RENEWAL_EXTENDED, None, NotificationKind::RenewalExtended),
+            (
+                T::RENEWAL_EXTENSION,
+                Some(Subtype::SUMMARY),
+                NotificationKind::RenewalExtensionSucceeded,
+            ),
+            (
+                T::RENEWAL_EXTENSION,
+                Some(Subtype::FAILURE),
+                NotificationKind::RenewalExtensionFailed,
+            ),
+            (T::REVOKE, None, NotificationKind::Revoked),
+            (
+                T::CONSUMPTION_REQUEST,
+                None,
+                NotificationKind::ConsumptionRequest,
+            ),
+            (T::ONE_TIME_CHARGE, None, NotificationKind::OneTimeCharge),
+            (T::TEST, None, NotificationKind::Test),
+        ];
+
+        for (notification_type, subtype, kind) in cases {
+            let notification = signer.notification(
+                notification_type,
+                subtype,
+                &transaction,
+                Some(&renewal_info),
+            );
+            let body = signer.webhook_body(&notification);
+
+            let event = handler.handle(&body).await.unwrap().unwrap();
+            assert_eq!(event.kind, kind);
+            assert_eq!(event.notification_uuid, notification.notification_uuid);
+            assert_eq!(
+                event.transaction.unwrap().transaction_id,
+                transaction.transaction_id
+            );
+            assert_eq!(
+                event.renewal_info.unwrap().auto_renew_product_id,
+                "pro.monthly"
+            );
+            assert!(handler.handle(&body).await.unwrap().is_none());
+        }
+    }
+}
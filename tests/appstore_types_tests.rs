@@ -0,0 +1,84 @@
+#[cfg(feature = "appstore")]
+mod appstore_types_tests {
+    use apple::appstore::*;
+
+    #[test]
+    fn test_integer_enums_keep_unknown_values() {
+        let status: SubscriptionStatus = serde_json::from_str("9").unwrap();
+        assert_eq!(status, SubscriptionStatus::Unknown(9));
+        assert_eq!(serde_json::to_string(&status).unwrap(), "9");
+
+        let offer: OfferType = serde_json::from_str("3").unwrap();
+        assert_eq!(offer, OfferType::OfferCode);
+        assert_eq!(i32::from(OfferType::from(42)), 42);
+    }
+
+    #[test]
+    fn test_expiration_intent_keeps_apples_unknown_reason() {
+        let intent: ExpirationIntent = serde_json::from_str("5").unwrap();
+        assert_eq!(intent, ExpirationIntent::Unknown);
+        let intent: ExpirationIntent = serde_json::from_str("6").unwrap();
+        assert_eq!(intent, ExpirationIntent::Unrecognized(6));
+        assert_eq!(serde_json::to_string(&intent).unwrap(), "6");
+    }
+
+    #[test]
+    fn test_string_enums_keep_unknown_values() {
+        let kind: NotificationTypeV2 = serde_json::from_str("\"NEW_TYPE\"").unwrap();
+        assert_eq!(kind, NotificationTypeV2::Unknown("NEW_TYPE".to_string()));
+        assert_eq!(serde_json::to_string(&kind).unwrap(), "\"NEW_TYPE\"");
+        assert_eq!(kind.to_string(), "NEW_TYPE");
+
+        let product: ProductType = serde_json::from_str("\"Non-Consumable\"").unwrap();
+        assert_eq!(product, ProductType::NonConsumable);
+        assert_eq!(
+            serde_json::to_string(&ProductType::AutoRenewableSubscription).unwrap(),
+            "\"Auto-Renewable Subscription\""
+        );
+        assert_eq!(
+            AppStoreEnvironment::from("Staging"),
+            AppStoreEnvironment::Unknown("Staging".to_string())
+        );
+        assert_eq!(AppStoreEnvironment::Sandbox.as_str(), "Sandbox");
+    }
+
+    #[test]
+    fn test_notification_with_new_values_round_trips() {
+        let json = serde_json::json!({
+            "notificationType": "SUBSCRIPTION_PAUSED",
+            "subtype": "PAUSED_BY_USER",
+            "version": "2.0",
+            "signedDate": 1_700_000_000_000i64,
+            "notificationUUID": "uuid",
+            "data": {
+                "environment": "Sandbox",
+                "bundleId": "com.example.app",
+                "status": 6,
+                "consumptionRequestReason": "NEW_REASON",
+            },
+        });
+        let payload: ResponseBodyV2DecodedPayload = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(
+            NotificationKind::new(&payload.notification_type, payload.subtype.as_ref()),
+            NotificationKind::Other(
+                NotificationTypeV2::Unknown("SUBSCRIPTION_PAUSED".to_string()),
+                Some(Subtype::Unknown("PAUSED_BY_USER".to_string())),
+            )
+        );
+        assert_eq!(
+            payload.data.as_ref().unwrap().status,
+            Some(SubscriptionStatus::Unknown(6))
+        );
+        assert_eq!(serde_json::to_value(&payload).unwrap(), json);
+    }
+
+    #[test]
+    fn test_v1_notification_with_new_type_parses() {
+        let notification: ServerNotificationV1 =
+            serde_json::from_str(r#"{"notification_type": "NEW_V1_TYPE"}"#).unwrap();
+        assert_eq!(
+            notification.notification_type,
+            NotificationTypeV1::Unknown("NEW_V1_TYPE".to_string())
+        );
+    }
+}
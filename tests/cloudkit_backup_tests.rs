@@ -0,0 +1,282 @@
+#[cfg(all(feature = "cloudkit-backup", feature = "cloudkit-emulator"))]
+mod cloudkit_backup_tests {
+    use apple::cloudkit::emulator::CloudKitEmulator;
+    use apple::cloudkit::*;
+    use apple::error::AppleError;
+    use apple::signing::AppleKeyPair;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn test_pem_bytes() -> Vec<u8> {
+        use p256::ecdsa::SigningKey;
+
+        let sk = SigningKey::from_slice(&[
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+            0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+            0x1d, 0x1e, 0x1f, 0x20,
+        ])
+        .unwrap();
+
+        let raw_bytes = sk.to_bytes();
+        let pem_obj = pem::Pem::new("EC PRIVATE KEY", raw_bytes.as_slice());
+        pem::encode(&pem_obj).into_bytes()
+    }
+
+    fn key_pair() -> Arc<AppleKeyPair> {
+        AppleKeyPair::from_pem_bytes("test-key", &test_pem_bytes()).unwrap()
+    }
+
+    fn setup(container: &str) -> (CloudKitEmulator, CloudKitClient) {
+        let kp = key_pair();
+        let emulator = CloudKitEmulator::builder()
+            .with_key_pair(&kp)
+            .start()
+            .unwrap();
+        let client = emulator
+            .client(CloudKitConfig {
+                container: container.to_string(),
+                environment: Environment::Development,
+                key_pair: kp,
+            })
+            .unwrap();
+        (emulator, client)
+    }
+
+    /// A fresh directory under the system temp dir, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos();
+            TempDir(std::env::temp_dir().join(format!(
+                "apple-backup-{}-{}-{}",
+                name,
+                std::process::id(),
+                nanos
+            )))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn upload(
+        client: &CloudKitClient,
+        db: &DatabaseType,
+        zone: &ZoneID,
+        data: &[u8],
+    ) -> AssetValue {
+        let upload = client
+            .request_asset_upload(db, "photo", "Photo", "image", Some(zone.clone()))
+            .await
+            .unwrap();
+        let url = upload.tokens[0].url.clone().unwrap();
+        client.upload_asset(&url, data).await.unwrap().into()
+    }
+
+    #[tokio::test]
+    async fn test_private_round_trip_remaps_zones_and_assets() {
+        let db = DatabaseType::Private;
+        let (_source_emulator, source) = setup("iCloud.com.test.source");
+        let albums = ZoneID::new("Albums");
+        let photos = ZoneID::new("Photos");
+        for zone in [&albums, &photos] {
+            source.create_zone(&db, zone.clone(), None).await.unwrap();
+        }
+        source
+            .create_record(
+                &db,
+                Record::new("Album")
+                    .with_name("album")
+                    .with_zone(albums.clone())
+                    .with_field("title", FieldValue::String("Trip".into())),
+            )
+            .await
+            .unwrap();
+        let asset = upload(&source, &db, &photos, b"jpeg-bytes").await;
+        source
+            .create_record(
+                &db,
+                Record::new("Photo")
+                    .with_name("photo")
+                    .with_zone(photos.clone())
+                    .with_field("image", FieldValue::Asset(asset))
+                    .with_field(
+                        "album",
+                        FieldValue::Reference(ReferenceValue {
+                            record_name: "album".into(),
+                            zone_id: Some(albums.clone()),
+                            action: None,
+                        }),
+                    ),
+            )
+            .await
+            .unwrap();
+
+        let dir = TempDir::new("private");
+        let manifest = source
+            .export_backup(&db, &dir.0, &ExportOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(manifest.version, 1);
+        assert_eq!(manifest.zones.len(), 2);
+        let photo_zone = manifest
+            .zones
+            .iter()
+            .find(|z| z.zone_id.zone_name == "Photos")
+            .unwrap();
+        assert_eq!((photo_zone.records, photo_zone.assets), (1, 1));
+
+        let (_target_emulator, target) = setup("iCloud.com.test.target");
+        let options = ImportOptions::default().with_zone_mapping("Albums", ZoneID::new("Albums2"));
+        let summary = target.import_backup(&db, &dir.0, &options).await.unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                zones: 2,
+                records: 2,
+                assets: 1
+            }
+        );
+
+        let restored = target
+            .lookup_records(&db, &["album"], Some(ZoneID::new("Albums2")), None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            restored[0].fields.get("title"),
+            Some(FieldValue::String(title)) if title == "Trip"
+        ));
+
+        let photo = &target
+            .lookup_records(&db, &["photo"], Some(photos), None)
+            .await
+            .unwrap()[0];
+        let Some(FieldValue::Reference(album)) = photo.fields.get("album") else {
+            panic!("Expected reference field");
+        };
+        assert_eq!(album.zone_id.as_ref().unwrap().zone_name, "Albums2");
+        let Some(FieldValue::Asset(image)) = photo.fields.get("image") else {
+            panic!("Expected asset field");
+        };
+        let download_url = image.download_url.clone().unwrap();
+        assert!(download_url.contains("${f}"));
+        let body = reqwest::get(download_url.replace("${f}", "photo.jpg"))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"jpeg-bytes");
+    }
+
+    #[tokio::test]
+    async fn test_public_export_uses_record_types_and_import_is_repeatable() {
+        let db = DatabaseType::Public;
+        let (_emulator, client) = setup("iCloud.com.test.app");
+        for name in ["n1", "n2", "n3"] {
+            client
+                .create_record(&db, Record::new("Note").with_name(name))
+                .await
+                .unwrap();
+        }
+
+        let dir = TempDir::new("public");
+        assert!(matches!(
+            client
+                .export_backup(&db, &dir.0, &ExportOptions::default())
+                .await,
+            Err(AppleError::ValidationError(_))
+        ));
+
+        let options = ExportOptions::default()
+            .with_record_type("Note")
+            .with_page_size(2);
+        let manifest = client.export_backup(&db, &dir.0, &options).await.unwrap();
+        assert_eq!(manifest.zones[0].records, 3);
+
+        // Importing over the live data replaces records instead of
+        // duplicating or conflicting with them.
+        for _ in 0..2 {
+            let summary = client
+                .import_backup(&db, &dir.0, &ImportOptions::default().with_batch_size(2))
+                .await
+                .unwrap();
+            assert_eq!(summary.records, 3);
+        }
+        let response = client
+            .query_records(
+                &db,
+                QueryBuilder::new("Note").build(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.records.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_paths_outside_the_archive() {
+        let db = DatabaseType::Public;
+        let (_emulator, client) = setup("iCloud.com.test.app");
+        let dir = TempDir::new("escape");
+        std::fs::create_dir_all(dir.0.join("zones")).unwrap();
+        let manifest = |file: &str| {
+            format!(
+                r#"{{"format":"cloudkit-backup","version":1,"container":"c","environment":"development","database":"public","createdAt":0,"zones":[{{"zoneID":{{"zoneName":"_defaultZone"}},"file":"{}","records":1,"assets":0}}]}}"#,
+                file
+            )
+        };
+
+        for file in ["../outside.jsonl", "/etc/passwd"] {
+            std::fs::write(dir.0.join("manifest.json"), manifest(file)).unwrap();
+            assert!(matches!(
+                client
+                    .import_backup(&db, &dir.0, &ImportOptions::default())
+                    .await,
+                Err(AppleError::ValidationError(_))
+            ));
+        }
+
+        std::fs::write(dir.0.join("manifest.json"), manifest("zones/notes.jsonl")).unwrap();
+        std::fs::write(
+            dir.0.join("zones/notes.jsonl"),
+            r#"{"recordName":"n1","recordType":"Note","fields":{"file":{"type":"ASSET","value":{"downloadURL":"assets/../../outside"}}}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            client
+                .import_backup(&db, &dir.0, &ImportOptions::default())
+                .await,
+            Err(AppleError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_manifest_rejects_newer_versions() {
+        let dir = TempDir::new("manifest");
+        std::fs::create_dir_all(&dir.0).unwrap();
+        std::fs::write(
+            dir.0.join("manifest.json"),
+            r#"{"format":"cloudkit-backup","version":99,"container":"c","environment":"development","database":"public","createdAt":0,"zones":[]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            read_manifest(&dir.0),
+            Err(AppleError::ValidationError(_))
+        ));
+
+        std::fs::write(dir.0.join("manifest.json"), r#"{"format":"other"}"#).unwrap();
+        assert!(read_manifest(&dir.0).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+#[cfg(all(feature = "cli", feature = "cloudkit-emulator"))]
+mod cloudkit_cli_tests {
+    use apple::cloudkit::emulator::CloudKitEmulator;
+    use apple::signing::AppleKeyPair;
+    use serde_json::Value;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    fn test_pem_bytes() -> Vec<u8> {
+        use p256::ecdsa::SigningKey;
+
+        let sk = SigningKey::from_slice(&[
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+            0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+            0x1d, 0x1e, 0x1f, 0x20,
+        ])
+        .unwrap();
+
+        let raw_bytes = sk.to_bytes();
+        let pem_obj = pem::Pem::new("EC PRIVATE KEY", raw_bytes.as_slice());
+        pem::encode(&pem_obj).into_bytes()
+    }
+
+    /// Runs the binary against an emulator with credentials from a config
+    /// file.
+    struct Cli {
+        _emulator: CloudKitEmulator,
+        config: PathBuf,
+        key: PathBuf,
+    }
+
+    impl Cli {
+        fn start(name: &str) -> Self {
+            let kp = AppleKeyPair::from_pem_bytes("test-key", &test_pem_bytes()).unwrap();
+            let emulator = CloudKitEmulator::builder()
+                .with_key_pair(&kp)
+                .start()
+                .unwrap();
+            let base =
+                std::env::temp_dir().join(format!("apple-cli-{}-{}", name, std::process::id()));
+            let key = base.with_extension("pem");
+            std::fs::write(&key, test_pem_bytes()).unwrap();
+            let config = base.with_extension("json");
+            let settings = serde_json::json!({
+                "container": "iCloud.com.test.app",
+                "keyId": "test-key",
+                "keyFile": key,
+                "baseURL": emulator.base_url(),
+            });
+            std::fs::write(&config, settings.to_string()).unwrap();
+            Cli {
+                _emulator: emulator,
+                config,
+                key,
+            }
+        }
+
+        fn run(&self, args: &[&str]) -> (bool, String, String) {
+            let output = Command::new(env!("CARGO_BIN_EXE_cloudkit"))
+                .arg("--config")
+                .arg(&self.config)
+                .args(args)
+                .env_remove("CLOUDKIT_CONTAINER")
+                .env_remove("CLOUDKIT_BASE_URL")
+                .output()
+                .unwrap();
+            (
+                output.status.success(),
+                String::from_utf8(output.stdout).unwrap(),
+                String::from_utf8(output.stderr).unwrap(),
+            )
+        }
+
+        fn json(&self, args: &[&str]) -> Value {
+            let mut all = vec!["--output", "json"];
+            all.extend_from_slice(args);
+            let (ok, stdout, stderr) = self.run(&all);
+            assert!(ok, "cloudkit {:?} failed: {}", args, stderr);
+            serde_json::from_str(&stdout).unwrap()
+        }
+    }
+
+    impl Drop for Cli {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.config);
+            let _ = std::fs::remove_file(&self.key);
+        }
+    }
+
+    #[test]
+    fn test_record_commands() {
+        let cli = Cli::start("records");
+        let created = cli.json(&[
+            "records",
+            "create",
+            "Note",
+            "--name",
+            "n1",
+            "--field",
+            "title=Hello",
+            "--field",
+            "count:int64=3",
+        ]);
+        assert_eq!(created[0]["fields"]["count"]["value"], 3);
+        cli.json(&[
+            "records",
+            "create",
+            "Note",
+            "--name",
+            "n2",
+            "--field",
+            "title=Bye",
+        ]);
+
+        let found = cli.json(&["records", "query", "Note", "--where", "title == \"Hello\""]);
+        assert_eq!(found.as_array().unwrap().len(), 1);
+        assert_eq!(found[0]["recordName"], "n1");
+
+        let updated = cli.json(&["records", "update", "n1", "--field", "title=Changed"]);
+        assert_eq!(updated[0]["fields"]["title"]["value"], "Changed");
+        assert_eq!(updated[0]["fields"]["count"]["value"], 3);
+
+        let (ok, table, _) = cli.run(&["records", "lookup", "n1", "n2"]);
+        assert!(ok);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("recordName  recordType  count  title"));
+        assert!(lines[1].contains("Changed"));
+
+        cli.json(&["records", "delete", "n1"]);
+        let remaining = cli.json(&["records", "query", "Note"]);
+        assert_eq!(remaining.as_array().unwrap().len(), 1);
+
+        cli.json(&["records", "create", "Note", "--name", "n3"]);
+        let deleted = cli.json(&["records", "delete", "n2", "n3"]);
+        assert_eq!(deleted.as_array().unwrap().len(), 2);
+        let remaining = cli.json(&["records", "query", "Note"]);
+        assert!(remaining.as_array().unwrap().is_empty());
+        let (ok, _, stderr) = cli.run(&["records", "delete", "n1"]);
+        assert!(!ok, "{}", stderr);
+
+        for args in [
+            &["records", "lookup", "missing"][..],
+            &["records", "update", "missing", "--field", "title=Lost"][..],
+        ] {
+            let (ok, stdout, stderr) = cli.run(args);
+            assert!(!ok, "{}", stdout);
+            assert!(stdout.is_empty(), "{}", stdout);
+            assert!(stderr.contains("NOT_FOUND"), "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn test_zone_subscription_and_change_commands() {
+        let cli = Cli::start("zones");
+        let private = ["--database", "private"];
+        let mut args = private.to_vec();
+        args.extend(["zones", "create", "Notes"]);
+        cli.json(&args);
+
+        let mut args = private.to_vec();
+        args.extend([
+            "--zone", "Notes", "records", "create", "Note", "--name", "z1",
+        ]);
+        cli.json(&args);
+
+        let mut args = private.to_vec();
+        args.extend(["--output", "json", "changes", "zone", "Notes"]);
+        let (ok, stdout, stderr) = cli.run(&args);
+        assert!(ok, "{}", stderr);
+        let changes: Value = serde_json::from_str(&stdout).unwrap();
+        assert_eq!(changes[0]["recordName"], "z1");
+        let token = stderr
+            .trim()
+            .strip_prefix("syncToken: ")
+            .unwrap()
+            .to_string();
+
+        let mut args = private.to_vec();
+        args.extend(["changes", "zone", "Notes", "--token", &token]);
+        assert_eq!(cli.json(&args).as_array().unwrap().len(), 0);
+
+        let mut args = private.to_vec();
+        args.extend(["subscriptions", "create-zone", "notes-sub", "Notes"]);
+        cli.json(&args);
+        let mut args = private.to_vec();
+        args.extend(["subscriptions", "list"]);
+        assert_eq!(cli.json(&args)[0]["subscriptionID"], "notes-sub");
+        let mut args = private.to_vec();
+        args.extend(["subscriptions", "delete", "notes-sub"]);
+        cli.json(&args);
+        let mut args = private.to_vec();
+        args.extend(["subscriptions", "list"]);
+        assert!(cli.json(&args).as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_usage_errors_and_production_guard() {
+        let cli = Cli::start("errors");
+        let (ok, _, stderr) = cli.run(&["records", "explode"]);
+        assert!(!ok);
+        assert!(stderr.contains("Unknown records subcommand explode"));
+
+        let (ok, _, stderr) =
+            cli.run(&["--environment", "production", "records", "create", "Note"]);
+        assert!(!ok);
+        assert!(stderr.contains("Environment guard"), "{}", stderr);
+
+        let (ok, _, stderr) = cli.run(&[
+            "--environment",
+            "production",
+            "--allow-production",
+            "records",
+            "create",
+            "Note",
+        ]);
+        assert!(ok, "{}", stderr);
+    }
+}
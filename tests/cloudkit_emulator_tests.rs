@@ -0,0 +1,662 @@
+#[cfg(feature = "cloudkit-emulator")]
+mod cloudkit_emulator_tests {
+    use apple::cloudkit::emulator::CloudKitEmulator;
+    use apple::cloudkit::*;
+    use apple::error::{AppleError, CloudKitErrorCode};
+    use apple::signing::AppleKeyPair;
+    use std::sync::Arc;
+
+    fn test_pem_bytes(seed: u8) -> Vec<u8> {
+        use p256::ecdsa::SigningKey;
+
+        let mut bytes = [0u8; 32];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = seed.wrapping_add(i as u8 + 1);
+        }
+        let sk = SigningKey::from_slice(&bytes).unwrap();
+
+        let raw_bytes = sk.to_bytes();
+        let pem_obj = pem::Pem::new("EC PRIVATE KEY", raw_bytes.as_slice());
+        pem::encode(&pem_obj).into_bytes()
+    }
+
+    fn key_pair(seed: u8) -> Arc<AppleKeyPair> {
+        AppleKeyPair::from_pem_bytes("test-key", &test_pem_bytes(seed)).unwrap()
+    }
+
+    fn config(key_pair: Arc<AppleKeyPair>) -> CloudKitConfig {
+        CloudKitConfig {
+            container: "iCloud.com.test.app".to_string(),
+            environment: Environment::Development,
+            key_pair,
+        }
+    }
+
+    fn setup() -> (CloudKitEmulator, CloudKitClient) {
+        let kp = key_pair(0);
+        let emulator = CloudKitEmulator::builder()
+            .with_key_pair(&kp)
+            .start()
+            .unwrap();
+        let client = emulator.client(config(kp)).unwrap();
+        (emulator, client)
+    }
+
+    fn error_code(err: AppleError) -> CloudKitErrorCode {
+        match err {
+            AppleError::CloudKitError(e) => e.server_error_code,
+            other => panic!("Expected CloudKitError, got {:?}", other),
+        }
+    }
+
+    fn note(name: &str, title: &str) -> Record {
+        Record::new("Note")
+            .with_name(name)
+            .with_field("title", FieldValue::String(title.to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_create_lookup_update() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+
+        let created = client
+            .create_record(&db, note("n1", "First"))
+            .await
+            .unwrap();
+        let tag = created.record_change_tag.clone().unwrap();
+        assert!(created.created.is_some());
+
+        let mut update = created.clone();
+        update
+            .fields
+            .insert("body".into(), FieldValue::String("Hello".into()));
+        let updated = client.update_record(&db, update).await.unwrap();
+        assert_ne!(updated.record_change_tag.as_deref(), Some(tag.as_str()));
+
+        let found = client
+            .lookup_records(&db, &["n1"], None, None)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(
+            matches!(found[0].fields.get("title"), Some(FieldValue::String(s)) if s == "First")
+        );
+        assert!(matches!(found[0].fields.get("body"), Some(FieldValue::String(s)) if s == "Hello"));
+    }
+
+    #[tokio::test]
+    async fn test_stale_change_tag_conflicts() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+
+        let created = client
+            .create_record(&db, note("n1", "First"))
+            .await
+            .unwrap();
+        client.update_record(&db, created.clone()).await.unwrap();
+
+        let err = client.update_record(&db, created).await.unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::Conflict);
+    }
+
+    #[tokio::test]
+    async fn test_create_existing_and_delete_missing() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+
+        client
+            .create_record(&db, note("n1", "First"))
+            .await
+            .unwrap();
+        let err = client
+            .create_record(&db, note("n1", "Again"))
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::Exists);
+
+        client.delete_record(&db, "n1", "Note", None).await.unwrap();
+        let err = client
+            .delete_record(&db, "n1", "Note", None)
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_missing_zone() {
+        let (_emulator, client) = setup();
+        let record = note("n1", "First").with_zone(ZoneID::new("Missing"));
+        let err = client
+            .create_record(&DatabaseType::Private, record)
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::ZoneNotFound);
+    }
+
+    #[tokio::test]
+    async fn test_query_filters_sorts_and_pages() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Public;
+        for (name, priority) in [("a", 3), ("b", 1), ("c", 2), ("d", 5)] {
+            let record = note(name, name).with_field("priority", FieldValue::Int64(priority));
+            client.create_record(&db, record).await.unwrap();
+        }
+
+        let query = || {
+            QueryBuilder::new("Note")
+                .filter("priority", Comparator::LessThan, FieldValue::Int64(5))
+                .sort("priority", true)
+                .build()
+        };
+        let first = client
+            .query_records(&db, query(), None, Some(2), None, None)
+            .await
+            .unwrap();
+        let names: Vec<_> = first
+            .records
+            .iter()
+            .map(|r| r.record_name.clone().unwrap())
+            .collect();
+        assert_eq!(names, ["b", "c"]);
+        let marker = first.continuation_marker.expect("more results");
+
+        let second = client
+            .query_records(&db, query(), None, Some(2), Some(marker), None)
+            .await
+            .unwrap();
+        assert_eq!(second.records.len(), 1);
+        assert_eq!(second.records[0].record_name.as_deref(), Some("a"));
+        assert!(second.continuation_marker.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_near_and_distance_sort() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Public;
+        // Roughly 0 km, 3 km and 50 km north of the origin.
+        for (name, latitude) in [("far", 37.75), ("here", 37.3), ("close", 37.327)] {
+            let location = LocationValue {
+                latitude,
+                longitude: -122.0,
+                altitude: None,
+                horizontal_accuracy: None,
+                vertical_accuracy: None,
+                course: None,
+                speed: None,
+                timestamp: None,
+            };
+            let record = note(name, name).with_field("location", FieldValue::Location(location));
+            client.create_record(&db, record).await.unwrap();
+        }
+
+        let query = QueryBuilder::new("Note")
+            .predicate("location NEAR (37.3, -122.0, 5km)")
+            .unwrap()
+            .sort_by_distance("location", 37.3, -122.0, false)
+            .build();
+        let response = client
+            .query_records(&db, query, None, None, None, None)
+            .await
+            .unwrap();
+        let names: Vec<_> = response
+            .records
+            .iter()
+            .map(|r| r.record_name.clone().unwrap())
+            .collect();
+        assert_eq!(names, ["close", "here"]);
+    }
+
+    #[tokio::test]
+    async fn test_zone_wide_query_and_builder_options() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+        client
+            .create_zone(&db, ZoneID::new("Archive"), None)
+            .await
+            .unwrap();
+        client.create_record(&db, note("a", "A")).await.unwrap();
+        let archived = note("b", "B")
+            .with_field("body", FieldValue::String("x".into()))
+            .with_zone(ZoneID::new("Archive"));
+        client.create_record(&db, archived).await.unwrap();
+
+        let query = QueryBuilder::new("Note")
+            .zone_wide(true)
+            .sort_by_system(SystemField::RecordId, true)
+            .desired_keys(&["title"])
+            .build();
+        let response = client
+            .query_records(&db, query, None, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(response.records.len(), 2);
+        assert!(
+            response
+                .records
+                .iter()
+                .all(|r| !r.fields.contains_key("body"))
+        );
+
+        let query = QueryBuilder::new("Note")
+            .zone(ZoneID::new("Archive"))
+            .results_limit(1)
+            .build();
+        let response = client
+            .query_records(&db, query, None, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(response.records.len(), 1);
+        assert_eq!(response.records[0].record_name.as_deref(), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn test_desired_keys() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+        let record = note("n1", "First").with_field("body", FieldValue::String("x".into()));
+        client.create_record(&db, record).await.unwrap();
+
+        let found = client
+            .lookup_records(&db, &["n1"], None, Some(vec!["title".into()]))
+            .await
+            .unwrap();
+        assert!(found[0].fields.contains_key("title"));
+        assert!(!found[0].fields.contains_key("body"));
+    }
+
+    #[tokio::test]
+    async fn test_zone_lookup_and_per_zone_results() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+
+        let results = client
+            .modify_zones(
+                &db,
+                vec![
+                    (
+                        ZoneOperationType::Create,
+                        Zone {
+                            zone_id: ZoneID::new("Ledger"),
+                            sync_token: None,
+                            atomic: Some(true),
+                        },
+                    ),
+                    (
+                        ZoneOperationType::Delete,
+                        Zone {
+                            zone_id: ZoneID::new("Missing"),
+                            sync_token: None,
+                            atomic: None,
+                        },
+                    ),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        let ledger = results.into_iter().next().unwrap().into_zone().unwrap();
+        assert_eq!(ledger.atomic, Some(true));
+
+        let err = client
+            .delete_zone(&db, ZoneID::new("Missing"))
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::ZoneNotFound);
+        let err = client
+            .create_zone(&DatabaseType::Public, ZoneID::new("Custom"), None)
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::BadRequest);
+
+        let found = client
+            .lookup_zones(&db, vec![ZoneID::new("Ledger"), ZoneID::new("Missing")])
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found[0].error().is_none());
+        assert_eq!(found[0].atomic, Some(true));
+        assert!(found[0].sync_token.is_some());
+        assert_eq!(
+            error_code(found[1].error().unwrap()),
+            CloudKitErrorCode::ZoneNotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zones_changes() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+        let initial = client.fetch_zones_changes(&db, None).await.unwrap();
+        let token = initial.sync_token.expect("sync token");
+
+        client
+            .create_zone(&db, ZoneID::new("A"), None)
+            .await
+            .unwrap();
+        client
+            .create_zone(&db, ZoneID::new("B"), None)
+            .await
+            .unwrap();
+        client.delete_zone(&db, ZoneID::new("A")).await.unwrap();
+
+        let changes = client.fetch_zones_changes(&db, Some(token)).await.unwrap();
+        assert_eq!(changes.zones.len(), 2);
+        let b = &changes.zones[0];
+        assert_eq!(b.zone_id.as_ref().unwrap().zone_name, "B");
+        assert!(b.sync_token.is_some() && !b.deleted);
+        let a = &changes.zones[1];
+        assert_eq!(a.zone_id.as_ref().unwrap().zone_name, "A");
+        assert!(a.deleted);
+
+        let latest = client
+            .fetch_zones_changes(&db, changes.sync_token)
+            .await
+            .unwrap();
+        assert!(latest.zones.is_empty());
+
+        assert!(matches!(
+            client
+                .fetch_zones_changes(&DatabaseType::Public, None)
+                .await,
+            Err(AppleError::ValidationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_zone_changes_paging_and_tombstones() {
+        let kp = key_pair(0);
+        let emulator = CloudKitEmulator::builder()
+            .with_key_pair(&kp)
+            .with_page_size(2)
+            .start()
+            .unwrap();
+        let client = emulator.client(config(kp)).unwrap();
+        let db = DatabaseType::Private;
+        let zone = ZoneID::new("Notes");
+
+        client.create_zone(&db, zone.clone(), None).await.unwrap();
+        for name in ["n1", "n2", "n3"] {
+            let record = note(name, name).with_zone(zone.clone());
+            client.create_record(&db, record).await.unwrap();
+        }
+
+        let page = client
+            .fetch_zone_changes(&db, zone.clone(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(page.records.len(), 2);
+        assert_eq!(page.more_coming, Some(true));
+
+        let page = client
+            .fetch_zone_changes(&db, zone.clone(), page.sync_token, None)
+            .await
+            .unwrap();
+        assert_eq!(page.records.len(), 1);
+        assert_eq!(page.more_coming, Some(false));
+        let token = page.sync_token;
+
+        client
+            .delete_record(&db, "n2", "Note", Some(zone.clone()))
+            .await
+            .unwrap();
+        let page = client
+            .fetch_zone_changes(&db, zone.clone(), token.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!(page.records.len(), 1);
+        assert!(page.records[0].deleted);
+        assert_eq!(page.records[0].record_name.as_deref(), Some("n2"));
+
+        let page = client
+            .fetch_zone_changes(&db, zone, page.sync_token, None)
+            .await
+            .unwrap();
+        assert!(page.records.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_sync_token() {
+        let (_emulator, client) = setup();
+        let err = client
+            .fetch_zone_changes(
+                &DatabaseType::Private,
+                ZoneID::default_zone(),
+                Some("garbage".into()),
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::BadRequest);
+    }
+
+    #[tokio::test]
+    async fn test_database_changes() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+
+        let initial = client
+            .fetch_database_changes(&db, None, None)
+            .await
+            .unwrap();
+        client
+            .create_zone(&db, ZoneID::new("Notes"), None)
+            .await
+            .unwrap();
+
+        let changes = client
+            .fetch_database_changes(&db, initial.sync_token, None)
+            .await
+            .unwrap();
+        assert_eq!(changes.zones.len(), 1);
+        assert_eq!(changes.zones[0].zone_id.zone_name, "Notes");
+
+        let zones = client.list_zones(&db).await.unwrap();
+        assert_eq!(zones.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_atomic_batch_rolls_back() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+        client
+            .create_record(&db, note("n1", "First"))
+            .await
+            .unwrap();
+
+        let results = client
+            .modify_records(
+                &db,
+                vec![
+                    (OperationType::Create, note("n2", "Second")),
+                    (OperationType::Create, note("n1", "Duplicate")),
+                ],
+                None,
+                Some(true),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            results[0].server_error_code.as_deref(),
+            Some("ATOMIC_ERROR")
+        );
+        assert_eq!(results[1].server_error_code.as_deref(), Some("EXISTS"));
+
+        let query = QueryBuilder::new("Note").build();
+        let all = client
+            .query_records(&db, query, None, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(all.records.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+
+        let created = client
+            .create_subscription(
+                &db,
+                Subscription {
+                    subscription_id: Some("sub-1".into()),
+                    subscription_type: SubscriptionType::Database,
+                    query: None,
+                    fires_on: None,
+                    fires_on_record_creation: None,
+                    fires_on_record_update: None,
+                    fires_on_record_deletion: None,
+                    notification_info: None,
+                    zone_id: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(created.subscription_id.as_deref(), Some("sub-1"));
+        assert_eq!(client.list_subscriptions(&db).await.unwrap().len(), 1);
+
+        client
+            .delete_subscription(&db, "sub-1", SubscriptionType::Database)
+            .await
+            .unwrap();
+        assert!(client.list_subscriptions(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_asset_upload_and_download() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+
+        let upload = client
+            .request_asset_upload(&db, "photo-1", "Photo", "image", None)
+            .await
+            .unwrap();
+        let url = upload.tokens[0].url.clone().unwrap();
+        let uploaded = client.upload_asset(&url, b"image-bytes").await.unwrap();
+
+        let record = Record::new("Photo")
+            .with_name("photo-1")
+            .with_field("image", FieldValue::Asset(uploaded.into()));
+        let saved = client.create_record(&db, record).await.unwrap();
+        let Some(FieldValue::Asset(asset)) = saved.fields.get("image") else {
+            panic!("Expected asset field");
+        };
+        assert_eq!(asset.size, Some(11));
+
+        let download_url = asset.download_url.clone().unwrap();
+        assert!(download_url.ends_with("/${f}"));
+        let body = reqwest::get(download_url.replace("${f}", "photo.jpg"))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(body.as_ref(), b"image-bytes");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_untrusted_signature() {
+        let emulator = CloudKitEmulator::builder()
+            .with_key_pair(&key_pair(0))
+            .start()
+            .unwrap();
+        let client = emulator.client(config(key_pair(7))).unwrap();
+
+        let err = client.list_zones(&DatabaseType::Private).await.unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::AuthenticationFailed);
+    }
+
+    #[tokio::test]
+    async fn test_unauthenticated_access_is_opt_in() {
+        assert!(matches!(
+            CloudKitEmulator::builder().start(),
+            Err(AppleError::ValidationError(_))
+        ));
+
+        let emulator = CloudKitEmulator::start(&key_pair(0)).unwrap();
+        let client = emulator.client(config(key_pair(7))).unwrap();
+        let err = client.list_zones(&DatabaseType::Private).await.unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::AuthenticationFailed);
+
+        let emulator = CloudKitEmulator::builder()
+            .allow_unauthenticated()
+            .start()
+            .unwrap();
+        let client = emulator.client(config(key_pair(7))).unwrap();
+        client.list_zones(&DatabaseType::Private).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_token_must_match() {
+        let kp = key_pair(0);
+        let emulator = CloudKitEmulator::builder()
+            .with_key_pair(&kp)
+            .with_api_token("api-token")
+            .start()
+            .unwrap();
+        let db = DatabaseType::Public;
+
+        let user = emulator
+            .client(config(kp.clone()))
+            .unwrap()
+            .with_api_token("api-token")
+            .as_user()
+            .unwrap();
+        user.create_record(&db, note("n1", "First")).await.unwrap();
+
+        let stranger = emulator
+            .client(config(kp))
+            .unwrap()
+            .with_api_token("guessed")
+            .as_user()
+            .unwrap();
+        let err = stranger
+            .create_record(&db, note("n2", "Second"))
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::AuthenticationFailed);
+    }
+
+    #[tokio::test]
+    async fn test_assets_need_upload_receipt() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+        let upload = client
+            .request_asset_upload(&db, "photo-1", "Photo", "image", None)
+            .await
+            .unwrap();
+        let url = upload.tokens[0].url.clone().unwrap();
+        let uploaded = client.upload_asset(&url, b"image-bytes").await.unwrap();
+        assert!(uploaded.receipt.is_some());
+
+        for receipt in [None, Some("forged".to_string())] {
+            let asset = AssetValue {
+                file_checksum: uploaded.file_checksum.clone(),
+                receipt,
+                ..Default::default()
+            };
+            let record = Record::new("Photo")
+                .with_name("photo-1")
+                .with_field("image", FieldValue::Asset(asset));
+            let err = client.create_record(&db, record).await.unwrap_err();
+            assert_eq!(error_code(err), CloudKitErrorCode::BadRequest);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reset() {
+        let (emulator, client) = setup();
+        let db = DatabaseType::Private;
+        client
+            .create_record(&db, note("n1", "First"))
+            .await
+            .unwrap();
+
+        emulator.reset();
+        let err = client
+            .delete_record(&db, "n1", "Note", None)
+            .await
+            .unwrap_err();
+        assert_eq!(error_code(err), CloudKitErrorCode::NotFound);
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(feature = "cloudkit")]
+mod cloudkit_fields_tests {
+    use apple::cloudkit::*;
+    use apple::error::AppleError;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn field_type_error(result: Result<impl std::fmt::Debug, AppleError>) -> FieldTypeError {
+        match result {
+            Err(AppleError::FieldTypeError(err)) => err,
+            other => panic!("Expected FieldTypeError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_primitive_conversions_round_trip() {
+        let when = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let record = Record::new("Note")
+            .with_field("title", "Hello")
+            .with_field("count", 3)
+            .with_field("score", 4.5)
+            .with_field("pinned", true)
+            .with_field("when", when)
+            .with_field("blob", &b"\x00\x01binary"[..])
+            .with_field("place", (37.33, -122.03))
+            .with_field("tags", vec!["a", "b"]);
+
+        assert!(matches!(record.fields["count"], FieldValue::Int64(3)));
+        assert!(matches!(record.fields["pinned"], FieldValue::Int64(1)));
+        assert!(matches!(
+            record.fields["when"],
+            FieldValue::Timestamp(1_709_294_400_000)
+        ));
+        assert!(matches!(&record.fields["blob"], FieldValue::Bytes(b) if b == "AAFiaW5hcnk="));
+
+        assert_eq!(record.get_string("title").unwrap(), "Hello");
+        assert_eq!(record.get_i64("count").unwrap(), 3);
+        assert_eq!(record.get_f64("score").unwrap(), 4.5);
+        assert!(record.get_bool("pinned").unwrap());
+        assert_eq!(record.get_timestamp("when").unwrap(), when);
+        assert_eq!(record.get_bytes("blob").unwrap(), b"\x00\x01binary");
+        assert_eq!(record.get::<(f64, f64)>("place").unwrap(), (37.33, -122.03));
+        assert_eq!(record.get::<Vec<String>>("tags").unwrap(), ["a", "b"]);
+
+        let count: i64 = (&record.fields["count"]).try_into().unwrap();
+        assert_eq!(count, 3);
+        // INT64 widens to DOUBLE, but not the other way round.
+        assert_eq!(record.get_f64("count").unwrap(), 3.0);
+        assert!(record.get_i64("score").is_err());
+    }
+
+    #[test]
+    fn test_mismatch_and_missing_errors_name_the_field() {
+        let record = Record::new("Note").with_field("title", "Hello");
+
+        let err = field_type_error(record.get_i64("title"));
+        assert_eq!(err.to_string(), "Field `title` is STRING, expected INT64");
+
+        let err = field_type_error(record.get_timestamp("published"));
+        assert_eq!(
+            err.to_string(),
+            "Field `published` is missing, expected TIMESTAMP"
+        );
+        assert!(
+            record
+                .get_optional::<DateTime<Utc>>("published")
+                .unwrap()
+                .is_none()
+        );
+
+        let err = field_type_error(String::try_from(FieldValue::Int64(1)));
+        assert_eq!(err.to_string(), "Expected STRING, found INT64");
+
+        assert!(matches!(
+            Vec::<u8>::try_from(FieldValue::Bytes("not base64!".into())),
+            Err(AppleError::Base64Error(_))
+        ));
+    }
+
+    #[test]
+    fn test_list_and_encrypted_variants_serialize() {
+        let asset = AssetValue {
+            file_checksum: Some("abc".into()),
+            size: Some(3),
+            ..Default::default()
+        };
+        let mut record = Record::new("Album");
+        record.set("covers", vec![asset.clone(), asset]);
+        record.set("thumbs", vec![b"one".to_vec(), b"two".to_vec()]);
+        record.set("secret", FieldValue::encrypted_bytes(b"pin"));
+
+        let json = serde_json::to_value(&record.fields).unwrap();
+        assert_eq!(json["covers"]["type"], "ASSET_LIST");
+        assert_eq!(json["covers"]["value"][1]["fileChecksum"], "abc");
+        assert_eq!(json["thumbs"]["type"], "BYTES_LIST");
+        assert_eq!(json["thumbs"]["value"][0], "b25l");
+        assert_eq!(json["secret"]["type"], "ENCRYPTED_BYTES");
+
+        let parsed: RecordResult = serde_json::from_value(serde_json::json!({
+            "recordName": "album",
+            "recordType": "Album",
+            "fields": json,
+        }))
+        .unwrap();
+        assert_eq!(parsed.get::<Vec<AssetValue>>("covers").unwrap().len(), 2);
+        assert_eq!(
+            parsed.get::<Vec<Vec<u8>>>("thumbs").unwrap(),
+            [b"one".to_vec(), b"two".to_vec()]
+        );
+        assert_eq!(parsed.get_bytes("secret").unwrap(), b"pin");
+        assert_eq!(parsed.fields["secret"].type_name(), "ENCRYPTED_BYTES");
+    }
+}
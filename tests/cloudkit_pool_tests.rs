@@ -0,0 +1,134 @@
+#[cfg(feature = "cloudkit-emulator")]
+mod cloudkit_pool_tests {
+    use apple::cloudkit::emulator::CloudKitEmulator;
+    use apple::cloudkit::*;
+    use apple::error::AppleError;
+    use apple::signing::AppleKeyPair;
+    use std::sync::Arc;
+
+    fn test_pem_bytes() -> Vec<u8> {
+        use p256::ecdsa::SigningKey;
+
+        let sk = SigningKey::from_slice(&[
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+            0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+            0x1d, 0x1e, 0x1f, 0x20,
+        ])
+        .unwrap();
+
+        let raw_bytes = sk.to_bytes();
+        let pem_obj = pem::Pem::new("EC PRIVATE KEY", raw_bytes.as_slice());
+        pem::encode(&pem_obj).into_bytes()
+    }
+
+    fn setup() -> (CloudKitEmulator, Arc<AppleKeyPair>) {
+        let kp = AppleKeyPair::from_pem_bytes("test-key", &test_pem_bytes()).unwrap();
+        let emulator = CloudKitEmulator::builder()
+            .with_key_pair(&kp)
+            .start()
+            .unwrap();
+        (emulator, kp)
+    }
+
+    fn note(name: &str) -> Record {
+        Record::new("Note").with_name(name)
+    }
+
+    async fn note_names(client: &CloudKitClient) -> Vec<String> {
+        let response = client
+            .query_records(
+                &DatabaseType::Public,
+                QueryBuilder::new("Note").build(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        response
+            .records
+            .into_iter()
+            .filter_map(|r| r.record_name)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_handles_address_separate_containers_and_environments() {
+        let (emulator, kp) = setup();
+        let pool = CloudKitPool::new(kp)
+            .unwrap()
+            .with_base_url(emulator.base_url());
+        let a_dev = pool.client(&ContainerHandle::development("iCloud.com.test.a"));
+        let b_dev = pool.client(&ContainerHandle::development("iCloud.com.test.b"));
+        let a_prod = pool.client(&ContainerHandle::production("iCloud.com.test.a"));
+
+        a_dev
+            .create_record(&DatabaseType::Public, note("a1"))
+            .await
+            .unwrap();
+        b_dev
+            .create_record(&DatabaseType::Public, note("b1"))
+            .await
+            .unwrap();
+
+        assert_eq!(note_names(&a_dev).await, ["a1"]);
+        assert_eq!(note_names(&b_dev).await, ["b1"]);
+        assert!(note_names(&a_prod).await.is_empty());
+        assert_eq!(a_prod.config().environment, Environment::Production);
+    }
+
+    #[tokio::test]
+    async fn test_production_writes_require_opt_in() {
+        let (emulator, kp) = setup();
+        let pool = CloudKitPool::new(kp)
+            .unwrap()
+            .with_base_url(emulator.base_url())
+            .with_production_writes("iCloud.com.test.live");
+        let guarded = pool.client(&ContainerHandle::production("iCloud.com.test.a"));
+        let live = pool.client(&ContainerHandle::production("iCloud.com.test.live"));
+
+        assert!(!guarded.production_writes_allowed());
+        assert!(matches!(
+            guarded
+                .create_record(&DatabaseType::Public, note("n1"))
+                .await,
+            Err(AppleError::EnvironmentGuardError(_))
+        ));
+        assert!(matches!(
+            guarded
+                .create_zone(&DatabaseType::Private, ZoneID::new("Z"), None)
+                .await,
+            Err(AppleError::EnvironmentGuardError(_))
+        ));
+        // Reads still go through, and user handles keep the guard.
+        assert!(note_names(&guarded).await.is_empty());
+        assert!(!guarded.as_server().production_writes_allowed());
+
+        live.create_record(&DatabaseType::Public, note("n1"))
+            .await
+            .unwrap();
+        assert_eq!(note_names(&live).await, ["n1"]);
+    }
+
+    #[tokio::test]
+    async fn test_standalone_clients_can_opt_out() {
+        let (emulator, kp) = setup();
+        let client = emulator
+            .client(CloudKitConfig {
+                container: "iCloud.com.test.app".to_string(),
+                environment: Environment::Production,
+                key_pair: kp,
+            })
+            .unwrap();
+        assert!(client.production_writes_allowed());
+
+        let client = client.with_production_writes(false);
+        assert!(matches!(
+            client
+                .create_record(&DatabaseType::Public, note("n1"))
+                .await,
+            Err(AppleError::EnvironmentGuardError(_))
+        ));
+    }
+}
@@ -0,0 +1,133 @@
+#[cfg(feature = "cloudkit")]
+mod cloudkit_predicate_tests {
+    use apple::cloudkit::*;
+    use apple::error::AppleError;
+
+    fn predicate_error(expression: &str) -> PredicateError {
+        match parse_predicate(expression) {
+            Err(AppleError::PredicateError(err)) => err,
+            other => panic!("Expected PredicateError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_compound_expression() {
+        let filters = parse_predicate(
+            r#"age >= 21 AND tags LIST_CONTAINS "rust" AND location NEAR (37.3, -122.0, 5km)"#,
+        )
+        .unwrap();
+        assert_eq!(filters.len(), 3);
+
+        assert_eq!(filters[0].field_name, "age");
+        assert!(matches!(
+            filters[0].comparator,
+            Comparator::GreaterThanOrEquals
+        ));
+        assert!(matches!(filters[0].field_value, FieldValue::Int64(21)));
+
+        assert!(matches!(filters[1].comparator, Comparator::ListContains));
+        assert!(matches!(&filters[1].field_value, FieldValue::String(s) if s == "rust"));
+
+        assert!(matches!(filters[2].comparator, Comparator::Near));
+        match &filters[2].field_value {
+            FieldValue::Location(loc) => {
+                assert_eq!(loc.latitude, 37.3);
+                assert_eq!(loc.longitude, -122.0);
+            }
+            other => panic!("Expected location, got {:?}", other),
+        }
+        assert_eq!(filters[2].distance, Some(5000.0));
+
+        let json = serde_json::to_value(&filters[2]).unwrap();
+        assert_eq!(json["comparator"], "NEAR");
+        assert_eq!(json["distance"], 5000.0);
+    }
+
+    #[test]
+    fn test_parse_lists_and_functions() {
+        let filters = parse_predicate(
+            r#"status in ["a", "b"] and score NOT IN [1, 2.5] and created < timestamp("2024-01-01T00:00:00Z") and owner = reference("user-1")"#,
+        )
+        .unwrap();
+
+        assert!(matches!(&filters[0].field_value, FieldValue::StringList(v) if v == &["a", "b"]));
+        assert!(matches!(filters[1].comparator, Comparator::NotIn));
+        assert!(matches!(&filters[1].field_value, FieldValue::DoubleList(v) if v == &[1.0, 2.5]));
+        assert!(matches!(
+            filters[2].field_value,
+            FieldValue::Timestamp(1_704_067_200_000)
+        ));
+        assert!(
+            matches!(&filters[3].field_value, FieldValue::Reference(r) if r.record_name == "user-1")
+        );
+    }
+
+    #[test]
+    fn test_near_units() {
+        let miles = parse_predicate("loc NEAR (0, 0, 2 mi)").unwrap();
+        assert_eq!(miles[0].distance, Some(3218.688));
+        let meters = parse_predicate("loc NEAR (0, 0, 250)").unwrap();
+        assert_eq!(meters[0].distance, Some(250.0));
+    }
+
+    #[test]
+    fn test_or_is_rejected() {
+        let err = predicate_error(r#"a = 1 OR b = 2"#);
+        assert_eq!(err.column, 7);
+        assert_eq!(err.token, "OR");
+        assert!(err.message.contains("only support AND"));
+    }
+
+    #[test]
+    fn test_type_errors_point_at_value() {
+        let err = predicate_error("location NEAR (37.3, -122.0)");
+        assert_eq!(err.column, 15);
+        assert!(err.message.contains("NEAR needs a distance"));
+
+        let err = predicate_error(r#"age IN 21"#);
+        assert_eq!(err.token, "21");
+        assert!(err.message.contains("IN needs a list"));
+
+        let err = predicate_error(r#"name BEGINSWITH 3"#);
+        assert!(err.message.contains("needs a string"));
+
+        let err = predicate_error(r#"tags LIST_CONTAINS_ANY ["a", 1]"#);
+        assert_eq!(err.column, 30);
+        assert!(err.message.contains("Expected a string in list"));
+
+        let err = predicate_error("loc NEAR (95, 0, 1km)");
+        assert_eq!(err.token, "95");
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let err = predicate_error("age ~ 3");
+        assert_eq!(err.column, 5);
+        assert_eq!(err.token, "~");
+
+        let err = predicate_error("age >=");
+        assert_eq!(err.message, "Expected a value");
+        assert_eq!(err.to_string(), "Expected a value at end of expression");
+
+        let err = predicate_error(r#"name = "open"#);
+        assert_eq!(err.message, "Unterminated string");
+
+        let err = predicate_error("age LIKE 3");
+        assert_eq!(err.pointer("age LIKE 3"), "age LIKE 3\n    ^^^^");
+    }
+
+    #[test]
+    fn test_query_builder_predicate() {
+        let query = QueryBuilder::new("Person")
+            .filter("active", Comparator::Equals, FieldValue::Int64(1))
+            .predicate(r#"name BEGINS_WITH "Al""#)
+            .unwrap()
+            .build();
+        assert_eq!(query.filter_by.as_ref().unwrap().len(), 2);
+
+        assert!(matches!(
+            QueryBuilder::new("Person").predicate("name ="),
+            Err(AppleError::PredicateError(_))
+        ));
+    }
+}
@@ -123,11 +123,13 @@ mod cloudkit_query_tests {
             field_name: "age".to_string(),
             comparator: Comparator::GreaterThan,
             field_value: FieldValue::Int64(18),
+            distance: None,
         };
         let json = serde_json::to_string(&filter).unwrap();
         assert!(json.contains("\"fieldName\":\"age\""));
         assert!(json.contains("\"comparator\":\"GREATER_THAN\""));
         assert!(json.contains("\"fieldValue\""));
+        assert!(!json.contains("distance"));
     }
 
     #[test]
@@ -135,6 +137,7 @@ mod cloudkit_query_tests {
         let sort = SortDescriptor {
             field_name: "name".to_string(),
             ascending: Some(true),
+            relative_location: None,
         };
         let json = serde_json::to_string(&sort).unwrap();
         assert!(json.contains("\"fieldName\":\"name\""));
@@ -146,8 +149,64 @@ mod cloudkit_query_tests {
         let sort = SortDescriptor {
             field_name: "date".to_string(),
             ascending: None,
+            relative_location: None,
         };
         let json = serde_json::to_string(&sort).unwrap();
         assert!(!json.contains("ascending"));
+        assert!(!json.contains("relativeLocation"));
+    }
+
+    #[test]
+    fn test_distance_sort_and_near() {
+        let query = QueryBuilder::new("Store")
+            .near("location", 37.3, -122.0, 5000.0)
+            .sort_by_distance("location", 37.3, -122.0, true)
+            .build();
+        let json = serde_json::to_value(&query).unwrap();
+        assert_eq!(json["filterBy"][0]["comparator"], "NEAR");
+        assert_eq!(json["filterBy"][0]["distance"], 5000.0);
+        assert_eq!(json["sortBy"][0]["relativeLocation"]["latitude"], 37.3);
+        assert_eq!(json["sortBy"][0]["relativeLocation"]["longitude"], -122.0);
+    }
+
+    #[test]
+    fn test_system_field_helpers() {
+        assert_eq!(SystemField::RecordId.field_name(), "___recordID");
+        assert_eq!(SystemField::CreatedBy.field_name(), "___createdBy");
+
+        let query = QueryBuilder::new("Item")
+            .record_id(Comparator::Equals, "item-1")
+            .created_by(Comparator::Equals, "_abc")
+            .created(Comparator::GreaterThan, 1000)
+            .modified(Comparator::LessThan, 2000)
+            .sort_by_system(SystemField::ModTime, false)
+            .build();
+        let json = serde_json::to_value(&query).unwrap();
+        let filters = json["filterBy"].as_array().unwrap();
+        assert_eq!(filters[0]["fieldName"], "___recordID");
+        assert_eq!(filters[0]["fieldValue"]["type"], "REFERENCE");
+        assert_eq!(filters[0]["fieldValue"]["value"]["recordName"], "item-1");
+        assert_eq!(filters[1]["fieldName"], "___createdBy");
+        assert_eq!(filters[2]["fieldName"], "___createTime");
+        assert_eq!(filters[2]["fieldValue"]["type"], "TIMESTAMP");
+        assert_eq!(filters[3]["fieldName"], "___modTime");
+        assert_eq!(json["sortBy"][0]["fieldName"], "___modTime");
+    }
+
+    #[test]
+    fn test_request_options_are_not_part_of_query_json() {
+        let query = QueryBuilder::new("Item")
+            .zone(ZoneID::new("Inbox"))
+            .zone_wide(true)
+            .results_limit(10)
+            .desired_keys(&["title"])
+            .build();
+        assert_eq!(query.zone_id.as_ref().unwrap().zone_name, "Inbox");
+        assert!(query.zone_wide);
+        assert_eq!(query.results_limit, Some(10));
+        assert_eq!(query.desired_keys, Some(vec!["title".to_string()]));
+
+        let json = serde_json::to_value(&query).unwrap();
+        assert_eq!(json, serde_json::json!({ "recordType": "Item" }));
     }
 }
@@ -0,0 +1,175 @@
+#[cfg(feature = "cloudkit")]
+mod cloudkit_request_verifier_tests {
+    use apple::cloudkit::client::{CloudKitClient, CloudKitConfig};
+    use apple::cloudkit::types::Environment;
+    use apple::cloudkit::{CloudKitRequestVerifier, SignedRequestHeaders};
+    use apple::error::AppleError;
+    use apple::signing::AppleKeyPair;
+    use std::time::{Duration, SystemTime};
+
+    const SUBPATH: &str = "/database/1/iCloud.com.test.app/development/public/records/query";
+
+    fn test_pem_bytes() -> Vec<u8> {
+        use p256::ecdsa::SigningKey;
+
+        let sk = SigningKey::from_slice(&[
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+            0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+            0x1d, 0x1e, 0x1f, 0x20,
+        ])
+        .unwrap();
+
+        let raw_bytes = sk.to_bytes();
+        let pem_obj = pem::Pem::new("EC PRIVATE KEY", raw_bytes.as_slice());
+        pem::encode(&pem_obj).into_bytes()
+    }
+
+    fn make_client() -> CloudKitClient {
+        let kp = AppleKeyPair::from_pem_bytes("test-key", &test_pem_bytes()).unwrap();
+        CloudKitClient::new(CloudKitConfig {
+            container: "iCloud.com.test.app".to_string(),
+            environment: Environment::Development,
+            key_pair: kp,
+        })
+        .unwrap()
+    }
+
+    fn signed_headers(client: &CloudKitClient, body: &str) -> SignedRequestHeaders {
+        let pairs = client.sign_request(body, SUBPATH).unwrap();
+        SignedRequestHeaders::from_pairs(&pairs).unwrap()
+    }
+
+    fn assert_signature_error(result: Result<(), AppleError>) {
+        match result {
+            Err(AppleError::SignatureError(_)) => {}
+            other => panic!("Expected SignatureError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let client = make_client();
+        let verifier = CloudKitRequestVerifier::new().with_key_pair(&client.config().key_pair);
+        let body = r#"{"query":{"recordType":"Note"}}"#;
+        let headers = signed_headers(&client, body);
+
+        assert_eq!(headers.key_id, "test-key");
+        verifier.verify(&headers, body.as_bytes(), SUBPATH).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip_with_public_key_pem() {
+        use p256::pkcs8::{EncodePublicKey, LineEnding};
+
+        let client = make_client();
+        let pem = client
+            .config()
+            .key_pair
+            .verifying_key()
+            .to_public_key_pem(LineEnding::LF)
+            .unwrap();
+        let verifier = CloudKitRequestVerifier::new()
+            .with_public_key_pem("test-key", &pem)
+            .unwrap();
+
+        let headers = signed_headers(&client, "");
+        verifier.verify(&headers, b"", SUBPATH).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_body_rejected() {
+        let client = make_client();
+        let verifier = CloudKitRequestVerifier::new().with_key_pair(&client.config().key_pair);
+        let headers = signed_headers(&client, r#"{"a":1}"#);
+        assert_signature_error(verifier.verify(&headers, br#"{"a":2}"#, SUBPATH));
+    }
+
+    #[test]
+    fn test_wrong_subpath_rejected() {
+        let client = make_client();
+        let verifier = CloudKitRequestVerifier::new().with_key_pair(&client.config().key_pair);
+        let headers = signed_headers(&client, "{}");
+        assert_signature_error(verifier.verify(
+            &headers,
+            b"{}",
+            "/database/1/iCloud.com.test.app/development/public/records/modify",
+        ));
+    }
+
+    #[test]
+    fn test_unknown_key_rejected() {
+        let client = make_client();
+        let verifier = CloudKitRequestVerifier::new();
+        let headers = signed_headers(&client, "{}");
+        assert_signature_error(verifier.verify(&headers, b"{}", SUBPATH));
+    }
+
+    #[test]
+    fn test_removed_key_rejected() {
+        let client = make_client();
+        let mut verifier = CloudKitRequestVerifier::new().with_key_pair(&client.config().key_pair);
+        assert!(verifier.remove_key("test-key").is_some());
+        let headers = signed_headers(&client, "{}");
+        assert_signature_error(verifier.verify(&headers, b"{}", SUBPATH));
+    }
+
+    #[test]
+    fn test_clock_skew_enforced() {
+        let client = make_client();
+        let verifier = CloudKitRequestVerifier::new()
+            .with_key_pair(&client.config().key_pair)
+            .with_max_clock_skew(Duration::from_secs(60));
+        let headers = signed_headers(&client, "{}");
+
+        let now = SystemTime::now();
+        verifier
+            .verify_at(&headers, b"{}", SUBPATH, now + Duration::from_secs(30))
+            .unwrap();
+        assert_signature_error(verifier.verify_at(
+            &headers,
+            b"{}",
+            SUBPATH,
+            now + Duration::from_secs(120),
+        ));
+        assert_signature_error(verifier.verify_at(
+            &headers,
+            b"{}",
+            SUBPATH,
+            now - Duration::from_secs(120),
+        ));
+    }
+
+    #[test]
+    fn test_invalid_date_rejected() {
+        let client = make_client();
+        let verifier = CloudKitRequestVerifier::new().with_key_pair(&client.config().key_pair);
+        let mut headers = signed_headers(&client, "{}");
+        headers.date = "yesterday".to_string();
+        assert_signature_error(verifier.verify(&headers, b"{}", SUBPATH));
+    }
+
+    #[test]
+    fn test_headers_from_pairs_case_insensitive() {
+        let pairs = vec![
+            ("x-apple-cloudkit-request-keyid", "k"),
+            (
+                "X-APPLE-CLOUDKIT-REQUEST-ISO8601DATE",
+                "2024-01-01T00:00:00Z",
+            ),
+            ("X-Apple-CloudKit-Request-SignatureV1", "c2ln"),
+        ];
+        let headers = SignedRequestHeaders::from_pairs(&pairs).unwrap();
+        assert_eq!(headers.key_id, "k");
+        assert_eq!(headers.date, "2024-01-01T00:00:00Z");
+        assert_eq!(headers.signature, "c2ln");
+    }
+
+    #[test]
+    fn test_headers_from_pairs_missing() {
+        let pairs = vec![("X-Apple-CloudKit-Request-KeyID", "k")];
+        match SignedRequestHeaders::from_pairs(&pairs) {
+            Err(AppleError::SignatureError(msg)) => assert!(msg.contains("ISO8601Date")),
+            _ => panic!("Expected SignatureError"),
+        }
+    }
+}
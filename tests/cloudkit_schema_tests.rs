@@ -0,0 +1,228 @@
+#[cfg(feature = "cloudkit")]
+mod cloudkit_schema_tests {
+    use apple::cloudkit::*;
+    use apple::error::AppleError;
+    use apple::signing::AppleKeyPair;
+
+    const SCHEMA: &str = r#"
+DEFINE SCHEMA
+
+    CREATE ROLE Moderator;
+
+    RECORD TYPE Note (
+        "___createTime" TIMESTAMP SORTABLE,
+        "___recordID"   REFERENCE QUERYABLE,
+        title           STRING QUERYABLE SEARCHABLE SORTABLE,
+        body            STRING,
+        priority        INT64 QUERYABLE SORTABLE,
+        tags            LIST<STRING> QUERYABLE,
+        secret          ENCRYPTED STRING,
+        image           ASSET,
+        // free-form notes about the record type
+        GRANT WRITE TO "_creator",
+        GRANT CREATE TO "_icloud",
+        GRANT READ, WRITE TO "Moderator",
+        GRANT READ TO "_world"
+    );
+
+    RECORD TYPE Users (
+        roles LIST<INT64>,
+        GRANT WRITE TO "_creator",
+        GRANT READ TO "_world"
+    );
+"#;
+
+    fn test_pem_bytes() -> Vec<u8> {
+        use p256::ecdsa::SigningKey;
+
+        let sk = SigningKey::from_slice(&[
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+            0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+            0x1d, 0x1e, 0x1f, 0x20,
+        ])
+        .unwrap();
+
+        let raw_bytes = sk.to_bytes();
+        let pem_obj = pem::Pem::new("EC PRIVATE KEY", raw_bytes.as_slice());
+        pem::encode(&pem_obj).into_bytes()
+    }
+
+    fn schema() -> Schema {
+        Schema::parse(SCHEMA).unwrap()
+    }
+
+    #[test]
+    fn test_parse_schema() {
+        let schema = schema();
+        assert_eq!(schema.roles, ["Moderator"]);
+        assert_eq!(schema.record_types.len(), 2);
+
+        let note = schema.record_type("Note").unwrap();
+        assert_eq!(note.fields.len(), 8);
+        let title = note.field("title").unwrap();
+        assert_eq!(title.field_type, SchemaFieldType::String);
+        assert!(title.queryable && title.searchable && title.sortable);
+        assert!(note.field("secret").unwrap().encrypted);
+        assert_eq!(
+            note.field("tags").unwrap().field_type,
+            SchemaFieldType::List(Box::new(SchemaFieldType::String))
+        );
+        assert!(note.field("___createTime").unwrap().sortable);
+
+        assert_eq!(note.grants.len(), 4);
+        assert_eq!(
+            note.grants[2].permissions,
+            [Permission::Read, Permission::Write]
+        );
+        assert_eq!(note.grants[2].role, "Moderator");
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let schema = schema();
+        let exported = schema.to_ckdb();
+        assert!(exported.starts_with("DEFINE SCHEMA"));
+        assert!(exported.contains("\"___createTime\" TIMESTAMP SORTABLE"));
+        assert!(exported.contains("secret ENCRYPTED STRING"));
+        assert!(exported.contains("GRANT READ, WRITE TO \"Moderator\""));
+        assert_eq!(Schema::parse(&exported).unwrap(), schema);
+    }
+
+    #[test]
+    fn test_builder_export() {
+        let schema = Schema::new().with_record_type(
+            RecordTypeSchema::new("Item")
+                .with_field(FieldSchema::new("name", SchemaFieldType::String).queryable())
+                .with_grant(&[Permission::Read], "_world"),
+        );
+        let parsed = Schema::parse(&schema.to_ckdb()).unwrap();
+        assert_eq!(parsed, schema);
+    }
+
+    #[test]
+    fn test_parse_errors_report_line() {
+        let err = Schema::parse("DEFINE SCHEMA\nRECORD TYPE Note (\n  title TEXT\n);").unwrap_err();
+        match err {
+            AppleError::SchemaError(msg) => {
+                assert!(msg.contains("line 3"), "{}", msg);
+                assert!(msg.contains("TEXT"));
+            }
+            _ => panic!("Expected SchemaError"),
+        }
+
+        assert!(matches!(
+            Schema::parse("RECORD TYPE Note ( title STRING"),
+            Err(AppleError::SchemaError(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_violations() {
+        let schema = schema();
+        let record = Record::new("Note")
+            .with_field("title", FieldValue::String("ok".into()))
+            .with_field("priority", FieldValue::String("high".into()))
+            .with_field("colour", FieldValue::String("red".into()));
+
+        let violations = schema.record_violations(&record);
+        assert_eq!(violations.len(), 2);
+        assert!(violations.contains(&SchemaViolation::UnknownField {
+            record_type: "Note".into(),
+            field: "colour".into(),
+        }));
+        assert!(violations.contains(&SchemaViolation::TypeMismatch {
+            record_type: "Note".into(),
+            field: "priority".into(),
+            expected: SchemaFieldType::Int64,
+            found: SchemaFieldType::String,
+        }));
+
+        assert_eq!(
+            schema.record_violations(&Record::new("Missing")),
+            [SchemaViolation::UnknownRecordType("Missing".into())]
+        );
+        assert!(
+            schema
+                .check_record(
+                    &Record::new("Users").with_field("roles", FieldValue::Int64List(vec![1]))
+                )
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_query_violations() {
+        let schema = schema();
+        let query = QueryBuilder::new("Note")
+            .filter("body", Comparator::Equals, FieldValue::String("x".into()))
+            .filter(
+                "priority",
+                Comparator::In,
+                FieldValue::Int64List(vec![1, 2]),
+            )
+            .filter(
+                "tags",
+                Comparator::ListContains,
+                FieldValue::String("work".into()),
+            )
+            .filter(
+                "title",
+                Comparator::ContainsAnyTokens,
+                FieldValue::String("a b".into()),
+            )
+            .sort("body", true)
+            .build();
+
+        let violations = schema.query_violations(&query);
+        assert_eq!(
+            violations,
+            [
+                SchemaViolation::NotQueryable {
+                    record_type: "Note".into(),
+                    field: "body".into(),
+                },
+                SchemaViolation::NotSortable {
+                    record_type: "Note".into(),
+                    field: "body".into(),
+                },
+            ]
+        );
+
+        let query = QueryBuilder::new("Note")
+            .filter("priority", Comparator::GreaterThan, FieldValue::Double(1.0))
+            .build();
+        assert!(matches!(
+            schema.query_violations(&query).as_slice(),
+            [SchemaViolation::TypeMismatch { .. }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_client_rejects_invalid_record_before_sending() {
+        let kp = AppleKeyPair::from_pem_bytes("test-key", &test_pem_bytes()).unwrap();
+        let client = CloudKitClient::new(CloudKitConfig {
+            container: "iCloud.com.test.app".to_string(),
+            environment: Environment::Development,
+            key_pair: kp,
+        })
+        .unwrap()
+        .with_base_url("http://127.0.0.1:9")
+        .with_schema(schema());
+
+        let record = Record::new("Note").with_field("priority", FieldValue::String("x".into()));
+        match client.create_record(&DatabaseType::Public, record).await {
+            Err(AppleError::ValidationError(msg)) => {
+                assert!(msg.contains("Note.priority expects INT64 but got STRING"))
+            }
+            other => panic!("Expected ValidationError, got {:?}", other),
+        }
+
+        let query = QueryBuilder::new("Note").sort("body", false).build();
+        assert!(matches!(
+            client
+                .query_records(&DatabaseType::Public, query, None, None, None, None)
+                .await,
+            Err(AppleError::ValidationError(_))
+        ));
+    }
+}
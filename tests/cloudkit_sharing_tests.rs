@@ -0,0 +1,170 @@
+#[cfg(feature = "cloudkit")]
+mod cloudkit_sharing_tests {
+    use apple::cloudkit::sharing::short_guid_from_url;
+    use apple::cloudkit::*;
+
+    #[test]
+    fn test_share_builder() {
+        let share = Share::new()
+            .with_title("Family Recipes")
+            .with_public_permission(ParticipantPermission::None)
+            .with_participant(ShareParticipant::by_email(
+                "friend@example.com",
+                ParticipantPermission::ReadWrite,
+            ));
+
+        assert_eq!(share.title(), Some("Family Recipes"));
+        assert_eq!(share.public_permission, Some(ParticipantPermission::None));
+        assert_eq!(share.participants.len(), 1);
+        assert!(!share.is_zone_wide());
+        assert!(share.url().is_none());
+    }
+
+    #[test]
+    fn test_zone_wide_share_name() {
+        let share = Share::new().with_name(Share::ZONE_WIDE_RECORD_NAME);
+        assert!(share.is_zone_wide());
+    }
+
+    #[test]
+    fn test_participant_by_email_serde() {
+        let participant =
+            ShareParticipant::by_email("friend@example.com", ParticipantPermission::ReadOnly);
+        let json = serde_json::to_string(&participant).unwrap();
+        assert!(json.contains("\"emailAddress\":\"friend@example.com\""));
+        assert!(json.contains("\"permission\":\"READ_ONLY\""));
+        assert!(json.contains("\"type\":\"USER\""));
+        assert!(!json.contains("acceptanceStatus"));
+    }
+
+    #[test]
+    fn test_participant_lookup_variants() {
+        let by_phone =
+            ShareParticipant::by_phone_number("+15555550100", ParticipantPermission::ReadOnly);
+        let lookup = by_phone.user_identity.lookup_info.unwrap();
+        assert_eq!(lookup.phone_number.as_deref(), Some("+15555550100"));
+        assert!(lookup.email_address.is_none());
+
+        let by_name =
+            ShareParticipant::by_user_record_name("_abc123", ParticipantPermission::ReadWrite);
+        let lookup = by_name.user_identity.lookup_info.unwrap();
+        assert_eq!(lookup.user_record_name.as_deref(), Some("_abc123"));
+    }
+
+    #[test]
+    fn test_share_record_result_deserialize() {
+        let json = r#"{
+            "recordName": "share-1",
+            "recordType": "cloudkit.share",
+            "recordChangeTag": "tag-1",
+            "shortGUID": "0aBcDeF",
+            "publicPermission": "NONE",
+            "fields": { "cloudkit.title": { "type": "STRING", "value": "Trip" } },
+            "participants": [
+                {
+                    "participantId": "p-1",
+                    "userIdentity": {
+                        "userRecordName": "_owner",
+                        "nameComponents": { "givenName": "Ada" }
+                    },
+                    "type": "OWNER",
+                    "acceptanceStatus": "ACCEPTED",
+                    "permission": "READ_WRITE"
+                }
+            ],
+            "owner": {
+                "userIdentity": { "userRecordName": "_owner" },
+                "type": "OWNER"
+            }
+        }"#;
+
+        let result: RecordResult = serde_json::from_str(json).unwrap();
+        assert_eq!(result.record_type.as_deref(), Some(Share::RECORD_TYPE));
+        assert_eq!(result.short_guid.as_deref(), Some("0aBcDeF"));
+        assert_eq!(result.public_permission, Some(ParticipantPermission::None));
+        assert_eq!(result.participants.len(), 1);
+
+        let participant = &result.participants[0];
+        assert_eq!(participant.participant_type, Some(ParticipantType::Owner));
+        assert_eq!(
+            participant.acceptance_status,
+            Some(ParticipantAcceptanceStatus::Accepted)
+        );
+        assert_eq!(
+            participant
+                .user_identity
+                .name_components
+                .as_ref()
+                .unwrap()
+                .given_name
+                .as_deref(),
+            Some("Ada")
+        );
+        assert!(result.owner.is_some());
+    }
+
+    #[test]
+    fn test_root_record_share_reference() {
+        let json = r#"{
+            "recordName": "album-1",
+            "recordType": "Album",
+            "share": { "recordName": "share-1", "zoneID": { "zoneName": "Albums" } }
+        }"#;
+
+        let record: Record = serde_json::from_str(json).unwrap();
+        let share = record.share.unwrap();
+        assert_eq!(share.record_name, "share-1");
+        assert_eq!(share.zone_id.unwrap().zone_name, "Albums");
+    }
+
+    #[test]
+    fn test_record_without_share_omits_field() {
+        let json = serde_json::to_string(&Record::new("Album")).unwrap();
+        assert!(!json.contains("share"));
+    }
+
+    #[test]
+    fn test_short_guid_from_url() {
+        assert_eq!(
+            short_guid_from_url("https://www.icloud.com/share/0aBcDeF#Family_Recipes"),
+            Some("0aBcDeF".to_string())
+        );
+        assert_eq!(
+            short_guid_from_url("https://www.icloud.com/share/0aBcDeF"),
+            Some("0aBcDeF".to_string())
+        );
+        assert_eq!(short_guid_from_url("https://www.icloud.com/share/"), None);
+        assert_eq!(short_guid_from_url("https://example.com/share/abc"), None);
+    }
+
+    #[test]
+    fn test_share_metadata_shared_zone_id() {
+        let json = r#"{
+            "shortGUID": "0aBcDeF",
+            "containerIdentifier": "iCloud.com.test.app",
+            "databaseScope": "SHARED",
+            "rootRecordName": "album-1",
+            "zoneID": { "zoneName": "Albums" },
+            "ownerIdentity": { "userRecordName": "_owner" },
+            "participantType": "USER",
+            "participantStatus": "PENDING",
+            "participantPermission": "READ_ONLY"
+        }"#;
+
+        let metadata: ShareMetadata = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            metadata.participant_status,
+            Some(ParticipantAcceptanceStatus::Pending)
+        );
+        let zone = metadata.shared_zone_id().unwrap();
+        assert_eq!(zone.zone_name, "Albums");
+        assert_eq!(zone.owner_record_name.as_deref(), Some("_owner"));
+    }
+
+    #[test]
+    fn test_zone_id_with_owner() {
+        let zone = ZoneID::with_owner("Albums", "_owner");
+        let json = serde_json::to_string(&zone).unwrap();
+        assert!(json.contains("\"ownerRecordName\":\"_owner\""));
+    }
+}
@@ -0,0 +1,206 @@
+mod common;
+
+#[cfg(feature = "cloudkit")]
+mod cloudkit_subscriptions_tests {
+    use apple::cloudkit::*;
+    use apple::error::AppleError;
+
+    fn note_query() -> Query {
+        QueryBuilder::new("Note").build()
+    }
+
+    #[test]
+    fn test_query_subscription_requires_fires_on_and_record_type() {
+        let sub = Subscription::query("notes", note_query(), &[FiresOn::Create]).unwrap();
+        assert_eq!(sub.subscription_type, SubscriptionType::Query);
+        assert_eq!(sub.fires_on, Some(vec![FiresOn::Create]));
+
+        assert!(matches!(
+            Subscription::query("notes", note_query(), &[]),
+            Err(AppleError::ValidationError(_))
+        ));
+        assert!(matches!(
+            Subscription::query("notes", QueryBuilder::new("").build(), &[FiresOn::Update]),
+            Err(AppleError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_rejects_fields_for_other_types() {
+        assert!(
+            Subscription::zone("z", ZoneID::new("Notes"))
+                .validate()
+                .is_ok()
+        );
+        assert!(Subscription::database("db").validate().is_ok());
+
+        let mut zone_without_zone = Subscription::zone("z", ZoneID::new("Notes"));
+        zone_without_zone.zone_id = None;
+        assert!(zone_without_zone.validate().is_err());
+
+        let mut database_with_fires_on = Subscription::database("db");
+        database_with_fires_on.fires_on = Some(vec![FiresOn::Delete]);
+        assert!(database_with_fires_on.validate().is_err());
+
+        let zoned_database = Subscription::database("db").with_zone(ZoneID::new("Notes"));
+        assert!(zoned_database.validate().is_err());
+    }
+
+    #[test]
+    fn test_notification_info_helpers() {
+        let info = NotificationInfo::alert("New note")
+            .with_sound("default")
+            .with_badge()
+            .with_desired_keys(&["title"]);
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["alertBody"], "New note");
+        assert_eq!(json["soundName"], "default");
+        assert_eq!(json["shouldBadge"], true);
+        assert_eq!(json["desiredKeys"][0], "title");
+
+        let silent = serde_json::to_value(NotificationInfo::silent()).unwrap();
+        assert_eq!(silent["shouldSendContentAvailable"], true);
+        assert!(silent.get("alertBody").is_none());
+    }
+}
+
+#[cfg(feature = "cloudkit-emulator")]
+mod cloudkit_subscriptions_emulator_tests {
+    use crate::common::cloudkit::setup;
+    use apple::cloudkit::*;
+    use apple::error::{AppleError, CloudKitErrorCode};
+
+    fn notes_subscription(fires_on: &[FiresOn]) -> Subscription {
+        Subscription::query("notes", QueryBuilder::new("Note").build(), fires_on)
+            .unwrap()
+            .with_notification_info(NotificationInfo::silent())
+    }
+
+    #[tokio::test]
+    async fn test_lookup_and_update() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Public;
+        client
+            .create_subscription(&db, notes_subscription(&[FiresOn::Create]))
+            .await
+            .unwrap();
+
+        let updated = client
+            .update_subscription(&db, notes_subscription(&[FiresOn::Create, FiresOn::Delete]))
+            .await
+            .unwrap();
+        assert_eq!(
+            updated.fires_on,
+            Some(vec![FiresOn::Create, FiresOn::Delete])
+        );
+
+        let results = client
+            .lookup_subscriptions(&db, &["notes", "missing"])
+            .await
+            .unwrap();
+        let found = results[0].clone().into_subscription().unwrap();
+        assert_eq!(found.fires_on, updated.fires_on);
+        assert_eq!(results[1].subscription_id.as_deref(), Some("missing"));
+        assert!(matches!(
+            results[1].error(),
+            Some(AppleError::CloudKitError(e)) if e.server_error_code == CloudKitErrorCode::NotFound
+        ));
+
+        let missing = Subscription::database("missing");
+        assert!(matches!(
+            client
+                .update_subscription(&DatabaseType::Private, missing)
+                .await,
+            Err(AppleError::CloudKitError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ensure_subscriptions_is_idempotent() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+        client
+            .create_zone(&db, ZoneID::new("Notes"), None)
+            .await
+            .unwrap();
+        client
+            .create_subscription(&db, Subscription::database("stale"))
+            .await
+            .unwrap();
+
+        let desired = || {
+            vec![
+                Subscription::zone("notes-zone", ZoneID::new("Notes")),
+                notes_subscription(&[FiresOn::Update, FiresOn::Create]),
+            ]
+        };
+        let first = client.ensure_subscriptions(&db, desired()).await.unwrap();
+        assert_eq!(first.created, ["notes-zone", "notes"]);
+        assert_eq!(first.deleted, ["stale"]);
+
+        let second = client.ensure_subscriptions(&db, desired()).await.unwrap();
+        assert!(second.created.is_empty() && second.deleted.is_empty());
+        assert!(second.updated.is_empty());
+        assert_eq!(second.unchanged.len(), 2);
+
+        // Order of firesOn does not count as a change; adding one does.
+        let changed = vec![
+            Subscription::zone("notes-zone", ZoneID::new("Notes")),
+            notes_subscription(&[FiresOn::Create, FiresOn::Update, FiresOn::Delete]),
+        ];
+        let third = client.ensure_subscriptions(&db, changed).await.unwrap();
+        assert_eq!(third.updated, ["notes"]);
+        assert_eq!(third.unchanged, ["notes-zone"]);
+        let listed = client.list_subscriptions(&db).await.unwrap();
+        assert_eq!(listed.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_subscriptions_ignores_fields_left_unset() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+        client
+            .create_subscription(
+                &db,
+                Subscription::database("all")
+                    .with_notification_info(NotificationInfo::alert("Changed")),
+            )
+            .await
+            .unwrap();
+
+        let plan = client
+            .ensure_subscriptions(&db, vec![Subscription::database("all")])
+            .await
+            .unwrap();
+        assert_eq!(plan.unchanged, ["all"]);
+
+        let plan = client
+            .ensure_subscriptions(
+                &db,
+                vec![
+                    Subscription::database("all")
+                        .with_notification_info(NotificationInfo::silent()),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(plan.updated, ["all"]);
+        let listed = client.list_subscriptions(&db).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        let info = listed[0].notification_info.as_ref().unwrap();
+        assert_eq!(info.should_send_content_available, Some(true));
+        assert!(info.alert_body.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ensure_subscriptions_rejects_duplicate_ids() {
+        let (_emulator, client) = setup();
+        let desired = vec![Subscription::database("a"), Subscription::database("a")];
+        assert!(matches!(
+            client
+                .ensure_subscriptions(&DatabaseType::Private, desired)
+                .await,
+            Err(AppleError::ValidationError(_))
+        ));
+    }
+}
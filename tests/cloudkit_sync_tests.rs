@@ -0,0 +1,278 @@
+mod common;
+
+#[cfg(feature = "cloudkit-emulator")]
+mod cloudkit_sync_tests {
+    use crate::common::cloudkit::setup;
+    use apple::cloudkit::notifications::parse_notification;
+    use apple::cloudkit::*;
+    use apple::error::{AppleError, CloudKitErrorCode};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn recorder() -> (
+        Arc<Mutex<Vec<SyncEvent>>>,
+        impl Fn(&SyncEvent) + Send + Sync,
+    ) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        (events, move |event: &SyncEvent| {
+            sink.lock().unwrap().push(event.clone())
+        })
+    }
+
+    fn notification(ck: serde_json::Value) -> CKNotification {
+        parse_notification(&json!({ "ck": ck }).to_string()).unwrap()
+    }
+
+    async fn seed(client: &CloudKitClient, zone: &str, names: &[&str]) {
+        let db = DatabaseType::Private;
+        client
+            .create_zone(&db, ZoneID::new(zone), None)
+            .await
+            .unwrap();
+        for name in names {
+            let record = Record::new("Note")
+                .with_name(name)
+                .with_zone(ZoneID::new(zone));
+            client.create_record(&db, record).await.unwrap();
+        }
+    }
+
+    fn changed_records(events: &[SyncEvent]) -> Vec<(String, String)> {
+        let mut changed = Vec::new();
+        for event in events {
+            if let SyncEvent::ZoneChanged {
+                zone_id, records, ..
+            } = event
+            {
+                for record in records {
+                    changed.push((
+                        zone_id.zone_name.clone(),
+                        record.record_name.clone().unwrap(),
+                    ));
+                }
+            }
+        }
+        changed.sort();
+        changed
+    }
+
+    #[tokio::test]
+    async fn test_zone_notifications_coalesce() {
+        let (_emulator, client) = setup();
+        seed(&client, "Notes", &["n1", "n2"]).await;
+        let (events, handler) = recorder();
+        let dispatcher = SyncDispatcher::new(&client).with_handler(handler);
+
+        let zone_notification = || notification(json!({ "nid": "1", "zid": "Notes", "dbs": 2 }));
+        dispatcher
+            .dispatch(vec![
+                zone_notification(),
+                zone_notification(),
+                zone_notification(),
+            ])
+            .await;
+
+        let recorded = events.lock().unwrap().clone();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(
+            changed_records(&recorded),
+            [("Notes".into(), "n1".into()), ("Notes".into(), "n2".into())]
+        );
+        let token = dispatcher
+            .zone_token(&DatabaseType::Private, &ZoneID::new("Notes"))
+            .expect("zone token");
+
+        // Only changes after the stored token are fetched next time.
+        let record = Record::new("Note")
+            .with_name("n3")
+            .with_zone(ZoneID::new("Notes"));
+        client
+            .create_record(&DatabaseType::Private, record)
+            .await
+            .unwrap();
+        dispatcher.dispatch(vec![zone_notification()]).await;
+        let recorded = events.lock().unwrap().clone();
+        assert_eq!(
+            changed_records(&recorded[1..]),
+            [("Notes".into(), "n3".into())]
+        );
+        assert_ne!(
+            dispatcher.zone_token(&DatabaseType::Private, &ZoneID::new("Notes")),
+            Some(token)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_database_notification_syncs_changed_zones() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Private;
+        let start = client
+            .fetch_database_changes(&db, None, None)
+            .await
+            .unwrap()
+            .sync_token
+            .unwrap();
+        seed(&client, "A", &["a1"]).await;
+        seed(&client, "B", &["b1"]).await;
+        client.delete_zone(&db, ZoneID::new("B")).await.unwrap();
+
+        let (events, handler) = recorder();
+        let dispatcher = SyncDispatcher::new(&client)
+            .with_handler(handler)
+            .with_database_token(&db, &start);
+        dispatcher
+            .dispatch(vec![notification(json!({ "nid": "1", "dbs": 2 }))])
+            .await;
+
+        let recorded = events.lock().unwrap().clone();
+        assert!(recorded.iter().any(|e| matches!(
+            e,
+            SyncEvent::ZoneDeleted { zone_id, .. } if zone_id.zone_name == "B"
+        )));
+        assert_eq!(changed_records(&recorded), [("A".into(), "a1".into())]);
+        assert_ne!(dispatcher.database_token(&db), Some(start));
+    }
+
+    #[tokio::test]
+    async fn test_pruned_notification_syncs_every_zone() {
+        let (_emulator, client) = setup();
+        seed(&client, "A", &["a1"]).await;
+        seed(&client, "B", &["b1"]).await;
+
+        let (events, handler) = recorder();
+        let dispatcher = SyncDispatcher::new(&client).with_handler(handler);
+        dispatcher
+            .dispatch(vec![
+                notification(json!({ "nid": "1", "zid": "A", "dbs": 2 })),
+                notification(json!({ "nid": "2", "p": true, "dbs": 2 })),
+            ])
+            .await;
+
+        let recorded = events.lock().unwrap().clone();
+        assert_eq!(
+            changed_records(&recorded),
+            [("A".into(), "a1".into()), ("B".into(), "b1".into())]
+        );
+
+        dispatcher
+            .dispatch(vec![notification(
+                json!({ "nid": "3", "p": true, "rid": "x", "fo": 1, "dbs": 1 }),
+            )])
+            .await;
+        assert!(matches!(
+            events.lock().unwrap().last(),
+            Some(SyncEvent::CatchUpRequired {
+                database: DatabaseType::Public
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_query_notifications_follow_up_on_records() {
+        let (_emulator, client) = setup();
+        let record = Record::new("Note")
+            .with_name("q1")
+            .with_field("title", FieldValue::String("hi".into()));
+        client
+            .create_record(&DatabaseType::Public, record)
+            .await
+            .unwrap();
+
+        let (events, handler) = recorder();
+        let dispatcher = SyncDispatcher::new(&client).with_handler(handler);
+        dispatcher
+            .dispatch(vec![
+                notification(json!({ "nid": "1", "rid": "q1", "fo": 1, "dbs": 1 })),
+                notification(json!({ "nid": "2", "rid": "q1", "fo": 2, "dbs": 1 })),
+                notification(json!({ "nid": "3", "rid": "gone", "fo": 3, "dbs": 1 })),
+            ])
+            .await;
+
+        let recorded = events.lock().unwrap().clone();
+        assert_eq!(recorded.len(), 2);
+        assert!(matches!(
+            &recorded[0],
+            SyncEvent::RecordChanged { record, .. } if record.record_name.as_deref() == Some("q1")
+        ));
+        assert!(matches!(
+            &recorded[1],
+            SyncEvent::RecordDeleted { record_name, .. } if record_name == "gone"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_query_follow_up_reports_records_gone_by_lookup() {
+        let (_emulator, client) = setup();
+        let db = DatabaseType::Public;
+        client
+            .create_record(&db, Record::new("Note").with_name("q2"))
+            .await
+            .unwrap();
+        client.delete_record(&db, "q2", "Note", None).await.unwrap();
+
+        let (events, handler) = recorder();
+        let dispatcher = SyncDispatcher::new(&client).with_handler(handler);
+        dispatcher
+            .dispatch(vec![
+                notification(json!({ "nid": "1", "rid": "q2", "fo": 1, "dbs": 1 })),
+                notification(
+                    json!({ "nid": "2", "rid": "q3", "zid": "Missing", "fo": 1, "dbs": 1 }),
+                ),
+            ])
+            .await;
+
+        let recorded = events.lock().unwrap().clone();
+        assert_eq!(recorded.len(), 2);
+        assert!(matches!(
+            &recorded[0],
+            SyncEvent::RecordDeleted { record_name, .. } if record_name == "q2"
+        ));
+        assert!(matches!(
+            &recorded[1],
+            SyncEvent::Failed {
+                error: AppleError::CloudKitError(error),
+                ..
+            } if error.server_error_code == CloudKitErrorCode::ZoneNotFound
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_run_flushes_stream_and_reports_failures() {
+        let (_emulator, client) = setup();
+        seed(&client, "A", &["a1"]).await;
+
+        let (events, handler) = recorder();
+        let dispatcher = SyncDispatcher::new(&client).with_handler(handler);
+        let items = vec![
+            Ok(notification(json!({ "nid": "1", "zid": "A", "dbs": 2 }))),
+            Err(AppleError::HttpError("connection reset".to_string())),
+            Ok(notification(
+                json!({ "nid": "2", "zid": "Missing", "dbs": 2 }),
+            )),
+        ];
+        dispatcher
+            .run(futures::stream::iter(items), Duration::from_millis(10))
+            .await;
+
+        let recorded = events.lock().unwrap().clone();
+        assert_eq!(changed_records(&recorded), [("A".into(), "a1".into())]);
+        assert!(recorded.iter().any(|e| matches!(
+            e,
+            SyncEvent::Failed {
+                database: Some(DatabaseType::Private),
+                zone_id: Some(zone_id),
+                ..
+            } if zone_id.zone_name == "Missing"
+        )));
+        assert!(recorded.iter().any(|e| matches!(
+            e,
+            SyncEvent::Failed {
+                database: None,
+                zone_id: None,
+                error: AppleError::HttpError(_),
+            }
+        )));
+    }
+}
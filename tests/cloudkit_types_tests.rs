@@ -269,4 +269,38 @@ mod cloudkit_types_tests {
         assert_eq!(DatabaseType::Public, DatabaseType::Public);
         assert_ne!(DatabaseType::Public, DatabaseType::Private);
     }
+
+    #[test]
+    fn test_zone_result_error_is_typed() {
+        let result: ZoneResult = serde_json::from_str(
+            r#"{"zoneID":{"zoneName":"Shared","ownerRecordName":"_owner"},"serverErrorCode":"ZONE_NOT_FOUND","reason":"gone"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            result
+                .zone_id
+                .as_ref()
+                .unwrap()
+                .owner_record_name
+                .as_deref(),
+            Some("_owner")
+        );
+        match result.into_zone() {
+            Err(apple::error::AppleError::CloudKitError(e)) => {
+                assert_eq!(
+                    e.server_error_code,
+                    apple::error::CloudKitErrorCode::ZoneNotFound
+                );
+                assert_eq!(e.reason, "gone");
+            }
+            other => panic!("Expected CloudKitError, got {:?}", other),
+        }
+
+        let ok: ZoneResult =
+            serde_json::from_str(r#"{"zoneID":{"zoneName":"Z"},"syncToken":"t","atomic":true}"#)
+                .unwrap();
+        let zone = ok.into_zone().unwrap();
+        assert_eq!(zone.sync_token.as_deref(), Some("t"));
+        assert_eq!(zone.atomic, Some(true));
+    }
 }
@@ -0,0 +1,122 @@
+#[cfg(feature = "cloudkit")]
+mod cloudkit_web_auth_tests {
+    use apple::cloudkit::client::{CloudKitClient, CloudKitConfig};
+    use apple::cloudkit::types::Environment;
+    use apple::cloudkit::web_auth::{CloudKitAuthMode, web_auth_token_from_callback};
+    use apple::error::{AppleError, CloudKitAuthenticationRequired};
+    use apple::signing::AppleKeyPair;
+
+    fn test_pem_bytes() -> Vec<u8> {
+        use p256::ecdsa::SigningKey;
+
+        let sk = SigningKey::from_slice(&[
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+            0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c,
+            0x1d, 0x1e, 0x1f, 0x20,
+        ])
+        .unwrap();
+
+        let raw_bytes = sk.to_bytes();
+        let pem_obj = pem::Pem::new("EC PRIVATE KEY", raw_bytes.as_slice());
+        pem::encode(&pem_obj).into_bytes()
+    }
+
+    fn make_client() -> CloudKitClient {
+        let kp = AppleKeyPair::from_pem_bytes("test-key", &test_pem_bytes()).unwrap();
+        CloudKitClient::new(CloudKitConfig {
+            container: "iCloud.com.test.app".to_string(),
+            environment: Environment::Development,
+            key_pair: kp,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_default_mode_is_server_to_server() {
+        let client = make_client();
+        assert_eq!(client.auth_mode(), CloudKitAuthMode::ServerToServer);
+        assert!(client.web_auth_token().is_none());
+    }
+
+    #[test]
+    fn test_as_user_requires_api_token() {
+        let client = make_client();
+        match client.as_user() {
+            Err(AppleError::ValidationError(msg)) => assert!(msg.contains("API token")),
+            _ => panic!("Expected ValidationError"),
+        }
+    }
+
+    #[test]
+    fn test_as_user_and_back() {
+        let client = make_client().with_api_token("api-token");
+        let user = client.as_user().unwrap();
+        assert_eq!(user.auth_mode(), CloudKitAuthMode::ApiToken);
+        assert_eq!(user.config().container, "iCloud.com.test.app");
+
+        let server = user.as_server();
+        assert_eq!(server.auth_mode(), CloudKitAuthMode::ServerToServer);
+    }
+
+    #[test]
+    fn test_web_auth_token_shared_between_handles() {
+        let client = make_client()
+            .with_api_token("api-token")
+            .with_web_auth_token("token-1");
+        let user = client.as_user().unwrap();
+        assert_eq!(user.web_auth_token().as_deref(), Some("token-1"));
+
+        user.set_web_auth_token(Some("token-2"));
+        assert_eq!(client.web_auth_token().as_deref(), Some("token-2"));
+
+        client.set_web_auth_token(None);
+        assert!(user.web_auth_token().is_none());
+    }
+
+    #[test]
+    fn test_web_auth_token_ignored_without_api_token() {
+        let client = make_client().with_web_auth_token("token-1");
+        assert!(client.web_auth_token().is_none());
+    }
+
+    #[test]
+    fn test_replacing_api_token_keeps_web_auth_token() {
+        let client = make_client()
+            .with_api_token("old")
+            .with_web_auth_token("token-1")
+            .with_api_token("new");
+        assert_eq!(client.web_auth_token().as_deref(), Some("token-1"));
+    }
+
+    #[test]
+    fn test_web_auth_token_from_callback_query() {
+        let token = web_auth_token_from_callback(
+            "https://example.com/callback?ckWebAuthToken=abc%2B123&ckSession=x",
+        );
+        assert_eq!(token.as_deref(), Some("abc+123"));
+    }
+
+    #[test]
+    fn test_web_auth_token_from_callback_fragment() {
+        let token = web_auth_token_from_callback("https://example.com/callback#ckWebAuthToken=xyz");
+        assert_eq!(token.as_deref(), Some("xyz"));
+    }
+
+    #[test]
+    fn test_web_auth_token_from_callback_missing() {
+        assert!(web_auth_token_from_callback("https://example.com/callback?foo=bar").is_none());
+        assert!(web_auth_token_from_callback("not a url").is_none());
+    }
+
+    #[test]
+    fn test_authentication_required_display() {
+        let err = AppleError::CloudKitAuthenticationRequired(CloudKitAuthenticationRequired {
+            redirect_url: "https://idmsa.apple.com/auth".into(),
+            reason: "request needs authorization".into(),
+            uuid: Some("uuid-1".into()),
+        });
+        let display = err.to_string();
+        assert!(display.contains("authentication required"));
+        assert!(display.contains("https://idmsa.apple.com/auth"));
+    }
+}
@@ -0,0 +1,109 @@
+mod common;
+
+#[cfg(feature = "cloudkit-emulator")]
+mod cloudkit_webcourier_tests {
+    use crate::common::cloudkit::setup;
+    use apple::cloudkit::*;
+    use apple::error::AppleError;
+    use futures::StreamExt;
+    use futures::future::{self, Either};
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn options() -> NotificationStreamOptions {
+        NotificationStreamOptions::default()
+            .with_poll_timeout(Duration::from_millis(300))
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(40))
+    }
+
+    fn zone_payload(id: &str) -> serde_json::Value {
+        json!({ "ck": { "cid": "iCloud.com.test.app", "nid": id, "zid": "Notes" } })
+    }
+
+    fn notification_id(item: Option<Result<CKNotification, AppleError>>) -> String {
+        match item {
+            Some(Ok(CKNotification::RecordZone(n))) => n.notification_id.unwrap(),
+            other => panic!("Expected zone notification, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_delivers_and_surfaces_parse_failures() {
+        let (emulator, client) = setup();
+        let token = client.create_token(None).await.unwrap();
+        emulator.notify(json!({ "aps": {} }));
+        emulator.notify(zone_payload("n1"));
+
+        let mut stream = client.notification_stream(token.webcourier_url.as_deref(), options());
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(AppleError::JsonError(_)))
+        ));
+        assert_eq!(notification_id(stream.next().await), "n1");
+    }
+
+    #[tokio::test]
+    async fn test_stream_registers_and_reregisters_after_expiry() {
+        let (emulator, client) = setup();
+        let mut stream = client.notification_stream(None, options());
+
+        // Keep notifying until the stream's registration picks one up; polls
+        // that time out in between must not surface as errors.
+        for id in ["first", "second"] {
+            let notify = async {
+                loop {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    emulator.notify(zone_payload(id));
+                }
+            };
+            let mut notify = Box::pin(notify);
+            // Duplicates of the previous id may still be queued.
+            loop {
+                let item = match future::select(Box::pin(stream.next()), notify.as_mut()).await {
+                    Either::Left((item, _)) => item,
+                    Either::Right(_) => unreachable!(),
+                };
+                if notification_id(item) == id {
+                    break;
+                }
+            }
+            emulator.expire_tokens();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_backs_off_and_cancels() {
+        let (_emulator, client) = setup();
+        let mut stream =
+            client.notification_stream(Some("http://127.0.0.1:9/webcourier/x"), options());
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(AppleError::HttpError(_)))
+        ));
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(AppleError::HttpError(_)))
+        ));
+
+        stream.cancel();
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_interrupts_pending_poll() {
+        let (_emulator, client) = setup();
+        let token = client.create_token(None).await.unwrap();
+        let mut stream = client.notification_stream(
+            token.webcourier_url.as_deref(),
+            options().with_poll_timeout(Duration::from_secs(30)),
+        );
+        let handle = stream.cancel_handle();
+
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            handle.abort();
+        };
+        let (item, _) = future::join(stream.next(), cancel).await;
+        assert!(item.is_none());
+    }
+}
@@ -0,0 +1,36 @@
+use apple::cloudkit::emulator::CloudKitEmulator;
+use apple::cloudkit::{CloudKitClient, CloudKitConfig, Environment};
+use apple::signing::AppleKeyPair;
+
+pub fn test_pem_bytes() -> Vec<u8> {
+    use p256::ecdsa::SigningKey;
+
+    let sk = SigningKey::from_slice(&[
+        0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+        0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e,
+        0x1f, 0x20,
+    ])
+    .unwrap();
+
+    let raw_bytes = sk.to_bytes();
+    let pem_obj = pem::Pem::new("EC PRIVATE KEY", raw_bytes.as_slice());
+    pem::encode(&pem_obj).into_bytes()
+}
+
+/// An emulator that checks request signatures, and a client signing with
+/// the matching key.
+pub fn setup() -> (CloudKitEmulator, CloudKitClient) {
+    let kp = AppleKeyPair::from_pem_bytes("test-key", &test_pem_bytes()).unwrap();
+    let emulator = CloudKitEmulator::builder()
+        .with_key_pair(&kp)
+        .start()
+        .unwrap();
+    let client = emulator
+        .client(CloudKitConfig {
+            container: "iCloud.com.test.app".to_string(),
+            environment: Environment::Development,
+            key_pair: kp,
+        })
+        .unwrap();
+    (emulator, client)
+}
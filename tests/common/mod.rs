@@ -0,0 +1,152 @@
+// Helpers shared by the integration tests. Each test crate uses a different
+// subset of them.
+#![allow(dead_code)]
+
+#[cfg(feature = "cloudkit-emulator")]
+pub mod cloudkit;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+pub struct StubRequest {
+    /// Path and query, like `/inApps/v1/history/1000?revision=r1`.
+    pub target: String,
+    pub body: Vec<u8>,
+}
+
+pub struct StubResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl StubResponse {
+    pub fn json(status: u16, body: &serde_json::Value) -> Self {
+        StubResponse {
+            status,
+            content_type: "application/json",
+            body: body.to_string().into_bytes(),
+        }
+    }
+}
+
+/// A local HTTP server that hands every request to a closure and records
+/// each request target. Serves until the test process exits.
+pub struct StubServer {
+    pub base_url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl StubServer {
+    pub fn start(respond: impl Fn(&StubRequest) -> StubResponse + Send + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let request = read_request(&mut stream);
+                seen.lock().unwrap().push(request.target.clone());
+                let response = respond(&request);
+                write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    response.status,
+                    response.content_type,
+                    response.body.len()
+                )
+                .unwrap();
+                stream.write_all(&response.body).unwrap();
+            }
+        });
+        StubServer { base_url, requests }
+    }
+
+    /// Serves canned JSON by exact request target, and 404 for anything
+    /// else.
+    pub fn routes(routes: Vec<(&str, u16, serde_json::Value)>) -> Self {
+        let routes: Vec<(String, u16, serde_json::Value)> = routes
+            .into_iter()
+            .map(|(target, status, body)| (target.to_string(), status, body))
+            .collect();
+        Self::start(move |request| {
+            routes
+                .iter()
+                .find(|(target, _, _)| *target == request.target)
+                .map(|(_, status, body)| StubResponse::json(*status, body))
+                .unwrap_or_else(|| StubResponse::json(404, &serde_json::json!({})))
+        })
+    }
+
+    /// Every request target served so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request(stream: &mut TcpStream) -> StubRequest {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut buf).unwrap();
+        data.extend_from_slice(&buf[..n]);
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&data[..end]).to_string();
+            let length: usize = head
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                .map(|(_, value)| value.trim().parse().unwrap())
+                .unwrap_or(0);
+            if data.len() >= end + 4 + length {
+                return StubRequest {
+                    target: head.split(' ').nth(1).unwrap_or_default().to_string(),
+                    body: data[end + 4..end + 4 + length].to_vec(),
+                };
+            }
+        }
+        if n == 0 {
+            return StubRequest {
+                target: String::new(),
+                body: Vec::new(),
+            };
+        }
+    }
+}
+
+/// The `signedDate` of the App Store payloads below, in milliseconds.
+pub const SIGNED_DATE: i64 = 1_700_000_000_000;
+
+/// A Sandbox transaction payload for `com.example.app`.
+pub fn transaction(signed_date: i64) -> serde_json::Value {
+    serde_json::json!({
+        "transactionId": "1000",
+        "originalTransactionId": "1000",
+        "bundleId": "com.example.app",
+        "productId": "pro.monthly",
+        "purchaseDate": signed_date,
+        "quantity": 1,
+        "type": "Auto-Renewable Subscription",
+        "inAppOwnershipType": "PURCHASED",
+        "signedDate": signed_date,
+        "environment": "Sandbox",
+        "transactionReason": "PURCHASE",
+    })
+}
+
+/// A notification payload with `body` merged over the defaults.
+pub fn notification(body: serde_json::Value) -> serde_json::Value {
+    let mut payload = serde_json::json!({
+        "notificationType": "SUBSCRIBED",
+        "version": "2.0",
+        "signedDate": SIGNED_DATE,
+        "notificationUUID": "8c3b5a2e-1111-2222-3333-444455556666",
+    });
+    payload
+        .as_object_mut()
+        .unwrap()
+        .extend(body.as_object().unwrap().clone());
+    payload
+}
@@ -60,6 +60,10 @@ fn test_apple_error_display_variants() {
         AppleError::UnrecognizedError("unk".into()).to_string(),
         "Unrecognized error: unk"
     );
+    assert_eq!(
+        AppleError::ValidationError("bad".into()).to_string(),
+        "Validation error: bad"
+    );
 }
 
 #[test]
@@ -232,4 +236,48 @@ mod cloudkit_error_tests {
         let err = AppleError::SignatureError("bad sig".into());
         assert_eq!(err.to_string(), "Signature error: bad sig");
     }
+
+    #[test]
+    fn test_apple_error_schema_variant_display() {
+        let err = AppleError::SchemaError("line 3: unknown field type TEXT".into());
+        assert_eq!(
+            err.to_string(),
+            "Schema error: line 3: unknown field type TEXT"
+        );
+    }
+
+    #[test]
+    fn test_apple_error_predicate_variant_display() {
+        let err = AppleError::PredicateError(apple::cloudkit::PredicateError {
+            message: "Expected a comparator".into(),
+            column: 5,
+            token: "~".into(),
+        });
+        assert_eq!(
+            err.to_string(),
+            "Predicate error: Expected a comparator at column 5 (`~`)"
+        );
+    }
+
+    #[test]
+    fn test_apple_error_environment_guard_variant_display() {
+        let err = AppleError::EnvironmentGuardError("production writes are not enabled".into());
+        assert_eq!(
+            err.to_string(),
+            "Environment guard: production writes are not enabled"
+        );
+    }
+
+    #[test]
+    fn test_apple_error_field_type_variant_display() {
+        let err = AppleError::FieldTypeError(apple::cloudkit::FieldTypeError {
+            field: Some("rating".into()),
+            expected: "INT64",
+            found: Some("STRING"),
+        });
+        assert_eq!(
+            err.to_string(),
+            "Field type error: Field `rating` is STRING, expected INT64"
+        );
+    }
 }